@@ -0,0 +1,196 @@
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use clap::{Parser, ValueEnum};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{ObjectMeta, PutMode, PutOptions, UpdateVersion};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use crate::copy::{Upload, PART_SIZE};
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Append {
+    /// The object to append to. It's created if it doesn't exist yet.
+    #[arg(short, long)]
+    dest: String,
+    /// Append the contents of these objects, in order, instead of reading stdin
+    #[arg(long)]
+    src: Vec<String>,
+    /// How to add to the object
+    #[arg(long, value_enum, default_value_t = Strategy::Auto)]
+    strategy: Strategy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Strategy {
+    /// Append natively on the local filesystem, rewrite small objects and use multipart for the rest
+    Auto,
+    /// Open the file for appending, which only the local filesystem can do
+    Native,
+    /// Download the object and upload it again with the new data, only if it hasn't changed meanwhile
+    Rewrite,
+    /// Stream the object and the new data into a multipart upload, checking the etag before completing
+    Multipart,
+}
+
+/// Read stdin in chunks as they arrive
+fn stdin_chunks() -> BoxStream<'static, Result<Bytes>> {
+    futures::stream::try_unfold(tokio::io::stdin(), |mut stdin| async move {
+        let mut chunk = vec![0; 64 * 1024];
+        let read = stdin.read(&mut chunk).await?;
+        chunk.truncate(read);
+        Ok((read > 0).then(|| (Bytes::from(chunk), stdin)))
+    })
+    .boxed()
+}
+
+impl Append {
+    /// Everything to append, one chunk at a time
+    fn data<'a>(&'a self, dest: &'a Root) -> BoxStream<'a, Result<Bytes>> {
+        if self.src.is_empty() {
+            return stdin_chunks();
+        }
+        futures::stream::iter(&self.src)
+            .then(move |src| async move {
+                let source = dest.open_sibling(&listing::parse_root(src)?)?;
+                let body = source.store.get(&source.path).await?.into_stream();
+                anyhow::Ok(body.map_err(anyhow::Error::from))
+            })
+            .try_flatten()
+            .boxed()
+    }
+
+    async fn native(&self, dest: &Root) -> Result<()> {
+        let path = match dest.url.scheme() {
+            "file" => dest.url.to_file_path().ok(),
+            _ => None,
+        };
+        let Some(path) = path else {
+            bail!("Only the local filesystem can append natively, try --strategy rewrite or multipart");
+        };
+        let mut file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&path)
+            .await
+            .with_context(|| format!("Opening {}", path.display()))?;
+        let mut data = self.data(dest);
+        while let Some(chunk) = data.try_next().await? {
+            file.write_all(&chunk).await?;
+        }
+        Ok(file.flush().await?)
+    }
+
+    async fn rewrite(&self, dest: &Root, existing: Option<&ObjectMeta>) -> Result<()> {
+        let (mut content, mode) = match existing {
+            Some(meta) => (
+                dest.store.get(&dest.path).await?.bytes().await?.to_vec(),
+                PutMode::Update(UpdateVersion {
+                    e_tag: meta.e_tag.clone(),
+                    version: meta.version.clone(),
+                }),
+            ),
+            None => (vec![], PutMode::Create),
+        };
+        let mut data = self.data(dest);
+        while let Some(chunk) = data.try_next().await? {
+            content.extend_from_slice(&chunk);
+        }
+        let opts = PutOptions {
+            mode,
+            ..Default::default()
+        };
+        match dest.store.put_opts(&dest.path, content.into(), opts).await {
+            Ok(_) => Ok(()),
+            Err(
+                object_store::Error::Precondition { .. }
+                | object_store::Error::AlreadyExists { .. },
+            ) => {
+                bail!(
+                    "{} changed while appending to it, nothing was written",
+                    dest.url
+                )
+            }
+            Err(object_store::Error::NotImplemented) => bail!(
+                "{} can't be rewritten safely, since the store doesn't support conditional writes",
+                dest.url
+            ),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn multipart(&self, dest: &Root, existing: Option<&ObjectMeta>) -> Result<()> {
+        if existing.is_some_and(|meta| meta.e_tag.is_none()) {
+            bail!(
+                "{} has no etag, so a multipart append couldn't tell if it changed meanwhile",
+                dest.url
+            );
+        }
+        let mut upload = Upload::start(dest, &dest.path).await?;
+        let written: Result<()> = async {
+            if existing.is_some() {
+                let mut body = dest.store.get(&dest.path).await?.into_stream();
+                while let Some(chunk) = body.try_next().await? {
+                    upload.write(&chunk).await?;
+                }
+            }
+            let mut data = self.data(dest);
+            while let Some(chunk) = data.try_next().await? {
+                upload.write(&chunk).await?;
+            }
+            // Completing can't be made conditional, so check as late as possible instead
+            let now = match dest.store.head(&dest.path).await {
+                Ok(meta) => Some(meta.e_tag),
+                Err(object_store::Error::NotFound { .. }) => None,
+                Err(e) => return Err(e.into()),
+            };
+            if now != existing.map(|meta| meta.e_tag.clone()) {
+                bail!(
+                    "{} changed while appending to it, nothing was written",
+                    dest.url
+                );
+            }
+            Ok(())
+        }
+        .await;
+        upload.finish_or_abort(written).await
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let dest = Root::open(&listing::parse_root(&self.dest)?)?;
+        let existing = match dest.store.head(&dest.path).await {
+            Ok(meta) => Some(meta),
+            Err(object_store::Error::NotFound { .. }) => None,
+            Err(e) => return Err(e.into()),
+        };
+        let strategy = match self.strategy {
+            Strategy::Auto if dest.url.scheme() == "file" => Strategy::Native,
+            Strategy::Auto if existing.as_ref().is_none_or(|meta| meta.size <= PART_SIZE) => {
+                Strategy::Rewrite
+            }
+            Strategy::Auto => Strategy::Multipart,
+            strategy => strategy,
+        };
+        match strategy {
+            Strategy::Native => self.native(&dest).await?,
+            Strategy::Rewrite => self.rewrite(&dest, existing.as_ref()).await?,
+            Strategy::Multipart | Strategy::Auto => {
+                self.multipart(&dest, existing.as_ref()).await?
+            }
+        }
+
+        let appended = dest.store.head(&dest.path).await?;
+        let before = existing.map_or(0, |meta| meta.size);
+        eprintln!(
+            "Appended {} bytes, making {} bytes",
+            appended.size.saturating_sub(before),
+            appended.size
+        );
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.store_url()?))?;
+        stdout.write(ObjectExport::from(appended)).await?;
+        stdout.finish().await
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use tokio::io::AsyncWriteExt;
+
+use crate::listing;
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Cat {
+    /// The object to print. If not specified, every object in a listing read from stdin is printed in order.
+    url: Option<String>,
+    /// Print this line between objects
+    #[arg(long, allow_hyphen_values = true)]
+    objects_delimiter: Option<String>,
+}
+
+impl Cat {
+    /// Stream an object to stdout
+    async fn print(
+        root: &Root,
+        location: &ObjectStorePath,
+        out: &mut tokio::io::Stdout,
+    ) -> Result<()> {
+        let mut body = root.store.get(location).await?.into_stream();
+        while let Some(chunk) = body.try_next().await? {
+            out.write_all(&chunk).await?;
+        }
+        Ok(())
+    }
+
+    async fn print_all(&self, out: &mut tokio::io::Stdout) -> Result<()> {
+        if let Some(url) = &self.url {
+            let root = Root::open(&listing::parse_root(url)?)?;
+            return Self::print(&root, &root.path, out).await;
+        }
+        let root = Root::open(listing::read_preamble()?.root()?)?;
+        let mut objects = std::pin::pin!(listing::read_stdin());
+        let mut first = true;
+        while let Some(meta) = objects.try_next().await? {
+            if let (Some(delimiter), false) = (&self.objects_delimiter, first) {
+                out.write_all(delimiter.as_bytes()).await?;
+                out.write_all(b"\n").await?;
+            }
+            first = false;
+            Self::print(&root, &meta.location, out).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let mut out = tokio::io::stdout();
+        let printed = match self.print_all(&mut out).await {
+            Ok(()) => out.flush().await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        listing::ignore_broken_pipe(printed)
+    }
+}
@@ -0,0 +1,203 @@
+//! Streaming content checksums that `hash` can compute.
+
+use clap::ValueEnum;
+use sha2::Digest;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    Sha256,
+    Md5,
+    Blake3,
+    Crc32c,
+}
+
+/// Incrementally computes a checksum over data that arrives in pieces
+pub trait Checksum: Send {
+    fn update(&mut self, data: &[u8]);
+    /// The finished checksum as lowercase hex
+    fn finish(self: Box<Self>) -> String;
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Md5 => "md5",
+            Algorithm::Blake3 => "blake3",
+            Algorithm::Crc32c => "crc32c",
+        }
+    }
+
+    pub fn start(self) -> Box<dyn Checksum> {
+        match self {
+            Algorithm::Sha256 => Box::new(sha2::Sha256::new()),
+            Algorithm::Md5 => Box::new(md5::Md5::new()),
+            Algorithm::Blake3 => Box::new(blake3::Hasher::new()),
+            Algorithm::Crc32c => Box::new(Crc32c(0)),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl Checksum for sha2::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> String {
+        hex(&self.finalize())
+    }
+}
+
+impl Checksum for md5::Md5 {
+    fn update(&mut self, data: &[u8]) {
+        Digest::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> String {
+        hex(&self.finalize())
+    }
+}
+
+impl Checksum for blake3::Hasher {
+    fn update(&mut self, data: &[u8]) {
+        blake3::Hasher::update(self, data);
+    }
+
+    fn finish(self: Box<Self>) -> String {
+        self.finalize().to_hex().to_string()
+    }
+}
+
+/// The CRC so far, big endian in hex once finished like `gsutil hash` shows it
+struct Crc32c(u32);
+
+impl Checksum for Crc32c {
+    fn update(&mut self, data: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, data);
+    }
+
+    fn finish(self: Box<Self>) -> String {
+        hex(&self.0.to_be_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checksum(algorithm: Algorithm, data: &[u8], chunk: usize) -> String {
+        let mut checksum = algorithm.start();
+        for piece in data.chunks(chunk) {
+            checksum.update(piece);
+        }
+        checksum.finish()
+    }
+
+    /// Published answers, from FIPS 180-4's examples, RFC 1321's test suite, RFC 3720's
+    /// appendix B.4 and the BLAKE3 reference test vectors
+    #[test]
+    fn matches_the_published_answers() {
+        let cases: &[(Algorithm, &[u8], &str)] = &[
+            (
+                Algorithm::Sha256,
+                b"",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            (
+                Algorithm::Sha256,
+                b"abc",
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            ),
+            (
+                Algorithm::Sha256,
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq",
+                "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1",
+            ),
+            (Algorithm::Md5, b"", "d41d8cd98f00b204e9800998ecf8427e"),
+            (Algorithm::Md5, b"abc", "900150983cd24fb0d6963f7d28e17f72"),
+            (
+                Algorithm::Md5,
+                b"12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+            (Algorithm::Crc32c, &[0; 32], "8a9136aa"),
+            (Algorithm::Crc32c, &[0xff; 32], "62a8ab43"),
+            (Algorithm::Crc32c, b"123456789", "e3069283"),
+            (
+                Algorithm::Blake3,
+                b"",
+                "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262",
+            ),
+        ];
+        for (algorithm, data, expected) in cases {
+            assert_eq!(checksum(*algorithm, data, 64), *expected, "{algorithm:?}");
+        }
+        let ascending: Vec<u8> = (0..32).collect();
+        assert_eq!(checksum(Algorithm::Crc32c, &ascending, 64), "46dd794e");
+    }
+
+    #[test]
+    fn matches_the_published_answers_for_a_million_bytes() {
+        let million = vec![b'a'; 1_000_000];
+        assert_eq!(
+            checksum(Algorithm::Sha256, &million, 4096),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    /// The BLAKE3 test vectors hash the bytes 0, 1, ... 250, 0, 1, ... of increasing lengths,
+    /// crossing its chunk and tree boundaries
+    #[test]
+    fn matches_the_blake3_test_vectors_across_chunks() {
+        let input: Vec<u8> = (0..3072).map(|i| (i % 251) as u8).collect();
+        let cases = [
+            (
+                1,
+                "2d3adedff11b61f14c886e35afa036736dcd87a74d27b5c1510225d0f592e213",
+            ),
+            (
+                1024,
+                "42214739f095a406f3fc83deb889744ac00df831c10daa55189b5d121c855af7",
+            ),
+            (
+                1025,
+                "d00278ae47eb27b34faecf67b4fe263f82d5412916c1ffd97c8cb7fb814b8444",
+            ),
+            (
+                3072,
+                "b98cb0ff3623be03326b373de6b9095218513e64f1ee2edd2525c7ad1e5cffd2",
+            ),
+        ];
+        for (len, expected) in cases {
+            assert_eq!(
+                checksum(Algorithm::Blake3, &input[..len], 100),
+                expected,
+                "{len}"
+            );
+        }
+    }
+
+    #[test]
+    fn gives_the_same_answer_however_the_data_is_split() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 256) as u8).collect();
+        for algorithm in [
+            Algorithm::Sha256,
+            Algorithm::Md5,
+            Algorithm::Blake3,
+            Algorithm::Crc32c,
+        ] {
+            let whole = checksum(algorithm, &data, data.len());
+            for chunk in [1, 63, 64, 65, 1023, 1024, 4097] {
+                assert_eq!(
+                    checksum(algorithm, &data, chunk),
+                    whole,
+                    "{algorithm:?} {chunk}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Compressing and decompressing streams that arrive a chunk at a time
+
+use std::io::Write;
+
+use anyhow::{ensure, Result};
+use clap::ValueEnum;
+
+/// A compression format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Codec {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+/// Enough of the start of a stream to recognize every codec's magic bytes
+pub const MAGIC_LEN: usize = 4;
+
+impl Codec {
+    const ALL: [Codec; 3] = [Codec::Gzip, Codec::Zstd, Codec::Bzip2];
+
+    /// The extension added to compressed objects
+    pub fn extension(self) -> &'static str {
+        match self {
+            Codec::Gzip => "gz",
+            Codec::Zstd => "zst",
+            Codec::Bzip2 => "bz2",
+        }
+    }
+
+    /// The bytes every stream in this format starts with
+    pub fn magic(self) -> &'static [u8] {
+        match self {
+            Codec::Gzip => &[0x1f, 0x8b],
+            Codec::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+            Codec::Bzip2 => b"BZh",
+        }
+    }
+
+    /// Recognize a stream by its first bytes
+    pub fn from_magic(start: &[u8]) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|codec| start.starts_with(codec.magic()))
+    }
+
+    /// Recognize a compressed name by its extension, in any case
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "gz" | "tgz" => Some(Codec::Gzip),
+            "zst" => Some(Codec::Zstd),
+            "bz2" => Some(Codec::Bzip2),
+            _ => None,
+        }
+    }
+}
+
+/// Compresses data as it arrives, handing back whatever compressed output is ready each time
+pub struct Encoder(EncoderState);
+
+enum EncoderState {
+    Gzip(flate2::write::GzEncoder<Vec<u8>>),
+    Zstd(zstd::stream::write::Encoder<'static, Vec<u8>>),
+    Bzip2(bzip2::write::BzEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    pub fn new(codec: Codec) -> Result<Self> {
+        Ok(Self(match codec {
+            Codec::Gzip => EncoderState::Gzip(flate2::write::GzEncoder::new(
+                vec![],
+                flate2::Compression::default(),
+            )),
+            Codec::Zstd => EncoderState::Zstd(zstd::stream::write::Encoder::new(
+                vec![],
+                zstd::DEFAULT_COMPRESSION_LEVEL,
+            )?),
+            Codec::Bzip2 => EncoderState::Bzip2(bzip2::write::BzEncoder::new(
+                vec![],
+                bzip2::Compression::default(),
+            )),
+        }))
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match &mut self.0 {
+            EncoderState::Gzip(encoder) => {
+                encoder.write_all(data)?;
+                std::mem::take(encoder.get_mut())
+            }
+            EncoderState::Zstd(encoder) => {
+                encoder.write_all(data)?;
+                std::mem::take(encoder.get_mut())
+            }
+            EncoderState::Bzip2(encoder) => {
+                encoder.write_all(data)?;
+                std::mem::take(encoder.get_mut())
+            }
+        })
+    }
+
+    /// The rest of the compressed stream
+    pub fn finish(self) -> Result<Vec<u8>> {
+        Ok(match self.0 {
+            EncoderState::Gzip(encoder) => encoder.finish()?,
+            EncoderState::Zstd(encoder) => encoder.finish()?,
+            EncoderState::Bzip2(encoder) => encoder.finish()?,
+        })
+    }
+}
+
+/// Decompresses a stream as it arrives, including streams of several concatenated members
+pub struct Decoder(DecoderState);
+
+enum DecoderState {
+    Gzip(flate2::write::MultiGzDecoder<Vec<u8>>),
+    Zstd(zstd::stream::zio::Writer<Vec<u8>, zstd::stream::raw::Decoder<'static>>),
+    Bzip2(Bzip2Decoder),
+}
+
+impl Decoder {
+    pub fn new(codec: Codec) -> Result<Self> {
+        Ok(Self(match codec {
+            Codec::Gzip => DecoderState::Gzip(flate2::write::MultiGzDecoder::new(vec![])),
+            Codec::Zstd => DecoderState::Zstd(zstd::stream::zio::Writer::new(
+                vec![],
+                zstd::stream::raw::Decoder::new()?,
+            )),
+            Codec::Bzip2 => DecoderState::Bzip2(Bzip2Decoder::new()),
+        }))
+    }
+
+    pub fn update(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match &mut self.0 {
+            DecoderState::Gzip(decoder) => {
+                decoder.write_all(data)?;
+                std::mem::take(decoder.get_mut())
+            }
+            DecoderState::Zstd(decoder) => {
+                decoder.write_all(data)?;
+                std::mem::take(decoder.writer_mut())
+            }
+            DecoderState::Bzip2(decoder) => decoder.update(data)?,
+        })
+    }
+
+    /// The rest of the decompressed data, or an error if the stream was cut short
+    pub fn finish(self) -> Result<Vec<u8>> {
+        Ok(match self.0 {
+            DecoderState::Gzip(decoder) => decoder.finish()?,
+            DecoderState::Zstd(mut decoder) => {
+                decoder.finish()?;
+                decoder.into_inner().0
+            }
+            DecoderState::Bzip2(decoder) => {
+                ensure!(decoder.ended, "The bzip2 stream ends early");
+                vec![]
+            }
+        })
+    }
+}
+
+/// Decodes bzip2 directly, because the crate's writer can't read concatenated streams, like
+/// pbzip2 writes, and never finishes a truncated one
+struct Bzip2Decoder {
+    raw: bzip2::Decompress,
+    /// Whether the last stream ended, so the next byte starts another
+    ended: bool,
+}
+
+impl Bzip2Decoder {
+    fn new() -> Self {
+        Self {
+            raw: bzip2::Decompress::new(false),
+            ended: false,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) -> Result<Vec<u8>> {
+        let mut out = vec![];
+        loop {
+            if self.ended {
+                if data.is_empty() {
+                    break;
+                }
+                *self = Self::new();
+            }
+            out.reserve(64 * 1024);
+            let (read, written) = (self.raw.total_in(), out.len());
+            let status = self.raw.decompress_vec(data, &mut out)?;
+            let read = (self.raw.total_in() - read) as usize;
+            data = &data[read..];
+            if status == bzip2::Status::StreamEnd {
+                self.ended = true;
+            } else if data.is_empty() && out.len() < out.capacity() {
+                // It needs more input before it can write anything more
+                break;
+            } else {
+                ensure!(read > 0 || out.len() > written, "The bzip2 stream is stuck");
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Data that compresses well, with repeats both near and far
+    fn sample() -> Vec<u8> {
+        (0..200_000u32)
+            .flat_map(|i| format!("{{\"key\":\"logs/{}\",\"size\":{}}}\n", i % 977, i).into_bytes())
+            .collect()
+    }
+
+    /// Bytes that don't compress at all
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    fn compress(codec: Codec, data: &[u8], chunk: usize) -> Vec<u8> {
+        let mut encoder = Encoder::new(codec).unwrap();
+        let mut out = vec![];
+        for piece in data.chunks(chunk) {
+            out.extend(encoder.update(piece).unwrap());
+        }
+        out.extend(encoder.finish().unwrap());
+        out
+    }
+
+    fn decompress(codec: Codec, data: &[u8], chunk: usize) -> Result<Vec<u8>> {
+        let mut decoder = Decoder::new(codec)?;
+        let mut out = vec![];
+        for piece in data.chunks(chunk) {
+            out.extend(decoder.update(piece)?);
+        }
+        out.extend(decoder.finish()?);
+        Ok(out)
+    }
+
+    #[test]
+    fn round_trips_in_any_size_of_chunk() {
+        let data = sample();
+        for codec in Codec::ALL {
+            for chunk in [1, 7, 4096, 1 << 20] {
+                let compressed = compress(codec, &data[..data.len().min(chunk * 5000)], chunk);
+                assert_eq!(Codec::from_magic(&compressed), Some(codec));
+                let restored = decompress(codec, &compressed, chunk).unwrap();
+                assert_eq!(restored, &data[..data.len().min(chunk * 5000)], "{codec:?}");
+            }
+            let compressed = compress(codec, &data, 65536);
+            assert!(compressed.len() < data.len() / 5, "{codec:?}");
+        }
+    }
+
+    #[test]
+    fn round_trips_empty_streams() {
+        for codec in Codec::ALL {
+            let compressed = compress(codec, b"", 1);
+            assert_eq!(decompress(codec, &compressed, 1).unwrap(), b"");
+        }
+    }
+
+    #[test]
+    fn barely_grows_incompressible_data() {
+        let data = noise(1 << 20);
+        for codec in Codec::ALL {
+            let compressed = compress(codec, &data, 65536);
+            assert!(
+                compressed.len() < data.len() + data.len() / 100,
+                "{codec:?}"
+            );
+            assert_eq!(decompress(codec, &compressed, 65536).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn reads_concatenated_streams() {
+        for codec in Codec::ALL {
+            let mut joined = compress(codec, b"first\n", 64);
+            joined.extend(compress(codec, b"second\n", 64));
+            assert_eq!(decompress(codec, &joined, 3).unwrap(), b"first\nsecond\n");
+        }
+    }
+
+    #[test]
+    fn reads_what_the_usual_tools_wrote() {
+        // `printf 'hello\n' | gzip -9n`
+        let gzip = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9,
+            0xc9, 0xe7, 0x02, 0x00, 0x20, 0x30, 0x3a, 0x36, 0x06, 0x00, 0x00, 0x00,
+        ];
+        // `printf 'hello\n' | zstd`
+        let zstd = [
+            0x28, 0xb5, 0x2f, 0xfd, 0x04, 0x58, 0x31, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c, 0x6f,
+            0x0a, 0x53, 0x88, 0xbd, 0x91,
+        ];
+        // `printf 'hello\n' | bzip2 -9`
+        let bzip2 = [
+            0x42, 0x5a, 0x68, 0x39, 0x31, 0x41, 0x59, 0x26, 0x53, 0x59, 0xc1, 0xc0, 0x80, 0xe2,
+            0x00, 0x00, 0x01, 0x41, 0x00, 0x00, 0x10, 0x02, 0x44, 0xa0, 0x00, 0x30, 0xcd, 0x00,
+            0xc3, 0x46, 0x29, 0x97, 0x17, 0x72, 0x45, 0x38, 0x50, 0x90, 0xc1, 0xc0, 0x80, 0xe2,
+        ];
+        for (codec, written) in [
+            (Codec::Gzip, &gzip[..]),
+            (Codec::Zstd, &zstd[..]),
+            (Codec::Bzip2, &bzip2[..]),
+        ] {
+            assert_eq!(Codec::from_magic(written), Some(codec));
+            assert_eq!(
+                decompress(codec, written, 5).unwrap(),
+                b"hello\n",
+                "{codec:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn notices_streams_cut_short_or_corrupted() {
+        let data = sample();
+        for codec in Codec::ALL {
+            let compressed = compress(codec, &data, 65536);
+            let truncated = &compressed[..compressed.len() - 3];
+            assert!(decompress(codec, truncated, 4096).is_err(), "{codec:?}");
+
+            let mut corrupted = compressed.clone();
+            let middle = corrupted.len() / 2;
+            corrupted[middle] ^= 0xff;
+            assert!(decompress(codec, &corrupted, 4096).is_err(), "{codec:?}");
+        }
+    }
+
+    #[test]
+    fn recognizes_extensions_in_any_case() {
+        assert_eq!(Codec::from_extension("GZ"), Some(Codec::Gzip));
+        assert_eq!(Codec::from_extension("tgz"), Some(Codec::Gzip));
+        assert_eq!(Codec::from_extension("zst"), Some(Codec::Zstd));
+        assert_eq!(Codec::from_extension("Bz2"), Some(Codec::Bzip2));
+        assert_eq!(Codec::from_extension("json"), None);
+    }
+
+    #[test]
+    fn leaves_uncompressed_data_unrecognized() {
+        assert_eq!(Codec::from_magic(b"{\"a\""), None);
+        assert_eq!(Codec::from_magic(&[0x1f]), None);
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::codec::{Codec, Encoder};
+use crate::copy::{Upload, PART_SIZE};
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+/// Extensions of formats that are already compressed, so compressing them again is a waste
+pub const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "gz", "tgz", "zst", "bz2", "xz", "lz4", "br", "zip", "7z", "snappy",
+];
+
+#[derive(Debug, Parser)]
+pub struct Compress {
+    /// The root to write compressed objects into. Paths relative to the source root are preserved.
+    #[arg(short, long)]
+    dest: String,
+    /// How to compress the objects
+    #[arg(long, value_enum, default_value_t = Codec::Gzip)]
+    codec: Codec,
+    /// Compress objects even if their extension says they are already compressed
+    #[arg(long)]
+    force: bool,
+}
+
+/// Whether an object's extension says it is already compressed
+pub fn looks_compressed(location: &ObjectStorePath) -> bool {
+    location
+        .extension()
+        .is_some_and(|ext| COMPRESSED_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+impl Compress {
+    /// Compress one object into its destination, streaming it unless it fits in one part
+    async fn compress(
+        codec: Codec,
+        source: &Root,
+        meta: &ObjectMeta,
+        dest: &Root,
+        location: &ObjectStorePath,
+    ) -> Result<()> {
+        let mut body = source.store.get(&meta.location).await?.into_stream();
+        let mut encoder = Encoder::new(codec)?;
+        if meta.size <= PART_SIZE {
+            let mut compressed = vec![];
+            while let Some(chunk) = body.try_next().await? {
+                compressed.extend(encoder.update(&chunk)?);
+            }
+            compressed.extend(encoder.finish()?);
+            dest.store.put(location, compressed.into()).await?;
+            return Ok(());
+        }
+        let mut upload = Upload::start(dest, location).await?;
+        let streamed: Result<()> = async {
+            while let Some(chunk) = body.try_next().await? {
+                upload.write(&encoder.update(&chunk)?).await?;
+            }
+            upload.write(&encoder.finish()?).await
+        }
+        .await;
+        upload.finish_or_abort(streamed).await
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let source = Root::open(listing::read_preamble()?.root()?)?;
+        let dest = source.open_sibling(&listing::parse_root(&self.dest)?)?;
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.url.clone()))?;
+        let writer = &stdout;
+        let (source, dest) = (&source, &dest);
+        let original = &AtomicUsize::new(0);
+        let compressed = &AtomicUsize::new(0);
+        let compressed_objects = &AtomicUsize::new(0);
+        let skipped = &AtomicUsize::new(0);
+
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                if !self.force && looks_compressed(&meta.location) {
+                    skipped.fetch_add(1, Ordering::Relaxed);
+                    return Ok(());
+                }
+                let rebased = source.rebase(&meta.location, dest)?;
+                let location =
+                    ObjectStorePath::parse(format!("{rebased}.{}", self.codec.extension()))?;
+                Self::compress(self.codec, source, &meta, dest, &location).await?;
+                let created = dest.store.head(&location).await?;
+                original.fetch_add(meta.size, Ordering::Relaxed);
+                compressed.fetch_add(created.size, Ordering::Relaxed);
+                compressed_objects.fetch_add(1, Ordering::Relaxed);
+                writer.write(ObjectExport::from(created)).await
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let original = original.load(Ordering::Relaxed);
+        let compressed = compressed.load(Ordering::Relaxed);
+        eprintln!(
+            "Compressed {} objects from {original} to {compressed} bytes ({:.1}%), skipped {} already compressed",
+            compressed_objects.load(Ordering::Relaxed),
+            100.0 * compressed as f64 / original.max(1) as f64,
+            skipped.load(Ordering::Relaxed),
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Decoder;
+    use url::Url;
+
+    async fn decompressed(codec: Codec, root: &Root, location: &ObjectStorePath) -> Vec<u8> {
+        let body = root
+            .store
+            .get(location)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        let mut decoder = Decoder::new(codec).unwrap();
+        let mut data = decoder.update(&body).unwrap();
+        data.extend(decoder.finish().unwrap());
+        data
+    }
+
+    #[tokio::test]
+    async fn compresses_small_and_streamed_objects_with_either_codec() {
+        let url = Url::parse("memory:///logs/").unwrap();
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url.join("/packed/").unwrap()).unwrap();
+        let small = b"one line\n".repeat(10);
+        // Big enough to be streamed in more than one part
+        let large: Vec<u8> = (0..PART_SIZE / 4)
+            .flat_map(|i| (i as u32 % 5000).to_le_bytes())
+            .chain(*b"tail")
+            .collect();
+        for (key, body) in [("small.txt", small), ("large.bin", large)] {
+            let location = source.path.child(key);
+            source
+                .store
+                .put(&location, body.clone().into())
+                .await
+                .unwrap();
+            let meta = source.store.head(&location).await.unwrap();
+            for codec in [Codec::Gzip, Codec::Zstd] {
+                let packed = dest.path.child(format!("{key}.{}", codec.extension()));
+                Compress::compress(codec, &source, &meta, &dest, &packed)
+                    .await
+                    .unwrap();
+                assert_eq!(
+                    decompressed(codec, &dest, &packed).await,
+                    body,
+                    "{key} {codec:?}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+
+use crate::copy::Upload;
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Concat {
+    /// The object to write everything into
+    #[arg(short, long)]
+    dest: String,
+    /// Concatenate objects in the order they were read, instead of sorting them by key
+    #[arg(long)]
+    keep_order: bool,
+    /// Insert this between objects that don't already end with it. `\n`, `\t`, `\0` and `\\` are understood.
+    #[arg(long, allow_hyphen_values = true)]
+    separator: Option<String>,
+}
+
+/// Turn the escapes people type on the command line into the bytes they mean
+fn unescape(text: &str) -> Result<Vec<u8>> {
+    let mut bytes = vec![];
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+            continue;
+        }
+        match chars.next() {
+            Some('n') => bytes.push(b'\n'),
+            Some('r') => bytes.push(b'\r'),
+            Some('t') => bytes.push(b'\t'),
+            Some('0') => bytes.push(0),
+            Some('\\') => bytes.push(b'\\'),
+            Some(other) => bail!("Unknown escape \\{other}"),
+            None => bail!("Nothing follows the last \\"),
+        }
+    }
+    Ok(bytes)
+}
+
+impl Concat {
+    /// Write every object into the upload in order, fetching ahead concurrently
+    async fn write_all(
+        root: &Root,
+        objects: Vec<ObjectMeta>,
+        separator: &[u8],
+        upload: &mut Upload,
+        concurrency: usize,
+    ) -> Result<()> {
+        let mut bodies = std::pin::pin!(futures::stream::iter(objects)
+            .map(|meta| async move {
+                let object = root.store.get(&meta.location).await?;
+                anyhow::Ok((meta, object))
+            })
+            .buffered(concurrency));
+        // Whether the previous object needs a separator, which is only known once its end is seen
+        let mut needs_separator = false;
+        while let Some((meta, object)) = bodies.try_next().await? {
+            let mut body = object.into_stream();
+            let mut tail = vec![];
+            let mut written = 0;
+            while let Some(chunk) = body.try_next().await? {
+                if needs_separator {
+                    upload.write(separator).await?;
+                    needs_separator = false;
+                }
+                upload.write(&chunk).await?;
+                written += chunk.len();
+                tail.extend_from_slice(&chunk);
+                tail.drain(..tail.len().saturating_sub(separator.len()));
+            }
+            ensure!(
+                written == meta.size,
+                "{} changed size while it was being concatenated",
+                meta.location
+            );
+            if written > 0 {
+                needs_separator = !tail.ends_with(separator);
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let separator = unescape(self.separator.as_deref().unwrap_or_default())?;
+        let root = Root::open(listing::read_preamble()?.root()?)?;
+        let dest = root.open_sibling(&listing::parse_root(&self.dest)?)?;
+        let mut objects: Vec<ObjectMeta> = listing::read_stdin().try_collect().await?;
+        if !self.keep_order {
+            objects.sort_by(|a, b| a.location.cmp(&b.location));
+        }
+        let count = objects.len();
+
+        let mut upload = Upload::start(&dest, &dest.path).await?;
+        let written = Self::write_all(
+            &root,
+            objects,
+            &separator,
+            &mut upload,
+            global_args.concurrency,
+        )
+        .await;
+        upload.finish_or_abort(written).await?;
+
+        let created = dest.store.head(&dest.path).await?;
+        eprintln!("Concatenated {count} objects into {} bytes", created.size);
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.store_url()?))?;
+        stdout.write(ObjectExport::from(created)).await?;
+        stdout.finish().await
+    }
+}
@@ -0,0 +1,374 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{ensure, Result};
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectStorePath;
+use object_store::{Attributes, ObjectMeta, PutMultipartOpts, TagSet, WriteMultipart};
+
+use crate::listing::{self, StdoutWriter};
+use crate::store::{self, Root};
+use crate::units::parse_size;
+use crate::{Args, ObjectExport, Preamble};
+
+/// Objects larger than this are streamed as multipart uploads rather than a single put
+pub const PART_SIZE: usize = 5 * 1024 * 1024;
+/// How many parts of a single object may be uploading at once
+const PART_CONCURRENCY: usize = 8;
+/// S3 refuses to copy objects larger than this in one request, so they are streamed instead
+pub const MAX_SINGLE_COPY: usize = 5 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Parser)]
+pub struct Cp {
+    /// The root to copy objects into. Paths relative to the source root are preserved.
+    #[arg(short, long)]
+    dest: String,
+    /// Copy objects that can't be copied server side in parts of this size, like `64MiB`
+    #[arg(long, value_parser = parse_size, default_value = "5MiB")]
+    part_size: u64,
+    /// How many parts of each object to transfer at once
+    #[arg(long, default_value_t = PART_CONCURRENCY)]
+    part_concurrency: usize,
+}
+
+/// How large objects are split up while they are transferred
+#[derive(Debug, Clone, Copy)]
+pub struct Parts {
+    pub size: usize,
+    pub concurrency: usize,
+}
+
+impl Default for Parts {
+    fn default() -> Self {
+        Self {
+            size: PART_SIZE,
+            concurrency: PART_CONCURRENCY,
+        }
+    }
+}
+
+/// Streams data of any length into a new object, as a multipart upload
+pub struct Upload {
+    inner: WriteMultipart,
+    concurrency: usize,
+}
+
+impl Upload {
+    pub async fn start(to: &Root, dest: &ObjectStorePath) -> Result<Self> {
+        Self::start_with(to, dest, Attributes::new()).await
+    }
+
+    /// Start an upload that sets some attributes on the new object, for stores that support it
+    pub async fn start_with(
+        to: &Root,
+        dest: &ObjectStorePath,
+        attributes: Attributes,
+    ) -> Result<Self> {
+        Self::start_in_parts(to, dest, attributes, Parts::default()).await
+    }
+
+    /// Start an upload that sets attributes and tags on the new object, for stores that support
+    /// them
+    pub async fn start_tagged(
+        to: &Root,
+        dest: &ObjectStorePath,
+        attributes: Attributes,
+        tags: TagSet,
+    ) -> Result<Self> {
+        let opts = PutMultipartOpts { attributes, tags };
+        Self::start_opts(to, dest, opts, Parts::default()).await
+    }
+
+    /// Start an upload with parts of a chosen size, and a limit on how many upload at once
+    pub async fn start_in_parts(
+        to: &Root,
+        dest: &ObjectStorePath,
+        attributes: Attributes,
+        parts: Parts,
+    ) -> Result<Self> {
+        let opts = PutMultipartOpts {
+            attributes,
+            ..Default::default()
+        };
+        Self::start_opts(to, dest, opts, parts).await
+    }
+
+    async fn start_opts(
+        to: &Root,
+        dest: &ObjectStorePath,
+        opts: PutMultipartOpts,
+        parts: Parts,
+    ) -> Result<Self> {
+        Ok(Self {
+            inner: WriteMultipart::new_with_chunk_size(
+                to.store.put_multipart_opts(dest, opts).await?,
+                parts.size,
+            ),
+            concurrency: parts.concurrency,
+        })
+    }
+
+    /// Add data to the object, waiting if too many parts are already uploading
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        self.inner.wait_for_capacity(self.concurrency).await?;
+        self.inner.write(data);
+        Ok(())
+    }
+
+    /// Complete the object if everything was written, otherwise abort it and pass on the error
+    pub async fn finish_or_abort(self, written: Result<()>) -> Result<()> {
+        match written {
+            Ok(()) => {
+                self.inner.finish().await?;
+                Ok(())
+            }
+            Err(e) => {
+                // Don't leave an incomplete upload lying around
+                let _ = self.inner.abort().await;
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Copy one object, server side when both roots share a store and streaming otherwise
+pub async fn copy_object(
+    from: &Root,
+    meta: &ObjectMeta,
+    to: &Root,
+    dest: &ObjectStorePath,
+) -> Result<()> {
+    copy_object_in_parts(from, meta, to, dest, Parts::default()).await
+}
+
+/// Like [`copy_object`], choosing how objects that have to be streamed are split up
+pub async fn copy_object_in_parts(
+    from: &Root,
+    meta: &ObjectMeta,
+    to: &Root,
+    dest: &ObjectStorePath,
+    parts: Parts,
+) -> Result<()> {
+    if copies_server_side(from, meta, to) {
+        from.store.copy(&meta.location, dest).await?;
+        Ok(())
+    } else {
+        stream_object_in_parts(from, meta, to, dest, parts).await
+    }
+}
+
+/// Whether an object can be copied without passing through here
+fn copies_server_side(from: &Root, meta: &ObjectMeta, to: &Root) -> bool {
+    // object_store can't copy byte ranges server side, so the biggest objects are streamed
+    from.same_store(to) && meta.size <= MAX_SINGLE_COPY
+}
+
+/// Copy one object by downloading and uploading it, in one piece if it's small enough
+pub async fn stream_object(
+    from: &Root,
+    meta: &ObjectMeta,
+    to: &Root,
+    dest: &ObjectStorePath,
+) -> Result<()> {
+    stream_object_in_parts(from, meta, to, dest, Parts::default()).await
+}
+
+/// Like [`stream_object`], downloading several byte ranges of large objects at once
+pub async fn stream_object_in_parts(
+    from: &Root,
+    meta: &ObjectMeta,
+    to: &Root,
+    dest: &ObjectStorePath,
+    parts: Parts,
+) -> Result<()> {
+    if meta.size <= parts.size {
+        let body = from.store.get(&meta.location).await?.bytes().await?;
+        to.store.put(dest, body.into()).await?;
+    } else {
+        let ranges = (0..meta.size)
+            .step_by(parts.size)
+            .map(|start| start..(start + parts.size).min(meta.size));
+        let mut chunks = futures::stream::iter(ranges)
+            .map(|range| from.store.get_range(&meta.location, range))
+            .buffered(parts.concurrency);
+        let mut upload = Upload::start_in_parts(to, dest, Attributes::new(), parts).await?;
+        let streamed: Result<()> = async {
+            while let Some(chunk) = chunks.next().await {
+                upload.write(&chunk?).await?;
+            }
+            Ok(())
+        }
+        .await;
+        upload.finish_or_abort(streamed).await?;
+    }
+    Ok(())
+}
+
+impl Cp {
+    /// Copy one object to where it goes below `dest`, unless it's only a directory marker
+    async fn copy(
+        source: &Root,
+        meta: &ObjectMeta,
+        dest: &Root,
+        parts: Parts,
+    ) -> Result<Option<ObjectMeta>> {
+        if store::is_dir_marker(meta) {
+            return Ok(None);
+        }
+        let location = source.rebase(&meta.location, dest)?;
+        copy_object_in_parts(source, meta, dest, &location, parts).await?;
+        Ok(Some(dest.store.head(&location).await?))
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        ensure!(self.part_size > 0, "--part-size must be above zero");
+        ensure!(
+            self.part_concurrency > 0,
+            "--part-concurrency must be at least 1"
+        );
+        let parts = Parts {
+            size: self.part_size as usize,
+            concurrency: self.part_concurrency,
+        };
+        let source = Root::open(listing::read_preamble()?.root()?)?;
+        let dest = source.open_sibling(&listing::parse_root(&self.dest)?)?;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.url.clone()))?;
+        let writer = &stdout;
+        let (source, dest) = (&source, &dest);
+        let markers = &AtomicUsize::new(0);
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                match Self::copy(source, &meta, dest, parts).await? {
+                    Some(copied) => writer.write(ObjectExport::from(copied)).await,
+                    None => {
+                        markers.fetch_add(1, Ordering::Relaxed);
+                        Ok(())
+                    }
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+        let markers = markers.load(Ordering::Relaxed);
+        if markers > 0 {
+            eprintln!("Skipped {markers} directory markers, which don't need copying");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use url::Url;
+
+    /// Parts small enough that a few kilobytes take several of them
+    const SMALL_PARTS: Parts = Parts {
+        size: 1000,
+        concurrency: 2,
+    };
+
+    /// An empty local directory to copy into, and its URL
+    fn scratch(name: &str) -> (PathBuf, Url) {
+        let dir = std::env::temp_dir().join(format!("obvious3-cp-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = Url::from_directory_path(&dir).unwrap();
+        (dir, url)
+    }
+
+    /// Bytes that differ from part to part, so parts out of order would show
+    fn body(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    async fn put(root: &Root, key: &str, body: Vec<u8>) -> ObjectMeta {
+        let location = root.path.child(key);
+        root.store.put(&location, body.into()).await.unwrap();
+        root.store.head(&location).await.unwrap()
+    }
+
+    async fn read(root: &Root, location: &ObjectStorePath) -> Vec<u8> {
+        let body = root.store.get(location).await.unwrap();
+        body.bytes().await.unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn streams_objects_too_large_to_copy_server_side() {
+        let url = Url::parse("memory:///src/").unwrap();
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url.join("/dest/").unwrap()).unwrap();
+        let meta = put(&source, "big.bin", body(10_500)).await;
+        assert!(copies_server_side(&source, &meta, &dest));
+        let huge = ObjectMeta {
+            size: MAX_SINGLE_COPY + 1,
+            ..meta.clone()
+        };
+        assert!(!copies_server_side(&source, &huge, &dest));
+
+        // What happens to those, in the same store, in parts
+        let location = source.rebase(&meta.location, &dest).unwrap();
+        stream_object_in_parts(&source, &meta, &dest, &location, SMALL_PARTS)
+            .await
+            .unwrap();
+        assert_eq!(read(&dest, &location).await, body(10_500));
+    }
+
+    #[tokio::test]
+    async fn copies_between_stores_in_parts() {
+        let source = Root::open(&Url::parse("memory:///src/").unwrap()).unwrap();
+        let (_dir, url) = scratch("between");
+        let dest = source.open_sibling(&url).unwrap();
+        assert!(!source.same_store(&dest));
+        for (key, len) in [("small.bin", 999), ("exact.bin", 3000), ("big.bin", 10_500)] {
+            let meta = put(&source, key, body(len)).await;
+            let location = source.rebase(&meta.location, &dest).unwrap();
+            copy_object_in_parts(&source, &meta, &dest, &location, SMALL_PARTS)
+                .await
+                .unwrap();
+            assert_eq!(read(&dest, &location).await, body(len), "{key}");
+        }
+    }
+
+    #[tokio::test]
+    async fn aborts_the_upload_when_a_part_fails() {
+        let source = Root::open(&Url::parse("memory:///src/").unwrap()).unwrap();
+        let (dir, url) = scratch("aborts");
+        let dest = source.open_sibling(&url).unwrap();
+        let mut meta = put(&source, "shrunk.bin", body(10_500)).await;
+        // It was listed larger than it is now, so the last parts can't be read
+        meta.size = 20_000;
+        let location = source.rebase(&meta.location, &dest).unwrap();
+
+        let copied = copy_object_in_parts(&source, &meta, &dest, &location, SMALL_PARTS).await;
+        assert!(copied.is_err());
+        assert!(dest.store.head(&location).await.is_err());
+        // Nor are the parts left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn leaves_dir_markers_behind() {
+        let url = Url::parse("memory:///src/").unwrap();
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url.join("/dest/").unwrap()).unwrap();
+        let marker = put(&source, "logs_$folder$", vec![]).await;
+        let empty = put(&source, "empty.csv", vec![]).await;
+
+        assert!(Cp::copy(&source, &marker, &dest, SMALL_PARTS)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(dest
+            .store
+            .head(&dest.path.child("logs_$folder$"))
+            .await
+            .is_err());
+        let copied = Cp::copy(&source, &empty, &dest, SMALL_PARTS)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(copied.location.as_ref(), "dest/empty.csv");
+    }
+}
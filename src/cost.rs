@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use futures::TryStreamExt;
+use serde::Serialize;
+
+use crate::du;
+use crate::find::Find;
+use crate::store::Root;
+use crate::units::format_size;
+use crate::Args;
+
+/// Stores bill by the binary gigabyte
+const GB: f64 = (1u64 << 30) as f64;
+
+/// List prices in US dollars per GB-month, for the cheapest US regions at the time of writing
+const DEFAULT_PRICES: &[(&str, f64)] = &[
+    ("s3-standard", 0.023),
+    ("s3-standard-ia", 0.0125),
+    ("s3-one-zone-ia", 0.01),
+    ("s3-glacier-instant-retrieval", 0.004),
+    ("s3-glacier-flexible-retrieval", 0.0036),
+    ("s3-glacier-deep-archive", 0.00099),
+    ("gcs-standard", 0.02),
+    ("gcs-nearline", 0.01),
+    ("gcs-coldline", 0.004),
+    ("gcs-archive", 0.0012),
+    ("azure-hot", 0.018),
+    ("azure-cool", 0.01),
+    ("azure-cold", 0.0045),
+    ("azure-archive", 0.00099),
+];
+
+#[derive(Debug, Parser)]
+pub struct Cost {
+    /// Which objects to price. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// The storage class to price objects at, since listings don't record one
+    #[arg(long, default_value = "s3-standard")]
+    assume_class: String,
+    /// Price the assumed class at this many dollars per GB-month instead
+    #[arg(long)]
+    price_per_gb: Option<f64>,
+    /// Read prices from a file of `class = dollars per GB-month` lines, like a flat TOML table
+    #[arg(long)]
+    prices: Option<PathBuf>,
+    /// Print tables for people, or one JSON document for scripts
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// What some objects cost to keep for a month
+#[derive(Debug, Default, Serialize)]
+struct Line {
+    objects: u64,
+    bytes: u64,
+    monthly_cost: f64,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct Report {
+    total: Line,
+    by_class: BTreeMap<String, Line>,
+    by_prefix: BTreeMap<String, Line>,
+}
+
+/// Read prices from `class = price` lines, skipping blank lines and `#` comments
+fn read_prices(path: &std::path::Path) -> Result<HashMap<String, f64>> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("Reading {}", path.display()))?;
+    let mut prices = HashMap::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((class, price)) = line.split_once('=') else {
+            bail!(
+                "{} line {}: expected `class = price`, found {line:?}",
+                path.display(),
+                number + 1
+            );
+        };
+        let class = class.trim().trim_matches('"');
+        let price = price
+            .trim()
+            .parse()
+            .with_context(|| format!("{} line {}: invalid price", path.display(), number + 1))?;
+        prices.insert(class.to_string(), price);
+    }
+    Ok(prices)
+}
+
+/// Format dollars, keeping enough digits that small prefixes don't all round to nothing
+fn dollars(cost: f64) -> String {
+    match cost >= 1.0 {
+        true => format!("${cost:.2}"),
+        false => format!("${cost:.4}"),
+    }
+}
+
+impl Line {
+    fn add(&mut self, bytes: u64, price: f64) {
+        self.objects += 1;
+        self.bytes += bytes;
+        self.monthly_cost += bytes as f64 / GB * price;
+    }
+}
+
+impl Report {
+    fn print(&self) {
+        let table = |title: &str, lines: &BTreeMap<String, Line>| {
+            println!("{title}");
+            let mut lines: Vec<_> = lines.iter().collect();
+            lines.sort_by(|a, b| {
+                b.1.monthly_cost
+                    .total_cmp(&a.1.monthly_cost)
+                    .then(a.0.cmp(b.0))
+            });
+            let rows: Vec<[String; 4]> = lines
+                .into_iter()
+                .map(|(name, line)| {
+                    [
+                        dollars(line.monthly_cost),
+                        format_size(line.bytes),
+                        line.objects.to_string(),
+                        name.clone(),
+                    ]
+                })
+                .collect();
+            let mut widths = [0; 3];
+            for row in &rows {
+                for (width, cell) in widths.iter_mut().zip(row) {
+                    *width = (*width).max(cell.len());
+                }
+            }
+            for [cost, bytes, objects, name] in rows {
+                println!(
+                    "  {cost:>w0$}  {bytes:>w1$}  {objects:>w2$}  {name}",
+                    w0 = widths[0],
+                    w1 = widths[1],
+                    w2 = widths[2],
+                );
+            }
+        };
+        println!(
+            "Total: {} per month for {} in {} objects",
+            dollars(self.total.monthly_cost),
+            format_size(self.total.bytes),
+            self.total.objects
+        );
+        table("By storage class:", &self.by_class);
+        table("By prefix:", &self.by_prefix);
+    }
+}
+
+impl Cost {
+    fn prices(&self) -> Result<HashMap<String, f64>> {
+        let mut prices: HashMap<String, f64> = DEFAULT_PRICES
+            .iter()
+            .map(|(class, price)| (class.to_string(), *price))
+            .collect();
+        if let Some(path) = &self.prices {
+            prices.extend(read_prices(path)?);
+        }
+        if let Some(price) = self.price_per_gb {
+            prices.insert(self.assume_class.clone(), price);
+        }
+        if !prices.contains_key(&self.assume_class) {
+            let mut known: Vec<_> = prices.keys().map(String::as_str).collect();
+            known.sort();
+            bail!(
+                "There's no price for {:?}, give one with --price-per-gb or choose from {}",
+                self.assume_class,
+                known.join(", ")
+            );
+        }
+        Ok(prices)
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let prices = self.prices()?;
+        let filter = self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+
+        let mut report = Report::default();
+        let mut objects = std::pin::pin!(self.find.objects(listed.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if !filter.is_match(&meta) {
+                continue;
+            }
+            let class = &self.assume_class;
+            let price = prices[class];
+            let bytes = meta.size as u64;
+            let prefix = du::prefix(&root, &meta.location, 1)?;
+            report.total.add(bytes, price);
+            report
+                .by_class
+                .entry(class.clone())
+                .or_default()
+                .add(bytes, price);
+            report
+                .by_prefix
+                .entry(format!("{prefix}/"))
+                .or_default()
+                .add(bytes, price);
+        }
+
+        match self.format {
+            Format::Text => report.print(),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        }
+        Ok(())
+    }
+}
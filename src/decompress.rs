@@ -0,0 +1,286 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::codec::{self, Codec, Decoder};
+use crate::copy::{copy_object, Upload, PART_SIZE};
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Decompress {
+    /// The root to write decompressed objects into. Paths relative to the source root are preserved.
+    #[arg(short, long)]
+    dest: String,
+    /// Copy objects that aren't compressed as they are, rather than skipping them
+    #[arg(long)]
+    passthrough: bool,
+    /// Also save the objects that failed to decompress here, as a listing that can be piped back in
+    #[arg(long)]
+    failed_out: Option<PathBuf>,
+}
+
+/// Where a decompressed object goes: the same path, without the compression extension
+fn decompressed_location(location: &ObjectStorePath) -> Result<ObjectStorePath> {
+    let name = location.as_ref();
+    let stripped = match location.extension().map(|ext| ext.to_ascii_lowercase()) {
+        Some(ext) if ext == "tgz" => format!("{}.tar", &name[..name.len() - 4]),
+        Some(ext) if Codec::from_extension(&ext).is_some() => {
+            name[..name.len() - ext.len() - 1].to_string()
+        }
+        _ => name.to_string(),
+    };
+    Ok(ObjectStorePath::parse(stripped)?)
+}
+
+/// Collects output in memory while it's small, switching to a multipart upload once it isn't
+struct Output<'a> {
+    dest: &'a Root,
+    location: &'a ObjectStorePath,
+    buffer: Vec<u8>,
+    upload: Option<Upload>,
+}
+
+impl Output<'_> {
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(upload) = &mut self.upload {
+            return upload.write(data).await;
+        }
+        self.buffer.extend_from_slice(data);
+        if self.buffer.len() > PART_SIZE {
+            let mut upload = Upload::start(self.dest, self.location).await?;
+            upload.write(&std::mem::take(&mut self.buffer)).await?;
+            self.upload = Some(upload);
+        }
+        Ok(())
+    }
+
+    async fn finish(self, written: Result<()>) -> Result<()> {
+        match self.upload {
+            Some(upload) => upload.finish_or_abort(written).await,
+            None => {
+                written?;
+                self.dest
+                    .store
+                    .put(self.location, self.buffer.into())
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Decompress {
+    /// Decompress one object, or pass it through, returning the object that was written
+    async fn decompress(
+        &self,
+        source: &Root,
+        meta: &ObjectMeta,
+        dest: &Root,
+    ) -> Result<Option<ObjectMeta>> {
+        let mut body = source.store.get(&meta.location).await?.into_stream();
+        // Magic bytes are more reliable than extensions, so look at those first
+        let mut start = vec![];
+        while start.len() < codec::MAGIC_LEN {
+            match body.try_next().await? {
+                Some(chunk) => start.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        let codec = Codec::from_magic(&start)
+            .or_else(|| meta.location.extension().and_then(Codec::from_extension));
+        let rebased = source.rebase(&meta.location, dest)?;
+        let location = match codec {
+            Some(_) => decompressed_location(&rebased)?,
+            None if self.passthrough => rebased,
+            None => return Ok(None),
+        };
+        // Compressed objects without an extension keep their name, so they'd be overwritten
+        ensure!(
+            !source.is_same_object(&meta.location, dest, &location),
+            "{location} would be written over itself, choose a --dest apart from the source"
+        );
+        let Some(codec) = codec else {
+            copy_object(source, meta, dest, &location).await?;
+            return Ok(Some(dest.store.head(&location).await?));
+        };
+
+        let mut output = Output {
+            dest,
+            location: &location,
+            buffer: vec![],
+            upload: None,
+        };
+        let mut decoder = Decoder::new(codec)?;
+        let written: Result<()> = async {
+            output.write(&decoder.update(&start)?).await?;
+            while let Some(chunk) = body.try_next().await? {
+                output.write(&decoder.update(&chunk)?).await?;
+            }
+            output.write(&decoder.finish()?).await
+        }
+        .await;
+        output.finish(written).await?;
+        Ok(Some(dest.store.head(&location).await?))
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let source = Root::open(preamble.root()?)?;
+        let dest = source.open_sibling(&listing::parse_root(&self.dest)?)?;
+        let failures = FailureLog::create(self.failed_out.as_deref(), &preamble)?;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.url.clone()))?;
+        let writer = &stdout;
+        let skipped = &AtomicUsize::new(0);
+        let (source, dest, failures_ref) = (&source, &dest, &failures);
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                match self.decompress(source, &meta, dest).await {
+                    Ok(Some(written)) => writer.write(written.into()).await,
+                    Ok(None) => {
+                        skipped.fetch_add(1, Ordering::Relaxed);
+                        Ok(())
+                    }
+                    Err(e) => failures_ref.record(&meta, &e),
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let skipped = skipped.load(Ordering::Relaxed);
+        if skipped > 0 {
+            eprintln!("Skipped {skipped} objects that aren't compressed. Pass --passthrough to copy them.");
+        }
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to decompress {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Encoder;
+    use url::Url;
+
+    fn compressed(codec: Codec, data: &[u8]) -> Vec<u8> {
+        let mut encoder = Encoder::new(codec).unwrap();
+        let mut out = encoder.update(data).unwrap();
+        out.extend(encoder.finish().unwrap());
+        out
+    }
+
+    fn decompressing(dest: &str, passthrough: bool) -> Decompress {
+        let mut args = vec!["decompress", "--dest", dest];
+        if passthrough {
+            args.push("--passthrough");
+        }
+        Decompress::try_parse_from(args).unwrap()
+    }
+
+    async fn put(root: &Root, key: &str, body: Vec<u8>) -> ObjectMeta {
+        let location = root.path.child(key);
+        root.store.put(&location, body.into()).await.unwrap();
+        root.store.head(&location).await.unwrap()
+    }
+
+    async fn read(root: &Root, location: &ObjectStorePath) -> Vec<u8> {
+        let body = root.store.get(location).await.unwrap();
+        body.bytes().await.unwrap().to_vec()
+    }
+
+    #[tokio::test]
+    async fn decompresses_every_codec_by_name_or_by_first_bytes() {
+        let url = Url::parse("memory:///packed/").unwrap();
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url.join("/plain/").unwrap()).unwrap();
+        let command = decompressing("memory:///plain/", false);
+        let text = b"a line of text\n".repeat(1000);
+        for codec in [Codec::Gzip, Codec::Zstd, Codec::Bzip2] {
+            let named = format!("{codec:?}.txt.{}", codec.extension());
+            let unnamed = format!("{codec:?}-unnamed");
+            for (key, expected) in [
+                (named, format!("plain/{codec:?}.txt")),
+                (unnamed.clone(), format!("plain/{unnamed}")),
+            ] {
+                let meta = put(&source, &key, compressed(codec, &text)).await;
+                let written = command
+                    .decompress(&source, &meta, &dest)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(written.location.as_ref(), expected);
+                assert_eq!(read(&dest, &written.location).await, text, "{key}");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn skips_or_passes_through_what_isnt_compressed() {
+        let url = Url::parse("memory:///packed/").unwrap();
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url.join("/plain/").unwrap()).unwrap();
+        let meta = put(&source, "notes.txt", b"not compressed".to_vec()).await;
+
+        let skipped = decompressing("memory:///plain/", false);
+        assert!(skipped
+            .decompress(&source, &meta, &dest)
+            .await
+            .unwrap()
+            .is_none());
+        let passed = decompressing("memory:///plain/", true);
+        let written = passed
+            .decompress(&source, &meta, &dest)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(written.location.as_ref(), "plain/notes.txt");
+        assert_eq!(read(&dest, &written.location).await, b"not compressed");
+    }
+
+    #[tokio::test]
+    async fn refuses_to_write_over_its_source() {
+        let url = Url::parse("memory:///packed/").unwrap();
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url).unwrap();
+        let body = compressed(Codec::Gzip, b"keep me");
+        let meta = put(&source, "report", body.clone()).await;
+
+        for passthrough in [false, true] {
+            let command = decompressing("memory:///packed/", passthrough);
+            let error = command.decompress(&source, &meta, &dest).await.unwrap_err();
+            assert!(error.to_string().contains("over itself"), "{error}");
+        }
+        let plain = put(&source, "plain.txt", b"as is".to_vec()).await;
+        let command = decompressing("memory:///packed/", true);
+        assert!(command.decompress(&source, &plain, &dest).await.is_err());
+        assert_eq!(read(&source, &meta.location).await, body);
+    }
+
+    #[tokio::test]
+    async fn fails_on_truncated_objects() {
+        let url = Url::parse("memory:///packed/").unwrap();
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url.join("/plain/").unwrap()).unwrap();
+        let command = decompressing("memory:///plain/", false);
+        for codec in [Codec::Gzip, Codec::Zstd, Codec::Bzip2] {
+            let mut body = compressed(codec, &b"a line of text\n".repeat(1000));
+            body.truncate(body.len() - 4);
+            let meta = put(&source, &format!("cut.{}", codec.extension()), body).await;
+            assert!(
+                command.decompress(&source, &meta, &dest).await.is_err(),
+                "{codec:?}"
+            );
+            assert!(dest.store.head(&dest.path.child("cut")).await.is_err());
+        }
+    }
+}
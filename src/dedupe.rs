@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::Algorithm;
+use crate::hash;
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::{self, Root};
+use crate::{Args, ObjectExport};
+
+#[derive(Debug, Parser)]
+pub struct Dedupe {
+    /// Compare checksums of the contents rather than trusting etags
+    #[arg(long)]
+    hash: bool,
+    /// Delete every duplicate except the canonical object of each group
+    #[arg(long)]
+    delete_duplicates: bool,
+    /// Actually delete the duplicates. Without this, only print what would be deleted.
+    #[arg(short, long, visible_alias = "force", requires = "delete_duplicates")]
+    yes: bool,
+}
+
+/// An object that has the same contents as at least one other object in the listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Duplicate {
+    #[serde(flatten)]
+    pub object: ObjectExport,
+    /// Numbers each group of identical objects, starting from 0
+    pub group_id: usize,
+    /// Whether this is the object kept in its group: the oldest, or the first listed among equals
+    pub is_canonical: bool,
+}
+
+/// What makes two objects of the same size identical
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Identity {
+    ETag(String),
+    Checksum(String),
+}
+
+impl Dedupe {
+    /// Split objects of one size into groups with identical contents.
+    ///
+    /// Etags are trusted when every object has one, otherwise all of them are hashed,
+    /// since an object without an etag could still match any of the others.
+    async fn identify(
+        &self,
+        root: &Root,
+        candidates: Vec<(usize, ObjectMeta)>,
+        concurrency: usize,
+    ) -> Result<Vec<(Identity, usize, ObjectMeta)>> {
+        if !self.hash && candidates.iter().all(|(_, meta)| meta.e_tag.is_some()) {
+            return Ok(candidates
+                .into_iter()
+                .map(|(order, meta)| {
+                    let e_tag = store::etag(&meta).unwrap_or_default().to_string();
+                    (Identity::ETag(e_tag), order, meta)
+                })
+                .collect());
+        }
+        futures::stream::iter(candidates)
+            .map(|(order, meta)| async move {
+                let checksum = hash::checksum(Algorithm::Sha256, root, &meta).await?;
+                anyhow::Ok((Identity::Checksum(checksum), order, meta))
+            })
+            .buffer_unordered(concurrency)
+            .try_collect()
+            .await
+    }
+
+    /// Find every group of identical objects, each sorted with its canonical object first
+    async fn groups(&self, root: &Root, concurrency: usize) -> Result<Vec<Vec<ObjectMeta>>> {
+        // Only objects of the same size can be identical, so that narrows down what to compare
+        let mut by_size = HashMap::<usize, Vec<(usize, ObjectMeta)>>::new();
+        let mut order = 0;
+        let mut objects = std::pin::pin!(listing::read_stdin());
+        while let Some(meta) = objects.try_next().await? {
+            by_size.entry(meta.size).or_default().push((order, meta));
+            order += 1;
+        }
+
+        let mut groups = HashMap::<(usize, Identity), Vec<(usize, ObjectMeta)>>::new();
+        for (size, candidates) in by_size {
+            if candidates.len() < 2 {
+                continue;
+            }
+            for (identity, order, meta) in self.identify(root, candidates, concurrency).await? {
+                groups
+                    .entry((size, identity))
+                    .or_default()
+                    .push((order, meta));
+            }
+        }
+
+        let mut groups: Vec<_> = groups
+            .into_values()
+            .filter(|group| group.len() > 1)
+            .map(|mut group| {
+                group.sort_by_key(|(order, meta)| (meta.last_modified, *order));
+                group
+            })
+            .collect();
+        // Keep the output stable by ordering groups by where their canonical object was listed
+        groups.sort_by_key(|group| group[0].0);
+        Ok(groups
+            .into_iter()
+            .map(|group| group.into_iter().map(|(_, meta)| meta).collect())
+            .collect())
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let root = &Root::open(preamble.root()?)?;
+        let groups = self.groups(root, global_args.concurrency).await?;
+
+        let stdout = StdoutWriter::start(&preamble)?;
+        let mut duplicates = vec![];
+        for (group_id, group) in groups.iter().enumerate() {
+            for (i, meta) in group.iter().enumerate() {
+                stdout
+                    .write(Duplicate {
+                        object: meta.clone().into(),
+                        group_id,
+                        is_canonical: i == 0,
+                    })
+                    .await?;
+                if i > 0 {
+                    duplicates.push(meta.clone());
+                }
+            }
+        }
+        stdout.finish().await?;
+
+        let bytes: usize = groups
+            .iter()
+            .flat_map(|group| group.iter().skip(1))
+            .map(|meta| meta.size)
+            .sum();
+        eprintln!(
+            "Found {} duplicates in {} groups, taking {bytes} bytes",
+            duplicates.len(),
+            groups.len()
+        );
+        if !self.delete_duplicates {
+            return Ok(());
+        }
+        if !self.yes {
+            eprintln!(
+                "Dry run: {} duplicates would be deleted. Pass --yes to delete them.",
+                duplicates.len()
+            );
+            return Ok(());
+        }
+
+        // The duplicates were already printed along with the rest of their groups
+        let failures = FailureLog::create(None, &preamble)?;
+        let deleted = listing::delete_all(
+            root,
+            futures::stream::iter(duplicates.into_iter().map(anyhow::Ok)),
+            true,
+            None,
+            &failures,
+            global_args.concurrency,
+        )
+        .await?;
+        eprintln!("Deleted {} duplicates", deleted.count);
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to delete {failed} duplicates");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::path::Path as ObjectStorePath;
+    use url::Url;
+
+    #[tokio::test]
+    async fn hashes_a_whole_size_when_any_object_lacks_an_etag() {
+        let root = Root::open(&Url::parse("memory:///data/").unwrap()).unwrap();
+        let mut candidates = vec![];
+        for (order, key) in ["data/a", "data/b", "data/c"].iter().enumerate() {
+            let location = ObjectStorePath::from(*key);
+            root.store.put(&location, "same".into()).await.unwrap();
+            candidates.push((order, root.store.head(&location).await.unwrap()));
+        }
+        let dedupe = Dedupe::try_parse_from(["dedupe"]).unwrap();
+
+        // Every object has its own etag, so none of them look the same
+        let identities = dedupe.identify(&root, candidates.clone(), 4).await.unwrap();
+        assert!(identities
+            .iter()
+            .all(|(identity, _, _)| matches!(identity, Identity::ETag(_))));
+        let distinct: std::collections::HashSet<_> =
+            identities.iter().map(|(identity, _, _)| identity).collect();
+        assert_eq!(distinct.len(), 3);
+
+        // Once one is missing its etag, all of them are hashed, and turn out the same
+        candidates[1].1.e_tag = None;
+        let identities = dedupe.identify(&root, candidates, 4).await.unwrap();
+        assert_eq!(identities.len(), 3);
+        assert!(identities
+            .iter()
+            .all(|(identity, _, _)| matches!(identity, Identity::Checksum(_))));
+        let distinct: std::collections::HashSet<_> =
+            identities.iter().map(|(identity, _, _)| identity).collect();
+        assert_eq!(distinct.len(), 1);
+    }
+}
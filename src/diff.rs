@@ -0,0 +1,145 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+use serde::Serialize;
+
+use crate::join::{merge_join, Joined};
+use crate::listing::{self, ListingPath, StdoutWriter};
+use crate::store::{self, Root};
+use crate::{Args, ObjectExport};
+
+#[derive(Debug, Parser)]
+pub struct Diff {
+    /// The old side: a saved listing file, `-` for a listing on stdin, or a root to list live.
+    ///
+    /// Saved listings must be sorted by key, which live listings always are.
+    #[arg(long)]
+    left: String,
+    /// The new side, given the same way as `--left`
+    #[arg(long)]
+    right: String,
+}
+
+/// How an object differs between the two sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Change {
+    /// Only the right side has the object
+    Added,
+    /// Only the left side has the object
+    Removed,
+    /// Both sides have the object, but the size or etag differs
+    Changed,
+}
+
+/// A line of the diff, naming the object by its path relative to each side's root
+#[derive(Debug, Serialize)]
+struct Difference {
+    change: Change,
+    key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    left: Option<ObjectExport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    right: Option<ObjectExport>,
+}
+
+/// One side of the diff, with the objects already read if it came from a listing
+struct Side {
+    root: Root,
+    listing: Option<BoxStream<'static, Result<ObjectMeta>>>,
+}
+
+impl Side {
+    async fn open(arg: &str) -> Result<Self> {
+        if arg == "-" {
+            let preamble = listing::read_preamble()?;
+            return Ok(Self {
+                root: Root::open(preamble.root()?)?,
+                listing: Some(listing::read_stdin().boxed()),
+            });
+        }
+        let path = std::path::Path::new(arg);
+        if url::Url::parse(arg).is_err() && path.is_file() {
+            let (preamble, objects) =
+                listing::read_file(&ListingPath::File(path.to_path_buf())).await?;
+            return Ok(Self {
+                root: Root::open(preamble.root()?)?,
+                listing: Some(objects.boxed()),
+            });
+        }
+        Ok(Self {
+            root: Root::open(&listing::parse_root(arg)?)?,
+            listing: None,
+        })
+    }
+
+    /// Every object on this side in key order, paired with its key
+    fn keyed(&mut self) -> BoxStream<'_, Result<(String, ObjectMeta)>> {
+        let root = &self.root;
+        match self.listing.take() {
+            Some(objects) => objects
+                .and_then(move |meta| async move { Ok((root.key(&meta.location)?, meta)) })
+                .boxed(),
+            None => root.list_keyed(),
+        }
+    }
+}
+
+/// Whether an object present on both sides has changed, comparing etags only if both have one
+pub fn has_changed(left: &ObjectMeta, right: &ObjectMeta) -> bool {
+    left.size != right.size
+        || matches!((store::etag(left), store::etag(right)), (Some(l), Some(r)) if l != r)
+}
+
+impl Diff {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        if self.left == "-" && self.right == "-" {
+            bail!("Only one side can be read from stdin");
+        }
+        let mut left = Side::open(&self.left).await?;
+        let mut right = Side::open(&self.right).await?;
+        let stdout = StdoutWriter::start_bare()?;
+        let (mut added, mut removed, mut changed) = (0, 0, 0);
+
+        // Keep the keys alongside the objects, to name them in the output
+        let with_keys = |(key, meta): (String, ObjectMeta)| (key.clone(), (key, meta));
+        let mut joined = std::pin::pin!(merge_join(
+            left.keyed().map_ok(with_keys),
+            right.keyed().map_ok(with_keys)
+        ));
+        while let Some(step) = joined.try_next().await? {
+            let (change, key, left, right) = match step {
+                Joined::Left((key, l)) => {
+                    removed += 1;
+                    (Change::Removed, key, Some(l), None)
+                }
+                Joined::Right((key, r)) => {
+                    added += 1;
+                    (Change::Added, key, None, Some(r))
+                }
+                Joined::Both((key, l), (_, r)) if has_changed(&l, &r) => {
+                    changed += 1;
+                    (Change::Changed, key, Some(l), Some(r))
+                }
+                Joined::Both(..) => continue,
+            };
+            stdout
+                .write(Difference {
+                    change,
+                    key,
+                    left: left.map(ObjectExport::from),
+                    right: right.map(ObjectExport::from),
+                })
+                .await?;
+        }
+        stdout.finish().await?;
+
+        eprintln!("{added} added, {removed} removed, {changed} changed");
+        if added + removed + changed > 0 {
+            bail!("The two sides differ");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::find::Find;
+use crate::store::Root;
+use crate::units::format_size;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Du {
+    /// Which objects to count. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// How many levels of prefixes below the root to total separately. 0 gives only the grand total.
+    #[arg(short, long, default_value = "1")]
+    depth: usize,
+}
+
+/// The objects found under one prefix
+#[derive(Debug, Default)]
+pub struct Usage {
+    pub bytes: u64,
+    pub objects: u64,
+}
+
+/// The prefix `depth` levels below the root that an object is under, which never includes its own name
+pub fn prefix(root: &Root, location: &ObjectStorePath, depth: usize) -> Result<ObjectStorePath> {
+    let parts: Vec<_> = root.relative(location)?.collect();
+    let depth = depth.min(parts.len().saturating_sub(1));
+    Ok(root
+        .path
+        .parts()
+        .chain(parts.into_iter().take(depth))
+        .collect())
+}
+
+/// Print a table of totals, largest first
+pub fn print_totals(totals: HashMap<ObjectStorePath, Usage>) {
+    let mut totals: Vec<_> = totals.into_iter().collect();
+    totals.sort_by(|(a_prefix, a), (b_prefix, b)| {
+        b.bytes.cmp(&a.bytes).then_with(|| a_prefix.cmp(b_prefix))
+    });
+    let rows: Vec<[String; 4]> = totals
+        .into_iter()
+        .map(|(prefix, usage)| {
+            [
+                format_size(usage.bytes),
+                usage.bytes.to_string(),
+                usage.objects.to_string(),
+                format!("{prefix}/"),
+            ]
+        })
+        .collect();
+    let mut widths = [0; 3];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+    for [human, bytes, objects, prefix] in rows {
+        println!(
+            "{human:>w0$}  {bytes:>w1$}  {objects:>w2$}  {prefix}",
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+        );
+    }
+}
+
+impl Du {
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let filter = &self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        // Piped listings don't open the store, but the root is needed to find relative paths
+        let root = &match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+        let totals = &Mutex::new(HashMap::<ObjectStorePath, Usage>::new());
+
+        let add_matches = |meta: ObjectMeta| async move {
+            if filter.is_match(&meta) {
+                let prefix = prefix(root, &meta.location, self.depth)?;
+                let mut totals = totals.lock().unwrap();
+                let usage = totals.entry(prefix).or_default();
+                usage.bytes += meta.size as u64;
+                usage.objects += 1;
+            }
+            Ok(())
+        };
+        self.find
+            .objects(listed.as_ref())
+            .try_for_each_concurrent(global_args.concurrency, add_matches)
+            .await?;
+
+        let mut totals = totals.lock().unwrap();
+        if totals.is_empty() {
+            // Even an empty root has a total
+            totals.insert(root.path.clone(), Usage::default());
+        }
+        print_totals(std::mem::take(&mut *totals));
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+
+use crate::get::download;
+use crate::listing::{self, FailureLog};
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Exec {
+    /// Keep running commands after one fails, and exit successfully anyway
+    #[arg(long)]
+    keep_going: bool,
+    /// Also save the objects whose command failed here, as a listing that can be piped back in
+    #[arg(long)]
+    failed_out: Option<PathBuf>,
+    /// Download each object to a temporary file first, which `{file}` refers to
+    #[arg(long)]
+    download: bool,
+    /// The command to run for each object, after `--`.
+    ///
+    /// `{url}`, `{path}`, `{basename}` and `{size}` are replaced in every argument,
+    /// as is `{file}` with `--download`.
+    #[arg(last = true, required = true)]
+    command: Vec<String>,
+}
+
+impl Exec {
+    /// Fill in the placeholders of every argument for one object
+    fn arguments(
+        &self,
+        root: &Root,
+        meta: &ObjectMeta,
+        file: Option<&Path>,
+    ) -> Result<Vec<String>> {
+        let url = root.object_url(&meta.location)?.to_string();
+        let path = meta.location.to_string();
+        let basename = meta.location.filename().unwrap_or_default();
+        let size = meta.size.to_string();
+        let file = file.map(|f| f.display().to_string()).unwrap_or_default();
+        Ok(self
+            .command
+            .iter()
+            .map(|arg| {
+                arg.replace("{url}", &url)
+                    .replace("{path}", &path)
+                    .replace("{basename}", basename)
+                    .replace("{size}", &size)
+                    .replace("{file}", &file)
+            })
+            .collect())
+    }
+
+    /// Run the command for one object, downloading it first if needed
+    async fn execute(&self, root: &Root, meta: &ObjectMeta, index: usize) -> Result<()> {
+        let file = self.download.then(|| {
+            // Keep the basename so tools that look at extensions still work
+            std::env::temp_dir().join(format!(
+                "obvious3-exec-{}-{index}-{}",
+                std::process::id(),
+                meta.location.filename().unwrap_or_default()
+            ))
+        });
+        let result = async {
+            if let Some(file) = &file {
+                download(root, meta, file).await?;
+            }
+            let args = self.arguments(root, meta, file.as_deref())?;
+            let status = tokio::process::Command::new(&args[0])
+                .args(&args[1..])
+                .kill_on_drop(true)
+                .status()
+                .await
+                .with_context(|| format!("Running {}", args[0]))?;
+            ensure!(status.success(), "{} failed with {status}", args[0]);
+            Ok(())
+        }
+        .await;
+        if let Some(file) = &file {
+            let _ = tokio::fs::remove_file(file).await;
+        }
+        result
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        if !self.download && self.command.iter().any(|arg| arg.contains("{file}")) {
+            bail!("{{file}} only refers to something with --download");
+        }
+        let preamble = listing::read_preamble()?;
+        let root = &Root::open(preamble.root()?)?;
+        let failures = FailureLog::create(self.failed_out.as_deref(), &preamble)?;
+        let failures_ref = &failures;
+        let started = &AtomicUsize::new(0);
+
+        let ran = listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                let index = started.fetch_add(1, Ordering::Relaxed);
+                if let Err(e) = self.execute(root, &meta, index).await {
+                    failures_ref.record(&meta, &e)?;
+                    if !self.keep_going {
+                        bail!("Stopping, pass --keep-going to run the rest anyway");
+                    }
+                }
+                Ok(())
+            })
+            .await;
+        let failed = failures.finish()?;
+        ran?;
+
+        if failed > 0 {
+            eprintln!(
+                "The command failed for {failed} of {} objects",
+                started.load(Ordering::Relaxed)
+            );
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+
+use crate::find::Find;
+use crate::latest::newest_first;
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::Root;
+use crate::units::{format_size, parse_duration};
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Expire {
+    /// Which objects can expire. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// Delete objects last modified longer ago than this, like `30d` or `12h`
+    #[arg(long, value_parser = parse_duration)]
+    older_than: Duration,
+    /// Always keep at least this many of the newest objects, however old they are
+    #[arg(long, default_value = "0")]
+    min_keep: usize,
+    /// Actually delete the objects. Without this, only print what would be deleted.
+    #[arg(short, long)]
+    yes: bool,
+}
+
+impl Expire {
+    /// Which of the matches are older than the cutoff, except for the `--min-keep` newest,
+    /// which are kept whatever their age
+    fn expired(&self, mut matches: Vec<ObjectMeta>, cutoff: DateTime<Utc>) -> Vec<ObjectMeta> {
+        matches.sort_by(|a, b| newest_first(a).cmp(&newest_first(b)));
+        matches
+            .into_iter()
+            .skip(self.min_keep)
+            .filter(|meta| meta.last_modified < cutoff)
+            .collect()
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let filter = self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+        let cutoff = Utc::now() - chrono::Duration::from_std(self.older_than)?;
+
+        // The whole listing has to succeed before anything is deleted,
+        // since a partial one could make the newest objects look like they're missing
+        let matches: Vec<ObjectMeta> = self
+            .find
+            .objects(listed.as_ref())
+            .try_filter(|meta| std::future::ready(filter.is_match(meta)))
+            .try_collect()
+            .await?;
+        let expired = self.expired(matches, cutoff);
+        let bytes: u64 = expired.iter().map(|meta| meta.size as u64).sum();
+        eprintln!(
+            "{} objects ({}) are older than {}",
+            expired.len(),
+            format_size(bytes),
+            humantime::format_duration(self.older_than)
+        );
+
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let failures = FailureLog::create(None, &preamble)?;
+        let deleted = listing::delete_all(
+            &root,
+            futures::stream::iter(expired.into_iter().map(anyhow::Ok)),
+            self.yes,
+            Some(&stdout),
+            &failures,
+            global_args.concurrency,
+        )
+        .await?;
+        stdout.finish().await?;
+
+        let reclaimed = format_size(deleted.bytes);
+        let deleted = deleted.count;
+        if self.yes {
+            eprintln!("Deleted {deleted} objects, reclaiming {reclaimed}");
+        } else {
+            eprintln!(
+                "Dry run: {deleted} objects would be deleted, reclaiming {reclaimed}. Pass --yes to delete them."
+            );
+        }
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to delete {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::path::Path as ObjectStorePath;
+
+    fn aged(location: &str, days: i64) -> ObjectMeta {
+        ObjectMeta {
+            location: ObjectStorePath::from(location),
+            last_modified: Utc::now() - chrono::Duration::days(days),
+            size: 0,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    fn expired(args: &[&str], matches: Vec<ObjectMeta>) -> Vec<String> {
+        let expire = Expire::try_parse_from(["expire"].iter().chain(args)).unwrap();
+        let cutoff = Utc::now() - chrono::Duration::from_std(expire.older_than).unwrap();
+        let mut expired: Vec<_> = expire
+            .expired(matches, cutoff)
+            .iter()
+            .map(|meta| meta.location.to_string())
+            .collect();
+        expired.sort();
+        expired
+    }
+
+    #[test]
+    fn min_keep_holds_on_to_the_newest_however_old() {
+        let matches = vec![aged("data/a", 40), aged("data/b", 50), aged("data/c", 60)];
+        assert_eq!(
+            expired(&["--older-than", "30d"], matches.clone()),
+            ["data/a", "data/b", "data/c"]
+        );
+        // Everything is past the cutoff, but the two newest stay anyway
+        assert_eq!(
+            expired(&["--older-than", "30d", "--min-keep", "2"], matches.clone()),
+            ["data/c"]
+        );
+        assert!(expired(&["--older-than", "30d", "--min-keep", "5"], matches).is_empty());
+
+        // Objects too young to expire count toward what's kept
+        let matches = vec![
+            aged("data/new", 1),
+            aged("data/old", 40),
+            aged("data/older", 50),
+        ];
+        assert_eq!(
+            expired(&["--older-than", "30d", "--min-keep", "2"], matches),
+            ["data/older"]
+        );
+    }
+}
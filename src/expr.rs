@@ -0,0 +1,455 @@
+//! A small language for filtering objects, like `size > 10MiB && basename =~ "\.parquet$"`
+//!
+//! Comparisons look like `field op value`, and combine with `&&`, `||`, `!` and parentheses.
+//! * `path`, `basename`, `etag` and `version` compare to quoted strings with `==` and `!=`,
+//!   or to regexes with `=~` and `!~`
+//! * `size` compares to sizes like `4096` or `1.5GiB` with `==`, `!=`, `<`, `<=`, `>` and `>=`
+//! * `age` compares the same way to durations like `7d` or `2h30m`
+//! * `last_modified`, or `mtime`, compares the same way to quoted times, written any way
+//!   `--after-absolute` accepts
+
+use std::cmp::Ordering;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use object_store::ObjectMeta;
+
+use crate::store;
+use crate::units::{parse_duration, parse_size, parse_time};
+
+/// A parsed expression, ready to test objects against
+#[derive(Debug)]
+pub struct Expression {
+    root: Node,
+    /// Ages are measured from when the expression was parsed, so they don't drift during a listing
+    now: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Text(TextField, TextTest),
+    Size(Compare, u64),
+    Age(Compare, Duration),
+    Modified(Compare, DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TextField {
+    Path,
+    Basename,
+    Etag,
+    Version,
+}
+
+#[derive(Debug)]
+enum TextTest {
+    Equal(String),
+    NotEqual(String),
+    Match(regex::Regex),
+    NotMatch(regex::Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compare {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Compare {
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Compare::Equal => ordering.is_eq(),
+            Compare::NotEqual => ordering.is_ne(),
+            Compare::Less => ordering.is_lt(),
+            Compare::LessOrEqual => ordering.is_le(),
+            Compare::Greater => ordering.is_gt(),
+            Compare::GreaterOrEqual => ordering.is_ge(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A field name, or an unquoted value like `10MiB`
+    Word(String),
+    Quoted(String),
+    Op(&'static str),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    End,
+}
+
+/// Describe where in the expression something went wrong, with a caret under it
+fn error_at(text: &str, at: usize, message: impl std::fmt::Display) -> anyhow::Error {
+    let column = text[..at.min(text.len())].chars().count();
+    anyhow::anyhow!("{message}\n  {text}\n  {}^", " ".repeat(column))
+}
+
+/// Split an expression into tokens, along with where each one starts
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>> {
+    const OPERATORS: [&str; 8] = ["==", "!=", "<=", ">=", "=~", "!~", "<", ">"];
+    let mut tokens = vec![];
+    let mut chars = text.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        let rest = &text[at..];
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let (token, len) = if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            (Token::Op(op), op.len())
+        } else if rest.starts_with("&&") {
+            (Token::And, 2)
+        } else if rest.starts_with("||") {
+            (Token::Or, 2)
+        } else if c == '!' {
+            (Token::Not, 1)
+        } else if c == '(' {
+            (Token::Open, 1)
+        } else if c == ')' {
+            (Token::Close, 1)
+        } else if c == '"' {
+            // Backslashes only escape quotes and themselves, so regexes can be written naturally
+            let mut value = String::new();
+            let mut escaped = false;
+            let mut end = None;
+            for (i, c) in rest.char_indices().skip(1) {
+                match (escaped, c) {
+                    (false, '\\') => escaped = true,
+                    (false, '"') => {
+                        end = Some(i + 1);
+                        break;
+                    }
+                    (true, '"' | '\\') => {
+                        value.push(c);
+                        escaped = false;
+                    }
+                    (true, c) => {
+                        value.push('\\');
+                        value.push(c);
+                        escaped = false;
+                    }
+                    (false, c) => value.push(c),
+                }
+            }
+            let Some(end) = end else {
+                return Err(error_at(text, at, "This string isn't closed"));
+            };
+            (Token::Quoted(value), end)
+        } else if c.is_alphanumeric() || c == '_' || c == '.' {
+            let len = rest
+                .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(rest.len());
+            (Token::Word(rest[..len].to_string()), len)
+        } else {
+            return Err(error_at(text, at, format!("Unexpected {c:?}")));
+        };
+        tokens.push((token, at));
+        while chars.peek().is_some_and(|&(i, _)| i < at + len) {
+            chars.next();
+        }
+    }
+    tokens.push((Token::End, text.len()));
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    /// Whether regexes ignore case, as with `--ignore-case`
+    case_insensitive: bool,
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].0
+    }
+
+    fn take(&mut self) -> (Token, usize) {
+        let token = self.tokens[self.next].clone();
+        if token.0 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn error(&self, at: usize, message: impl std::fmt::Display) -> anyhow::Error {
+        error_at(self.text, at, message)
+    }
+
+    fn or(&mut self) -> Result<Node> {
+        let mut node = self.and()?;
+        while self.peek() == &Token::Or {
+            self.take();
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node> {
+        let mut node = self.unary()?;
+        while self.peek() == &Token::And {
+            self.take();
+            node = Node::And(Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node> {
+        match self.take() {
+            (Token::Not, _) => Ok(Node::Not(Box::new(self.unary()?))),
+            (Token::Open, _) => {
+                let node = self.or()?;
+                match self.take() {
+                    (Token::Close, _) => Ok(node),
+                    (_, at) => Err(self.error(at, "Expected ) to close an earlier (")),
+                }
+            }
+            (Token::Word(field), at) => self.comparison(&field, at),
+            (_, at) => Err(self.error(at, "Expected a field like size, or (")),
+        }
+    }
+
+    /// Point at the value when it can't be understood
+    fn check<T, E: std::fmt::Display>(&self, at: usize, result: Result<T, E>) -> Result<T> {
+        result.map_err(|e| self.error(at, e))
+    }
+
+    fn comparison(&mut self, field: &str, field_at: usize) -> Result<Node> {
+        let text_field = match field {
+            "path" => Some(TextField::Path),
+            "basename" => Some(TextField::Basename),
+            "etag" => Some(TextField::Etag),
+            "version" => Some(TextField::Version),
+            "size" | "age" | "last_modified" | "mtime" => None,
+            _ => {
+                return Err(self.error(
+                    field_at,
+                    format!("Unknown field {field:?}, expected path, basename, etag, version, size, age or last_modified"),
+                ))
+            }
+        };
+        let (op, op_at) = match self.take() {
+            (Token::Op(op), at) => (op, at),
+            (_, at) => return Err(self.error(at, format!("Expected an operator after {field}"))),
+        };
+        let (value, value_at) = match self.take() {
+            (Token::Word(value) | Token::Quoted(value), at) => (value, at),
+            (_, at) => {
+                return Err(self.error(at, format!("Expected a value to compare {field} to")))
+            }
+        };
+        if let Some(text_field) = text_field {
+            let regex = || {
+                let built = regex::RegexBuilder::new(&value)
+                    .case_insensitive(self.case_insensitive)
+                    .build();
+                self.check(value_at, built)
+            };
+            let test = match op {
+                "==" => TextTest::Equal(value.clone()),
+                "!=" => TextTest::NotEqual(value.clone()),
+                "=~" => TextTest::Match(regex()?),
+                "!~" => TextTest::NotMatch(regex()?),
+                _ => {
+                    return Err(self.error(
+                        op_at,
+                        format!("{field} can only be compared with ==, !=, =~ or !~"),
+                    ))
+                }
+            };
+            return Ok(Node::Text(text_field, test));
+        }
+        let compare = match op {
+            "==" => Compare::Equal,
+            "!=" => Compare::NotEqual,
+            "<" => Compare::Less,
+            "<=" => Compare::LessOrEqual,
+            ">" => Compare::Greater,
+            ">=" => Compare::GreaterOrEqual,
+            _ => return Err(self.error(op_at, format!("{op} only works on text fields"))),
+        };
+        Ok(match field {
+            "size" => Node::Size(compare, self.check(value_at, parse_size(&value))?),
+            "age" => Node::Age(compare, self.check(value_at, parse_duration(&value))?),
+            _ => Node::Modified(compare, self.check(value_at, parse_time(&value))?),
+        })
+    }
+}
+
+impl Expression {
+    /// Parse an expression, explaining where it's wrong if it can't be parsed
+    pub fn parse(text: &str, case_insensitive: bool) -> Result<Self> {
+        let mut parser = Parser {
+            text,
+            case_insensitive,
+            tokens: tokenize(text)?,
+            next: 0,
+        };
+        let root = parser.or()?;
+        let (token, at) = parser.take();
+        if token != Token::End {
+            bail!(parser.error(at, "Expected && or || before this"));
+        }
+        Ok(Self {
+            root,
+            now: Utc::now(),
+        })
+    }
+
+    /// Whether an object satisfies the expression
+    pub fn is_match(&self, meta: &ObjectMeta) -> bool {
+        self.root.eval(meta, self.now)
+    }
+}
+
+impl Node {
+    fn eval(&self, meta: &ObjectMeta, now: DateTime<Utc>) -> bool {
+        match self {
+            Node::And(left, right) => left.eval(meta, now) && right.eval(meta, now),
+            Node::Or(left, right) => left.eval(meta, now) || right.eval(meta, now),
+            Node::Not(node) => !node.eval(meta, now),
+            Node::Text(field, test) => {
+                let value = match field {
+                    TextField::Path => Some(meta.location.as_ref()),
+                    TextField::Basename => meta.location.filename(),
+                    TextField::Etag => store::etag(meta),
+                    TextField::Version => meta.version.as_deref(),
+                };
+                // Objects without an etag or version only pass negative tests on them
+                match (test, value) {
+                    (TextTest::Equal(expected), value) => value == Some(expected.as_str()),
+                    (TextTest::NotEqual(expected), value) => value != Some(expected.as_str()),
+                    (TextTest::Match(reg), value) => value.is_some_and(|v| reg.is_match(v)),
+                    (TextTest::NotMatch(reg), value) => !value.is_some_and(|v| reg.is_match(v)),
+                }
+            }
+            Node::Size(compare, size) => compare.holds((meta.size as u64).cmp(size)),
+            Node::Age(compare, age) => {
+                let actual = (now - meta.last_modified).to_std().unwrap_or_default();
+                compare.holds(actual.cmp(age))
+            }
+            Node::Modified(compare, time) => compare.holds(meta.last_modified.cmp(time)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::path::Path as ObjectStorePath;
+
+    fn object(location: &str, size: usize) -> ObjectMeta {
+        ObjectMeta {
+            location: ObjectStorePath::from(location),
+            last_modified: Utc::now(),
+            size,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    fn matches(text: &str, meta: &ObjectMeta) -> bool {
+        Expression::parse(text, false).unwrap().is_match(meta)
+    }
+
+    /// The column the caret points at, under the expression quoted in the error
+    fn caret(text: &str) -> (String, usize) {
+        let error = Expression::parse(text, false).unwrap_err().to_string();
+        let lines: Vec<_> = error.lines().collect();
+        assert_eq!(lines[1], format!("  {text}"));
+        let pointer = lines[2].strip_prefix("  ").unwrap();
+        assert_eq!(pointer.trim_start(), "^", "{error}");
+        (lines[0].to_string(), pointer.len() - 1)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or_and_not_tighter_than_both() {
+        let ten = object("data/a.csv", 10);
+        // Read as `size > 5 || (size < 2 && size > 100)`
+        assert!(matches("size > 5 || size < 2 && size > 100", &ten));
+        assert!(!matches("(size > 5 || size < 2) && size > 100", &ten));
+        // Read as `(!(size < 5)) && size == 3`
+        assert!(!matches("!size < 5 && size == 3", &ten));
+        assert!(matches("!(size < 5 && size == 3)", &ten));
+        assert!(matches("((size == 10))", &ten));
+    }
+
+    #[test]
+    fn text_fields_compare_as_strings_or_regexes() {
+        let parquet = object("data/2024/part-1.parquet", 0);
+        assert!(matches(r#"basename =~ "\.parquet$""#, &parquet));
+        assert!(!matches(r#"path =~ "\.parquet\.bak$""#, &parquet));
+        assert!(matches(r#"path !~ "^logs/""#, &parquet));
+        assert!(matches(r#"basename == "part-1.parquet""#, &parquet));
+        assert!(!matches(r#"basename != "part-1.parquet""#, &parquet));
+        // Regexes search anywhere in the value, unless they're anchored
+        assert!(matches(r#"path =~ "2024""#, &parquet));
+        assert!(!matches(r#"path =~ "PARQUET""#, &parquet));
+        let ignoring_case = Expression::parse(r#"path =~ "PARQUET""#, true).unwrap();
+        assert!(ignoring_case.is_match(&parquet));
+        // Only quotes and backslashes are escaped, so regexes keep theirs
+        assert!(matches(r#"path =~ "\d{4}/""#, &parquet));
+        assert!(matches(r#"basename != "say \"hi\"""#, &parquet));
+        // An object without an etag only passes negative tests on it
+        assert!(!matches(r#"etag =~ ".*""#, &parquet));
+        assert!(matches(r#"etag !~ ".*""#, &parquet));
+    }
+
+    #[test]
+    fn sizes_and_durations_are_written_with_units() {
+        assert!(matches("size == 1.5KiB", &object("data/a", 1536)));
+        assert!(matches("size == 1.5k", &object("data/a", 1500)));
+        assert!(matches(
+            "size >= 1MB && size < 1MiB",
+            &object("data/a", 1_000_000)
+        ));
+        assert!(matches("size <= 4096", &object("data/a", 4096)));
+        assert!(!matches("size > 4096", &object("data/a", 4096)));
+
+        let old = ObjectMeta {
+            last_modified: Utc::now() - chrono::Duration::hours(3),
+            ..object("data/a", 0)
+        };
+        assert!(matches("age > 2h30m", &old));
+        assert!(matches("age < 1d && age >= 180s", &old));
+        assert!(!matches("age > 7d", &old));
+        assert!(matches(r#"mtime < "2999-01-01""#, &old));
+        assert!(matches(r#"last_modified > "2000-01-01T00:00:00Z""#, &old));
+    }
+
+    #[test]
+    fn errors_point_at_what_is_wrong() {
+        assert_eq!(
+            caret("size > 1 & size < 5"),
+            ("Unexpected '&'".to_string(), 9)
+        );
+        assert_eq!(
+            caret("(size > 1 || size < 0"),
+            ("Expected ) to close an earlier (".to_string(), 21)
+        );
+        let (message, column) = caret(r#"size > 1 && colour == "red""#);
+        assert!(message.starts_with("Unknown field \"colour\""), "{message}");
+        assert_eq!(column, 12);
+        // Columns count characters, not bytes
+        let (message, column) = caret(r#"basename == "é" && colour == "red""#);
+        assert!(message.starts_with("Unknown field \"colour\""), "{message}");
+        assert_eq!(column, 19);
+        assert_eq!(caret("size > 1x").1, 7);
+        assert_eq!(caret("size > 1 size").1, 9);
+        assert_eq!(caret(r#"path == "open"#).1, 8);
+        assert_eq!(caret("size =~ 1").1, 5);
+    }
+}
@@ -1,208 +1,4004 @@
-use std::io::Write;
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use chrono::{DateTime, Utc};
-use clap::Parser;
-use futures::TryStreamExt;
-use object_store::{ObjectMeta, ObjectStore};
-use tokio::io::AsyncBufReadExt;
+use clap::{Parser, ValueEnum};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectStorePath;
+use object_store::{Attribute, GetOptions, ObjectMeta, ObjectStoreScheme};
+use serde::Serialize;
 use url::Url;
 
+use crate::expr::Expression;
+use crate::glob;
+use crate::listing::{self, ListingPath, StdoutWriter};
+use crate::random::{self, Rng};
+use crate::sort::SortKey;
+use crate::store::{self, Root};
+use crate::units::{format_size, parse_duration, parse_size, parse_time};
 use crate::{Args, ObjectExport, Preamble};
 
 #[derive(Debug, Parser)]
 pub struct Find {
     /// The paths to recurse from, if not specified, individual object metadata will be read from stdin.
+    ///
+    /// `find` can list several roots at once, from any stores, when this is given more than once.
+    /// Each object then records which root it came from.
     #[arg(short, long)]
-    root: Option<String>,
-    /// Objects full paths must match this regex.
+    root: Vec<String>,
+    /// Objects full paths must match this regex, or every one of them if given more than once.
+    /// It can match anywhere in the path, so `data` matches `old/mydata.csv`, unless `--full-match`.
     ///
     /// Case sensitive by default, `(?i)foo` would match `FOO`, `Foo`, `foo`, etc.
     ///
     /// See https://docs.rs/regex/1.5.4/regex/#syntax for full regex syntax
     #[arg(short, long)]
-    path_match: Option<String>,
-    /// Object's basenames must match this regex. Same syntax as `path_match`.
+    path_match: Vec<String>,
+    /// Print what the groups of the first `--path-match` captured as extra fields with these
+    /// names, like `--captures dataset,date` for `dataset=([^/]+)/date=([^/]+)/`.
+    /// A name can also pick out a named group, like `(?P<date>...)`. Groups that didn't match are null.
+    #[arg(long, value_delimiter = ',', requires = "path_match")]
+    captures: Vec<String>,
+    /// Objects' full URLs, like `s3://bucket/prefix/key`, must match this regex, or every one of them
+    /// if given more than once, to tell apart objects from roots in different stores.
+    #[arg(long)]
+    url_match: Vec<String>,
+    /// Objects full paths must not match this regex.
+    /// Unlike `--exclude`, `--not` inverts this along with the other filters.
+    #[arg(long)]
+    path_not_match: Option<String>,
+    /// Object's basenames must match this regex, or every one of them if given more than once.
+    /// Same syntax as `path_match`, and likewise it can match anywhere in the basename.
+    #[arg(short, long)]
+    basename_match: Vec<String>,
+    /// Object's basenames must not match this regex
+    #[arg(long)]
+    basename_not_match: Option<String>,
+    /// Make `--path-match`, `--path-not-match`, `--basename-match` and `--basename-not-match`
+    /// match the whole path or basename, as if they were wrapped in `^(?:...)$`.
+    /// Patterns that are anchored already still work.
+    #[arg(long)]
+    full_match: bool,
+    /// Objects' keys relative to the root must match this glob, if given more than once, any of them.
+    ///
+    /// `*` and `?` stay within one segment, `[a-z]` and `{a,b}` match one of a set,
+    /// and a `**` segment matches any number of segments, even none:
+    /// `logs/**/*.gz` matches `logs/a.gz` and `logs/2024/01/a.gz`, and `**/*.gz` matches `a.gz`.
+    /// Objects have to match `--path-match` too when both are given.
+    #[arg(long)]
+    glob: Vec<String>,
+    /// One segment of objects' keys relative to the root must be exactly something, like
+    /// `1=dataset=clickstream`, or match a regex, like `2~^date=2024`, or every one if given more
+    /// than once. Segments count from 1, and from the end when negative, so `-1~\.parquet$`
+    /// tests the basename. Keys with too few segments don't match.
+    #[arg(long, value_parser = parse_segment, allow_hyphen_values = true)]
+    segment: Vec<SegmentPattern>,
+    /// Objects' basenames must end in this extension, in any case, or one of them if given more
+    /// than once or separated by commas, like `--ext parquet,csv`.
+    ///
+    /// Compound extensions work too, so `data.csv.gz` matches both `gz` and `csv.gz`.
+    #[arg(long, value_delimiter = ',')]
+    ext: Vec<String>,
+    /// Objects' etags must be exactly this, ignoring the quotes some stores wrap them in
+    #[arg(long)]
+    etag: Option<String>,
+    /// Objects' etags, without quotes, must match this regex. Same syntax as `path_match`.
+    ///
+    /// Objects without an etag never match `--etag` or `--etag-match`.
+    #[arg(long)]
+    etag_match: Option<String>,
+    /// Objects must be kept in this storage class, in any case, or one of them if given more
+    /// than once, like `--storage-class GLACIER --storage-class DEEP_ARCHIVE`.
+    ///
+    /// Objects in stores that don't report a class never match.
+    #[arg(long)]
+    storage_class: Vec<String>,
+    /// Objects must have a version, as from a versioned bucket.
+    /// With `--not`, only objects without one are shown, like `--no-version`.
+    #[arg(long, conflicts_with = "no_version")]
+    has_version: bool,
+    /// Objects must not have a version.
+    /// With `--not`, only objects with one are shown, like `--has-version`.
+    #[arg(long, visible_alias = "missing-version")]
+    no_version: bool,
+    /// Objects must not have an etag, as some stores and listings leave out, or report as empty.
+    /// With `--not`, only objects with one are shown.
+    #[arg(long, conflicts_with_all = ["etag", "etag_match"])]
+    missing_etag: bool,
+    /// Objects' versions must match this regex. Same syntax as `path_match`.
+    /// Objects without a version never match.
+    #[arg(long)]
+    version_match: Option<String>,
+    /// Only show objects that also appear in this listing, a file or an object in a store.
+    /// Only the listing is held in memory, so the objects being found still stream.
+    #[arg(long("in"), value_parser = ListingPath::parse)]
+    in_listing: Option<ListingPath>,
+    /// Leave out objects that appear in this listing, a file or an object in a store.
+    /// It can be used along with `--in`, with a different listing.
+    #[arg(long, value_parser = ListingPath::parse)]
+    not_in: Option<ListingPath>,
+    /// How to match up objects with `--in` and `--not-in` listings: by full URL, in the same
+    /// store, or by key below each listing's root
+    #[arg(long, value_enum, default_value_t = JoinOn::Absolute)]
+    join_on: JoinOn,
+    /// Also compare these, separated by commas, for objects to count as the same as in a listing
+    #[arg(long, value_enum, value_delimiter = ',')]
+    join_fields: Vec<JoinField>,
+    /// Leave out objects whose full paths match this regex, or any of them if given more than once.
+    ///
+    /// Excludes apply after every other filter, and `--not` doesn't invert them.
+    #[arg(long)]
+    exclude: Vec<String>,
+    /// Match every regex and glob regardless of case, as if they started with `(?i)`.
+    /// Inline flags still win, so `(?-i:Foo)` only matches `Foo`. `--ext` ignores case anyway.
     #[arg(short, long)]
-    basename_match: Option<String>,
-    /// Invert the meaning of both regexes: only show objects that don't match
+    ignore_case: bool,
+    /// Invert the result of all the filters together: only show objects that don't match.
+    ///
+    /// Negative filters like `--path-not-match` are negated first, so `--path-not-match a --not`
+    /// shows objects matching `a`, while `--exclude` and the directory marker flags aren't inverted.
+    /// To flip only one filter, use its opposite, like `--max-size` for `--min-size`.
     #[arg(long("not"))]
     invert: bool,
-    /// Objects should be at least this size in bytes
+    /// Show objects that pass any of the filters, rather than all of them.
+    /// `--not`, `--exclude` and the directory marker flags apply the same as ever.
     #[arg(long)]
-    min_size: Option<usize>,
-    /// Objects should be at most this size in bytes
+    any: bool,
+    /// Only show objects matching an expression, like `size > 10MiB && basename =~ "\.parquet$"`.
+    ///
+    /// Compare `path`, `basename`, `etag` or `version` to quoted strings with `==` or `!=`,
+    /// or to regexes with `=~` or `!~`. Compare `size` to sizes like `1.5GiB`, `age` to durations
+    /// like `7d`, and `last_modified` to quoted times like `"2024-06-01"`, with `==`, `!=`, `<`, `<=`, `>` or `>=`.
+    /// Combine them with `&&`, `||`, `!` and parentheses. The expression counts as one more filter.
+    #[arg(long("where"), value_name = "EXPRESSION")]
+    where_expr: Option<String>,
+    /// Objects should be at least this size, in bytes or like `100MB` or `1.5GiB`
+    #[arg(long, value_parser = parse_size)]
+    min_size: Option<u64>,
+    /// Objects should be at most this size, in bytes or like `100MB` or `1.5GiB`
+    #[arg(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+    /// Objects' sizes, like GNU find: `+1G` is over 1 GiB, `-10k` under 10 KiB, and `0` exactly 0 bytes.
+    ///
+    /// `c`, `k`, `M`, `G`, `T` and `P` are bytes and powers of 1024, and a bare number is bytes,
+    /// not 512-byte blocks. Give it more than once for a range, like `--size +1M --size -1G`,
+    /// which counts as one filter.
+    #[arg(long, value_parser = parse_size_bound, allow_hyphen_values = true)]
+    size: Vec<SizeBound>,
+    /// Leave out directory markers, the empty `_$folder$` objects Hadoop creates to stand in for
+    /// directories. Like `--exclude`, `--not` doesn't invert this.
+    #[arg(long, conflicts_with = "only_dir_markers")]
+    skip_dir_markers: bool,
+    /// Only show directory markers. Like `--exclude`, `--not` doesn't invert this.
     #[arg(long)]
-    max_size: Option<usize>,
-    /// Objects should have been modified after this time in RFC3339 format
+    only_dir_markers: bool,
+    /// Objects must be empty, like directory markers and failed uploads often are
+    #[arg(long, conflicts_with = "non_empty")]
+    empty: bool,
+    /// Objects must not be empty
     #[arg(long)]
+    non_empty: bool,
+    /// Objects should have been modified after this time, like `2024-06-01`, `2024-06-01 12:00`,
+    /// RFC3339 or RFC2822, or seconds or milliseconds since 1970. Times without an offset are UTC.
+    #[arg(long, value_parser = parse_time)]
     after_absolute: Option<DateTime<Utc>>,
-    /// Objects should have been modified before this time in RFC3339 format
-    #[arg(long)]
+    /// Objects should have been modified before this time, written any way `--after-absolute` is
+    #[arg(long, value_parser = parse_time)]
     before_absolute: Option<DateTime<Utc>>,
-    /// Objects should have been modified after this many seconds ago
+    /// Objects should have been modified less than this long ago, like `3d`, `2h30m`, `1w` or seconds
+    #[arg(long, value_parser = parse_duration)]
+    after: Option<Duration>,
+    /// Objects should have been modified more than this long ago, like `3d`, `2h30m`, `1w` or seconds
+    #[arg(long, value_parser = parse_duration)]
+    before: Option<Duration>,
+    /// Objects should have been modified at or after this other object, like a checkpoint.
+    /// It can be in any store.
+    #[arg(long)]
+    newer_than_object: Option<String>,
+    /// Objects should have been modified at or before this other object
+    #[arg(long)]
+    older_than_object: Option<String>,
+    /// If the object given to `--newer-than-object` or `--older-than-object` doesn't exist,
+    /// ignore that filter instead of failing
+    #[arg(long)]
+    missing_ok: bool,
+    /// Only list objects at most this many segments below the root, so `1` lists its direct children.
+    ///
+    /// The store skips deeper prefixes itself, so this stays fast however much is below them.
+    /// `0` only looks at the root itself, like `stat`.
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Objects must be at least this many segments below the root, so `2` leaves out its direct children
+    #[arg(long)]
+    min_depth: Option<usize>,
+    /// List this many prefixes at once, for much faster scans of wide roots, since one listing
+    /// only fetches a page at a time. The prefixes are found first by listing down to `--shard-depth`,
+    /// and objects come out in no particular order, or with `--ordered` in the order one listing
+    /// would give, reading ahead in the next prefixes. A root with fewer prefixes than two is listed as usual.
+    #[arg(long, conflicts_with = "max_depth")]
+    parallel_prefixes: Option<usize>,
+    /// With `--parallel-prefixes`, how many segments below the root the prefixes listed at once are
+    #[arg(long, default_value = "1", requires = "parallel_prefixes")]
+    shard_depth: usize,
+    /// Only list objects after this location, written like those printed, to resume a listing
+    /// or split one up along with `--limit`. On stdin, objects are skipped until it turns up,
+    /// since they might not be sorted.
+    #[arg(long, value_parser = parse_location)]
+    start_after: Option<ObjectStorePath>,
+    /// Stop after this many matches, without listing the rest of the root
+    #[arg(long)]
+    limit: Option<usize>,
+    /// Keep at most this many matches under each prefix, like `2` for a couple of objects from
+    /// every partition. With `--sample-rate` or `--shuffle`, that makes a sample across partitions.
+    /// One count is kept per prefix, so memory grows with the number of prefixes.
     #[arg(long)]
-    after: Option<i64>,
-    /// Objects should have been modified before this many seconds ago
+    per_prefix_limit: Option<usize>,
+    /// With `--per-prefix-limit`, count objects under the prefix this many segments below the root,
+    /// instead of under their own directory. Shallower objects count under their own directory.
+    #[arg(long, requires = "per_prefix_limit")]
+    prefix_depth: Option<usize>,
+    /// Keep each object that passes the other filters with this probability, like `0.01` for
+    /// about one in a hundred. Unlike `sample`, nothing is held in memory.
+    #[arg(long, value_parser = parse_sample_rate)]
+    sample_rate: Option<f64>,
+    /// Seed random choices like `--sample-rate` and `--shuffle`, so the same seed picks the same objects from
+    /// the same listing. Without it, the seed is different every time, and `--verbose` shows it.
     #[arg(long)]
-    before: Option<i64>,
+    seed: Option<u64>,
+    /// Leave out objects already seen, as when several listings on stdin overlap.
+    /// Everything seen is remembered, so memory grows with the number of distinct objects.
+    #[arg(long)]
+    unique: bool,
+    /// With `--unique`, what makes two objects the same.
+    /// A single root lists each location once anyway, so then `location` is free.
+    #[arg(long, value_enum, default_value_t = UniqueBy::Location, requires = "unique")]
+    unique_by: UniqueBy,
+    /// The paths of the listing roots, which globs are matched relative to, known once opened
+    #[arg(skip)]
+    root_paths: OnceLock<Vec<ObjectStorePath>>,
+    /// The URLs of the listing roots, which `--url-match` joins locations to, known once opened
+    #[arg(skip)]
+    root_urls: OnceLock<Vec<Url>>,
+    /// The seed given, or the one chosen the first time it was needed
+    #[arg(skip)]
+    chosen_seed: OnceLock<u64>,
 }
 
-/// A queue that writes lines to stdout.
-///
-/// In case the motivation is not clear, there are a few reasons for this:
-/// * We don't want to panic when the pipe is closed
-/// * We want to make sure only whole lines are written
-/// * We want to include a BufRead for performance
-///
-/// * But synchronous blocking on the main thread is not a super big deal as long as
-///   it isn't on every single line
-struct StdoutWriter {
-    tx: tokio::sync::mpsc::Sender<ObjectExport>,
-}
-
-impl StdoutWriter {
-    fn start(preamble: &Preamble) -> Result<Self> {
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<ObjectExport>(100);
-        let mut buffer = std::io::BufWriter::new(std::io::stdout());
-        buffer.write_all(serde_json::to_string(&preamble).unwrap().as_bytes())?;
-        buffer.write_all(b"\n")?;
-        tokio::spawn(async move {
-            while let Some(obj) = rx.recv().await {
-                let mut line = serde_json::to_string(&obj).unwrap();
-                // This is blocking IO and it could cause a momentary pause in the stream
-                // For our use case that is probably okay
-                line.push('\n');
-                if buffer.write_all(line.as_bytes()).is_err() {
-                    // Writing to stdout failed, so we should stop, but we don't need to error
-                    // because probably it's just a broken pipe
-                    break;
+/// One filter from the command line, which an object passes or fails on its own
+enum Test {
+    Path(regex::Regex),
+    /// Matched against the object's full URL, in the store of the root it came from
+    Url(regex::Regex),
+    Basename(regex::Regex),
+    /// Globs matched against the key below the root, passing if any of them match
+    Globs(Vec<regex::Regex>),
+    /// One segment of the key below the root, counting from 1, or back from -1 for the last
+    Segment(isize, SegmentTest),
+    Etag(String),
+    EtagMatch(regex::Regex),
+    NoEtag,
+    /// Kept in one of these storage classes, passing if the store reports any of them
+    StorageClass(Vec<String>),
+    HasVersion,
+    NoVersion,
+    VersionMatch(regex::Regex),
+    MinDepth(usize),
+    /// Extensions in lowercase with a leading dot, like `.csv.gz`, passing if any of them match
+    Extensions(Vec<String>),
+    MinSize(u64),
+    MaxSize(u64),
+    /// At least the first size and at most the second, from `--size`
+    SizeRange(u64, u64),
+    Empty,
+    NonEmpty,
+    /// Modified at or after this time, from `--after-absolute` or `--after`
+    After(DateTime<Utc>),
+    /// Modified at or before this time, from `--before-absolute` or `--before`
+    Before(DateTime<Utc>),
+    /// Appears in a reference listing
+    Listed(HashSet<JoinKey>),
+    Where(Expression),
+}
+
+/// How objects in reference listings are matched up with the objects being found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum JoinOn {
+    /// By their full URL, so they have to be in the same store too
+    Absolute,
+    /// By their key below their own listing's root, so listings of different roots can be compared
+    Relative,
+}
+
+/// What else has to match, besides the location, for objects to count as the same
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum JoinField {
+    Etag,
+    Size,
+}
+
+/// How a `--manifest` is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ManifestFormat {
+    Sha256sum,
+    Ndjson,
+}
+
+/// How a match compares to the `--manifest`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum ManifestStatus {
+    Ok,
+    /// The checksum or size differs
+    Mismatch,
+    MissingFromManifest,
+}
+
+/// A field `--emit-fields` can add to each match
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum EmittedField {
+    /// Whole seconds since the object was modified, negative if that's in the future
+    AgeSeconds,
+    Basename,
+    /// What's after the last dot in the basename, or null if there isn't one
+    Extension,
+    /// Segments below the root, like `--max-depth` counts
+    Depth,
+    /// The full path of the directory the object is in, without a trailing slash
+    Parent,
+}
+
+impl EmittedField {
+    fn name(self) -> &'static str {
+        match self {
+            EmittedField::AgeSeconds => "age_seconds",
+            EmittedField::Basename => "basename",
+            EmittedField::Extension => "extension",
+            EmittedField::Depth => "depth",
+            EmittedField::Parent => "parent",
+        }
+    }
+}
+
+/// What a `--manifest` recorded about one key
+struct Recorded {
+    /// The algorithm and the hex digest, like `sha256:e3b0c442...`
+    checksum: Option<String>,
+    size: Option<usize>,
+}
+
+/// A `--manifest` to compare matches with
+struct Manifest {
+    /// What it recorded, by key below its root
+    recorded: HashMap<String, Recorded>,
+    /// How many matches were in it with nothing there to compare them on
+    unchecked: AtomicUsize,
+}
+
+impl Manifest {
+    /// Compare a match with what was recorded for its key, recording its `manifest_status` too
+    fn check(&self, find: &Find, found: &mut Found) -> ManifestStatus {
+        fn algorithm(checksum: &str) -> Option<&str> {
+            checksum.split_once(':').map(|(algorithm, _)| algorithm)
+        }
+        let status = match self.recorded.get(&find.key(&found.meta.location)) {
+            None => ManifestStatus::MissingFromManifest,
+            Some(recorded) => {
+                let checksum = found.extra.get("checksum").and_then(|c| c.as_str());
+                match (checksum, &recorded.checksum, recorded.size) {
+                    (Some(ours), Some(theirs), _) if algorithm(ours) == algorithm(theirs) => {
+                        match ours.eq_ignore_ascii_case(theirs) {
+                            true => ManifestStatus::Ok,
+                            false => ManifestStatus::Mismatch,
+                        }
+                    }
+                    (_, _, Some(size)) if size != found.meta.size => ManifestStatus::Mismatch,
+                    (_, _, Some(_)) => ManifestStatus::Ok,
+                    _ => {
+                        self.unchecked.fetch_add(1, Ordering::Relaxed);
+                        ManifestStatus::Ok
+                    }
+                }
+            }
+        };
+        found
+            .extra
+            .insert("manifest_status".to_string(), serde_json::json!(status));
+        status
+    }
+}
+
+/// What only `find` itself checks matches against, after the [`Filter`]
+struct Checks {
+    content_type: Option<regex::Regex>,
+    manifest: Option<Manifest>,
+}
+
+/// A `--segment` as given, with its pattern not yet compiled
+#[derive(Debug, Clone)]
+pub struct SegmentPattern {
+    index: isize,
+    /// Whether the pattern is a regex, after `~`, rather than exact, after `=`
+    regex: bool,
+    pattern: String,
+}
+
+/// How a `--segment` is tested
+enum SegmentTest {
+    Exactly(String),
+    Match(regex::Regex),
+}
+
+/// One `--size`, where sizes over or under it are exclusive, as in GNU find
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeBound {
+    Over(u64),
+    Under(u64),
+    Exactly(u64),
+}
+
+/// What identifies an object for `--unique`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum UniqueBy {
+    Location,
+    /// Objects with the same contents, wherever they are. Objects without etags are never repeats.
+    Etag,
+    #[value(name = "location+etag")]
+    LocationEtag,
+}
+
+/// Whatever identifies an object when joining it with a reference listing
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct JoinKey {
+    key: String,
+    e_tag: Option<String>,
+    size: Option<usize>,
+}
+
+impl Test {
+    /// Whether an object passes, given which of several roots it came from, if it's known
+    fn passes(&self, find: &Find, meta: &ObjectMeta, root: Option<usize>) -> bool {
+        match self {
+            Test::Path(reg) => reg.is_match(meta.location.as_ref()),
+            Test::Url(reg) => find
+                .root_url(root)
+                .and_then(|url| store::object_url(url, &meta.location).ok())
+                .is_some_and(|url| reg.is_match(url.as_str())),
+            Test::Basename(reg) => reg.is_match(meta.location.filename().unwrap_or_default()),
+            Test::Globs(globs) => {
+                let key = find.key(&meta.location);
+                globs.iter().any(|glob| glob.is_match(&key))
+            }
+            Test::Segment(index, test) => {
+                let key = find.key(&meta.location);
+                let segments: Vec<_> = key.split('/').collect();
+                let segment = match *index {
+                    index if index > 0 => segments.get(index as usize - 1),
+                    index => segments
+                        .len()
+                        .checked_sub(index.unsigned_abs())
+                        .and_then(|at| segments.get(at)),
+                };
+                match (test, segment) {
+                    (SegmentTest::Exactly(expected), Some(segment)) => segment == expected,
+                    (SegmentTest::Match(reg), Some(segment)) => reg.is_match(segment),
+                    (_, None) => false,
+                }
+            }
+            Test::Etag(expected) => store::etag(meta) == Some(expected.as_str()),
+            Test::EtagMatch(reg) => store::etag(meta).is_some_and(|e_tag| reg.is_match(e_tag)),
+            Test::NoEtag => store::etag(meta).is_none(),
+            Test::StorageClass(classes) => store::storage_class(meta)
+                .is_some_and(|class| classes.iter().any(|c| c.eq_ignore_ascii_case(class))),
+            Test::HasVersion => meta.version.is_some(),
+            Test::NoVersion => meta.version.is_none(),
+            Test::VersionMatch(reg) => meta.version.as_deref().is_some_and(|v| reg.is_match(v)),
+            Test::MinDepth(min_depth) => find.depth(&meta.location) >= *min_depth,
+            Test::Extensions(extensions) => {
+                let basename = meta.location.filename().unwrap_or_default().to_lowercase();
+                extensions.iter().any(|ext| basename.ends_with(ext))
+            }
+            Test::MinSize(min_size) => meta.size as u64 >= *min_size,
+            Test::MaxSize(max_size) => meta.size as u64 <= *max_size,
+            Test::SizeRange(min, max) => (*min..=*max).contains(&(meta.size as u64)),
+            Test::Empty => meta.size == 0,
+            Test::NonEmpty => meta.size > 0,
+            Test::After(after) => meta.last_modified >= *after,
+            Test::Before(before) => meta.last_modified <= *before,
+            Test::Listed(keys) => {
+                let key = match find.join_on {
+                    JoinOn::Absolute => absolute_key(find.root_url(root), &meta.location),
+                    JoinOn::Relative => find.key(&meta.location),
+                };
+                keys.contains(&find.join_key(key, meta))
+            }
+            Test::Where(expression) => expression.is_match(meta),
+        }
+    }
+}
+
+/// The filters of a [`Find`], compiled and ready to test objects against
+pub struct Filter<'a> {
+    find: &'a Find,
+    /// Every filter given, and whether objects have to fail it instead of pass
+    tests: Vec<(Test, bool)>,
+    excludes: Vec<regex::Regex>,
+    /// How many objects have matched so far, for `--limit`
+    matched: AtomicUsize,
+    /// Chooses which objects to keep with `--sample-rate`
+    sampler: Mutex<Rng>,
+    /// The first `--path-match`, and the group for each of the `--captures`
+    captures: Option<(regex::Regex, Vec<usize>)>,
+    /// What's been seen with `--unique`, unless objects can't repeat anyway
+    seen: Option<Mutex<HashSet<String>>>,
+    /// How many objects `--unique` left out
+    repeated: AtomicUsize,
+    /// How many objects have been kept under each prefix, with `--per-prefix-limit`
+    per_prefix: Option<Mutex<HashMap<String, usize>>>,
+    /// How many objects `--per-prefix-limit` left out
+    over_quota: AtomicUsize,
+    /// When the filters were compiled, which every age is measured from
+    now: DateTime<Utc>,
+}
+
+impl Filter<'_> {
+    /// Whether an object passes every filter (or none of them, with `--not`).
+    ///
+    /// With `--limit`, only that many objects match, however many are tested at once,
+    /// so each object should only be tested once.
+    pub fn is_match(&self, meta: &ObjectMeta) -> bool {
+        self.passes(meta) && self.admit(meta)
+    }
+
+    /// Whether an object that passed the filters is kept by `--sample-rate`, `--unique`,
+    /// `--per-prefix-limit` and `--limit`
+    fn admit(&self, meta: &ObjectMeta) -> bool {
+        if let Some(rate) = self.find.sample_rate {
+            let mut sampler = self.sampler.lock().unwrap_or_else(|e| e.into_inner());
+            if sampler.next_f64() >= rate {
+                return false;
+            }
+        }
+        if let Some(seen) = &self.seen {
+            let identity = match self.find.unique_by {
+                UniqueBy::Location => Some(meta.location.to_string()),
+                UniqueBy::Etag => store::etag(meta).map(str::to_string),
+                UniqueBy::LocationEtag => {
+                    store::etag(meta).map(|e_tag| format!("{}\0{e_tag}", meta.location))
                 }
+            };
+            let mut seen = seen.lock().unwrap_or_else(|e| e.into_inner());
+            if identity.is_some_and(|identity| !seen.insert(identity)) {
+                self.repeated.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+        }
+        if let (Some(per_prefix), Some(quota)) = (&self.per_prefix, self.find.per_prefix_limit) {
+            let prefix = self.find.prefix(&meta.location);
+            let mut per_prefix = per_prefix.lock().unwrap_or_else(|e| e.into_inner());
+            let kept = per_prefix.entry(prefix).or_default();
+            if *kept >= quota {
+                self.over_quota.fetch_add(1, Ordering::Relaxed);
+                return false;
             }
+            *kept += 1;
+        }
+        match self.find.limit {
+            Some(limit) => self.matched.fetch_add(1, Ordering::Relaxed) < limit,
+            None => true,
+        }
+    }
+
+    /// What the `--captures` groups captured from an object's path, or null where they didn't
+    pub fn captures(
+        &self,
+        location: &ObjectStorePath,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        let Some((reg, groups)) = &self.captures else {
+            return Default::default();
+        };
+        let captured = reg.captures(location.as_ref());
+        self.find
+            .captures
+            .iter()
+            .zip(groups)
+            .map(|(name, &group)| {
+                let value = captured
+                    .as_ref()
+                    .and_then(|captured| captured.get(group))
+                    .map_or(serde_json::Value::Null, |m| m.as_str().into());
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// These `--emit-fields` for an object
+    fn emitted(
+        &self,
+        fields: &[EmittedField],
+        meta: &ObjectMeta,
+    ) -> serde_json::Map<String, serde_json::Value> {
+        let basename = meta.location.filename().unwrap_or_default();
+        fields
+            .iter()
+            .map(|&field| {
+                let value = match field {
+                    EmittedField::AgeSeconds => {
+                        (self.now - meta.last_modified).num_seconds().into()
+                    }
+                    EmittedField::Basename => basename.into(),
+                    EmittedField::Extension => match basename.rsplit_once('.') {
+                        // A leading dot, like `.env`, is part of the name
+                        Some((stem, ext)) if !stem.is_empty() => ext.into(),
+                        _ => serde_json::Value::Null,
+                    },
+                    EmittedField::Depth => self.find.depth(&meta.location).into(),
+                    EmittedField::Parent => meta
+                        .location
+                        .as_ref()
+                        .rsplit_once('/')
+                        .map_or("", |(parent, _)| parent)
+                        .into(),
+                };
+                (field.name().to_string(), value)
+            })
+            .collect()
+    }
+
+    /// How many objects `--unique` left out as repeats
+    pub fn repeated(&self) -> usize {
+        self.repeated.load(Ordering::Relaxed)
+    }
+
+    /// How many objects `--per-prefix-limit` left out, and from how many prefixes
+    pub fn over_quota(&self) -> (usize, usize) {
+        let prefixes = self.per_prefix.as_ref().map_or(0, |per_prefix| {
+            let per_prefix = per_prefix.lock().unwrap_or_else(|e| e.into_inner());
+            per_prefix.len()
         });
-        Ok(Self { tx })
+        (self.over_quota.load(Ordering::Relaxed), prefixes)
+    }
+
+    /// Whether `--limit` objects have matched already, so there's no need to list any more
+    pub fn is_exhausted(&self) -> bool {
+        self.find
+            .limit
+            .is_some_and(|limit| self.matched.load(Ordering::Relaxed) >= limit)
+    }
+
+    fn passes(&self, meta: &ObjectMeta) -> bool {
+        self.passes_from(meta, None)
     }
 
-    /// Write a line to stdout
-    async fn write(&self, meta: ObjectExport) -> Result<()> {
-        Ok(self.tx.send(meta).await?)
+    /// Like [`Filter::passes`], for an object from one of several roots
+    fn passes_from(&self, meta: &ObjectMeta, root: Option<usize>) -> bool {
+        let find = self.find;
+        let mut results = self
+            .tests
+            .iter()
+            .map(|(test, negated)| test.passes(find, meta, root) != *negated);
+        // With no filters at all, everything matches either way
+        let valid = match find.any && !self.tests.is_empty() {
+            true => results.any(|passed| passed),
+            false => results.all(|passed| passed),
+        };
+        let excluded = self
+            .excludes
+            .iter()
+            .any(|reg| reg.is_match(meta.location.as_ref()));
+        let marker_allowed = match store::is_dir_marker(meta) {
+            true => !find.skip_dir_markers,
+            false => !find.only_dir_markers,
+        };
+        (valid != find.invert) && !excluded && marker_allowed
     }
 }
 
 impl Find {
-    fn base_url(&self) -> Result<Option<Url>> {
-        let root = match &self.root {
-            Some(r) => r,
-            None => return Ok(None),
-        };
-        // If it parses, it's a URL
-        if let Ok(u) = Url::parse(root) {
-            return Ok(Some(u));
-        }
-        // If it doesn't parse, try interpreting it as a local path
-        let path = std::path::PathBuf::from(root).canonicalize()?;
-        Ok(Some(
-            Url::from_file_path(path).map_err(|_| anyhow::anyhow!("Invalid path"))?,
-        ))
+    /// The root given on the command line, if any
+    pub fn base_url(&self) -> Result<Option<Url>> {
+        match self.root.as_slice() {
+            [] => Ok(None),
+            [root] => Ok(Some(listing::parse_root(root)?)),
+            _ => bail!("Only find can list several roots at once"),
+        }
     }
 
-    fn preamble(&self) -> Result<Preamble> {
-        match self.base_url()? {
-            Some(url) => Ok(Preamble::Obvious3_0 { root: url }),
-            None => {
-                // Try to read the preamble from stdin
-                let mut buf = String::new();
-                std::io::stdin().read_line(&mut buf)?;
-                let preamble: Preamble = serde_json::from_str(&buf)
-                    .context("Reading first JSON line as a Preamble. (Remember to include one)")?;
-                Ok(preamble)
+    /// Open every root given, skipping any that are the same as or inside another
+    fn distinct_roots(&self) -> Result<Vec<Root>> {
+        let mut roots: Vec<Root> = vec![];
+        for text in &self.root {
+            let url = listing::parse_root(text)?;
+            let root = match roots.first() {
+                Some(first) => first.open_sibling(&url)?,
+                None => Root::open(&url)?,
+            };
+            if let Some(outer) = roots.iter().find(|outer| outer.contains(&root)) {
+                eprintln!(
+                    "Skipping {}, since it's already listed in {}",
+                    root.url, outer.url
+                );
+                continue;
             }
+            roots.retain(|inner| {
+                let nested = root.contains(inner);
+                if nested {
+                    eprintln!(
+                        "Skipping {}, since it's already listed in {}",
+                        inner.url, root.url
+                    );
+                }
+                !nested
+            });
+            roots.push(root);
         }
+        Ok(roots)
     }
 
-    /// Produce an asynchronous stream of ObjectMeta objects read as NDJSON from stdin
-    fn read_stdin() -> impl futures::Stream<Item = Result<ObjectMeta>> {
-        let stdin = tokio::io::stdin();
-        let reader = tokio::io::BufReader::new(stdin).lines();
-        let stream = tokio_stream::wrappers::LinesStream::new(reader);
-        stream
-            .map_err(anyhow::Error::from)
-            .and_then(
-                |line| async move { anyhow::Ok(serde_json::from_str::<ObjectExport>(&line)?) },
-            )
-            .map_ok(ObjectMeta::from)
+    /// Read the preamble, and open the store if objects are to be listed live.
+    ///
+    /// When there is no root, the preamble is read from stdin and the store is left unopened,
+    /// since filtering a saved listing doesn't need it.
+    pub fn open(&self) -> Result<(Preamble, Option<Root>)> {
+        let (mut preamble, root) = match self.base_url()? {
+            Some(url) => (Preamble::new(url.clone()), Some(Root::open(&url)?)),
+            // Try to read the preamble from stdin
+            None => (listing::read_preamble()?, None),
+        };
+        let _ = self.root_paths.set(root_paths(&preamble)?);
+        let _ = self.root_urls.set(preamble.roots().to_vec());
+        if let Some(regex) = self.reusable_path_match() {
+            preamble.set_path_match(&regex);
+        }
+        Ok((preamble, root))
     }
 
-    pub async fn run(&self, global_args: &Args) -> Result<()> {
-        // These ref's mean that `async move` later doesn't take ownership of the fields
-        let path_regex = &self
-            .path_match
-            .as_ref()
-            .map(|s| regex::Regex::new(s))
-            .transpose()?;
-        let base_regex = &self
-            .basename_match
-            .as_ref()
-            .map(|s| regex::Regex::new(s))
-            .transpose()?;
+    /// An object's key below the root, or its whole location if it isn't under the root
+    fn key(&self, location: &ObjectStorePath) -> String {
+        key_below(self.root_paths.get().map_or(&[], Vec::as_slice), location)
+    }
+
+    /// What identifies an object when comparing it to `--in` and `--not-in` listings
+    fn join_key(&self, key: String, meta: &ObjectMeta) -> JoinKey {
+        JoinKey {
+            key,
+            e_tag: self
+                .join_fields
+                .contains(&JoinField::Etag)
+                .then(|| store::etag(meta).unwrap_or_default().to_string()),
+            size: self
+                .join_fields
+                .contains(&JoinField::Size)
+                .then_some(meta.size),
+        }
+    }
+
+    /// Load every object in a reference listing, keyed the same way as the objects they're compared to
+    async fn load_listing(&self, path: &ListingPath) -> Result<HashSet<JoinKey>> {
+        let (preamble, objects) = listing::read_file(path).await?;
+        let roots = root_paths(&preamble)?;
+        // Objects in a listing don't say which root they're from, only where they are within it
+        let store = preamble.roots().first();
+        if self.join_on == JoinOn::Absolute {
+            let stores = preamble
+                .roots()
+                .iter()
+                .map(store::store_url)
+                .collect::<Result<HashSet<_>>>()?;
+            ensure!(
+                stores.len() <= 1,
+                "{path} lists objects in several stores, so they can't be matched up by location. \
+                 Use --join-on relative to match them by key instead."
+            );
+        }
+        let mut objects = std::pin::pin!(objects);
+        let mut keys = HashSet::new();
+        while let Some(meta) = objects.try_next().await? {
+            let key = match self.join_on {
+                JoinOn::Absolute => absolute_key(store, &meta.location),
+                JoinOn::Relative => key_below(&roots, &meta.location),
+            };
+            keys.insert(self.join_key(key, &meta));
+        }
+        Ok(keys)
+    }
 
-        let preamble = self.preamble()?;
-        let writer = &StdoutWriter::start(&preamble)?;
+    /// The prefix `--per-prefix-limit` counts an object under, as a key below the root
+    fn prefix(&self, location: &ObjectStorePath) -> String {
+        let key = self.key(location);
+        let mut parts: Vec<_> = key.split('/').collect();
+        parts.pop();
+        if let Some(depth) = self.prefix_depth {
+            parts.truncate(depth);
+        }
+        parts.join("/")
+    }
+
+    /// Whether an object from stdin is within `--max-depth`, since only listings can skip deeper ones
+    fn within_depth(&self, meta: &ObjectMeta) -> bool {
+        self.max_depth
+            .is_none_or(|max_depth| self.depth(&meta.location) <= max_depth)
+    }
+
+    /// How many segments below the root an object is, where the root itself is 0
+    fn depth(&self, location: &ObjectStorePath) -> usize {
+        match self.key(location).as_str() {
+            "" => 0,
+            key => key.split('/').count(),
+        }
+    }
+
+    /// Stream every object under the root, or from stdin if objects are not being listed
+    pub fn objects<'a>(&'a self, root: Option<&'a Root>) -> BoxStream<'a, Result<ObjectMeta>> {
+        self.objects_in_order(root, false)
+    }
 
-        let print_matches = |meta: ObjectMeta| async {
-            let mut valid = true;
-            // Try to match the path
-            if let Some(reg) = &path_regex {
-                valid &= reg.is_match(meta.location.as_ref());
+    /// Stream every object like [`Find::objects`], where `ordered` keeps sharded listings in
+    /// key order
+    fn objects_in_order<'a>(
+        &'a self,
+        root: Option<&'a Root>,
+        ordered: bool,
+    ) -> BoxStream<'a, Result<ObjectMeta>> {
+        let objects = match (root, self.max_depth, self.parallel_prefixes) {
+            (Some(root), None, None) => {
+                let objects = match &self.start_after {
+                    Some(offset) => root.store.list_with_offset(Some(&root.path), offset),
+                    None => root.store.list(Some(&root.path)),
+                };
+                return objects.map_err(anyhow::Error::from).boxed();
+            }
+            (Some(root), Some(max_depth), _) => list_to_depth(root, max_depth),
+            (Some(root), None, Some(streams)) => {
+                list_sharded(root, self.shard_depth, streams, ordered)
             }
-            // Try to match the basename
-            if let Some(reg) = &base_regex {
-                valid &= reg.is_match(meta.location.filename().unwrap_or_default());
+            (None, _, _) => {
+                let objects = listing::read_stdin()
+                    .try_filter(move |meta| futures::future::ready(self.within_depth(meta)));
+                return self.after_start(objects, |meta| &meta.location);
             }
+        };
+        // Listings by directory can't start from an offset, so they skip what comes before it
+        match &self.start_after {
+            Some(offset) => objects
+                .try_filter(move |meta| futures::future::ready(&meta.location > offset))
+                .boxed(),
+            None => objects,
+        }
+    }
+
+    /// Leave out objects from stdin up to the one at `--start-after`, which has to turn up
+    /// for any to be kept, since a listing on stdin might not be sorted
+    fn after_start<'a, T: Send + 'a>(
+        &'a self,
+        objects: impl futures::Stream<Item = Result<T>> + Send + 'a,
+        location: fn(&T) -> &ObjectStorePath,
+    ) -> BoxStream<'a, Result<T>> {
+        let Some(start) = &self.start_after else {
+            return objects.boxed();
+        };
+        let mut started = false;
+        objects
+            .try_filter(move |item| {
+                let keep = started;
+                started |= location(item) == start;
+                futures::future::ready(keep)
+            })
+            .boxed()
+    }
 
-            // Try to match the size
-            if let Some(min_size) = self.min_size {
-                valid &= meta.size >= min_size;
+    /// When a reference object was last modified, or `None` if it's missing and that's allowed
+    async fn modified_time_of(&self, url: &str) -> Result<Option<DateTime<Utc>>> {
+        let reference = Root::open(&listing::parse_root(url)?)?;
+        match reference.store.head(&reference.path).await {
+            Ok(meta) => Ok(Some(meta.last_modified)),
+            Err(object_store::Error::NotFound { .. }) if self.missing_ok => Ok(None),
+            Err(object_store::Error::NotFound { .. }) => bail!(
+                "The reference object {} doesn't exist. Pass --missing-ok to match everything instead.",
+                reference.url
+            ),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Compile the filters
+    pub async fn filter(&self) -> Result<Filter<'_>> {
+        ensure!(
+            self.parallel_prefixes != Some(0),
+            "--parallel-prefixes must be at least 1"
+        );
+        ensure!(self.shard_depth > 0, "--shard-depth must be at least 1");
+        // Every `--size` together is one inclusive range, which can contradict itself
+        let size_range = self
+            .size
+            .iter()
+            .try_fold((0, u64::MAX), |(min, max), bound| {
+                Ok::<_, anyhow::Error>(match *bound {
+                    SizeBound::Over(size) => (min.max(size.saturating_add(1)), max),
+                    SizeBound::Under(0) => {
+                        bail!("--size -0 can't match anything, nothing is under 0 bytes")
+                    }
+                    SizeBound::Under(size) => (min, max.min(size - 1)),
+                    SizeBound::Exactly(size) => (min.max(size), max.min(size)),
+                })
+            })?;
+        if size_range.0 > size_range.1 {
+            bail!("The --size bounds contradict each other, since no size is in all of them");
+        }
+        // Contradictions only keep everything from matching when every filter has to pass
+        if !self.any {
+            if !self.size.is_empty() {
+                if self.min_size.is_some_and(|min| min > size_range.1) {
+                    bail!(
+                        "--min-size contradicts --size, which only allows up to {} bytes",
+                        size_range.1
+                    );
+                }
+                if self.max_size.is_some_and(|max| max < size_range.0) {
+                    bail!(
+                        "--max-size contradicts --size, which needs at least {} bytes",
+                        size_range.0
+                    );
+                }
             }
-            if let Some(max_size) = self.max_size {
-                valid &= meta.size <= max_size;
+            if self.empty && self.min_size.is_some_and(|min| min > 0) {
+                bail!("--empty contradicts --min-size, since empty objects are 0 bytes");
             }
-
-            // Try to match the absolute last modified time
-            if let Some(after_absolute) = self.after_absolute {
-                valid &= meta.last_modified >= after_absolute;
+            if let (Some(min), Some(max)) = (self.min_depth, self.max_depth) {
+                if min > max {
+                    bail!("--min-depth {min} is deeper than --max-depth {max}, so nothing could match");
+                }
             }
-            if let Some(before_absolute) = self.before_absolute {
-                valid &= meta.last_modified <= before_absolute;
+            if self.non_empty && self.max_size == Some(0) {
+                bail!("--non-empty contradicts --max-size 0, which only empty objects pass");
             }
+        }
+        let now = Utc::now();
+        let ago = |age: Duration| -> Result<_> { Ok(now - chrono::Duration::from_std(age)?) };
+        let regex = |s: &String| {
+            regex::RegexBuilder::new(s)
+                .case_insensitive(self.ignore_case)
+                .build()
+        };
 
-            // Try to match the relative last modified time
-            if let Some(after) = self.after {
-                valid &= meta.last_modified >= (Utc::now() - chrono::Duration::seconds(after));
-            }
-            if let Some(before) = self.before {
-                valid &= meta.last_modified <= Utc::now() - chrono::Duration::seconds(before);
+        let anchored = |s: &String| regex(&self.anchor(s));
+
+        let mut tests = vec![];
+        for s in &self.path_match {
+            let reg = anchored(s).with_context(|| format!("Invalid --path-match {s:?}"))?;
+            tests.push((Test::Path(reg), false));
+        }
+        if let Some(s) = &self.path_not_match {
+            tests.push((Test::Path(anchored(s)?), true));
+        }
+        for s in &self.url_match {
+            let reg = regex(s).with_context(|| format!("Invalid --url-match {s:?}"))?;
+            tests.push((Test::Url(reg), false));
+        }
+        for s in &self.basename_match {
+            let reg = anchored(s).with_context(|| format!("Invalid --basename-match {s:?}"))?;
+            tests.push((Test::Basename(reg), false));
+        }
+        if let Some(s) = &self.basename_not_match {
+            tests.push((Test::Basename(anchored(s)?), true));
+        }
+        if !self.glob.is_empty() {
+            let globs = self
+                .glob
+                .iter()
+                .map(|s| glob::compile(s, self.ignore_case))
+                .collect::<Result<_>>()?;
+            tests.push((Test::Globs(globs), false));
+        }
+        for segment in &self.segment {
+            let test = match segment.regex {
+                true => SegmentTest::Match(
+                    regex(&segment.pattern)
+                        .with_context(|| format!("Invalid --segment {:?}", segment.pattern))?,
+                ),
+                false => SegmentTest::Exactly(segment.pattern.clone()),
+            };
+            tests.push((Test::Segment(segment.index, test), false));
+        }
+        if let Some(e_tag) = &self.etag {
+            tests.push((Test::Etag(e_tag.trim_matches('"').to_string()), false));
+        }
+        if let Some(s) = &self.etag_match {
+            tests.push((Test::EtagMatch(regex(s)?), false));
+        }
+        if self.missing_etag {
+            tests.push((Test::NoEtag, false));
+        }
+        if !self.storage_class.is_empty() {
+            tests.push((Test::StorageClass(self.storage_class.clone()), false));
+        }
+        if self.has_version {
+            tests.push((Test::HasVersion, false));
+        }
+        if self.no_version {
+            tests.push((Test::NoVersion, false));
+        }
+        if let Some(s) = &self.version_match {
+            tests.push((Test::VersionMatch(regex(s)?), false));
+        }
+        if let Some(min_depth) = self.min_depth {
+            tests.push((Test::MinDepth(min_depth), false));
+        }
+        if !self.ext.is_empty() {
+            let extensions = self
+                .ext
+                .iter()
+                .map(|ext| format!(".{}", ext.trim_start_matches('.').to_lowercase()))
+                .collect();
+            tests.push((Test::Extensions(extensions), false));
+        }
+        if let Some(min_size) = self.min_size {
+            tests.push((Test::MinSize(min_size), false));
+        }
+        if let Some(max_size) = self.max_size {
+            tests.push((Test::MaxSize(max_size), false));
+        }
+        if !self.size.is_empty() {
+            tests.push((Test::SizeRange(size_range.0, size_range.1), false));
+        }
+        if self.empty {
+            tests.push((Test::Empty, false));
+        }
+        if self.non_empty {
+            tests.push((Test::NonEmpty, false));
+        }
+        if let Some(after) = self.after_absolute {
+            tests.push((Test::After(after), false));
+        }
+        if let Some(before) = self.before_absolute {
+            tests.push((Test::Before(before), false));
+        }
+        // Relative times are worked out once, so a long listing doesn't use a moving window
+        if let Some(after) = self.after {
+            tests.push((Test::After(ago(after)?), false));
+        }
+        if let Some(before) = self.before {
+            tests.push((Test::Before(ago(before)?), false));
+        }
+        if let Some(url) = &self.newer_than_object {
+            if let Some(after) = self.modified_time_of(url).await? {
+                tests.push((Test::After(after), false));
             }
-            if valid != self.invert {
-                writer.write(meta.into()).await?;
+        }
+        if let Some(url) = &self.older_than_object {
+            if let Some(before) = self.modified_time_of(url).await? {
+                tests.push((Test::Before(before), false));
             }
-            Ok(())
+        }
+        if let Some(path) = &self.in_listing {
+            tests.push((Test::Listed(self.load_listing(path).await?), false));
+        }
+        if let Some(path) = &self.not_in {
+            tests.push((Test::Listed(self.load_listing(path).await?), true));
+        }
+        if let Some(text) = &self.where_expr {
+            let expression =
+                Expression::parse(text, self.ignore_case).context("Can't parse --where")?;
+            tests.push((Test::Where(expression), false));
+        }
+
+        Ok(Filter {
+            find: self,
+            tests,
+            excludes: self.exclude.iter().map(regex).collect::<Result<_, _>>()?,
+            matched: AtomicUsize::new(0),
+            sampler: Mutex::new(Rng::new(self.seed())),
+            captures: self.capture_groups(anchored)?,
+            seen: (self.unique && !(self.root.len() == 1 && self.unique_by == UniqueBy::Location))
+                .then(Default::default),
+            repeated: AtomicUsize::new(0),
+            per_prefix: self.per_prefix_limit.is_some().then(Default::default),
+            over_quota: AtomicUsize::new(0),
+            now,
+        })
+    }
+
+    /// A path or basename pattern, made to match the whole text with `--full-match`.
+    /// The group doesn't capture, so `--captures` still counts groups the same way.
+    fn anchor(&self, pattern: &str) -> String {
+        match self.full_match {
+            true => format!("^(?:{pattern})$"),
+            false => pattern.to_string(),
+        }
+    }
+
+    /// Which group of the first `--path-match` each of the `--captures` names,
+    /// by name if there's a group with that name and otherwise by position among the rest
+    fn capture_groups(
+        &self,
+        regex: impl Fn(&String) -> Result<regex::Regex, regex::Error>,
+    ) -> Result<Option<(regex::Regex, Vec<usize>)>> {
+        let (Some(first), false) = (self.path_match.first(), self.captures.is_empty()) else {
+            return Ok(None);
         };
-        match self.base_url()? {
-            Some(url) => {
-                let (store, path) = object_store::parse_url(&url)?;
-                let store = Arc::new(store);
-                let gen = store.list(Some(&path)).map_err(anyhow::Error::from);
-                gen.try_for_each_concurrent(global_args.concurrency, print_matches)
-                    .await?;
+        let reg = regex(first)?;
+        let named = |name: &str| reg.capture_names().position(|group| group == Some(name));
+        // Names that aren't groups take the groups no other name took, in order
+        let mut unclaimed = (1..reg.captures_len())
+            .filter(|&group| !self.captures.iter().any(|name| named(name) == Some(group)));
+        let groups = self
+            .captures
+            .iter()
+            .map(|name| {
+                named(name).or_else(|| unclaimed.next()).with_context(|| {
+                    format!(
+                        "--path-match {first:?} doesn't have enough groups for --captures {name:?}"
+                    )
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Some((reg, groups)))
+    }
+
+    /// The seed for random choices, from `--seed` or chosen once if it wasn't given
+    pub fn seed(&self) -> u64 {
+        *self
+            .chosen_seed
+            .get_or_init(|| self.seed.unwrap_or_else(random::random_seed))
+    }
+
+    /// Open the roots to list, or read the preamble from stdin when there aren't any
+    fn open_all(&self) -> Result<(Preamble, Vec<Root>)> {
+        if self.root.len() <= 1 {
+            let (preamble, root) = self.open()?;
+            return Ok((preamble, root.into_iter().collect()));
+        }
+        let roots = self.distinct_roots()?;
+        let _ = self
+            .root_paths
+            .set(roots.iter().map(|root| root.path.clone()).collect());
+        let _ = self
+            .root_urls
+            .set(roots.iter().map(|root| root.url.clone()).collect());
+        let mut preamble =
+            Preamble::with_roots(roots.iter().map(|root| root.url.clone()).collect());
+        if let Some(regex) = self.reusable_path_match() {
+            preamble.set_path_match(&regex);
+        }
+        Ok((preamble, roots))
+    }
+
+    /// The regex for later commands like `rename` to reuse, when every object found matches it
+    /// as it would be read back: the only `--path-match`, not inverted by `--not`, not one of
+    /// several alternatives with `--any`, and not relying on `--ignore-case`
+    fn reusable_path_match(&self) -> Option<String> {
+        match self.path_match.as_slice() {
+            [regex] if !self.invert && !self.any && !self.ignore_case => Some(self.anchor(regex)),
+            _ => None,
+        }
+    }
+
+    /// Stream the objects of every root concurrently, along with which root each came from
+    /// when there are several.
+    ///
+    /// With `checksums`, objects from stdin keep any checksum from `hash` to compare, and with
+    /// `ordered`, one root's listing finishes before the next starts.
+    fn objects_of_all<'a>(
+        &'a self,
+        roots: &'a [Root],
+        concurrency: usize,
+        checksums: bool,
+        ordered: bool,
+    ) -> BoxStream<'a, Result<Found>> {
+        if roots.is_empty() {
+            // Objects keep which of the listing's roots they're from, so they stay with its store
+            let objects = match checksums {
+                true => listing::read_stdin_checksums()
+                    .map(move |object| {
+                        let object = object?;
+                        let mut found = self.listed(object.object)?;
+                        if let Some(checksum) = object.checksum {
+                            found.extra.insert("checksum".to_string(), checksum.into());
+                        }
+                        Ok(found)
+                    })
+                    .boxed(),
+                false => listing::read_stdin_exports()
+                    .map(move |object| self.listed(object?))
+                    .boxed(),
+            };
+            let objects = objects
+                .try_filter(move |found| futures::future::ready(self.within_depth(&found.meta)));
+            return self.after_start(objects, |found| &found.meta.location);
+        }
+        if roots.len() == 1 {
+            return self
+                .objects_in_order(roots.first(), ordered)
+                .map_ok(|meta| Found::new(None, meta))
+                .boxed();
+        }
+        let listings = futures::stream::iter(roots.iter().enumerate()).map(move |(index, root)| {
+            self.objects_in_order(Some(root), ordered)
+                .map_ok(move |meta| Found::new(Some(index), meta))
+        });
+        match ordered {
+            true => listings.flatten().boxed(),
+            false => listings.flatten_unordered(concurrency).boxed(),
+        }
+    }
+
+    /// Which of the listing's roots an object read from stdin says it's from,
+    /// where there's nothing to say when there's only one
+    fn listed_root(&self, root: Option<usize>) -> Result<Option<usize>> {
+        let count = self.root_urls.get().map_or(1, Vec::len);
+        match root {
+            Some(index) if index >= count => {
+                bail!("An object is from root {index}, but the listing only has {count}")
             }
-            None => {
-                Self::read_stdin()
-                    .try_for_each_concurrent(global_args.concurrency, print_matches)
-                    .await?;
+            None if count > 1 => {
+                bail!("An object doesn't say which of the listing's {count} roots it's from")
             }
+            root => Ok(root),
+        }
+    }
+
+    /// An object read from stdin, keeping any fields earlier commands added to it
+    fn listed(&self, object: ObjectExport) -> Result<Found> {
+        let (meta, annotations) = object.split();
+        Ok(Found {
+            root: self.listed_root(annotations.root)?,
+            meta,
+            extra: annotations.extra,
+        })
+    }
+
+    /// The URL of the root an object is from, which needs no saying when there's only one
+    fn root_url(&self, root: Option<usize>) -> Option<&Url> {
+        match (root, self.root_urls.get()?.as_slice()) {
+            (Some(index), urls) => urls.get(index),
+            (None, [url]) => Some(url),
+            (None, _) => None,
+        }
+    }
+}
+
+/// A match on its way to be printed, with any extra fields found along the way
+struct Found {
+    /// Which of several roots it was listed from
+    root: Option<usize>,
+    meta: ObjectMeta,
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Found {
+    fn new(root: Option<usize>, meta: ObjectMeta) -> Self {
+        Self {
+            root,
+            meta,
+            extra: Default::default(),
+        }
+    }
+
+    fn export(self, filter: &Filter, fields: &[EmittedField]) -> ObjectExport {
+        // Fields from earlier commands stay, unless they're worked out again here
+        let mut extra = self.extra;
+        extra.extend(filter.captures(&self.meta.location));
+        extra.extend(filter.emitted(fields, &self.meta));
+        ObjectExport {
+            root: self.root,
+            extra,
+            ..self.meta.into()
+        }
+    }
+}
+
+/// Fetch an object's metadata again along with its content type,
+/// which listings leave out, or `None` if it's gone since it was listed
+async fn fetch(
+    root: &Root,
+    meta: ObjectMeta,
+    counters: &Counters,
+) -> Result<Option<(ObjectMeta, Option<String>)>> {
+    let head = || async {
+        counters.heads.fetch_add(1, Ordering::Relaxed);
+        let options = GetOptions {
+            head: true,
+            ..Default::default()
         };
-        Ok(())
+        match root.store.get_opts(&meta.location, options).await {
+            Ok(result) => {
+                let content_type = result.attributes.get(&Attribute::ContentType);
+                Ok(Some((result.meta, content_type.map(|ct| ct.to_string()))))
+            }
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    };
+    store::with_retries(FETCH_RETRIES, head).await
+}
+
+/// Print the counters for `--progress` until aborted, away from the listing itself
+async fn show_progress(counters: Arc<Counters>, started: Instant) {
+    let terminal = std::io::stderr().is_terminal();
+    let mut ticks = tokio::time::interval(Duration::from_secs(if terminal { 1 } else { 10 }));
+    // The first tick is immediate, and there's nothing to say yet
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        let scanned = counters.scanned.load(Ordering::Relaxed);
+        let elapsed = started.elapsed().as_secs_f64();
+        let line = format!(
+            "Scanned {scanned} objects and matched {} in {elapsed:.0}s, {:.0} objects/s",
+            counters.matched.load(Ordering::Relaxed),
+            scanned as f64 / elapsed
+        );
+        match terminal {
+            true => eprint!("\r{line}\x1b[K"),
+            false => eprintln!("{line}"),
+        }
+    }
+}
+
+/// How many more times to fetch an object when the store has trouble answering
+const FETCH_RETRIES: usize = 3;
+
+/// The exit code with `--fail-if-empty` when nothing matched, apart from 1 for other errors
+const EMPTY_EXIT_CODE: i32 = 3;
+/// The exit code with `--fail-if-found` when something matched
+const FOUND_EXIT_CODE: i32 = 4;
+/// The exit code with `--keep-going` when any objects were skipped after errors
+const ERRORS_EXIT_CODE: i32 = 5;
+
+/// What `--stats` counts, as the listing goes
+#[derive(Debug, Default)]
+struct Counters {
+    scanned: AtomicUsize,
+    matched: AtomicUsize,
+    bytes: AtomicU64,
+    /// Requests for single objects, from `--verify` and `--content-type`
+    heads: AtomicUsize,
+    /// Objects skipped after errors, with `--keep-going`
+    errors: AtomicUsize,
+}
+
+impl Counters {
+    /// Count a match once it's certain, only as it's printed or counted, so anything dropped
+    /// later, like by `--verify` or `--only-duplicates`, isn't
+    fn matched(&self, found: &Found) {
+        self.matched.fetch_add(1, Ordering::Relaxed);
+        self.bytes
+            .fetch_add(found.meta.size as u64, Ordering::Relaxed);
+    }
+}
+
+/// What `--follow` has printed already, so each listing only prints what's new
+#[derive(Debug, Default)]
+struct Followed {
+    /// When each location and etag pair was modified, to forget them outside the window
+    seen: HashMap<(String, Option<String>), DateTime<Utc>>,
+    /// Whether the first listing is done
+    listed: bool,
+}
+
+impl Followed {
+    /// Whether to print a match from the current listing, remembering it if so.
+    /// After the first listing, anything modified before the window started is left out.
+    fn is_new(&mut self, meta: &ObjectMeta, window_start: Option<DateTime<Utc>>) -> bool {
+        let recent = window_start.is_none_or(|start| meta.last_modified >= start);
+        if self.listed && !recent {
+            return false;
+        }
+        let key = (meta.location.to_string(), meta.e_tag.clone());
+        self.seen.insert(key, meta.last_modified).is_none()
+    }
+
+    /// End a listing, forgetting objects modified before the window started
+    fn finish_listing(&mut self, window_start: Option<DateTime<Utc>>) {
+        if let Some(start) = window_start {
+            self.seen.retain(|_, modified| *modified >= start);
+        }
+        self.listed = true;
+    }
+}
+
+/// The summary `--stats` prints once the listing ends
+#[derive(Debug, Serialize)]
+struct Stats {
+    scanned: usize,
+    matched: usize,
+    bytes: u64,
+    heads: usize,
+    errors: usize,
+    /// In seconds
+    elapsed: f64,
+    /// Objects scanned per second
+    rate: f64,
+}
+
+impl Stats {
+    fn of(counters: &Counters, elapsed: Duration) -> Self {
+        Self {
+            scanned: counters.scanned.load(Ordering::Relaxed),
+            matched: counters.matched.load(Ordering::Relaxed),
+            bytes: counters.bytes.load(Ordering::Relaxed),
+            heads: counters.heads.load(Ordering::Relaxed),
+            errors: counters.errors.load(Ordering::Relaxed),
+            elapsed: elapsed.as_secs_f64(),
+            rate: counters.scanned.load(Ordering::Relaxed) as f64
+                / elapsed.as_secs_f64().max(f64::EPSILON),
+        }
+    }
+
+    /// The summary as a sentence, leaving out what didn't happen
+    fn text(&self) -> String {
+        format!(
+            "Scanned {} objects and matched {} ({}) in {:.2}s, {:.0} objects/s{}{}",
+            self.scanned,
+            self.matched,
+            format_size(self.bytes),
+            self.elapsed,
+            self.rate,
+            match self.heads {
+                0 => String::new(),
+                heads => format!(", fetching {heads} objects again"),
+            },
+            match self.errors {
+                0 => String::new(),
+                errors => format!(", skipping {errors} after errors"),
+            }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// The `find` subcommand: the filters every listing command shares, and what only `find` does
+#[derive(Debug, Parser)]
+pub struct FindCommand {
+    #[command(flatten)]
+    find: Find,
+    /// Compare each match with a checksum manifest, a file or an object in a store, joined by
+    /// key below the root, and print how it compares as a `manifest_status` field:
+    /// `ok`, `mismatch` or `missing_from_manifest`.
+    ///
+    /// A `checksum` field from `hash` on stdin is compared when the manifest has one from the same
+    /// algorithm, and otherwise the size is, if the manifest has it.
+    #[arg(long, value_parser = ListingPath::parse)]
+    manifest: Option<ListingPath>,
+    /// How `--manifest` is written: `sha256sum` output, with a `<hash>  <path>` line per file,
+    /// or a listing like `hash` prints. Paths may start with `./` and use backslashes.
+    #[arg(long, value_enum, default_value_t = ManifestFormat::Sha256sum, requires = "manifest")]
+    manifest_format: ManifestFormat,
+    /// Only show matches with this `manifest_status`, or one of them if separated by commas
+    #[arg(long, value_enum, value_delimiter = ',', requires = "manifest")]
+    only_status: Vec<ManifestStatus>,
+    /// Only keep matches modified at most this long before the newest match, like `1h` for the
+    /// latest batch, whenever it ran. Matches modified at the same time as the newest are all kept.
+    /// Every match is held in memory until the listing finishes, so there can be at most `--sort-max`.
+    #[arg(long, value_parser = parse_duration)]
+    newest_within: Option<Duration>,
+    /// Print the matches sorted by this, the path if not given, instead of as they're found.
+    ///
+    /// Every match is held in memory until the listing finishes, so there can be at most `--sort-max`.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "path")]
+    sort_by: Option<SortKey>,
+    /// Objects' content types must match this regex, like `text/.*`. Listings don't include them,
+    /// so each object that passes every other filter is fetched again to find out.
+    /// Like `--exclude`, `--not` and `--any` don't change this.
+    #[arg(long)]
+    content_type: Option<String>,
+    /// Print each object's content type as an extra field, fetching it like `--content-type` does
+    #[arg(long)]
+    emit_content_type: bool,
+    /// Print these fields worked out from each object, separated by commas, so later stages
+    /// don't have to: `age_seconds` since it was modified, as of when `find` started,
+    /// its `basename`, `extension` without the dot, `depth` below the root and `parent` path.
+    /// Later commands that pass objects on, like another `find` or `sort`, keep them.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    emit_fields: Vec<EmittedField>,
+    /// Check that each match from a listing on stdin still exists, leaving it out if not,
+    /// and print its current size, modification time and etag. Filters see the listing's values,
+    /// but `--limit`, `--unique` and `--sample-rate` only count objects that still exist.
+    #[arg(long, conflicts_with = "root")]
+    verify: bool,
+    /// With `--verify`, print objects that no longer exist anyway, marked with `"missing": true`
+    #[arg(long, requires = "verify")]
+    verify_keep_missing: bool,
+    /// Skip objects that can't be read, like a malformed line on stdin or a failed fetch,
+    /// instead of stopping at the first. Each is explained on stderr, and if there were any,
+    /// `find` exits with code 5 at the end.
+    #[arg(long)]
+    keep_going: bool,
+    /// Print matches in the order they were listed or read from stdin, even when they're fetched
+    /// concurrently, with several roots listed one after another. Only `--concurrency` matches
+    /// wait at once for a slow one ahead of them.
+    #[arg(long, conflicts_with_all = ["sort_by", "shuffle", "only_duplicates"])]
+    ordered: bool,
+    /// With `--sort-by`, sort from largest to smallest, newest to oldest, or last path to first
+    #[arg(long, requires = "sort_by")]
+    reverse: bool,
+    /// Save the listing to this file, or an object like `s3://bucket/listing.ndjson`, instead of
+    /// printing it. It's compressed if the name ends in `.gz` or `.zst`, and an object only appears
+    /// once complete.
+    #[arg(long, value_parser = ListingPath::parse)]
+    output: Option<ListingPath>,
+    /// Print the matches in a random order, so workers reading them don't all start on the same
+    /// prefix. Every match is held in memory, up to `--sort-max`, unless there's a `--shuffle-window`.
+    #[arg(long, conflicts_with = "sort_by")]
+    shuffle: bool,
+    /// With `--shuffle`, only shuffle within a sliding window of this many matches, so a huge
+    /// listing doesn't have to fit in memory. Matches can only move this far from where they were.
+    #[arg(long, requires = "shuffle")]
+    shuffle_window: Option<usize>,
+    /// Only print matches with the same size and etag as another match, as likely copies,
+    /// once the listing is done. Each gets a `duplicate_group` field numbering its group.
+    #[arg(long, conflicts_with_all = ["sort_by", "shuffle"])]
+    only_duplicates: bool,
+    /// With `--only-duplicates`, group objects without etags by their size alone,
+    /// instead of leaving them out
+    #[arg(long, requires = "only_duplicates")]
+    loose: bool,
+    /// With `--sort-by`, `--shuffle` or `--newest-within`, give up rather than hold more than this
+    /// many matches in memory
+    #[arg(long, default_value = "1000000")]
+    sort_max: usize,
+    /// Only print how many objects matched, instead of a listing
+    #[arg(long, conflicts_with_all = ["sort_by", "shuffle", "output", "verify", "only_duplicates"])]
+    count: bool,
+    /// With `--count`, also print the total bytes of the matches, after a tab
+    #[arg(long, requires = "count")]
+    bytes: bool,
+    /// Print how many objects were scanned and matched, and how fast, to stderr at the end.
+    /// It's printed even if the listing stops early from an error.
+    #[arg(long)]
+    stats: bool,
+    /// How to print `--stats`, as a sentence or as JSON for scripts
+    #[arg(long, value_enum, default_value_t = Format::Text, requires = "stats")]
+    stats_format: Format,
+    /// Keep listing the roots after the first time, printing objects that weren't there before,
+    /// until interrupted with Ctrl-C. Objects that change count as new, since their etag changes.
+    /// Each listing filters afresh, so times like `--after 1h` count back from when it starts,
+    /// and limits like `--per-prefix-limit` apply to each listing on its own.
+    #[arg(long, conflicts_with_all = ["count", "sort_by", "shuffle", "limit", "unique", "only_duplicates"])]
+    follow: bool,
+    /// With `--follow`, how long to wait between listings, like `15s` or `5m`
+    #[arg(long, value_parser = parse_duration, default_value = "30s", requires = "follow")]
+    poll_interval: Duration,
+    /// With `--follow`, only remember objects modified this recently, like `1h`, so memory stays
+    /// bounded. After the first listing, anything older is left out rather than printed again.
+    #[arg(long, value_parser = parse_duration, requires = "follow")]
+    follow_window: Option<Duration>,
+    /// Show how the listing is going on stderr: updating a line every second on a terminal,
+    /// or printing one every ten seconds otherwise
+    #[arg(long)]
+    progress: bool,
+    /// Exit with code 3 if nothing matched, to check that something exists
+    #[arg(long, conflicts_with = "fail_if_found")]
+    fail_if_empty: bool,
+    /// Exit with code 4 if anything matched, to check that nothing is left over.
+    /// That's so even if the output was cut short, like by `head`.
+    #[arg(long)]
+    fail_if_found: bool,
+}
+
+impl FindCommand {
+    /// Compile the filters, along with what only `find` itself checks
+    async fn start(&self, global_args: &Args) -> Result<(Filter<'_>, Checks)> {
+        let find = &self.find;
+        let filter = find.filter().await?;
+        ensure!(
+            self.shuffle_window != Some(0),
+            "--shuffle-window must be at least 1"
+        );
+        if global_args.verbose
+            && (find.sample_rate.is_some() || self.shuffle)
+            && find.seed.is_none()
+        {
+            eprintln!("Choosing randomly with --seed {}", find.seed());
+        }
+        let content_type = self
+            .content_type
+            .as_ref()
+            .map(|s| {
+                regex::RegexBuilder::new(s)
+                    .case_insensitive(find.ignore_case)
+                    .build()
+            })
+            .transpose()?;
+        let manifest = match &self.manifest {
+            Some(path) => Some(Manifest {
+                recorded: self.load_manifest(path).await?,
+                unchecked: AtomicUsize::new(0),
+            }),
+            None => None,
+        };
+        Ok((
+            filter,
+            Checks {
+                content_type,
+                manifest,
+            },
+        ))
+    }
+
+    /// Compare a match with the `--manifest`, if there is one, and whether `--only-status` keeps it
+    fn check_manifest(&self, checks: &Checks, found: &mut Found) -> bool {
+        let Some(manifest) = &checks.manifest else {
+            return true;
+        };
+        let status = manifest.check(&self.find, found);
+        self.only_status.is_empty() || self.only_status.contains(&status)
+    }
+
+    /// Load a `--manifest`, keyed by normalized path below its root
+    async fn load_manifest(&self, path: &ListingPath) -> Result<HashMap<String, Recorded>> {
+        let mut lines = listing::read_lines(path).await?;
+        let mut manifest = HashMap::new();
+        match self.manifest_format {
+            ManifestFormat::Sha256sum => {
+                while let Some(line) = lines.try_next().await? {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    // Binary mode marks the path with a `*` instead of the second space
+                    let Some((hash, name)) = line.split_once(' ') else {
+                        bail!("{path} has a line that isn't `<hash>  <path>`: {line:?}");
+                    };
+                    let name = name.strip_prefix([' ', '*']).unwrap_or(name);
+                    let recorded = Recorded {
+                        checksum: Some(format!("sha256:{}", hash.to_lowercase())),
+                        size: None,
+                    };
+                    manifest.insert(normalize_manifest_path(name), recorded);
+                }
+            }
+            ManifestFormat::Ndjson => {
+                let first = lines.try_next().await?.unwrap_or_default();
+                let preamble: Preamble = serde_json::from_str(&first)
+                    .with_context(|| format!("Reading the first line of {path} as a Preamble"))?;
+                let roots = root_paths(&preamble)?;
+                while let Some(line) = lines.try_next().await? {
+                    let line: listing::Checksummed = serde_json::from_str(&line)?;
+                    let meta = ObjectMeta::from(line.object);
+                    let recorded = Recorded {
+                        checksum: line.checksum,
+                        size: Some(meta.size),
+                    };
+                    let key = key_below(&roots, &meta.location);
+                    manifest.insert(normalize_manifest_path(&key), recorded);
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    /// The roots to fetch objects from when they're read from stdin, if anything needs them,
+    /// in the same order as the listing's
+    fn stdin_roots(&self, preamble: &Preamble, roots: &[Root]) -> Result<Vec<Root>> {
+        let fetches = self.verify || self.content_type.is_some() || self.emit_content_type;
+        if !roots.is_empty() || !fetches {
+            return Ok(vec![]);
+        }
+        let mut opened: Vec<Root> = vec![];
+        for url in preamble.roots() {
+            opened.push(match opened.first() {
+                Some(first) => first.open_sibling(url)?,
+                None => Root::open(url)?,
+            });
+        }
+        Ok(opened)
+    }
+
+    /// With `--keep-going`, explain and count an error instead of failing with it
+    fn tolerate<T>(&self, result: Result<T>, counters: &Counters) -> Result<Option<T>> {
+        match result {
+            Err(e) if self.keep_going => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                eprintln!("Skipping after an error: {e:#}");
+                Ok(None)
+            }
+            result => result.map(Some),
+        }
+    }
+
+    /// Every object from the roots that matches, stopping early at `--limit`
+    fn matches<'a>(
+        &'a self,
+        filter: &'a Filter,
+        checks: &'a Checks,
+        roots: &'a [Root],
+        stdin_roots: &'a [Root],
+        concurrency: usize,
+        counters: &'a Counters,
+    ) -> BoxStream<'a, Result<Found>> {
+        let listed =
+            self.find
+                .objects_of_all(roots, concurrency, self.manifest.is_some(), self.ordered);
+        // Objects from stdin are fetched from the listing's own roots
+        let roots = if roots.is_empty() { stdin_roots } else { roots };
+        self.matches_of(listed, filter, checks, roots, concurrency, counters)
+    }
+
+    /// The objects listed that match, where `--verify` and `--content-type` fetch them from the
+    /// roots, and only then are they counted toward `--limit`
+    fn matches_of<'a>(
+        &'a self,
+        listed: BoxStream<'a, Result<Found>>,
+        filter: &'a Filter,
+        checks: &'a Checks,
+        roots: &'a [Root],
+        concurrency: usize,
+        counters: &'a Counters,
+    ) -> BoxStream<'a, Result<Found>> {
+        let listed = listed
+            .filter_map(|found| futures::future::ready(self.tolerate(found, counters).transpose()))
+            // Stop listing once the limit is reached, rather than paging through the rest
+            .try_take_while(|_| futures::future::ready(Ok(!filter.is_exhausted())))
+            .inspect_ok(|_| {
+                counters.scanned.fetch_add(1, Ordering::Relaxed);
+            })
+            .try_filter_map(|mut found| {
+                let passed = filter.passes_from(&found.meta, found.root)
+                    && self.check_manifest(checks, &mut found);
+                futures::future::ready(Ok(passed.then_some(found)))
+            });
+        let fetched = if checks.content_type.is_none() && !self.emit_content_type {
+            listed.boxed()
+        } else {
+            // Only objects that pass the cheap filters are fetched
+            listed
+                .map_ok(
+                    move |Found {
+                              root: index,
+                              meta,
+                              extra,
+                          }| async move {
+                        let root = roots
+                            .get(index.unwrap_or(0))
+                            .context("Nowhere to fetch from")?;
+                        let location = meta.location.clone();
+                        let fetched = fetch(root, meta, counters)
+                            .await
+                            .with_context(|| format!("Fetching {location}"));
+                        let Some((meta, content_type)) =
+                            self.tolerate(fetched, counters)?.flatten()
+                        else {
+                            return Ok(None);
+                        };
+                        if let Some(reg) = &checks.content_type {
+                            if !content_type.as_deref().is_some_and(|ct| reg.is_match(ct)) {
+                                return Ok(None);
+                            }
+                        }
+                        let mut found = Found {
+                            root: index,
+                            meta,
+                            extra,
+                        };
+                        if self.emit_content_type {
+                            found
+                                .extra
+                                .insert("content_type".to_string(), content_type.into());
+                        }
+                        Ok(Some(found))
+                    },
+                )
+                .try_buffered(concurrency)
+                .try_filter_map(|found| futures::future::ready(Ok(found)))
+                .boxed()
+        };
+        // Objects that are gone are dropped before they can count toward `--limit`
+        // Only objects read from stdin can have gone since they were listed
+        let verified = match self.verify && self.find.root.is_empty() && !roots.is_empty() {
+            true => self.verify(fetched, roots, concurrency, counters),
+            false => fetched,
+        };
+        let matches = verified
+            .try_filter(|found| futures::future::ready(filter.admit(&found.meta)))
+            .boxed();
+        self.newest_within(matches)
+    }
+
+    /// Hold every match until the listing finishes, to pass on only those within `--newest-within`
+    /// of the newest
+    fn newest_within<'a>(
+        &'a self,
+        matches: BoxStream<'a, Result<Found>>,
+    ) -> BoxStream<'a, Result<Found>> {
+        let Some(within) = self.newest_within else {
+            return matches;
+        };
+        futures::stream::once(async move {
+            let mut matches = matches;
+            let mut held = vec![];
+            while let Some(found) = matches.try_next().await? {
+                if held.len() >= self.sort_max {
+                    bail!(
+                        "More than --sort-max {} objects matched, too many to hold in memory for --newest-within",
+                        self.sort_max
+                    );
+                }
+                held.push(found);
+            }
+            if let Some(newest) = held.iter().map(|found| found.meta.last_modified).max() {
+                let since = newest - chrono::Duration::from_std(within)?;
+                held.retain(|found| found.meta.last_modified >= since);
+            }
+            anyhow::Ok(futures::stream::iter(held.into_iter().map(Ok)))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// Replace each match with how it is in the store now, for `--verify`.
+    /// Objects that are gone are left out, or passed on as they were and marked missing.
+    fn verify<'a>(
+        &'a self,
+        matches: BoxStream<'a, Result<Found>>,
+        roots: &'a [Root],
+        concurrency: usize,
+        counters: &'a Counters,
+    ) -> BoxStream<'a, Result<Found>> {
+        let checks = matches.map_ok(move |mut found| async move {
+            let root = roots
+                .get(found.root.unwrap_or(0))
+                .context("Nowhere to verify objects in")?;
+            let head = || async {
+                counters.heads.fetch_add(1, Ordering::Relaxed);
+                match root.store.head(&found.meta.location).await {
+                    Ok(current) => Ok(Some(current)),
+                    Err(object_store::Error::NotFound { .. }) => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            };
+            let current = store::with_retries(FETCH_RETRIES, head)
+                .await
+                .with_context(|| format!("Verifying {}", found.meta.location));
+            let Some(current) = self.tolerate(current, counters)? else {
+                return Ok(None);
+            };
+            Ok(match current {
+                Some(current) => Some(Found {
+                    meta: current,
+                    ..found
+                }),
+                None if self.verify_keep_missing => {
+                    found.extra.insert("missing".to_string(), true.into());
+                    Some(found)
+                }
+                None => None,
+            })
+        });
+        let verified = match self.ordered {
+            true => checks.try_buffered(concurrency).boxed(),
+            false => checks.try_buffer_unordered(concurrency).boxed(),
+        };
+        verified
+            .try_filter_map(|verified| futures::future::ready(Ok(verified)))
+            .boxed()
+    }
+
+    /// Where matches go: stdout, or `--output`
+    async fn writer(&self, preamble: &Preamble) -> Result<StdoutWriter> {
+        match &self.output {
+            Some(output) => StdoutWriter::save(preamble, output).await,
+            None => StdoutWriter::start(preamble),
+        }
+    }
+
+    /// Report anything left out along the way, once the listing is done
+    fn finish(&self, filter: &Filter, checks: &Checks) {
+        if self.find.unique {
+            eprintln!("Left out {} repeated objects", filter.repeated());
+        }
+        if let Some(quota) = self.find.per_prefix_limit {
+            let (left_out, prefixes) = filter.over_quota();
+            eprintln!(
+                "Left out {left_out} objects over --per-prefix-limit {quota} across {prefixes} prefixes"
+            );
+        }
+        let unchecked = checks
+            .manifest
+            .as_ref()
+            .map_or(0, |manifest| manifest.unchecked.load(Ordering::Relaxed));
+        if unchecked > 0 {
+            eprintln!(
+                "{unchecked} objects were in the manifest with nothing to compare, so they count as ok. \
+                 Pipe them through `hash` first to compare checksums."
+            );
+        }
+    }
+
+    /// List the matches, or read them from stdin, and print them
+    async fn list(&self, global_args: &Args, counters: &Counters) -> Result<()> {
+        let (filter, checks) = &self.start(global_args).await?;
+        let (preamble, roots) = self.find.open_all()?;
+        let stdout = self.writer(&preamble).await?;
+        let stdin_roots = self.stdin_roots(&preamble, &roots)?;
+        let concurrency = global_args.concurrency;
+        let matches = self.matches(filter, checks, &roots, &stdin_roots, concurrency, counters);
+        self.print(matches, filter, &stdout, concurrency, counters)
+            .await?;
+        stdout.finish().await?;
+        self.finish(filter, checks);
+        Ok(())
+    }
+
+    /// Print the matches in the order asked for, counting each as it's written
+    async fn print(
+        &self,
+        matches: BoxStream<'_, Result<Found>>,
+        filter: &Filter<'_>,
+        writer: &StdoutWriter,
+        concurrency: usize,
+        counters: &Counters,
+    ) -> Result<()> {
+        // These ref's mean that `async move` later doesn't take ownership of the fields
+        let export = |found: Found| {
+            counters.matched(&found);
+            found.export(filter, &self.emit_fields)
+        };
+        match self.sort_by {
+            None if self.only_duplicates => {
+                let mut groups = Vec::<Vec<Found>>::new();
+                let mut group_of = HashMap::<(usize, Option<String>), usize>::new();
+                let mut matches = std::pin::pin!(matches);
+                while let Some(found) = matches.try_next().await? {
+                    let e_tag = store::etag(&found.meta).map(str::to_string);
+                    if e_tag.is_none() && !self.loose {
+                        continue;
+                    }
+                    let next = groups.len();
+                    let group = *group_of.entry((found.meta.size, e_tag)).or_insert(next);
+                    if group == next {
+                        groups.push(vec![]);
+                    }
+                    groups[group].push(found);
+                }
+                let duplicates = groups.into_iter().filter(|group| group.len() > 1);
+                for (index, group) in duplicates.enumerate() {
+                    for mut found in group {
+                        found
+                            .extra
+                            .insert("duplicate_group".to_string(), index.into());
+                        writer.write(export(found)).await?;
+                    }
+                }
+            }
+            None if self.shuffle => {
+                // Without a window, it's as big as the most matches allowed in memory
+                let window = self.shuffle_window.unwrap_or(self.sort_max);
+                let mut rng = Rng::new(self.find.seed());
+                let mut matches = std::pin::pin!(matches);
+                let mut pending = vec![];
+                while let Some(found) = matches.try_next().await? {
+                    if pending.len() >= window {
+                        if self.shuffle_window.is_none() {
+                            bail!(
+                                "More than --sort-max {} objects matched, too many to shuffle in memory",
+                                self.sort_max
+                            );
+                        }
+                        let found = pending.swap_remove(rng.below(pending.len() as u64) as usize);
+                        writer.write(export(found)).await?;
+                    }
+                    pending.push(found);
+                }
+                while !pending.is_empty() {
+                    let found = pending.swap_remove(rng.below(pending.len() as u64) as usize);
+                    writer.write(export(found)).await?;
+                }
+            }
+            None if self.ordered => {
+                let mut matches = std::pin::pin!(matches);
+                while let Some(found) = matches.try_next().await? {
+                    writer.write(export(found)).await?;
+                }
+            }
+            None => {
+                matches
+                    .try_for_each_concurrent(concurrency, |found| async move {
+                        writer.write(export(found)).await
+                    })
+                    .await?
+            }
+            Some(key) => {
+                let mut matches = std::pin::pin!(matches);
+                let mut sorted = vec![];
+                while let Some(found) = matches.try_next().await? {
+                    if sorted.len() >= self.sort_max {
+                        bail!(
+                            "More than --sort-max {} objects matched, too many to sort in memory",
+                            self.sort_max
+                        );
+                    }
+                    sorted.push(found);
+                }
+                sorted.sort_by(|a: &Found, b: &Found| {
+                    let ordering = key.compare(&a.meta, &b.meta);
+                    if self.reverse {
+                        ordering.reverse()
+                    } else {
+                        ordering
+                    }
+                });
+                for found in sorted {
+                    writer.write(export(found)).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Count the matches instead of printing them
+    async fn count(&self, global_args: &Args, counters: &Counters) -> Result<()> {
+        let (filter, checks) = &self.start(global_args).await?;
+        let (preamble, roots) = self.find.open_all()?;
+        let stdin_roots = self.stdin_roots(&preamble, &roots)?;
+        let concurrency = global_args.concurrency;
+        let matches = self.matches(filter, checks, &roots, &stdin_roots, concurrency, counters);
+        println!("{}", self.tally(matches, concurrency, counters).await?);
+        self.finish(filter, checks);
+        Ok(())
+    }
+
+    /// Count the matches, into the line `--count` prints
+    async fn tally(
+        &self,
+        matches: BoxStream<'_, Result<Found>>,
+        concurrency: usize,
+        counters: &Counters,
+    ) -> Result<String> {
+        matches
+            .try_for_each_concurrent(concurrency, |found| async move {
+                counters.matched(&found);
+                Ok(())
+            })
+            .await?;
+        let matched = counters.matched.load(Ordering::Relaxed);
+        Ok(match self.bytes {
+            true => format!("{matched}\t{}", counters.bytes.load(Ordering::Relaxed)),
+            false => matched.to_string(),
+        })
+    }
+
+    /// List the roots again and again, printing only objects that are new each time
+    async fn follow(&self, global_args: &Args, counters: &Counters) -> Result<()> {
+        let find = &self.find;
+        // Checked once up front, so mistakes show before anything is listed
+        let (mut filter, checks) = self.start(global_args).await?;
+        let (preamble, roots) = find.open_all()?;
+        if roots.is_empty() {
+            bail!("--follow needs a --root to list");
+        }
+
+        // Listen for Ctrl-C from the start, so it never interrupts a listing halfway through
+        let (stop, mut stopped) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                let _ = stop.send(true);
+            }
+        });
+
+        let stdout = self.writer(&preamble).await?;
+        let mut followed = Followed::default();
+        loop {
+            if followed.listed {
+                filter = find.filter().await?;
+            }
+            let filter = &filter;
+            let window_start = self
+                .follow_window
+                .map(|window| Utc::now() - chrono::Duration::from_std(window).unwrap_or_default());
+            let concurrency = global_args.concurrency;
+            let mut matches = self.matches(filter, &checks, &roots, &[], concurrency, counters);
+            while let Some(found) = matches.try_next().await? {
+                if followed.is_new(&found.meta, window_start) {
+                    counters.matched(&found);
+                    stdout
+                        .write(found.export(filter, &self.emit_fields))
+                        .await?;
+                }
+            }
+            followed.finish_listing(window_start);
+
+            if *stopped.borrow() {
+                break;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.poll_interval) => {}
+                _ = stopped.changed() => break,
+            }
+        }
+        stdout.finish().await?;
+        self.finish(&filter, &checks);
+        Ok(())
+    }
+
+    fn print_stats(&self, counters: &Counters, elapsed: Duration) -> Result<()> {
+        let stats = Stats::of(counters, elapsed);
+        match self.stats_format {
+            Format::Text => eprintln!("{}", stats.text()),
+            Format::Json => eprintln!("{}", serde_json::to_string(&stats)?),
+        }
+        Ok(())
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let started = Instant::now();
+        let counters = Arc::new(Counters::default());
+        let progress = self
+            .progress
+            .then(|| tokio::spawn(show_progress(counters.clone(), started)));
+        let result = if self.count {
+            self.count(global_args, &counters).await
+        } else if self.follow {
+            self.follow(global_args, &counters).await
+        } else {
+            self.list(global_args, &counters).await
+        };
+        if let Some(progress) = progress {
+            progress.abort();
+            let _ = progress.await;
+            if std::io::stderr().is_terminal() {
+                eprintln!();
+            }
+        }
+        if self.stats {
+            self.print_stats(&counters, started.elapsed())?;
+        }
+        // Output cut short, like by `head`, still counts as finding what matched up to then
+        listing::ignore_broken_pipe(result)?;
+        if let Some((code, message)) = self.failure(&counters) {
+            eprintln!("{message}");
+            std::process::exit(code);
+        }
+        Ok(())
+    }
+
+    /// The exit code and why, when what was found isn't what `--fail-if-found`,
+    /// `--fail-if-empty` or `--keep-going` expected
+    fn failure(&self, counters: &Counters) -> Option<(i32, String)> {
+        let matched = counters.matched.load(Ordering::Relaxed);
+        if self.fail_if_found && matched > 0 {
+            let message = format!("{matched} objects matched, but --fail-if-found expected none");
+            return Some((FOUND_EXIT_CODE, message));
+        }
+        let errors = counters.errors.load(Ordering::Relaxed);
+        if errors > 0 {
+            return Some((
+                ERRORS_EXIT_CODE,
+                format!("Skipped {errors} objects after errors"),
+            ));
+        }
+        if self.fail_if_empty && matched == 0 {
+            let message = "Nothing matched, but --fail-if-empty expected something".to_string();
+            return Some((EMPTY_EXIT_CODE, message));
+        }
+        None
+    }
+}
+
+/// What identifies an object to `--join-on absolute`: its full URL, where its root is known,
+/// so objects at the same location in different stores are still different
+fn absolute_key(root: Option<&Url>, location: &ObjectStorePath) -> String {
+    root.and_then(|url| store::object_url(url, location).ok())
+        .map_or_else(|| location.to_string(), String::from)
+}
+
+/// A `--size` like GNU find's: `+` for over, `-` for under, or neither for exactly.
+///
+/// A single-letter unit is a power of 1024 in either case, while longer ones like `MB` or `GiB`
+/// mean what they do for `--min-size`.
+fn parse_size_bound(text: &str) -> Result<SizeBound> {
+    let text = text.trim();
+    let (bound, rest): (fn(u64) -> SizeBound, _) = match text.split_at_checked(1) {
+        Some(("+", rest)) => (SizeBound::Over, rest),
+        Some(("-", rest)) => (SizeBound::Under, rest),
+        _ => (SizeBound::Exactly, text),
+    };
+    let split = rest
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(rest.len());
+    let multiplier: u64 = match rest[split..].to_ascii_lowercase().as_str() {
+        "" | "c" => 1,
+        "k" => 1 << 10,
+        "m" => 1 << 20,
+        "g" => 1 << 30,
+        "t" => 1 << 40,
+        "p" => 1 << 50,
+        _ => return Ok(bound(parse_size(rest)?)),
+    };
+    let number: f64 = rest[..split]
+        .parse()
+        .map_err(|_| anyhow::anyhow!("{text:?} isn't a size like +1G, -10k or 0"))?;
+    let size = number * multiplier as f64;
+    ensure!(size <= u64::MAX as f64, "{text:?} is too large");
+    Ok(bound(size.round() as u64))
+}
+
+/// A `--segment` like `2=logs` or `-1~\.parquet$`: which segment, then `=` or `~`, then the pattern
+fn parse_segment(text: &str) -> Result<SegmentPattern> {
+    let split = text
+        .char_indices()
+        .find(|&(at, c)| !(c.is_ascii_digit() || (at == 0 && c == '-')))
+        .map_or(text.len(), |(at, _)| at);
+    let (index, rest) = text.split_at(split);
+    let (regex, pattern) = match rest.split_at_checked(1) {
+        Some(("=", pattern)) => (false, pattern),
+        Some(("~", pattern)) => (true, pattern),
+        _ => bail!("{text:?} isn't a segment like 2=logs or -1~\\.parquet$"),
+    };
+    let index: isize = index
+        .parse()
+        .with_context(|| format!("{text:?} doesn't start with which segment, like 2 or -1"))?;
+    ensure!(index != 0, "Segments count from 1, or from -1 for the last");
+    Ok(SegmentPattern {
+        index,
+        regex,
+        pattern: pattern.to_string(),
+    })
+}
+
+/// A location within a store, as listings print them
+fn parse_location(text: &str) -> Result<ObjectStorePath> {
+    Ok(ObjectStorePath::parse(text.trim_start_matches('/'))?)
+}
+
+/// A probability for `--sample-rate`, above 0 and at most 1
+fn parse_sample_rate(text: &str) -> Result<f64> {
+    let rate: f64 = text.parse()?;
+    if !(rate > 0.0 && rate <= 1.0) {
+        bail!("The rate must be above 0 and at most 1, like 0.01");
+    }
+    Ok(rate)
+}
+
+/// The paths within their stores of the roots a listing was made from
+fn root_paths(preamble: &Preamble) -> Result<Vec<ObjectStorePath>> {
+    preamble
+        .roots()
+        .iter()
+        .map(|url| Ok(ObjectStoreScheme::parse(url)?.1))
+        .collect()
+}
+
+/// An object's key below whichever of the roots it's under, or its whole location if none
+fn key_below(roots: &[ObjectStorePath], location: &ObjectStorePath) -> String {
+    roots
+        .iter()
+        .find_map(|root| location.prefix_match(root))
+        .map(|parts| parts.collect::<ObjectStorePath>().to_string())
+        .unwrap_or_else(|| location.to_string())
+}
+
+/// A path from a manifest as a key below the root, without `./` prefixes or backslashes
+fn normalize_manifest_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut key = path.as_str();
+    while let Some(rest) = key.strip_prefix("./") {
+        key = rest;
+    }
+    key.trim_start_matches('/').to_string()
+}
+
+/// List objects at most `max_depth` segments below the root, one level at a time,
+/// so the store never lists anything deeper
+fn list_to_depth(root: &Root, max_depth: usize) -> BoxStream<'_, Result<ObjectMeta>> {
+    if max_depth == 0 {
+        return futures::stream::once(async move {
+            match root.store.head(&root.path).await {
+                Ok(meta) => Ok(Some(meta)),
+                Err(object_store::Error::NotFound { .. }) => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        })
+        .try_filter_map(|meta| futures::future::ready(Ok(meta)))
+        .boxed();
+    }
+    let start = (VecDeque::new(), vec![(root.path.clone(), 1)]);
+    futures::stream::try_unfold(start, move |(mut ready, mut prefixes)| async move {
+        loop {
+            if let Some(meta) = ready.pop_front() {
+                return Ok(Some((meta, (ready, prefixes))));
+            }
+            let Some((prefix, depth)) = prefixes.pop() else {
+                return Ok(None);
+            };
+            let listed = root.store.list_with_delimiter(Some(&prefix)).await?;
+            ready.extend(listed.objects);
+            if depth < max_depth {
+                // Reversed, so popping visits prefixes in order
+                prefixes.extend(
+                    listed
+                        .common_prefixes
+                        .into_iter()
+                        .rev()
+                        .map(|p| (p, depth + 1)),
+                );
+            }
+        }
+    })
+    .boxed()
+}
+
+/// List a root as several listings at once, one for each prefix `shard_depth` segments below it.
+///
+/// Objects above that depth turn up while looking for the prefixes, and are passed on first,
+/// unless `ordered`, when everything comes out in the order of the store's own listing.
+/// With fewer than two prefixes there's nothing to gain, so the root is listed as usual.
+fn list_sharded(
+    root: &Root,
+    shard_depth: usize,
+    streams: usize,
+    ordered: bool,
+) -> BoxStream<'_, Result<ObjectMeta>> {
+    futures::stream::once(async move {
+        let mut shallow = vec![];
+        let mut prefixes = vec![root.path.clone()];
+        for _ in 0..shard_depth {
+            let listed: Vec<_> = futures::stream::iter(std::mem::take(&mut prefixes))
+                .map(|prefix| async move { root.store.list_with_delimiter(Some(&prefix)).await })
+                .buffered(streams)
+                .try_collect()
+                .await?;
+            for listed in listed {
+                shallow.extend(listed.objects);
+                prefixes.extend(listed.common_prefixes);
+            }
+        }
+        if prefixes.len() < 2 {
+            return Ok(root
+                .store
+                .list(Some(&root.path))
+                .map_err(anyhow::Error::from)
+                .boxed());
+        }
+        if ordered {
+            return Ok(list_shards_in_order(root, shallow, prefixes, streams));
+        }
+        let listings = futures::stream::iter(prefixes)
+            .map(move |prefix| root.store.list(Some(&prefix)).map_err(anyhow::Error::from));
+        anyhow::Ok(
+            futures::stream::iter(shallow.into_iter().map(Ok))
+                .chain(listings.flatten_unordered(streams))
+                .boxed(),
+        )
+    })
+    .try_flatten()
+    .boxed()
+}
+
+/// How many objects each prefix listed ahead by [`list_shards_in_order`] holds while it waits,
+/// about a page of a listing
+const READ_AHEAD: usize = 1000;
+
+/// Part of a sharded listing in the order the whole listing would give
+enum Shard {
+    /// Objects found on the way down to the prefixes
+    Objects(Vec<ObjectMeta>),
+    Prefix(ObjectStorePath),
+}
+
+/// Merge the objects found above the prefixes with the prefixes' listings, so they come out
+/// in the order of one listing of the root. Stores list in order of location, so everything
+/// under `a/` comes between `a.csv` and `b.csv`.
+///
+/// The listing of each prefix starts up to `streams` prefixes ahead of the one being read,
+/// holding [`READ_AHEAD`] objects until its turn.
+fn list_shards_in_order(
+    root: &Root,
+    shallow: Vec<ObjectMeta>,
+    prefixes: Vec<ObjectStorePath>,
+    streams: usize,
+) -> BoxStream<'static, Result<ObjectMeta>> {
+    let mut sorted: Vec<(String, Shard)> = shallow
+        .into_iter()
+        .map(|meta| (meta.location.to_string(), Shard::Objects(vec![meta])))
+        .chain(
+            prefixes
+                .into_iter()
+                .map(|prefix| (format!("{prefix}/"), Shard::Prefix(prefix))),
+        )
+        .collect();
+    sorted.sort_by(|a, b| a.0.cmp(&b.0));
+    // Objects next to each other are one shard, so they don't take the place of a prefix read ahead
+    let mut shards = vec![];
+    for (_, shard) in sorted {
+        match (shard, shards.last_mut()) {
+            (Shard::Objects(more), Some(Shard::Objects(objects))) => objects.extend(more),
+            (shard, _) => shards.push(shard),
+        }
+    }
+    let store = root.store.clone();
+    futures::stream::iter(shards)
+        .map(move |shard| {
+            // Buffering ready listings starts the next few while this one is read
+            futures::future::ready(match shard {
+                Shard::Objects(objects) => {
+                    futures::stream::iter(objects.into_iter().map(Ok)).boxed()
+                }
+                Shard::Prefix(prefix) => read_ahead(store.clone(), prefix),
+            })
+        })
+        .buffered(streams)
+        .flatten()
+        .boxed()
+}
+
+/// List a prefix in the background, holding up to [`READ_AHEAD`] objects until they're read
+fn read_ahead(
+    store: Arc<dyn object_store::ObjectStore>,
+    prefix: ObjectStorePath,
+) -> BoxStream<'static, Result<ObjectMeta>> {
+    let (tx, rx) = tokio::sync::mpsc::channel(READ_AHEAD);
+    tokio::spawn(async move {
+        let mut objects = store.list(Some(&prefix));
+        while let Some(meta) = objects.next().await {
+            // Nothing is reading any more, so stop listing
+            if tx.send(meta.map_err(anyhow::Error::from)).await.is_err() {
+                break;
+            }
+        }
+    });
+    tokio_stream::wrappers::ReceiverStream::new(rx).boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `find` as if it had read a listing of these roots from stdin
+    fn reading(roots: &[&str], args: &[&str]) -> Find {
+        let find = Find::try_parse_from(["find"].iter().chain(args)).unwrap();
+        let roots = roots.iter().map(|url| Url::parse(url).unwrap()).collect();
+        let preamble = Preamble::with_roots(roots);
+        find.root_paths.set(root_paths(&preamble).unwrap()).unwrap();
+        find.root_urls.set(preamble.roots().to_vec()).unwrap();
+        find
+    }
+
+    #[test]
+    fn objects_from_stdin_keep_their_root() {
+        let one = reading(&["s3://a/x"], &[]);
+        assert_eq!(one.listed_root(None).unwrap(), None);
+        assert!(one.listed_root(Some(1)).is_err());
+
+        let several = reading(&["s3://a/x", "s3://b/y"], &[]);
+        assert_eq!(several.listed_root(Some(1)).unwrap(), Some(1));
+        // Guessing would put the object in the wrong store
+        assert!(several.listed_root(None).is_err());
+        assert!(several.listed_root(Some(2)).is_err());
+    }
+
+    fn object(location: &str) -> ObjectMeta {
+        ObjectMeta {
+            location: ObjectStorePath::from(location),
+            last_modified: Utc::now(),
+            size: 0,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    /// Which of these objects pass a `find` of `s3://bucket/data/` with these arguments
+    async fn passing<'a>(args: &[&str], objects: &'a [ObjectMeta]) -> Vec<&'a str> {
+        let find = reading(&["s3://bucket/data/"], args);
+        let filter = find.filter().await.unwrap();
+        objects
+            .iter()
+            .filter(|meta| filter.passes(meta))
+            .map(|meta| meta.location.as_ref())
+            .collect()
+    }
+
+    /// Which of these objects a `find` of `s3://bucket/data/` keeps, in order, once
+    /// `--sample-rate`, `--unique`, `--per-prefix-limit` and `--limit` have had their say too
+    async fn matching<'a>(args: &[&str], objects: &'a [ObjectMeta]) -> Vec<&'a str> {
+        let find = reading(&["s3://bucket/data/"], args);
+        let filter = find.filter().await.unwrap();
+        objects
+            .iter()
+            .filter(|meta| filter.is_match(meta))
+            .map(|meta| meta.location.as_ref())
+            .collect()
+    }
+
+    /// Objects in `s3://bucket/data/` with these keys
+    fn keys(keys: &[&str]) -> Vec<ObjectMeta> {
+        keys.iter()
+            .map(|key| object(&format!("data/{key}")))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn url_match_joins_each_object_with_its_own_root() {
+        let find = reading(&["s3://a/x/", "gs://b/y"], &["--url-match", "^gs://b/y/"]);
+        let filter = find.filter().await.unwrap();
+        assert!(filter.passes_from(&object("y/k"), Some(1)));
+        assert!(!filter.passes_from(&object("y/k"), Some(0)));
+        // Without a root, there's no telling which store it's in
+        assert!(!filter.passes_from(&object("y/k"), None));
+
+        let find = reading(&["s3://a/x/"], &["--url-match", "^s3://a/x/k$"]);
+        let filter = find.filter().await.unwrap();
+        assert!(filter.passes_from(&object("x/k"), None));
+    }
+
+    #[tokio::test]
+    async fn fields_from_an_earlier_find_flow_through_a_later_one() {
+        let emitting = |fields: &str| {
+            let args = ["find", "--emit-fields", fields];
+            FindCommand::try_parse_from(args).unwrap().emit_fields
+        };
+        let first = reading(
+            &["s3://a/x/"],
+            &[
+                "--path-match",
+                "x/dataset=([^/]+)/.*",
+                "--captures",
+                "dataset",
+            ],
+        );
+        let filter = first.filter().await.unwrap();
+        let found = Found::new(None, object("x/dataset=logs/part-1.csv"));
+        let fields = emitting("basename,depth");
+        let line = serde_json::to_string(&found.export(&filter, &fields)).unwrap();
+
+        // As the next find in the pipe reads it from stdin
+        let second = reading(&["s3://a/x/"], &[]);
+        let filter = second.filter().await.unwrap();
+        let found = second.listed(serde_json::from_str(&line).unwrap()).unwrap();
+        let fields = emitting("extension");
+        let written = serde_json::to_value(found.export(&filter, &fields)).unwrap();
+        assert_eq!(written["basename"], "part-1.csv");
+        assert_eq!(written["dataset"], "logs");
+        assert_eq!(written["depth"], 2);
+        assert_eq!(written["extension"], "csv");
+    }
+
+    #[tokio::test]
+    async fn globs_match_keys_below_the_root() {
+        let objects = keys(&["a.gz", "logs/a.gz", "logs/2024/01/a.gz", "logs/a.csv"]);
+        assert_eq!(
+            passing(&["--glob", "logs/**/*.gz"], &objects).await,
+            ["data/logs/a.gz", "data/logs/2024/01/a.gz"]
+        );
+        // The root's own path isn't part of the key
+        assert!(passing(&["--glob", "data/**"], &objects).await.is_empty());
+        assert_eq!(
+            passing(&["--glob", "*.gz", "--glob", "logs/*.csv"], &objects).await,
+            ["data/a.gz", "data/logs/a.csv"]
+        );
+        assert_eq!(
+            passing(&["--glob", "**/*.gz", "--path-match", "2024"], &objects).await,
+            ["data/logs/2024/01/a.gz"]
+        );
+    }
+
+    fn sized(key: &str, size: usize) -> ObjectMeta {
+        ObjectMeta {
+            size,
+            ..object(&format!("data/{key}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn min_and_max_sizes_take_units() {
+        let objects = [
+            sized("small", 999),
+            sized("kilo", 1000),
+            sized("kibi", 1024),
+            sized("big", 1 << 20),
+        ];
+        assert_eq!(
+            passing(&["--min-size", "1kB"], &objects).await,
+            ["data/kilo", "data/kibi", "data/big"]
+        );
+        assert_eq!(
+            passing(&["--min-size", "1KiB", "--max-size", "0.5MiB"], &objects).await,
+            ["data/kibi"]
+        );
+        assert!(Find::try_parse_from(["find", "--min-size", "lots"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn zero_sizes_and_empty_objects() {
+        let objects = [sized("empty", 0), sized("byte", 1), sized("big", 1 << 20)];
+        // Every size is at least nothing
+        assert_eq!(
+            passing(&["--min-size", "0"], &objects).await,
+            ["data/empty", "data/byte", "data/big"]
+        );
+        assert_eq!(
+            passing(&["--max-size", "0"], &objects).await,
+            ["data/empty"]
+        );
+        assert_eq!(passing(&["--empty"], &objects).await, ["data/empty"]);
+        assert_eq!(
+            passing(&["--non-empty"], &objects).await,
+            ["data/byte", "data/big"]
+        );
+        assert_eq!(
+            passing(&["--empty", "--min-size", "0"], &objects).await,
+            ["data/empty"]
+        );
+        let contradiction = reading(&["s3://bucket/data/"], &["--empty", "--min-size", "1"]);
+        assert!(contradiction.filter().await.is_err());
+        assert!(Find::try_parse_from(["find", "--empty", "--non-empty"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn excludes_apply_after_everything_else() {
+        let objects = keys(&["a.csv", "a.csv.tmp", "b.json", "_tmp/c.csv"]);
+        assert_eq!(
+            passing(&["--exclude", r"\.tmp$", "--exclude", "/_tmp/"], &objects).await,
+            ["data/a.csv", "data/b.json"]
+        );
+        // --not inverts the other filters, but what's excluded stays out
+        assert_eq!(
+            passing(
+                &["--path-match", "csv", "--not", "--exclude", r"\.tmp$"],
+                &objects
+            )
+            .await,
+            ["data/b.json"]
+        );
+        assert_eq!(
+            passing(
+                &["--path-match", "json", "--any", "--exclude", "b"],
+                &objects
+            )
+            .await,
+            Vec::<&str>::new()
+        );
+    }
+
+    /// Save a listing of objects at these locations under a root, for `--in` and `--not-in`
+    async fn reference(path: &std::path::Path, root: &str, locations: &[&str]) -> String {
+        let preamble = Preamble::new(Url::parse(root).unwrap());
+        let writer = StdoutWriter::save(&preamble, &ListingPath::File(path.to_path_buf()))
+            .await
+            .unwrap();
+        for location in locations {
+            let export: ObjectExport = object(location).into();
+            writer.write(export).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn in_and_not_in_listings_intersect_and_subtract() {
+        let dir = std::env::temp_dir().join(format!("obvious3-find-in-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let root = "s3://bucket/data/";
+        let old = ["data/a.csv", "data/b.csv", "data/c.csv"];
+        let old = reference(&dir.join("old.ndjson"), root, &old).await;
+        let done = ["data/b.csv", "data/d.csv"];
+        let done = reference(&dir.join("done.ndjson"), root, &done).await;
+        let objects = keys(&["a.csv", "b.csv", "c.csv", "d.csv", "e.csv"]);
+
+        assert_eq!(
+            passing(&["--in", &old], &objects).await,
+            ["data/a.csv", "data/b.csv", "data/c.csv"]
+        );
+        assert_eq!(
+            passing(&["--not-in", &done], &objects).await,
+            ["data/a.csv", "data/c.csv", "data/e.csv"]
+        );
+        // In the first listing, but not the second
+        assert_eq!(
+            passing(&["--in", &old, "--not-in", &done], &objects).await,
+            ["data/a.csv", "data/c.csv"]
+        );
+        assert_eq!(
+            passing(&["--in", &old, "--not-in", &done, "--any"], &objects).await,
+            ["data/a.csv", "data/b.csv", "data/c.csv", "data/e.csv"]
+        );
+        // Joining on sizes too, nothing is the same as the empty objects listed
+        let sized = ["--in", &old, "--join-fields", "size"];
+        let mut bigger = keys(&["a.csv", "b.csv"]);
+        bigger[1].size = 10;
+        assert_eq!(passing(&sized, &bigger).await, ["data/a.csv"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn versioned(key: &str, version: Option<&str>) -> ObjectMeta {
+        ObjectMeta {
+            version: version.map(str::to_string),
+            ..object(&format!("data/{key}"))
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_on_versions() {
+        let objects = [
+            versioned("old", Some("3HL4kqtJlcpXroDTDmJ")),
+            versioned("new", Some("null")),
+            versioned("plain", None),
+        ];
+        assert_eq!(
+            passing(&["--has-version"], &objects).await,
+            ["data/old", "data/new"]
+        );
+        assert_eq!(passing(&["--no-version"], &objects).await, ["data/plain"]);
+        assert_eq!(
+            passing(&["--has-version", "--not"], &objects).await,
+            ["data/plain"]
+        );
+        assert_eq!(
+            passing(&["--version-match", "^3HL"], &objects).await,
+            ["data/old"]
+        );
+        // Objects without a version don't match, even a pattern that matches anything
+        assert_eq!(
+            passing(&["--version-match", ""], &objects).await,
+            ["data/old", "data/new"]
+        );
+        assert!(Find::try_parse_from(["find", "--has-version", "--no-version"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn dir_markers_can_be_left_out_or_picked_out() {
+        let objects = [
+            sized("logs_$folder$", 0),
+            sized("logs/a.csv", 0),
+            sized("logs/b.csv", 5),
+        ];
+        assert_eq!(
+            passing(&["--skip-dir-markers"], &objects).await,
+            ["data/logs/a.csv", "data/logs/b.csv"]
+        );
+        assert_eq!(
+            passing(&["--only-dir-markers"], &objects).await,
+            ["data/logs_$folder$"]
+        );
+        // --not inverts the other filters, not these
+        assert_eq!(
+            passing(&["--skip-dir-markers", "--non-empty", "--not"], &objects).await,
+            ["data/logs/a.csv"]
+        );
+    }
+
+    /// A memory store with these keys below `memory:///data/`, and the root
+    async fn stocked(keys: &[&str]) -> Root {
+        let root = Root::open(&Url::parse("memory:///data/").unwrap()).unwrap();
+        for key in keys {
+            let location = ObjectStorePath::from(format!("data/{key}"));
+            root.store.put(&location, "x".into()).await.unwrap();
+        }
+        root
+    }
+
+    async fn listed(find: &Find, root: &Root) -> Vec<String> {
+        let objects: Vec<_> = find.objects(Some(root)).try_collect().await.unwrap();
+        objects
+            .iter()
+            .map(|meta| meta.location.to_string())
+            .collect()
+    }
+
+    /// The same as [`listed`], as `find --ordered` lists them
+    async fn listed_in_order(find: &Find, root: &Root) -> Vec<String> {
+        let objects = find.objects_in_order(Some(root), true);
+        let objects: Vec<_> = objects.try_collect().await.unwrap();
+        objects
+            .iter()
+            .map(|meta| meta.location.to_string())
+            .collect()
+    }
+
+    /// What a whole `find` run printed, and how it would exit
+    struct Printed {
+        keys: Vec<String>,
+        /// Everything printed about each match, like the fields added to it
+        exported: Vec<ObjectExport>,
+        /// The line `--count` printed instead of a listing
+        counted: Option<String>,
+        counters: Counters,
+        exit: Option<i32>,
+    }
+
+    /// Run `find` with these flags over `root`, or over these objects as if they were read from
+    /// a listing of it on stdin
+    async fn printed(
+        args: &[&str],
+        root: &Root,
+        stdin: Option<Vec<Result<ObjectMeta>>>,
+    ) -> Result<Printed> {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let global_args = Args::try_parse_from(["obvious3", "find"].iter().chain(args))?;
+        let crate::IOAction::Find(command) = &global_args.cmd else {
+            unreachable!("parsed as find");
+        };
+        let preamble = Preamble::with_roots(vec![root.url.clone()]);
+        let _ = command.find.root_paths.set(root_paths(&preamble)?);
+        let _ = command.find.root_urls.set(preamble.roots().to_vec());
+        let (filter, checks) = command.start(&global_args).await?;
+
+        let counters = Counters::default();
+        let roots = std::slice::from_ref(root);
+        let matches = match stdin {
+            None => command.matches(&filter, &checks, roots, &[], 4, &counters),
+            Some(objects) => {
+                let objects = objects
+                    .into_iter()
+                    .map(|meta| meta.map(|meta| Found::new(None, meta)));
+                let listed = futures::stream::iter(objects).boxed();
+                command.matches_of(listed, &filter, &checks, roots, 4, &counters)
+            }
+        };
+        if command.count {
+            let counted = command.tally(matches, 4, &counters).await?;
+            return Ok(Printed {
+                keys: vec![],
+                exported: vec![],
+                counted: Some(counted),
+                exit: command.failure(&counters).map(|(code, _)| code),
+                counters,
+            });
+        }
+        let run = RUNS.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!(
+            "obvious3-find-printed-{}-{run}.ndjson",
+            std::process::id()
+        ));
+        let output = ListingPath::File(path.clone());
+        let writer = StdoutWriter::save(&preamble, &output).await?;
+        let result = command.print(matches, &filter, &writer, 4, &counters).await;
+        writer.finish().await?;
+        result?;
+
+        let mut lines = listing::read_lines(&output).await?;
+        // Past the preamble
+        lines.try_next().await?;
+        let exported: Vec<ObjectExport> = lines
+            .map(|line| -> Result<ObjectExport> { Ok(serde_json::from_str(&line?)?) })
+            .try_collect()
+            .await?;
+        std::fs::remove_file(&path)?;
+        let exit = command.failure(&counters).map(|(code, _)| code);
+        Ok(Printed {
+            keys: exported
+                .iter()
+                .map(|export| export.location.clone())
+                .collect(),
+            exported,
+            counted: None,
+            counters,
+            exit,
+        })
+    }
+
+    #[tokio::test]
+    async fn depth_counts_segments_below_the_root() {
+        let find = reading(&["s3://bucket/data/"], &["--max-depth", "1"]);
+        assert_eq!(find.depth(&ObjectStorePath::from("data")), 0);
+        assert_eq!(find.depth(&ObjectStorePath::from("data/a.csv")), 1);
+        assert_eq!(find.depth(&ObjectStorePath::from("data/2024/01/a.csv")), 3);
+        // From stdin, the deeper objects are left out after the fact
+        assert!(find.within_depth(&object("data/a.csv")));
+        assert!(!find.within_depth(&object("data/2024/a.csv")));
+
+        let objects = keys(&["a.csv", "2024/a.csv", "2024/01/a.csv"]);
+        assert_eq!(
+            passing(&["--min-depth", "2"], &objects).await,
+            ["data/2024/a.csv", "data/2024/01/a.csv"]
+        );
+    }
+
+    #[tokio::test]
+    async fn max_depth_lists_only_so_far_down() {
+        let root = stocked(&["a.csv", "2024/a.csv", "2024/01/a.csv", "2025/b.csv"]).await;
+        let find = |depth: &str| Find::try_parse_from(["find", "--max-depth", depth]).unwrap();
+        assert_eq!(listed(&find("1"), &root).await, ["data/a.csv"]);
+        assert_eq!(
+            listed(&find("2"), &root).await,
+            ["data/a.csv", "data/2024/a.csv", "data/2025/b.csv"]
+        );
+        assert_eq!(listed(&find("5"), &root).await.len(), 4);
+
+        // Depth 0 is the root itself, if it's an object
+        assert!(listed(&find("0"), &root).await.is_empty());
+        let file = Root::open(&Url::parse("memory:///data/a.csv").unwrap()).unwrap();
+        file.store.put(&file.path, "x".into()).await.unwrap();
+        assert_eq!(listed(&find("0"), &file).await, ["data/a.csv"]);
+    }
+
+    #[tokio::test]
+    async fn negative_filters_combine_with_positive_ones() {
+        let objects = [
+            sized("logs/a.csv", 10),
+            sized("logs/big.csv", 5000),
+            sized("logs/a.json", 10),
+            sized("tmp/a.csv", 10),
+        ];
+        // Matches this regex but isn't bigger than 1KB
+        assert_eq!(
+            passing(&["--path-match", "csv", "--max-size", "1KB"], &objects).await,
+            ["data/logs/a.csv", "data/tmp/a.csv"]
+        );
+        assert_eq!(
+            passing(
+                &["--path-match", "csv", "--path-not-match", "tmp/"],
+                &objects
+            )
+            .await,
+            ["data/logs/a.csv", "data/logs/big.csv"]
+        );
+        assert_eq!(
+            passing(&["--basename-not-match", "^a"], &objects).await,
+            ["data/logs/big.csv"]
+        );
+        // --not flips the negative filter back, along with the rest
+        assert_eq!(
+            passing(&["--path-not-match", "tmp/", "--not"], &objects).await,
+            ["data/tmp/a.csv"]
+        );
+        assert_eq!(
+            passing(
+                &["--path-match", "csv", "--path-not-match", "tmp/", "--not"],
+                &objects
+            )
+            .await,
+            ["data/logs/a.json", "data/tmp/a.csv"]
+        );
+        // With --any, either one is enough
+        assert_eq!(
+            passing(
+                &[
+                    "--path-match",
+                    "json",
+                    "--basename-not-match",
+                    "^a",
+                    "--any"
+                ],
+                &objects
+            )
+            .await,
+            ["data/logs/big.csv", "data/logs/a.json"]
+        );
+    }
+
+    #[tokio::test]
+    async fn ignore_case_applies_to_every_pattern() {
+        let objects = keys(&["Logs/A.CSV", "logs/b.csv", "Logs/c.json"]);
+        assert_eq!(
+            passing(&["--path-match", "logs/"], &objects).await,
+            ["data/logs/b.csv"]
+        );
+        assert_eq!(
+            passing(&["-i", "--path-match", "logs/.*csv"], &objects).await,
+            ["data/Logs/A.CSV", "data/logs/b.csv"]
+        );
+        assert_eq!(
+            passing(&["-i", "--basename-match", "^a"], &objects).await,
+            ["data/Logs/A.CSV"]
+        );
+        assert_eq!(
+            passing(&["--ignore-case", "--glob", "logs/*.csv"], &objects).await,
+            ["data/Logs/A.CSV", "data/logs/b.csv"]
+        );
+        assert_eq!(
+            passing(&["-i", "--path-not-match", "LOGS/"], &objects).await,
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            passing(&["--ext", "csv"], &objects).await,
+            ["data/Logs/A.CSV", "data/logs/b.csv"]
+        );
+    }
+
+    #[tokio::test]
+    async fn inline_flags_win_over_ignore_case() {
+        let objects = keys(&["Logs/A.CSV", "logs/b.csv", "LOGS/c.csv"]);
+        assert_eq!(
+            passing(&["-i", "--path-match", "(?-i:Logs)/"], &objects).await,
+            ["data/Logs/A.CSV"]
+        );
+        // Only the group is case sensitive, the rest still ignores case
+        assert_eq!(
+            passing(&["-i", "--path-match", "(?-i:Logs)/a"], &objects).await,
+            ["data/Logs/A.CSV"]
+        );
+        assert_eq!(
+            passing(&["-i", "--path-match", "(?-i)logs/"], &objects).await,
+            ["data/logs/b.csv"]
+        );
+        // And (?i) works without the flag
+        assert_eq!(
+            passing(&["--path-match", "(?i)logs/c"], &objects).await,
+            ["data/LOGS/c.csv"]
+        );
+    }
+
+    #[tokio::test]
+    async fn size_bounds_are_exclusive_like_gnu_find() {
+        let objects = [
+            sized("empty", 0),
+            sized("under", 1023),
+            sized("kib", 1024),
+            sized("over", 1025),
+            sized("mib", 1 << 20),
+        ];
+        assert_eq!(
+            passing(&["--size", "+1k"], &objects).await,
+            ["data/over", "data/mib"]
+        );
+        assert_eq!(
+            passing(&["--size", "-1k"], &objects).await,
+            ["data/empty", "data/under"]
+        );
+        assert_eq!(passing(&["--size", "1k"], &objects).await, ["data/kib"]);
+        assert_eq!(passing(&["--size", "0"], &objects).await, ["data/empty"]);
+        assert_eq!(
+            passing(&["--size", "+1023c", "--size", "-1M"], &objects).await,
+            ["data/kib", "data/over"]
+        );
+        // Longer units mean what they do elsewhere
+        assert_eq!(
+            passing(&["--size", "+1kB", "--size", "-1KiB"], &objects).await,
+            ["data/under"]
+        );
+    }
+
+    #[tokio::test]
+    async fn size_bounds_that_contradict_are_refused() {
+        let compiles = |args: &[&str]| reading(&["s3://bucket/data/"], args);
+        for args in [
+            &["--size", "-0"][..],
+            &["--size", "+1k", "--size", "-1k"],
+            &["--size", "+1k", "--size", "1k"],
+            &["--size", "-1k", "--min-size", "1KiB"],
+            &["--size", "+1k", "--max-size", "1KiB"],
+        ] {
+            assert!(compiles(args).filter().await.is_err(), "{args:?}");
+        }
+        // Touching but not overlapping ranges are fine
+        assert!(compiles(&["--size", "-1k", "--min-size", "1023"])
+            .filter()
+            .await
+            .is_ok());
+        // And with --any, only one of them has to pass
+        assert!(compiles(&["--size", "-1k", "--min-size", "1MiB", "--any"])
+            .filter()
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn full_match_anchors_both_ends() {
+        let objects = keys(&["data", "mydata_old.csv", "logs/data"]);
+        assert_eq!(
+            passing(&["--basename-match", "data"], &objects).await,
+            ["data/data", "data/mydata_old.csv", "data/logs/data"]
+        );
+        assert_eq!(
+            passing(&["--basename-match", "data", "--full-match"], &objects).await,
+            ["data/data", "data/logs/data"]
+        );
+        // Path patterns have to match the whole location, root and all
+        assert_eq!(
+            passing(&["--path-match", "data", "--full-match"], &objects).await,
+            Vec::<&str>::new()
+        );
+        assert_eq!(
+            passing(&["--path-match", "data/.*data", "--full-match"], &objects).await,
+            ["data/data", "data/logs/data"]
+        );
+        // Alternatives are grouped before anchoring, so each one is anchored
+        assert_eq!(
+            passing(
+                &["--basename-match", "data|.*old.*", "--full-match"],
+                &objects
+            )
+            .await,
+            ["data/data", "data/mydata_old.csv", "data/logs/data"]
+        );
+        assert_eq!(
+            passing(&["--basename-match", "data|old", "--full-match"], &objects).await,
+            ["data/data", "data/logs/data"]
+        );
+    }
+
+    #[tokio::test]
+    async fn full_match_leaves_explicit_anchors_working() {
+        let objects = keys(&["data", "mydata_old.csv"]);
+        for pattern in ["^data$", "^data", "data$", "^(?:data)$"] {
+            assert_eq!(
+                passing(&["--basename-match", pattern, "--full-match"], &objects).await,
+                ["data/data"],
+                "{pattern}"
+            );
+        }
+        assert_eq!(
+            passing(&["--basename-match", "^my.*", "--full-match"], &objects).await,
+            ["data/mydata_old.csv"]
+        );
+        assert_eq!(
+            passing(
+                &["--basename-not-match", "^data$", "--full-match"],
+                &objects
+            )
+            .await,
+            ["data/mydata_old.csv"]
+        );
+    }
+
+    #[tokio::test]
+    async fn sharded_listings_find_the_same_objects() {
+        let root = stocked(&[
+            "a.csv",
+            "a/1.csv",
+            "a/x/2.csv",
+            "a0",
+            "b/y/3.csv",
+            "b/y/4.csv",
+            "b/z/5.csv",
+            "c",
+            "d/6.csv",
+        ])
+        .await;
+        let find = |args: &[&str]| Find::try_parse_from(["find"].iter().chain(args)).unwrap();
+        let plain = listed(&find(&[]), &root).await;
+        assert_eq!(plain.len(), 9);
+        for depth in ["1", "2", "3"] {
+            let sharded = ["--parallel-prefixes", "2", "--shard-depth", depth];
+            let mut unordered = listed(&find(&sharded), &root).await;
+            unordered.sort();
+            assert_eq!(unordered, plain, "--shard-depth {depth}");
+
+            // Everything under a/ still comes between a.csv and a0
+            assert_eq!(
+                listed_in_order(&find(&sharded), &root).await,
+                plain,
+                "--shard-depth {depth}"
+            );
+        }
+        let resumed = ["--parallel-prefixes", "3", "--start-after", "data/a0"];
+        assert_eq!(listed_in_order(&find(&resumed), &root).await, plain[4..]);
+    }
+
+    /// Which of these objects, modified this many minutes after noon, `--newest-within` keeps
+    async fn newest(args: &[&str], minutes: &[i64]) -> Result<Vec<String>> {
+        let find = FindCommand::try_parse_from(["find"].iter().chain(args)).unwrap();
+        let noon = "2024-06-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let matches = minutes.iter().enumerate().map(|(i, &minutes)| {
+            let meta = ObjectMeta {
+                last_modified: noon + chrono::Duration::minutes(minutes),
+                ..object(&format!("data/{i}"))
+            };
+            Ok(Found::new(None, meta))
+        });
+        let kept: Vec<_> = find
+            .newest_within(futures::stream::iter(matches).boxed())
+            .try_collect()
+            .await?;
+        Ok(kept
+            .iter()
+            .map(|found| found.meta.location.to_string())
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn newest_within_counts_back_from_the_newest_match() {
+        let within = ["--newest-within", "1h"];
+        // The newest can turn up anywhere, and the bound is inclusive
+        assert_eq!(
+            newest(&within, &[0, 150, 90, 60, 89]).await.unwrap(),
+            ["data/1", "data/2"]
+        );
+        assert_eq!(
+            newest(&within, &[0, 60, 59]).await.unwrap(),
+            ["data/0", "data/1", "data/2"]
+        );
+        // Ties with the newest are all kept, even when nothing else is
+        assert_eq!(
+            newest(&["--newest-within", "1s"], &[0, 120, 120, 30])
+                .await
+                .unwrap(),
+            ["data/1", "data/2"]
+        );
+        assert!(newest(&within, &[]).await.unwrap().is_empty());
+        assert_eq!(newest(&[], &[0, 150]).await.unwrap(), ["data/0", "data/1"]);
+    }
+
+    #[tokio::test]
+    async fn newest_within_holds_at_most_sort_max() {
+        let args = ["--newest-within", "1h", "--sort-max", "3"];
+        assert_eq!(newest(&args, &[0, 1, 2]).await.unwrap().len(), 3);
+        assert!(newest(&args, &[0, 1, 2, 3]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn missing_etags_can_be_picked_out_or_left_out() {
+        let tagged = |key: &str, e_tag: Option<&str>| ObjectMeta {
+            e_tag: e_tag.map(str::to_string),
+            ..object(&format!("data/{key}"))
+        };
+        let objects = [
+            tagged("tagged", Some("\"abc\"")),
+            tagged("none", None),
+            tagged("empty", Some("")),
+            tagged("quotes", Some("\"\"")),
+        ];
+        assert_eq!(
+            passing(&["--missing-etag"], &objects).await,
+            ["data/none", "data/empty", "data/quotes"]
+        );
+        // With --not, only objects that have one
+        assert_eq!(
+            passing(&["--missing-etag", "--not"], &objects).await,
+            ["data/tagged"]
+        );
+        assert!(Find::try_parse_from(["find", "--missing-etag", "--etag", "abc"]).is_err());
+
+        let objects = [versioned("old", Some("3HL4")), versioned("plain", None)];
+        assert_eq!(
+            passing(&["--missing-version"], &objects).await,
+            ["data/plain"]
+        );
+        assert_eq!(
+            passing(&["--missing-version", "--not"], &objects).await,
+            ["data/old"]
+        );
+    }
+
+    #[test]
+    fn only_a_path_match_every_object_passes_is_passed_on() {
+        let reused = |args: &[&str]| {
+            Find::try_parse_from(["find"].iter().chain(args))
+                .unwrap()
+                .reusable_path_match()
+        };
+        assert_eq!(reused(&["--path-match", "a(.)"]).as_deref(), Some("a(.)"));
+        assert_eq!(
+            reused(&["--path-match", "a|b", "--full-match"]).as_deref(),
+            Some("^(?:a|b)$")
+        );
+        assert_eq!(
+            reused(&["--path-match", "a", "--min-size", "1"]).as_deref(),
+            Some("a")
+        );
+        assert_eq!(reused(&[]), None);
+        assert_eq!(reused(&["--path-match", "a", "--path-match", "b"]), None);
+        assert_eq!(reused(&["--path-match", "a", "--not"]), None);
+        assert_eq!(reused(&["--path-match", "a", "--size", "0", "--any"]), None);
+        assert_eq!(reused(&["--path-match", "a", "-i"]), None);
+    }
+
+    #[tokio::test]
+    async fn fail_if_empty_counts_only_what_is_printed() {
+        let root = stocked(&["kept.csv"]).await;
+        let args = ["--verify", "--fail-if-empty"];
+        let gone = vec![Ok(object("data/gone.csv"))];
+        let run = printed(&args, &root, Some(gone)).await.unwrap();
+        assert!(run.keys.is_empty());
+        assert_eq!(run.counters.matched.load(Ordering::Relaxed), 0);
+        assert_eq!(run.exit, Some(EMPTY_EXIT_CODE));
+
+        let kept = vec![Ok(object("data/kept.csv"))];
+        let run = printed(&args, &root, Some(kept)).await.unwrap();
+        assert_eq!(run.keys, ["data/kept.csv"]);
+        assert_eq!(run.counters.matched.load(Ordering::Relaxed), 1);
+        assert_eq!(run.counters.bytes.load(Ordering::Relaxed), 1);
+        assert_eq!(run.exit, None);
+
+        // Every object here has its own etag, so none of them are duplicates to count
+        let root = stocked(&["a.csv", "b.csv"]).await;
+        let args = ["--only-duplicates", "--fail-if-empty"];
+        let run = printed(&args, &root, None).await.unwrap();
+        assert!(run.keys.is_empty());
+        assert_eq!(run.exit, Some(EMPTY_EXIT_CODE));
+    }
+
+    #[tokio::test]
+    async fn limit_counts_only_objects_that_verify() {
+        let root = stocked(&["a.csv", "b.csv", "c.csv"]).await;
+        let stdin = ["gone.csv", "a.csv", "also-gone.csv", "b.csv", "c.csv"]
+            .map(|key| Ok(object(&format!("data/{key}"))))
+            .into();
+        let args = ["--verify", "--limit", "2", "--ordered"];
+        let run = printed(&args, &root, Some(stdin)).await.unwrap();
+        assert_eq!(run.keys, ["data/a.csv", "data/b.csv"]);
+    }
+
+    #[tokio::test]
+    async fn where_must_pass_along_with_the_other_filters() {
+        let objects = [
+            ("data/big.csv", 100),
+            ("data/small.csv", 1),
+            ("data/big.json", 100),
+        ]
+        .map(|(location, size)| ObjectMeta {
+            size,
+            ..object(location)
+        });
+        let args = ["--where", "size > 10", "--basename-match", r".*\.csv"];
+        assert_eq!(passing(&args, &objects).await, ["data/big.csv"]);
+        let args = [
+            "--where",
+            "size < 10 || basename =~ \"json\"",
+            "--path-match",
+            "data/[a-z]+\\.csv",
+        ];
+        assert_eq!(passing(&args, &objects).await, ["data/small.csv"]);
+        // With --any, it's one more alternative instead
+        let args = [
+            "--where",
+            "size < 10",
+            "--basename-match",
+            r".*\.json",
+            "--any",
+        ];
+        assert_eq!(
+            passing(&args, &objects).await,
+            ["data/small.csv", "data/big.json"]
+        );
+    }
+
+    #[tokio::test]
+    async fn listings_join_up_only_within_the_same_store() {
+        let dir = std::env::temp_dir().join(format!("obvious3-find-stores-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let same = reference(&dir.join("same.ndjson"), "s3://bucket/", &["data/a.csv"]).await;
+        let other = reference(
+            &dir.join("other.ndjson"),
+            "s3://other/data/",
+            &["data/a.csv"],
+        )
+        .await;
+        let objects = keys(&["a.csv"]);
+        assert_eq!(passing(&["--in", &same], &objects).await, ["data/a.csv"]);
+        assert!(passing(&["--in", &other], &objects).await.is_empty());
+        assert_eq!(
+            passing(&["--not-in", &other], &objects).await,
+            ["data/a.csv"]
+        );
+        // Keys below each root still line up across stores
+        let relative = ["--in", &other, "--join-on", "relative"];
+        assert_eq!(passing(&relative, &objects).await, ["data/a.csv"]);
+
+        // Without knowing which store each object listed is in, there's nothing to match
+        let mixed = dir.join("mixed.ndjson");
+        let roots = ["s3://bucket/data/", "s3://other/data/"].map(|url| Url::parse(url).unwrap());
+        let writer: StdoutWriter = StdoutWriter::save(
+            &Preamble::with_roots(roots.to_vec()),
+            &ListingPath::File(mixed.clone()),
+        )
+        .await
+        .unwrap();
+        writer.finish().await.unwrap();
+        let find = reading(&["s3://bucket/data/"], &["--in", mixed.to_str().unwrap()]);
+        let error = find
+            .filter()
+            .await
+            .err()
+            .expect("several stores should be refused");
+        assert!(error.to_string().contains("several stores"), "{error}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn shuffles_the_same_way_for_the_same_seed() {
+        let keys: Vec<_> = (0..20).map(|i| format!("{i:02}.csv")).collect();
+        let root = stocked(&keys.iter().map(String::as_str).collect::<Vec<_>>()).await;
+        let shuffled = |seed: &'static str| {
+            let root = &root;
+            async move {
+                let args = ["--shuffle", "--seed", seed];
+                printed(&args, root, None).await.unwrap().keys
+            }
+        };
+        let first = shuffled("7").await;
+        assert_eq!(shuffled("7").await, first);
+        let other = shuffled("8").await;
+        assert_ne!(other, first);
+
+        let listed: Vec<_> = keys.iter().map(|key| format!("data/{key}")).collect();
+        assert_ne!(first, listed, "a shuffle of 20 should move something");
+        for mut order in [first, other] {
+            order.sort();
+            assert_eq!(order, listed);
+        }
+        // A window shuffles as it goes, and still prints everything once
+        let args = ["--shuffle", "--shuffle-window", "3", "--seed", "7"];
+        let mut windowed = printed(&args, &root, None).await.unwrap().keys;
+        windowed.sort();
+        assert_eq!(windowed, listed);
+    }
+
+    #[tokio::test]
+    async fn storage_classes_never_match_where_the_store_reports_none() {
+        let objects = keys(&["a.csv", "b.csv"]);
+        let glacier = ["--storage-class", "GLACIER", "--storage-class", "standard"];
+        assert!(passing(&glacier, &objects).await.is_empty());
+        assert_eq!(passing(&[], &objects).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn limit_counts_only_what_passes_and_stops_the_listing() {
+        let objects = keys(&["a.csv", "b.json", "c.csv", "d.json", "e.json"]);
+        assert_eq!(
+            matching(&["--limit", "2"], &objects).await,
+            ["data/a.csv", "data/b.json"]
+        );
+        assert_eq!(
+            matching(&["--limit", "2", "--ext", "json"], &objects).await,
+            ["data/b.json", "data/d.json"]
+        );
+        assert!(matching(&["--limit", "0"], &objects).await.is_empty());
+
+        let root = stocked(&["a.csv", "b.csv", "c.csv", "d.csv"]).await;
+        let run = printed(&["--limit", "2"], &root, None).await.unwrap();
+        assert_eq!(run.keys, ["data/a.csv", "data/b.csv"]);
+        // Nothing after the second match is even looked at
+        assert_eq!(run.counters.scanned.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn unique_leaves_out_repeats_by_what_identifies_them() {
+        let objects = [
+            ("data/a.csv", Some("1")),
+            ("data/a.csv", Some("2")),
+            ("data/b.csv", Some("\"1\"")),
+            ("data/c.csv", None),
+            ("data/c.csv", None),
+        ]
+        .map(|(location, e_tag)| ObjectMeta {
+            e_tag: e_tag.map(str::to_string),
+            ..object(location)
+        });
+        assert_eq!(
+            matching(&["--unique"], &objects).await,
+            ["data/a.csv", "data/b.csv", "data/c.csv"]
+        );
+        // Quotes don't make an etag different, and objects without one are never repeats
+        assert_eq!(
+            matching(&["--unique", "--unique-by", "etag"], &objects).await,
+            ["data/a.csv", "data/a.csv", "data/c.csv", "data/c.csv"]
+        );
+        assert_eq!(
+            matching(&["--unique", "--unique-by", "location+etag"], &objects).await,
+            [
+                "data/a.csv",
+                "data/a.csv",
+                "data/b.csv",
+                "data/c.csv",
+                "data/c.csv"
+            ]
+        );
+
+        let find = reading(&["s3://bucket/data/"], &["--unique"]);
+        let filter = find.filter().await.unwrap();
+        let kept = objects.iter().filter(|meta| filter.is_match(meta)).count();
+        assert_eq!((kept, filter.repeated()), (3, 2));
+    }
+
+    #[tokio::test]
+    async fn sample_rate_keeps_about_that_share_the_same_way_each_seed() {
+        let names: Vec<_> = (0..1000).map(|i| format!("{i:04}.csv")).collect();
+        let objects = keys(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        let sampled = |seed: &'static str| {
+            let objects = &objects;
+            async move { matching(&["--sample-rate", "0.1", "--seed", seed], objects).await }
+        };
+        let first = sampled("7").await;
+        assert!((50..150).contains(&first.len()), "{}", first.len());
+        assert_eq!(sampled("7").await, first);
+        assert_ne!(sampled("8").await, first);
+        assert_eq!(
+            matching(&["--sample-rate", "1"], &objects).await.len(),
+            1000
+        );
+
+        for rate in ["0", "1.5", "-0.1", "half"] {
+            let args = ["find", "--sample-rate", rate];
+            assert!(Find::try_parse_from(args).is_err(), "{rate}");
+        }
+    }
+
+    #[tokio::test]
+    async fn per_prefix_limit_keeps_a_few_from_each_directory() {
+        let objects = keys(&[
+            "a/1.csv",
+            "a/2.csv",
+            "a/3.csv",
+            "b/1.csv",
+            "b/2.csv",
+            "top.csv",
+            "x/y/1.csv",
+            "x/y/2.csv",
+            "x/z/1.csv",
+        ]);
+        let find = reading(&["s3://bucket/data/"], &["--per-prefix-limit", "1"]);
+        let filter = find.filter().await.unwrap();
+        let kept: Vec<_> = objects
+            .iter()
+            .filter(|meta| filter.is_match(meta))
+            .map(|meta| meta.location.as_ref())
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                "data/a/1.csv",
+                "data/b/1.csv",
+                "data/top.csv",
+                "data/x/y/1.csv",
+                "data/x/z/1.csv"
+            ]
+        );
+        assert_eq!(filter.over_quota(), (4, 5));
+
+        // Counted one segment down, x/y and x/z share a quota
+        let args = ["--per-prefix-limit", "2", "--prefix-depth", "1"];
+        assert_eq!(
+            matching(&args, &objects).await,
+            [
+                "data/a/1.csv",
+                "data/a/2.csv",
+                "data/b/1.csv",
+                "data/b/2.csv",
+                "data/top.csv",
+                "data/x/y/1.csv",
+                "data/x/y/2.csv"
+            ]
+        );
+        // Objects that don't pass don't use up the quota
+        let args = ["--per-prefix-limit", "1", "--basename-match", "2.csv"];
+        assert_eq!(
+            matching(&args, &objects).await,
+            ["data/a/2.csv", "data/b/2.csv", "data/x/y/2.csv"]
+        );
+    }
+
+    #[tokio::test]
+    async fn start_after_resumes_past_a_location() {
+        let root = stocked(&["a.csv", "b/1.csv", "b/2.csv", "c.csv"]).await;
+        let find = |args: &[&str]| Find::try_parse_from(["find"].iter().chain(args)).unwrap();
+        for start in ["data/b/1.csv", "/data/b/1.csv"] {
+            assert_eq!(
+                listed(&find(&["--start-after", start]), &root).await,
+                ["data/b/2.csv", "data/c.csv"],
+                "{start}"
+            );
+        }
+        // Between two objects is as good as on one
+        assert_eq!(
+            listed(&find(&["--start-after", "data/b"]), &root).await,
+            ["data/b/1.csv", "data/b/2.csv", "data/c.csv"]
+        );
+        let shallow = ["--max-depth", "1", "--start-after", "data/a.csv"];
+        assert_eq!(listed(&find(&shallow), &root).await, ["data/c.csv"]);
+
+        // Listings on stdin might not be sorted, so only what comes after it there is kept
+        let unsorted = || {
+            let objects = ["data/c.csv", "data/a.csv", "data/b/1.csv"];
+            futures::stream::iter(objects.map(|location| Ok(object(location))))
+        };
+        let after = |start: &str| {
+            let find = find(&["--start-after", start]);
+            async move {
+                let kept: Vec<_> = find
+                    .after_start(unsorted(), |meta| &meta.location)
+                    .try_collect()
+                    .await
+                    .unwrap();
+                kept.iter()
+                    .map(|meta| meta.location.to_string())
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert_eq!(after("data/a.csv").await, ["data/b/1.csv"]);
+        assert!(after("data/b").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn segments_match_by_position_from_either_end() {
+        let objects = keys(&[
+            "dataset=clicks/date=2024-01-01/a.parquet",
+            "dataset=clicks/date=2023-12-31/b.csv",
+            "dataset=views/date=2024-01-02/c.parquet",
+            "top.parquet",
+        ]);
+        assert_eq!(
+            passing(&["--segment", "1=dataset=clicks"], &objects).await,
+            [
+                "data/dataset=clicks/date=2024-01-01/a.parquet",
+                "data/dataset=clicks/date=2023-12-31/b.csv"
+            ]
+        );
+        // Every one has to match, and regexes aren't anchored
+        let args = ["--segment", "2~2024", "--segment", r"-1~\.parquet$"];
+        assert_eq!(
+            passing(&args, &objects).await,
+            [
+                "data/dataset=clicks/date=2024-01-01/a.parquet",
+                "data/dataset=views/date=2024-01-02/c.parquet"
+            ]
+        );
+        // Keys with too few segments never match, even from the end
+        assert!(passing(&["--segment", "3~."], &objects[3..])
+            .await
+            .is_empty());
+        assert!(passing(&["--segment", "-2~."], &objects[3..])
+            .await
+            .is_empty());
+        assert_eq!(
+            passing(&["--segment", "-1=top.parquet"], &objects).await,
+            ["data/top.parquet"]
+        );
+
+        for bad in ["0=x", "x=1", "2", "2:x"] {
+            let args = ["find", "--segment", bad];
+            assert!(Find::try_parse_from(args).is_err(), "{bad}");
+        }
+    }
+
+    #[tokio::test]
+    async fn extensions_match_the_end_of_the_basename() {
+        let objects = keys(&[
+            "a.csv",
+            "b.csv.gz",
+            "c.PARQUET",
+            "notcsv",
+            "d.csv/part-0",
+            "e.json",
+        ]);
+        assert_eq!(passing(&["--ext", "csv"], &objects).await, ["data/a.csv"]);
+        assert_eq!(passing(&["--ext", "gz"], &objects).await, ["data/b.csv.gz"]);
+        assert_eq!(
+            passing(&["--ext", ".csv.gz"], &objects).await,
+            ["data/b.csv.gz"]
+        );
+        assert_eq!(
+            passing(&["--ext", "parquet,json"], &objects).await,
+            ["data/c.PARQUET", "data/e.json"]
+        );
+        assert_eq!(
+            passing(&["--ext", "csv", "--ext", "Parquet"], &objects).await,
+            ["data/a.csv", "data/c.PARQUET"]
+        );
+    }
+
+    #[tokio::test]
+    async fn etags_match_without_their_quotes() {
+        let objects = [
+            ("data/quoted", Some("\"abc\"")),
+            ("data/bare", Some("abc")),
+            ("data/parts", Some("\"abd-3\"")),
+            ("data/none", None),
+        ]
+        .map(|(location, e_tag)| ObjectMeta {
+            e_tag: e_tag.map(str::to_string),
+            ..object(location)
+        });
+        for etag in ["abc", "\"abc\""] {
+            assert_eq!(
+                passing(&["--etag", etag], &objects).await,
+                ["data/quoted", "data/bare"],
+                "{etag}"
+            );
+        }
+        assert!(passing(&["--etag", "ab"], &objects).await.is_empty());
+        // Objects uploaded in parts have etags ending in how many there were
+        assert_eq!(
+            passing(&[r"--etag-match=-\d+$"], &objects).await,
+            ["data/parts"]
+        );
+        assert_eq!(
+            passing(&["--etag-match", "^ab"], &objects).await,
+            ["data/quoted", "data/bare", "data/parts"]
+        );
+        // Even a pattern that matches anything needs an etag to match
+        assert_eq!(passing(&["--etag-match", ""], &objects).await.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn empty_objects_are_found_listing_or_from_stdin() {
+        let root = stocked(&["full.csv"]).await;
+        let empty = ObjectStorePath::from("data/failed.csv");
+        root.store.put(&empty, "".into()).await.unwrap();
+        let run = printed(&["--empty"], &root, None).await.unwrap();
+        assert_eq!(run.keys, ["data/failed.csv"]);
+        let run = printed(&["--non-empty"], &root, None).await.unwrap();
+        assert_eq!(run.keys, ["data/full.csv"]);
+
+        let stdin: Vec<_> = root.store.list(None).map_err(Into::into).collect().await;
+        let run = printed(&["--empty"], &root, Some(stdin)).await.unwrap();
+        assert_eq!(run.keys, ["data/failed.csv"]);
+    }
+
+    #[tokio::test]
+    async fn manifests_are_joined_by_normalized_path() {
+        let dir =
+            std::env::temp_dir().join(format!("obvious3-find-manifest-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sums = dir.join("SHA256SUMS");
+        let (a, b) = ("AB".repeat(32), "cd".repeat(32));
+        std::fs::write(
+            &sums,
+            format!("{a}  ./a.csv\n\n{b} *sub\\b.csv\n{a}  old.csv\n"),
+        )
+        .unwrap();
+        let command =
+            FindCommand::try_parse_from(["find", "--manifest", sums.to_str().unwrap()]).unwrap();
+        let preamble = Preamble::new(Url::parse("s3://bucket/data/").unwrap());
+        command
+            .find
+            .root_paths
+            .set(root_paths(&preamble).unwrap())
+            .unwrap();
+        let manifest = Manifest {
+            recorded: command
+                .load_manifest(command.manifest.as_ref().unwrap())
+                .await
+                .unwrap(),
+            unchecked: AtomicUsize::new(0),
+        };
+        let status = |location: &str, checksum: &str| {
+            let mut found = Found::new(None, object(location));
+            found.extra.insert("checksum".to_string(), checksum.into());
+            let status = manifest.check(&command.find, &mut found);
+            assert_eq!(found.extra["manifest_status"], serde_json::json!(status));
+            status
+        };
+        // Hashes compare in any case
+        let ab = format!("sha256:{}", "ab".repeat(32));
+        assert_eq!(status("data/a.csv", &ab), ManifestStatus::Ok);
+        assert_eq!(status("data/sub/b.csv", &ab), ManifestStatus::Mismatch);
+        assert_eq!(
+            status("data/c.csv", &ab),
+            ManifestStatus::MissingFromManifest
+        );
+        // With nothing to compare, it's taken as it is, but counted
+        assert_eq!(status("data/old.csv", "md5:00"), ManifestStatus::Ok);
+        assert_eq!(manifest.unchecked.load(Ordering::Relaxed), 1);
+
+        // A listing like `hash` prints can be compared by size instead
+        let root = stocked(&["a.csv", "b.csv", "c.csv"]).await;
+        let listing = dir.join("manifest.ndjson");
+        let writer: StdoutWriter = StdoutWriter::save(
+            &Preamble::new(Url::parse("s3://elsewhere/copy/").unwrap()),
+            &ListingPath::File(listing.clone()),
+        )
+        .await
+        .unwrap();
+        for (location, size) in [("copy/a.csv", 1), ("copy/b.csv", 5)] {
+            let meta = ObjectMeta {
+                size,
+                ..object(location)
+            };
+            writer.write(ObjectExport::from(meta)).await.unwrap();
+        }
+        writer.finish().await.unwrap();
+        let listing = listing.to_str().unwrap();
+        let args = ["--manifest", listing, "--manifest-format", "ndjson"];
+        let run = printed(&args, &root, None).await.unwrap();
+        assert_eq!(run.keys, ["data/a.csv", "data/b.csv", "data/c.csv"]);
+        let only = [
+            &args[..],
+            &["--only-status", "mismatch,missing_from_manifest"],
+        ]
+        .concat();
+        let run = printed(&only, &root, None).await.unwrap();
+        assert_eq!(run.keys, ["data/b.csv", "data/c.csv"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn keep_going_skips_failures_and_exits_with_an_error() {
+        let root = stocked(&["a.csv", "b.csv"]).await;
+        let stdin = || {
+            vec![
+                Ok(object("data/a.csv")),
+                Err(anyhow::anyhow!("Line 3 isn't an object")),
+                Ok(object("data/b.csv")),
+            ]
+        };
+        let error = printed(&[], &root, Some(stdin())).await.err().unwrap();
+        assert!(error.to_string().contains("Line 3"), "{error}");
+
+        let run = printed(&["--keep-going"], &root, Some(stdin()))
+            .await
+            .unwrap();
+        assert_eq!(run.keys, ["data/a.csv", "data/b.csv"]);
+        assert_eq!(run.counters.errors.load(Ordering::Relaxed), 1);
+        assert_eq!(run.exit, Some(ERRORS_EXIT_CODE));
+        // Nothing going wrong is no reason to fail
+        let run = printed(&["--keep-going"], &root, None).await.unwrap();
+        assert_eq!(run.exit, None);
+    }
+
+    #[tokio::test]
+    async fn ordered_prints_fetched_matches_in_the_order_read() {
+        let names: Vec<_> = (0..20).rev().map(|i| format!("{i:02}.csv")).collect();
+        let root = stocked(&names.iter().map(String::as_str).collect::<Vec<_>>()).await;
+        let read: Vec<_> = names.iter().map(|name| format!("data/{name}")).collect();
+        let stdin = || read.iter().map(|location| Ok(object(location))).collect();
+        for fetching in ["--verify", "--emit-content-type"] {
+            let run = printed(&[fetching, "--ordered"], &root, Some(stdin()))
+                .await
+                .unwrap();
+            assert_eq!(run.keys, read, "{fetching}");
+            // Either way, every match is printed once
+            let mut unordered = printed(&[fetching], &root, Some(stdin()))
+                .await
+                .unwrap()
+                .keys;
+            unordered.sort();
+            assert_eq!(unordered.len(), 20);
+            assert!(unordered.iter().rev().eq(&read), "{fetching}");
+        }
+        assert!(FindCommand::try_parse_from(["find", "--ordered", "--shuffle"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn only_duplicates_groups_matches_by_size_and_etag() {
+        let root = stocked(&[]).await;
+        let stdin = || {
+            [
+                ("data/a", 1, Some("x")),
+                ("data/b", 1, Some("\"x\"")),
+                ("data/c", 2, Some("x")),
+                ("data/d", 1, Some("y")),
+                ("data/e", 1, None),
+                ("data/f", 1, None),
+            ]
+            .map(|(location, size, e_tag)| {
+                Ok(ObjectMeta {
+                    size,
+                    e_tag: e_tag.map(str::to_string),
+                    ..object(location)
+                })
+            })
+            .into()
+        };
+        fn groups(run: &Printed) -> Vec<(&str, serde_json::Value)> {
+            run.exported
+                .iter()
+                .map(|export| {
+                    (
+                        export.location.as_str(),
+                        export.extra["duplicate_group"].clone(),
+                    )
+                })
+                .collect()
+        }
+        let run = printed(&["--only-duplicates"], &root, Some(stdin()))
+            .await
+            .unwrap();
+        assert_eq!(groups(&run), [("data/a", 0.into()), ("data/b", 0.into())]);
+        assert_eq!(run.counters.matched.load(Ordering::Relaxed), 2);
+
+        // Objects without etags can only be told apart by size, if that's good enough
+        let run = printed(&["--only-duplicates", "--loose"], &root, Some(stdin()))
+            .await
+            .unwrap();
+        assert_eq!(
+            groups(&run),
+            [
+                ("data/a", 0.into()),
+                ("data/b", 0.into()),
+                ("data/e", 1.into()),
+                ("data/f", 1.into())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn count_prints_how_many_matched_and_their_bytes() {
+        let root = stocked(&["a.csv", "b.csv", "c.json"]).await;
+        let big = ObjectStorePath::from("data/big.csv");
+        root.store.put(&big, "x".repeat(1000).into()).await.unwrap();
+        let counted = |args: &'static [&'static str]| {
+            let root = &root;
+            async move { printed(args, root, None).await.unwrap().counted.unwrap() }
+        };
+        assert_eq!(counted(&["--count"]).await, "4");
+        assert_eq!(counted(&["--count", "--ext", "csv"]).await, "3");
+        assert_eq!(
+            counted(&["--count", "--bytes", "--ext", "csv"]).await,
+            "3\t1002"
+        );
+        assert_eq!(
+            counted(&["--count", "--bytes", "--ext", "txt"]).await,
+            "0\t0"
+        );
+        // Only matches that are left after --limit are counted
+        assert_eq!(counted(&["--count", "--limit", "2"]).await, "2");
+
+        let run = printed(&["--count", "--fail-if-empty", "--ext", "txt"], &root, None)
+            .await
+            .unwrap();
+        assert_eq!(run.exit, Some(EMPTY_EXIT_CODE));
+        assert!(FindCommand::try_parse_from(["find", "--bytes"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn stats_add_up_what_was_scanned_fetched_matched_and_skipped() {
+        let root = stocked(&["a.csv", "b.json"]).await;
+        let stdin = vec![
+            Ok(object("data/a.csv")),
+            Ok(object("data/gone.csv")),
+            Err(anyhow::anyhow!("Line 4 isn't an object")),
+            Ok(object("data/b.json")),
+        ];
+        let args = ["--stats", "--verify", "--keep-going", "--ext", "csv"];
+        let run = printed(&args, &root, Some(stdin)).await.unwrap();
+        let stats = Stats::of(&run.counters, Duration::from_secs(2));
+        // Only what passes the filters is fetched, and only what's still there matches
+        assert_eq!(
+            (
+                stats.scanned,
+                stats.heads,
+                stats.matched,
+                stats.bytes,
+                stats.errors
+            ),
+            (3, 2, 1, 1, 1)
+        );
+        assert_eq!(stats.rate, 1.5);
+        let text = stats.text();
+        assert!(
+            text.starts_with("Scanned 3 objects and matched 1 ("),
+            "{text}"
+        );
+        assert!(text.contains(" in 2.00s, 2 objects/s"), "{text}");
+        assert!(
+            text.ends_with(", fetching 2 objects again, skipping 1 after errors"),
+            "{text}"
+        );
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["errors"], 1);
+        assert_eq!(json["elapsed"], 2.0);
+
+        let quiet = Stats::of(&Counters::default(), Duration::ZERO).text();
+        assert!(quiet.ends_with(" objects/s"), "{quiet}");
+    }
+
+    #[test]
+    fn follow_prints_only_objects_that_are_new_or_changed() {
+        let at = |location: &str, e_tag: &str, hours_ago: i64| ObjectMeta {
+            e_tag: Some(e_tag.to_string()),
+            last_modified: Utc::now() - chrono::Duration::hours(hours_ago),
+            ..object(location)
+        };
+        let mut followed = Followed::default();
+        let listing = |followed: &mut Followed, objects: &[ObjectMeta], window| {
+            let new: Vec<_> = objects
+                .iter()
+                .filter(|meta| followed.is_new(meta, window))
+                .map(|meta| meta.location.to_string())
+                .collect();
+            followed.finish_listing(window);
+            new
+        };
+        let first = [at("data/a", "1", 0), at("data/b", "1", 0)];
+        assert_eq!(listing(&mut followed, &first, None), ["data/a", "data/b"]);
+        let second = [
+            at("data/a", "1", 0),
+            at("data/b", "2", 0),
+            at("data/c", "1", 0),
+        ];
+        assert_eq!(listing(&mut followed, &second, None), ["data/b", "data/c"]);
+        assert!(listing(&mut followed, &second, None).is_empty());
+
+        // Only recent objects are remembered, and older ones are only printed the first time
+        let window = Some(Utc::now() - chrono::Duration::hours(1));
+        let mut followed = Followed::default();
+        let objects = [at("data/old", "1", 2), at("data/new", "1", 0)];
+        assert_eq!(
+            listing(&mut followed, &objects, window),
+            ["data/old", "data/new"]
+        );
+        assert_eq!(followed.seen.len(), 1);
+        assert!(listing(&mut followed, &objects, window).is_empty());
     }
 }
@@ -0,0 +1,90 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use serde::Serialize;
+
+use crate::diff::has_changed;
+use crate::join::{merge_join, Joined};
+use crate::listing::{self, ListingPath, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Fsck {
+    /// The manifest to check against, a saved listing sorted by key, in a file or a store
+    #[arg(long, value_parser = ListingPath::parse)]
+    manifest: ListingPath,
+    /// The root to list, by default the one the manifest was made from
+    #[arg(short, long)]
+    root: Option<String>,
+}
+
+/// How a key compares between the manifest and the store
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    MissingFromStore,
+    MissingFromManifest,
+    /// The size or etag differs
+    Mismatched,
+}
+
+/// A line of the report: the object as the manifest recorded it, or as found if the manifest doesn't have it
+#[derive(Debug, Serialize)]
+struct Check {
+    status: Status,
+    #[serde(flatten)]
+    object: ObjectExport,
+    /// The object as it is now, only when it doesn't match
+    #[serde(skip_serializing_if = "Option::is_none")]
+    found: Option<ObjectExport>,
+}
+
+impl Fsck {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let (preamble, manifest) = listing::read_file(&self.manifest).await?;
+        let recorded = Root::open(preamble.root()?)?;
+        let live = match &self.root {
+            Some(root) => Root::open(&listing::parse_root(root)?)?,
+            None => recorded.clone(),
+        };
+        let recorded_ref = &recorded;
+        let manifest =
+            manifest.and_then(|meta| async move { Ok((recorded_ref.key(&meta.location)?, meta)) });
+
+        let stdout = StdoutWriter::start(&Preamble::new(live.url.clone()))?;
+        let mut counts = [0; 4];
+        // Both sides are sorted by key, so only one object from each is held at a time
+        let mut joined = std::pin::pin!(merge_join(manifest, live.list_keyed()));
+        while let Some(step) = joined.try_next().await? {
+            let (status, object, found) = match step {
+                Joined::Left(expected) => (Status::MissingFromStore, expected, None),
+                Joined::Right(found) => (Status::MissingFromManifest, found, None),
+                Joined::Both(expected, found) if has_changed(&expected, &found) => {
+                    (Status::Mismatched, expected, Some(found))
+                }
+                Joined::Both(expected, _) => (Status::Ok, expected, None),
+            };
+            counts[status as usize] += 1;
+            stdout
+                .write(Check {
+                    status,
+                    object: object.into(),
+                    found: found.map(Into::into),
+                })
+                .await?;
+        }
+        stdout.finish().await?;
+
+        let [ok, missing_from_store, missing_from_manifest, mismatched] = counts;
+        eprintln!(
+            "{ok} ok, {missing_from_store} missing from the store, \
+             {missing_from_manifest} missing from the manifest, {mismatched} mismatched"
+        );
+        if ok != counts.iter().sum::<usize>() {
+            bail!("The store doesn't match the manifest");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::{Duration, SystemTime};
+
+use anyhow::{ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::{Attribute, Attributes, PutOptions};
+
+use crate::copy::{Upload, PART_SIZE};
+use crate::listing::{self, StdoutWriter};
+use crate::random::Rng;
+use crate::store::Root;
+use crate::units::{parse_duration, parse_size};
+use crate::{Args, Preamble};
+
+/// How many prefixes each level of the tree is spread across
+const FANOUT: u64 = 10;
+
+#[derive(Debug, Parser)]
+pub struct Generate {
+    /// The root to create objects under
+    #[arg(short, long)]
+    root: String,
+    /// How many objects to create
+    #[arg(short = 'n', long, default_value = "100")]
+    count: usize,
+    /// How large each object is, like `4KiB`, or a range like `1KiB..10MiB` to choose from.
+    /// Sizes in a range are spread evenly across orders of magnitude.
+    #[arg(long, default_value = "1KiB")]
+    size: String,
+    /// How many levels of prefixes to put the objects under
+    #[arg(long, default_value = "2")]
+    depth: usize,
+    /// How long ago the objects were last modified, like `1d..365d`.
+    /// The local filesystem sets the modified time, other stores record it as `mtime` metadata.
+    #[arg(long)]
+    age_range: Option<String>,
+    /// Seed the names, sizes, ages and contents, so the same seed always makes the same tree
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+/// Everything about an object to create, chosen up front so uploading order doesn't matter
+struct Spec {
+    location: ObjectStorePath,
+    size: usize,
+    age: Option<Duration>,
+    content_seed: u64,
+}
+
+/// Split `a..b` into its ends, or use a single value for both
+fn split_range(text: &str) -> (&str, &str) {
+    text.split_once("..").unwrap_or((text, text))
+}
+
+/// Fill bytes with seeded noise, so the same seed gives the same content
+fn fill(rng: &mut Rng, len: usize) -> Vec<u8> {
+    let mut content = Vec::with_capacity(len + 8);
+    while content.len() < len {
+        content.extend_from_slice(&rng.next_u64().to_le_bytes());
+    }
+    content.truncate(len);
+    content
+}
+
+impl Generate {
+    fn specs(&self, root: &Root) -> Result<Vec<Spec>> {
+        let (min_size, max_size) = split_range(&self.size);
+        let (min_size, max_size) = (parse_size(min_size)?, parse_size(max_size)?);
+        ensure!(
+            min_size <= max_size,
+            "--size must go from smaller to larger"
+        );
+        let ages = self
+            .age_range
+            .as_deref()
+            .map(|text| -> Result<_> {
+                let (min, max) = split_range(text);
+                let (min, max) = (parse_duration(min)?, parse_duration(max)?);
+                ensure!(min <= max, "--age-range must go from newer to older");
+                Ok((min, max))
+            })
+            .transpose()?;
+
+        let mut rng = Rng::seeded(self.seed);
+        let mut specs = Vec::with_capacity(self.count);
+        for index in 0..self.count {
+            let mut location: ObjectStorePath = root.path.clone();
+            for _ in 0..self.depth {
+                location = location.child(format!("d{}", rng.below(FANOUT)));
+            }
+            // The index keeps names unique, the random part keeps them from sorting in creation order
+            location = location.child(format!("{:016x}-{index}.bin", rng.next_u64()));
+            // Sample sizes log-uniformly, so a range like 1KiB..10MiB isn't almost all megabytes
+            let (low, high) = ((min_size as f64 + 1.0).ln(), (max_size as f64 + 1.0).ln());
+            let size = ((low + (high - low) * rng.next_f64()).exp() - 1.0).round() as usize;
+            let age = ages.map(|(min, max)| min + (max - min).mul_f64(rng.next_f64()));
+            specs.push(Spec {
+                location,
+                size: size.clamp(min_size as usize, max_size as usize),
+                age,
+                content_seed: rng.next_u64(),
+            });
+        }
+        Ok(specs)
+    }
+
+    async fn create(root: &Root, spec: Spec) -> Result<object_store::ObjectMeta> {
+        let mut rng = Rng::new(spec.content_seed);
+        let modified = spec.age.map(|age| SystemTime::now() - age);
+        let mut attributes = Attributes::new();
+        if let (Some(modified), false) = (modified, root.url.scheme() == "file") {
+            let seconds = modified
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            attributes.insert(
+                Attribute::Metadata("mtime".into()),
+                seconds.to_string().into(),
+            );
+        }
+
+        if spec.size <= PART_SIZE {
+            let opts = PutOptions {
+                attributes,
+                ..Default::default()
+            };
+            let content = fill(&mut rng, spec.size);
+            root.store
+                .put_opts(&spec.location, content.into(), opts)
+                .await?;
+        } else {
+            let mut upload = Upload::start_with(root, &spec.location, attributes).await?;
+            let written: Result<()> = async {
+                let mut remaining = spec.size;
+                while remaining > 0 {
+                    let chunk = fill(&mut rng, remaining.min(PART_SIZE));
+                    remaining -= chunk.len();
+                    upload.write(&chunk).await?;
+                }
+                Ok(())
+            }
+            .await;
+            upload.finish_or_abort(written).await?;
+        }
+
+        if let (Some(modified), "file") = (modified, root.url.scheme()) {
+            let path = root
+                .object_url(&spec.location)?
+                .to_file_path()
+                .map_err(|()| anyhow::anyhow!("Invalid path"))?;
+            std::fs::File::options()
+                .write(true)
+                .open(path)?
+                .set_modified(modified)?;
+        }
+        Ok(root.store.head(&spec.location).await?)
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let root = &Root::open(&listing::parse_root(&self.root)?)?;
+        let specs = self.specs(root)?;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(root.url.clone()))?;
+        let writer = &stdout;
+        futures::stream::iter(specs.into_iter().map(anyhow::Ok))
+            .try_for_each_concurrent(global_args.concurrency, |spec| async move {
+                writer.write(Self::create(root, spec).await?.into()).await
+            })
+            .await?;
+        stdout.finish().await
+    }
+}
@@ -0,0 +1,210 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::listing::{self, StdoutWriter};
+use crate::store::{self, Root};
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Get {
+    /// The local directory to download into. Paths relative to the source root are preserved.
+    #[arg(short, long)]
+    dest: PathBuf,
+    /// Download objects even when a file of the same size already exists
+    #[arg(long)]
+    overwrite: bool,
+}
+
+/// What happened to a single object
+#[derive(Debug, Serialize)]
+struct Download {
+    /// The location of the object in the store
+    location: String,
+    /// The local file it was written to
+    path: PathBuf,
+    /// How many bytes were written, zero when the file was skipped
+    bytes: usize,
+    /// Seconds spent on the object
+    elapsed: f64,
+    /// Whether the download was skipped because the file already existed
+    skipped: bool,
+}
+
+/// Stream an object's body into a local file, returning how many bytes were written
+pub async fn download(root: &Root, meta: &ObjectMeta, path: &Path) -> Result<usize> {
+    if let Some(parent) = path.parent() {
+        if tokio::fs::create_dir_all(parent).await.is_err() {
+            clear_markers(parent).await?;
+            tokio::fs::create_dir_all(parent).await?;
+        }
+    }
+    let mut body = root.store.get(&meta.location).await?.into_stream();
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut bytes = 0;
+    while let Some(chunk) = body.try_next().await? {
+        file.write_all(&chunk).await?;
+        bytes += chunk.len();
+    }
+    file.flush().await?;
+    Ok(bytes)
+}
+
+/// Remove empty files where directories need to be, left by downloading a console's directory
+/// marker before the objects under it.
+///
+/// Console markers end in a slash, which object paths drop, so they come down as empty files
+/// named like the directory.
+async fn clear_markers(dir: &Path) -> Result<()> {
+    for ancestor in dir.ancestors() {
+        let metadata = tokio::fs::metadata(ancestor).await;
+        if metadata.is_ok_and(|m| m.is_file() && m.len() == 0) {
+            tokio::fs::remove_file(ancestor)
+                .await
+                .with_context(|| format!("Replacing {} with a directory", ancestor.display()))?;
+        }
+    }
+    Ok(())
+}
+
+impl Get {
+    /// Download one object, or nothing if it's only a directory marker
+    async fn get_object(&self, root: &Root, meta: &ObjectMeta) -> Result<Option<Download>> {
+        if store::is_dir_marker(meta) {
+            return Ok(None);
+        }
+        let start = Instant::now();
+        let path = root
+            .relative(&meta.location)?
+            .fold(self.dest.clone(), |path, part| path.join(part.as_ref()));
+        let existing = tokio::fs::metadata(&path).await.ok();
+        // An empty object where objects under it made a directory is a console's marker for it
+        if meta.size == 0 && existing.as_ref().is_some_and(|m| m.is_dir()) {
+            return Ok(None);
+        }
+        let skipped = !self.overwrite && existing.is_some_and(|m| m.len() == meta.size as u64);
+        let bytes = if skipped {
+            0
+        } else {
+            match download(root, meta, &path).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    // Don't leave a truncated file that looks like a finished download
+                    let _ = tokio::fs::remove_file(&path).await;
+                    return Err(e.context(format!("Downloading {}", meta.location)));
+                }
+            }
+        };
+        Ok(Some(Download {
+            location: meta.location.to_string(),
+            path,
+            bytes,
+            elapsed: start.elapsed().as_secs_f64(),
+            skipped,
+        }))
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let root = &Root::open(listing::read_preamble()?.root()?)?;
+        tokio::fs::create_dir_all(&self.dest)
+            .await
+            .with_context(|| format!("Creating {}", self.dest.display()))?;
+
+        let stdout = StdoutWriter::start_bare()?;
+        let writer = &stdout;
+        let markers = &AtomicUsize::new(0);
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                match self.get_object(root, &meta).await? {
+                    Some(download) => writer.write(download).await,
+                    None => {
+                        markers.fetch_add(1, Ordering::Relaxed);
+                        Ok(())
+                    }
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+        let markers = markers.load(Ordering::Relaxed);
+        if markers > 0 {
+            eprintln!("Skipped {markers} directory markers, which aren't files");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    #[tokio::test]
+    async fn downloads_files_but_not_dir_markers() {
+        let dir = std::env::temp_dir().join(format!("obvious3-get-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let root = Root::open(&Url::parse("memory:///data/").unwrap()).unwrap();
+        let get = Get::try_parse_from(["get", "--dest", dir.to_str().unwrap()]).unwrap();
+        let put = |location: &str, body: &'static str| {
+            let location = object_store::path::Path::from(location);
+            let store = root.store.clone();
+            async move {
+                store.put(&location, body.into()).await.unwrap();
+                store.head(&location).await.unwrap()
+            }
+        };
+        let marker = put("data/logs_$folder$", "").await;
+        let file = put("data/logs/a.csv", "a,b\n").await;
+
+        assert!(get.get_object(&root, &marker).await.unwrap().is_none());
+        assert!(!dir.join("logs_$folder$").exists());
+        let download = get.get_object(&root, &file).await.unwrap().unwrap();
+        assert_eq!(download.bytes, 4);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("logs/a.csv")).unwrap(),
+            "a,b\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn console_markers_give_way_to_directories() {
+        let dir = std::env::temp_dir().join(format!("obvious3-get-console-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let root = Root::open(&Url::parse("memory:///data/").unwrap()).unwrap();
+        let get = Get::try_parse_from(["get", "--dest", dir.to_str().unwrap()]).unwrap();
+        let put = |location: &str, body: &'static str| {
+            let location = object_store::path::Path::from(location);
+            let store = root.store.clone();
+            async move {
+                store.put(&location, body.into()).await.unwrap();
+                store.head(&location).await.unwrap()
+            }
+        };
+        // What a console's `logs/` and `raw/` look like once the slash is gone
+        let logs = put("data/logs", "").await;
+        let raw = put("data/raw", "").await;
+        let under_logs = put("data/logs/a.csv", "a").await;
+        let under_raw = put("data/raw/2024/b.csv", "b").await;
+
+        // After the objects under it, the marker is skipped
+        get.get_object(&root, &under_logs).await.unwrap().unwrap();
+        assert!(get.get_object(&root, &logs).await.unwrap().is_none());
+        assert!(dir.join("logs/a.csv").is_file());
+        // Before them, the empty file it left makes way for the directory
+        get.get_object(&root, &raw).await.unwrap().unwrap();
+        assert!(dir.join("raw").is_file());
+        get.get_object(&root, &under_raw).await.unwrap().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("raw/2024/b.csv")).unwrap(),
+            "b"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
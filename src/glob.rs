@@ -0,0 +1,143 @@
+//! Shell style glob patterns, translated into regexes
+
+use anyhow::{bail, Result};
+
+/// Compile a glob into a regex that has to match a whole key.
+///
+/// * `*` matches anything within one segment, and `?` matches one character other than `/`
+/// * `**` as a whole segment matches any number of segments, including none,
+///   so `logs/**/*.gz` matches `logs/a.gz` and `logs/2024/01/a.gz`, and `**/*.gz` matches `a.gz`
+/// * `[abc]`, `[a-z]` and `[!abc]` match one character from (or not from) a set
+/// * `{a,b}` matches either alternative, and alternatives can contain any of the above
+pub fn compile(pattern: &str, case_insensitive: bool) -> Result<regex::Regex> {
+    let mut regex = String::from("^");
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut alternatives = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '*' if chars.get(i + 1) == Some(&'*') => {
+                let starts_segment = i == 0 || chars[i - 1] == '/';
+                let ends_segment = chars.get(i + 2).is_none_or(|&next| next == '/');
+                if !(starts_segment && ends_segment) {
+                    bail!("In the glob {pattern:?}, ** has to be a whole segment, like a/**/b");
+                }
+                match chars.get(i + 2) {
+                    // `**/` matches any number of whole segments, including none
+                    Some(_) => {
+                        regex.push_str("(?:[^/]*/)*");
+                        i += 3;
+                    }
+                    // A trailing `**` matches everything below
+                    None => {
+                        regex.push_str(".*");
+                        i += 2;
+                    }
+                }
+                continue;
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                let Some(end) = chars[i + 1..]
+                    .iter()
+                    .skip(1)
+                    .position(|&c| c == ']')
+                    .map(|p| p + i + 2)
+                else {
+                    bail!("In the glob {pattern:?}, a [ isn't closed");
+                };
+                let mut class: String = chars[i + 1..end].iter().collect();
+                if let Some(rest) = class.strip_prefix('!') {
+                    class = format!("^{rest}");
+                }
+                regex.push('[');
+                regex.push_str(&class.replace('\\', "\\\\").replace('[', "\\["));
+                regex.push(']');
+                i = end + 1;
+                continue;
+            }
+            '{' => {
+                alternatives += 1;
+                regex.push_str("(?:");
+            }
+            ',' if alternatives > 0 => regex.push('|'),
+            '}' if alternatives > 0 => {
+                alternatives -= 1;
+                regex.push(')');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+        i += 1;
+    }
+    if alternatives > 0 {
+        bail!("In the glob {pattern:?}, a {{ isn't closed");
+    }
+    regex.push('$');
+    Ok(regex::RegexBuilder::new(&regex)
+        .case_insensitive(case_insensitive)
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, key: &str) -> bool {
+        compile(pattern, false).unwrap().is_match(key)
+    }
+
+    #[test]
+    fn stars_stay_within_a_segment() {
+        assert!(matches("*.gz", "a.gz"));
+        assert!(!matches("*.gz", "logs/a.gz"));
+        assert!(matches("logs/*/a.gz", "logs/2024/a.gz"));
+        assert!(!matches("logs/*/a.gz", "logs/2024/01/a.gz"));
+        assert!(matches("part-?.csv", "part-1.csv"));
+        assert!(!matches("part-?.csv", "part-10.csv"));
+        assert!(!matches("a?b", "a/b"));
+    }
+
+    #[test]
+    fn double_stars_cross_any_number_of_segments() {
+        for key in ["logs/a.gz", "logs/2024/a.gz", "logs/2024/01/a.gz"] {
+            assert!(matches("logs/**/*.gz", key), "{key}");
+        }
+        assert!(matches("**/*.gz", "a.gz"));
+        assert!(matches("logs/**", "logs/2024/01/a.gz"));
+        assert!(!matches("logs/**/*.gz", "other/a.gz"));
+        assert!(compile("logs/a**", false).is_err());
+        assert!(compile("**b/c", false).is_err());
+    }
+
+    #[test]
+    fn sets_and_alternatives() {
+        assert!(matches("[abc].txt", "b.txt"));
+        assert!(!matches("[abc].txt", "d.txt"));
+        assert!(matches("[a-c]x", "cx"));
+        assert!(matches("[!abc].txt", "d.txt"));
+        assert!(!matches("[!abc].txt", "a.txt"));
+        assert!(matches("*.{csv,json}", "a.json"));
+        assert!(matches("{logs/**/*.gz,*.csv}", "logs/x/a.gz"));
+        assert!(!matches("*.{csv,json}", "a.parquet"));
+        // Outside braces, a comma is just a comma
+        assert!(matches("a,b", "a,b"));
+        assert!(compile("[abc", false).is_err());
+        assert!(compile("{a,b", false).is_err());
+    }
+
+    #[test]
+    fn everything_else_is_literal() {
+        assert!(matches("a.b+(c)", "a.b+(c)"));
+        assert!(!matches("a.b", "axb"));
+        assert!(!matches("a.gz", "xa.gz"));
+        assert!(!matches("a.gz", "a.gzip"));
+    }
+
+    #[test]
+    fn ignores_case_when_asked() {
+        assert!(!matches("*.CSV", "a.csv"));
+        assert!(compile("*.CSV", true).unwrap().is_match("a.csv"));
+    }
+}
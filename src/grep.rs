@@ -0,0 +1,167 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+use regex::bytes::Regex;
+
+use crate::codec::{Codec, Decoder};
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport};
+
+/// How much of an object is looked at to decide whether it's binary, the same as GNU grep
+const BINARY_SNIFF: usize = 8192;
+
+#[derive(Debug, Parser)]
+pub struct Grep {
+    /// The regex to search each line for
+    #[arg(short = 'e', long)]
+    pattern: String,
+    /// Stop reading each object after this many bytes
+    #[arg(long)]
+    max_bytes: Option<usize>,
+    /// Search objects that look binary too, instead of skipping them
+    #[arg(long)]
+    binary: bool,
+    /// Only write a listing of the objects with a match, which can be piped into other commands
+    #[arg(short = 'l', long)]
+    files_with_matches: bool,
+}
+
+/// What was found in one object
+enum Searched {
+    Matches(usize),
+    Binary,
+}
+
+impl Grep {
+    /// Search one object, calling `found` with each matching line until it returns false
+    async fn search(
+        &self,
+        regex: &Regex,
+        root: &Root,
+        meta: &ObjectMeta,
+        mut found: impl FnMut(usize, &[u8]) -> Result<bool>,
+    ) -> Result<Searched> {
+        let mut body = root.store.get(&meta.location).await?.into_stream();
+        let mut decoder: Option<Decoder> = None;
+        // Lines can arrive in pieces, so the end of each chunk waits here for the rest
+        let mut pending = vec![];
+        let mut number = 0;
+        let mut sniffed = false;
+        let mut read = 0;
+        let mut matches = 0;
+        let mut first = true;
+        loop {
+            let mut chunk = match body.try_next().await? {
+                Some(chunk) => chunk.to_vec(),
+                None => vec![],
+            };
+            if let Some(max) = self.max_bytes {
+                chunk.truncate(max.saturating_sub(read));
+            }
+            read += chunk.len();
+            let done = chunk.is_empty();
+            // Compressed objects are recognized by their magic bytes rather than by extension
+            if first {
+                decoder = Codec::from_magic(&chunk).map(Decoder::new).transpose()?;
+            }
+            first = false;
+            match &mut decoder {
+                Some(decoder) => pending.extend(decoder.update(&chunk)?),
+                None => pending.extend_from_slice(&chunk),
+            }
+
+            if !sniffed {
+                if pending.len() < BINARY_SNIFF && !done {
+                    continue;
+                }
+                sniffed = true;
+                let sniff = &pending[..pending.len().min(BINARY_SNIFF)];
+                if !self.binary && sniff.contains(&0) {
+                    return Ok(Searched::Binary);
+                }
+            }
+
+            let mut start = 0;
+            while let Some(end) = pending[start..].iter().position(|&b| b == b'\n') {
+                let line = &pending[start..start + end];
+                number += 1;
+                start += end + 1;
+                if regex.is_match(line) {
+                    matches += 1;
+                    if !found(number, line)? {
+                        return Ok(Searched::Matches(matches));
+                    }
+                }
+            }
+            pending.drain(..start);
+
+            if done {
+                // The last line doesn't need to end with a newline
+                if !pending.is_empty() && regex.is_match(&pending) {
+                    matches += 1;
+                    found(number + 1, &pending)?;
+                }
+                return Ok(Searched::Matches(matches));
+            }
+        }
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let regex = &Regex::new(&self.pattern)?;
+        let preamble = listing::read_preamble()?;
+        let root = &Root::open(preamble.root()?)?;
+        // Listings only make sense for --files-with-matches, otherwise lines are printed as text
+        let listing = match self.files_with_matches {
+            true => Some(StdoutWriter::<ObjectExport>::start(&preamble)?),
+            false => None,
+        };
+        let writer = &listing;
+        let binary = &AtomicUsize::new(0);
+        let searched = listing::read_stdin_exports()
+            .try_for_each_concurrent(global_args.concurrency, |object| async move {
+                let (meta, annotations) = object.split();
+                let location = meta.location.to_string();
+                let outcome = self
+                    .search(regex, root, &meta, |number, line| {
+                        if writer.is_some() {
+                            return Ok(false);
+                        }
+                        let mut out = format!("{location}:{number}:").into_bytes();
+                        out.extend_from_slice(line);
+                        out.push(b'\n');
+                        // Each line is written in one go while holding the lock, so lines never mix
+                        std::io::stdout().lock().write_all(&out)?;
+                        Ok(true)
+                    })
+                    .await?;
+                match (outcome, writer) {
+                    (Searched::Matches(n), Some(writer)) if n > 0 => {
+                        writer
+                            .write(ObjectExport::annotated(meta, annotations))
+                            .await?
+                    }
+                    (Searched::Binary, _) => {
+                        binary.fetch_add(1, Ordering::Relaxed);
+                    }
+                    _ => {}
+                }
+                Ok(())
+            })
+            .await;
+        if let Some(listing) = listing {
+            listing.finish().await?;
+        }
+        listing::ignore_broken_pipe(searched)?;
+
+        let binary = binary.load(Ordering::Relaxed);
+        if binary > 0 {
+            eprintln!("Skipped {binary} binary objects. Pass --binary to search them too.");
+        }
+        Ok(())
+    }
+}
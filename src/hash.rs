@@ -0,0 +1,59 @@
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+use serde::{Deserialize, Serialize};
+
+use crate::checksum::Algorithm;
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport};
+
+#[derive(Debug, Parser)]
+pub struct Hash {
+    /// Which checksum to compute
+    #[arg(long, value_enum, default_value_t = Algorithm::Sha256)]
+    algo: Algorithm,
+}
+
+/// An object in the listing, along with a checksum of its contents
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hashed {
+    #[serde(flatten)]
+    pub object: ObjectExport,
+    /// The algorithm and the hex digest, like `sha256:e3b0c442...`
+    pub checksum: String,
+}
+
+/// Stream an object through a checksum, without holding it in memory.
+///
+/// The result names the algorithm, like `sha256:e3b0c442...`
+pub async fn checksum(algo: Algorithm, root: &Root, meta: &ObjectMeta) -> Result<String> {
+    let mut body = root.store.get(&meta.location).await?.into_stream();
+    let mut checksum = algo.start();
+    while let Some(chunk) = body.try_next().await? {
+        checksum.update(&chunk);
+    }
+    Ok(format!("{}:{}", algo.name(), checksum.finish()))
+}
+
+impl Hash {
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let root = &Root::open(preamble.root()?)?;
+        let stdout = StdoutWriter::start(&preamble)?;
+        let writer = &stdout;
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                let checksum = checksum(self.algo, root, &meta).await?;
+                writer
+                    .write(Hashed {
+                        object: meta.into(),
+                        checksum,
+                    })
+                    .await
+            })
+            .await?;
+        stdout.finish().await
+    }
+}
@@ -0,0 +1,118 @@
+use std::io::Write;
+
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+use tokio::io::AsyncWriteExt;
+
+use crate::listing;
+use crate::store::Root;
+use crate::Args;
+
+/// How much of an object to fetch at first, growing until enough lines are found
+const INITIAL_FETCH: usize = 64 * 1024;
+
+#[derive(Debug, Parser)]
+pub struct Head {
+    /// Print this many lines from the start of each object
+    #[arg(short = 'n', long, default_value = "10")]
+    lines: usize,
+    /// Print this many bytes from the start of each object, instead of lines
+    #[arg(short = 'c', long, conflicts_with = "lines")]
+    bytes: Option<usize>,
+    /// Print binary content as is, rather than summarizing it
+    #[arg(long)]
+    binary: bool,
+}
+
+/// Whether some content looks like something other than text
+fn is_binary(content: &[u8]) -> bool {
+    if content.contains(&0) {
+        return true;
+    }
+    match std::str::from_utf8(content) {
+        Ok(_) => false,
+        // A character cut in half at the end of the range is still text
+        Err(e) => e.error_len().is_some(),
+    }
+}
+
+impl Head {
+    /// Fetch the start of an object, using ranged reads so large objects aren't downloaded
+    async fn read_head(&self, root: &Root, meta: &ObjectMeta) -> Result<Vec<u8>> {
+        if let Some(bytes) = self.bytes {
+            let end = bytes.min(meta.size);
+            if end == 0 {
+                return Ok(vec![]);
+            }
+            return Ok(root.store.get_range(&meta.location, 0..end).await?.to_vec());
+        }
+        let mut content = vec![];
+        if self.lines == 0 {
+            return Ok(content);
+        }
+        let mut fetch = INITIAL_FETCH;
+        while content.len() < meta.size {
+            let end = (content.len() + fetch).min(meta.size);
+            let chunk = root
+                .store
+                .get_range(&meta.location, content.len()..end)
+                .await?;
+            content.extend_from_slice(&chunk);
+            // Cut right after the last line we need, once we have it
+            if let Some((cut, _)) = content
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| **b == b'\n')
+                .nth(self.lines - 1)
+            {
+                content.truncate(cut + 1);
+                break;
+            }
+            fetch *= 2;
+        }
+        Ok(content)
+    }
+
+    /// Render the start of an object, with a header naming it
+    async fn render(&self, root: &Root, meta: &ObjectMeta) -> Result<Vec<u8>> {
+        let content = self.read_head(root, meta).await?;
+        let mut block = format!("==> {} <==\n", meta.location).into_bytes();
+        if !self.binary && is_binary(&content) {
+            writeln!(
+                block,
+                "(binary content, {} bytes total, use --binary to print it)",
+                meta.size
+            )?;
+        } else {
+            block.extend_from_slice(&content);
+            if !content.ends_with(b"\n") && !content.is_empty() {
+                block.push(b'\n');
+            }
+        }
+        Ok(block)
+    }
+
+    async fn print_all(&self, global_args: &Args) -> Result<()> {
+        let root = &Root::open(listing::read_preamble()?.root()?)?;
+        let mut stdout = tokio::io::stdout();
+        // Fetch concurrently, but print in listing order
+        let mut blocks = std::pin::pin!(listing::read_stdin()
+            .map_ok(|meta| async move { self.render(root, &meta).await })
+            .try_buffered(global_args.concurrency));
+        let mut first = true;
+        while let Some(block) = blocks.try_next().await? {
+            if !first {
+                stdout.write_all(b"\n").await?;
+            }
+            first = false;
+            stdout.write_all(&block).await?;
+        }
+        Ok(stdout.flush().await?)
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        listing::ignore_broken_pipe(self.print_all(global_args).await)
+    }
+}
@@ -0,0 +1,192 @@
+use anyhow::{ensure, Result};
+use chrono::Utc;
+use clap::{Parser, ValueEnum};
+use futures::TryStreamExt;
+use serde::Serialize;
+
+use crate::find::Find;
+use crate::units::{format_size, parse_duration, parse_size};
+use crate::Args;
+
+/// How wide the longest bar is, in characters
+const BAR_WIDTH: usize = 40;
+
+#[derive(Debug, Parser)]
+pub struct Histogram {
+    /// Which objects to count. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// What to put objects into buckets by
+    #[arg(long, value_enum, default_value_t = Field::Size)]
+    field: Field,
+    /// Where each bucket starts, separated by commas. Sizes like `1MiB` for `--field size`,
+    /// and durations like `7d` for `--field age`. There is also a bucket below the first.
+    #[arg(long, value_delimiter = ',')]
+    buckets: Vec<String>,
+    /// Print a chart for people, or one JSON document for scripts
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Field {
+    /// The size in bytes
+    Size,
+    /// How long ago the object was last modified
+    Age,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// The objects in one range of sizes or ages
+#[derive(Debug, Serialize)]
+struct Bucket {
+    label: String,
+    /// The smallest value in the bucket, in bytes or seconds
+    min: u64,
+    /// Where the next bucket starts, unless this is the last one
+    max: Option<u64>,
+    objects: u64,
+    bytes: u64,
+    /// The bytes in this bucket and every one before it
+    cumulative_bytes: u64,
+}
+
+impl Field {
+    fn default_buckets(self) -> &'static [&'static str] {
+        match self {
+            Field::Size => &["1KiB", "1MiB", "100MiB", "1GiB"],
+            Field::Age => &["1d", "7d", "30d", "365d"],
+        }
+    }
+
+    /// Parse a bucket boundary into bytes or seconds
+    fn parse(self, boundary: &str) -> Result<u64> {
+        match self {
+            Field::Size => parse_size(boundary),
+            Field::Age => Ok(parse_duration(boundary)?.as_secs()),
+        }
+    }
+
+    fn format(self, value: u64) -> String {
+        match self {
+            Field::Size => format_size(value),
+            Field::Age => {
+                humantime::format_duration(std::time::Duration::from_secs(value)).to_string()
+            }
+        }
+    }
+}
+
+impl Histogram {
+    /// Parse the boundaries and make an empty bucket for each range between them
+    fn buckets(&self) -> Result<Vec<Bucket>> {
+        let given: Vec<&str> = match self.buckets.is_empty() {
+            true => self.field.default_buckets().to_vec(),
+            false => self.buckets.iter().map(String::as_str).collect(),
+        };
+        let boundaries = given
+            .into_iter()
+            .map(|b| self.field.parse(b))
+            .collect::<Result<Vec<_>>>()?;
+        // The first bucket always starts at zero, so the boundaries have to come after it
+        ensure!(
+            std::iter::once(&0)
+                .chain(&boundaries)
+                .collect::<Vec<_>>()
+                .windows(2)
+                .all(|pair| pair[0] < pair[1]),
+            "--buckets must be above zero and in increasing order"
+        );
+        let starts = std::iter::once(0).chain(boundaries.iter().copied());
+        let ends = boundaries.iter().copied().map(Some).chain([None]);
+        Ok(starts
+            .zip(ends)
+            .map(|(min, max)| Bucket {
+                label: match max {
+                    Some(max) => format!("{} - {}", self.field.format(min), self.field.format(max)),
+                    None => format!("{} +", self.field.format(min)),
+                },
+                min,
+                max,
+                objects: 0,
+                bytes: 0,
+                cumulative_bytes: 0,
+            })
+            .collect())
+    }
+
+    fn print(buckets: &[Bucket]) {
+        let most = buckets.iter().map(|b| b.objects).max().unwrap_or(0).max(1);
+        let widths = [
+            buckets.iter().map(|b| b.label.len()).max().unwrap_or(0),
+            buckets
+                .iter()
+                .map(|b| b.objects.to_string().len())
+                .max()
+                .unwrap_or(0),
+            buckets
+                .iter()
+                .map(|b| format_size(b.bytes).len())
+                .max()
+                .unwrap_or(0),
+            buckets
+                .iter()
+                .map(|b| format_size(b.cumulative_bytes).len())
+                .max()
+                .unwrap_or(0),
+        ];
+        for bucket in buckets {
+            let bar = "#".repeat((bucket.objects as usize * BAR_WIDTH).div_ceil(most as usize));
+            println!(
+                "{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}  {bar}",
+                bucket.label,
+                bucket.objects,
+                format_size(bucket.bytes),
+                format_size(bucket.cumulative_bytes),
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+            );
+        }
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let mut buckets = self.buckets()?;
+        let filter = self.find.filter().await?;
+        let (_, root) = self.find.open()?;
+        // Ages are measured from one moment, so a slow listing doesn't shift them
+        let now = Utc::now();
+        let mut objects = std::pin::pin!(self.find.objects(root.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if !filter.is_match(&meta) {
+                continue;
+            }
+            let value = match self.field {
+                Field::Size => meta.size as u64,
+                Field::Age => (now - meta.last_modified).num_seconds().max(0) as u64,
+            };
+            // Every value is at least 0, so there is always a bucket starting at or below it
+            if let Some(bucket) = buckets.iter_mut().rev().find(|b| b.min <= value) {
+                bucket.objects += 1;
+                bucket.bytes += meta.size as u64;
+            }
+        }
+        let mut cumulative = 0;
+        for bucket in &mut buckets {
+            cumulative += bucket.bytes;
+            bucket.cumulative_bytes = cumulative;
+        }
+
+        match self.format {
+            Format::Text => Self::print(&buckets),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&buckets)?),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,82 @@
+use std::pin::Pin;
+
+use anyhow::{bail, Result};
+use futures::stream::{BoxStream, Peekable, Stream, StreamExt};
+
+/// One step of a merge join: a key that only one side has, or that both sides have
+#[derive(Debug)]
+pub enum Joined<L, R> {
+    Left(L),
+    Right(R),
+    Both(L, R),
+}
+
+type Keyed<'a, T> = Pin<Box<Peekable<BoxStream<'a, Result<(String, T)>>>>>;
+
+/// One of the two sorted inputs to a merge join
+struct Side<'a, T> {
+    name: &'static str,
+    stream: Keyed<'a, T>,
+    last: Option<String>,
+}
+
+impl<T> Side<'_, T> {
+    /// The next key on this side, surfacing any error before comparing anything
+    async fn peek_key(&mut self) -> Result<Option<String>> {
+        match self.stream.as_mut().peek().await {
+            Some(Ok((key, _))) => Ok(Some(key.clone())),
+            Some(Err(_)) => match self.stream.next().await {
+                Some(Err(e)) => Err(e),
+                _ => unreachable!("the peeked item was an error"),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Take the next value, making sure keys only ever increase since the join breaks otherwise
+    async fn take(&mut self) -> Result<T> {
+        let (key, value) = self.stream.next().await.unwrap()?;
+        if let Some(last) = &self.last {
+            if *last > key {
+                bail!(
+                    "The {} listing is not sorted by key: {key} came after {last}",
+                    self.name
+                );
+            }
+        }
+        self.last = Some(key);
+        Ok(value)
+    }
+}
+
+/// Join two streams of (key, value) pairs that are both sorted by key.
+///
+/// This only holds one item from each side at a time, so it works on listings of any size.
+/// Keys are expected to be unique on each side, and the join fails if either side is out of order.
+pub fn merge_join<'a, L: Send + 'a, R: Send + 'a>(
+    left: impl Stream<Item = Result<(String, L)>> + Send + 'a,
+    right: impl Stream<Item = Result<(String, R)>> + Send + 'a,
+) -> impl Stream<Item = Result<Joined<L, R>>> + Send + 'a {
+    let sides = (
+        Side {
+            name: "left",
+            stream: Box::pin(left.boxed().peekable()),
+            last: None,
+        },
+        Side {
+            name: "right",
+            stream: Box::pin(right.boxed().peekable()),
+            last: None,
+        },
+    );
+    futures::stream::try_unfold(sides, |(mut left, mut right)| async move {
+        let joined = match (left.peek_key().await?, right.peek_key().await?) {
+            (None, None) => return Ok(None),
+            (Some(l), Some(r)) if l == r => Joined::Both(left.take().await?, right.take().await?),
+            (Some(l), Some(r)) if l < r => Joined::Left(left.take().await?),
+            (Some(_), None) => Joined::Left(left.take().await?),
+            (_, Some(_)) => Joined::Right(right.take().await?),
+        };
+        Ok(Some((joined, (left, right))))
+    })
+}
@@ -0,0 +1,104 @@
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+
+use crate::find::Find;
+use crate::listing::StdoutWriter;
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Latest {
+    /// Which objects to choose from. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// Group objects by what this regex captures from their key relative to the root.
+    ///
+    /// The first capture group is used if there is one, otherwise the whole match.
+    /// Objects the regex doesn't match are passed through, since they aren't in any group.
+    #[arg(long, conflicts_with = "group_by_prefix")]
+    group_by: Option<String>,
+    /// Group objects by the prefix they are directly under
+    #[arg(long)]
+    group_by_prefix: bool,
+    /// How many of the newest objects to keep in each group
+    #[arg(long, default_value = "1")]
+    keep: usize,
+}
+
+/// The order objects are kept in, newest first, with ties going to the greatest key
+pub fn newest_first(meta: &ObjectMeta) -> Reverse<(chrono::DateTime<chrono::Utc>, &str)> {
+    Reverse((meta.last_modified, meta.location.as_ref()))
+}
+
+impl Latest {
+    /// Which group an object is in, if any
+    fn group(&self, regex: Option<&regex::Regex>, key: &str) -> Option<String> {
+        match regex {
+            Some(regex) => regex.captures(key).map(|captures| {
+                captures
+                    .get(1)
+                    .or(captures.get(0))
+                    .map_or("", |m| m.as_str())
+                    .to_string()
+            }),
+            None => Some(
+                key.rsplit_once('/')
+                    .map_or("", |(prefix, _)| prefix)
+                    .to_string(),
+            ),
+        }
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        if self.group_by.is_none() && !self.group_by_prefix {
+            bail!("Choose how to group objects with --group-by or --group-by-prefix");
+        }
+        ensure!(self.keep > 0, "--keep must be at least 1");
+        let regex = self
+            .group_by
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()?;
+        let filter = self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let mut dropped = 0;
+
+        let mut groups = BTreeMap::<String, Vec<ObjectMeta>>::new();
+        let mut objects = std::pin::pin!(self.find.objects(listed.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if !filter.is_match(&meta) {
+                continue;
+            }
+            let Some(group) = self.group(regex.as_ref(), &root.key(&meta.location)?) else {
+                stdout.write(meta.into()).await?;
+                continue;
+            };
+            // Each group only ever holds the newest few, so memory grows with the number of groups
+            let kept = groups.entry(group).or_default();
+            kept.push(meta);
+            kept.sort_by(|a, b| newest_first(a).cmp(&newest_first(b)));
+            dropped += kept.len().saturating_sub(self.keep);
+            kept.truncate(self.keep);
+        }
+
+        for kept in groups.into_values() {
+            for meta in kept {
+                stdout.write(meta.into()).await?;
+            }
+        }
+        stdout.finish().await?;
+
+        eprintln!("Dropped {dropped} older objects");
+        Ok(())
+    }
+}
@@ -0,0 +1,580 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use url::Url;
+
+use crate::codec::{Codec, Decoder, Encoder};
+use crate::copy::Upload;
+use crate::store::Root;
+use crate::{ObjectExport, Preamble};
+
+/// Interpret a root given on the command line as a URL.
+///
+/// Anything that parses as a URL is used as is, otherwise it is treated as a local path.
+pub fn parse_root(root: &str) -> Result<Url> {
+    // If it parses, it's a URL
+    if let Ok(u) = Url::parse(root) {
+        return Ok(u);
+    }
+    // If it doesn't parse, try interpreting it as a local path
+    // Destinations may not exist yet, in which case they can't be canonicalized
+    let path = match std::path::PathBuf::from(root).canonicalize() {
+        Ok(path) => path,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => std::path::absolute(root)?,
+        Err(e) => return Err(e.into()),
+    };
+    Url::from_file_path(path).map_err(|_| anyhow::anyhow!("Invalid path"))
+}
+
+/// Treat stdout being closed as a normal way to finish, since it usually means the reader has seen enough
+pub fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.kind() == std::io::ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        other => other,
+    }
+}
+
+/// Read the preamble from the first line of stdin
+pub fn read_preamble() -> Result<Preamble> {
+    let mut buf = String::new();
+    std::io::stdin().read_line(&mut buf)?;
+    let preamble: Preamble = serde_json::from_str(&buf)
+        .context("Reading first JSON line as a Preamble. (Remember to include one)")?;
+    Ok(preamble)
+}
+
+/// Produce an asynchronous stream of ObjectMeta objects read as NDJSON from stdin
+///
+/// This should be called after [`read_preamble`] has consumed the first line.
+pub fn read_stdin() -> impl futures::Stream<Item = Result<ObjectMeta>> {
+    read_objects(tokio::io::BufReader::new(tokio::io::stdin()))
+}
+
+/// Like [`read_stdin`], but keeping which of the preamble's roots each object is from
+pub fn read_stdin_exports() -> impl futures::Stream<Item = Result<ObjectExport>> {
+    let reader = tokio::io::BufReader::new(tokio::io::stdin());
+    parse_exports(
+        tokio_stream::wrappers::LinesStream::new(reader.lines()).map_err(anyhow::Error::from),
+    )
+}
+
+/// Like [`read_stdin_exports`], but keeping the checksum `hash` adds to each object, if there is one
+pub fn read_stdin_checksums() -> impl futures::Stream<Item = Result<Checksummed>> {
+    let reader = tokio::io::BufReader::new(tokio::io::stdin());
+    tokio_stream::wrappers::LinesStream::new(reader.lines())
+        .map_err(anyhow::Error::from)
+        .enumerate()
+        .map(|(index, line)| {
+            serde_json::from_str::<Checksummed>(&line?)
+                .with_context(|| format!("Reading line {} as an object", index + 2))
+        })
+}
+
+/// An object in a listing, along with the checksum `hash` adds, if there is one
+#[derive(Debug, serde::Deserialize)]
+pub struct Checksummed {
+    #[serde(flatten)]
+    pub object: ObjectExport,
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+/// Like [`read_stdin`], but for several listings joined together, as by `cat a.ndjson b.ndjson`.
+///
+/// The preambles of the later listings are skipped, as long as they have the same root as `first`.
+pub fn read_stdin_concatenated(
+    first: &Preamble,
+) -> impl futures::Stream<Item = Result<ObjectMeta>> + '_ {
+    /// Either kind of line, since preambles can turn up anywhere
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Line {
+        Preamble(Preamble),
+        Object(ObjectExport),
+    }
+    let reader = tokio::io::BufReader::new(tokio::io::stdin());
+    tokio_stream::wrappers::LinesStream::new(reader.lines())
+        .map_err(anyhow::Error::from)
+        .try_filter_map(move |line| async move {
+            match serde_json::from_str::<Line>(&line)? {
+                Line::Object(object) => Ok(Some(ObjectMeta::from(object))),
+                Line::Preamble(preamble) if preamble.roots() == first.roots() => Ok(None),
+                Line::Preamble(preamble) => anyhow::bail!(
+                    "Listings from different roots can't be combined: {} and {}",
+                    first.root()?,
+                    preamble.root()?
+                ),
+            }
+        })
+}
+
+/// Where a listing is saved, either a local file or an object like `s3://bucket/listing.ndjson`
+#[derive(Debug, Clone)]
+pub enum ListingPath {
+    File(PathBuf),
+    Object(Url),
+}
+
+impl ListingPath {
+    /// Interpret a path or URL given on the command line, where only non-`file` URLs are objects
+    pub fn parse(text: &str) -> Result<Self> {
+        match Url::parse(text) {
+            Ok(url) if url.scheme() == "file" => {
+                Ok(Self::File(url.to_file_path().map_err(|()| {
+                    anyhow::anyhow!("{text} isn't a valid file URL")
+                })?))
+            }
+            Ok(url) => Ok(Self::Object(url)),
+            Err(_) => Ok(Self::File(PathBuf::from(text))),
+        }
+    }
+
+    /// How the listing is compressed, going by the extension of its name
+    fn codec(&self) -> Option<Codec> {
+        let name = match self {
+            Self::File(path) => path.file_name()?.to_str()?,
+            Self::Object(url) => url.path().rsplit('/').next()?,
+        };
+        Codec::from_extension(name.rsplit_once('.')?.1)
+    }
+
+    /// Read the whole listing a chunk at a time
+    async fn chunks(&self) -> Result<BoxStream<'static, Result<Bytes>>> {
+        match self {
+            Self::File(path) => {
+                let file = tokio::fs::File::open(path)
+                    .await
+                    .with_context(|| format!("Opening {self}"))?;
+                Ok(futures::stream::try_unfold(file, |mut file| async move {
+                    let mut chunk = vec![0; 64 * 1024];
+                    let read = file.read(&mut chunk).await?;
+                    chunk.truncate(read);
+                    Ok((read > 0).then(|| (Bytes::from(chunk), file)))
+                })
+                .boxed())
+            }
+            Self::Object(url) => {
+                let root = Root::open(url)?;
+                let body = root
+                    .store
+                    .get(&root.path)
+                    .await
+                    .with_context(|| format!("Opening {self}"))?;
+                Ok(body.into_stream().map_err(anyhow::Error::from).boxed())
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ListingPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Object(url) => write!(f, "{url}"),
+        }
+    }
+}
+
+/// Open a saved listing, reading its preamble and then streaming its objects.
+///
+/// Compressed listings are understood too, and are decompressed as they are read.
+pub async fn read_file(
+    path: &ListingPath,
+) -> Result<(Preamble, impl futures::Stream<Item = Result<ObjectMeta>>)> {
+    let mut lines = read_lines(path).await?;
+    let first = lines
+        .try_next()
+        .await
+        .with_context(|| format!("Reading {path}"))?
+        .unwrap_or_default();
+    let preamble: Preamble = serde_json::from_str(&first)
+        .with_context(|| format!("Reading the first line of {path} as a Preamble"))?;
+    Ok((preamble, parse_objects(lines)))
+}
+
+/// Stream the lines of a file or object, decompressing them first if they're compressed
+pub async fn read_lines(path: &ListingPath) -> Result<BoxStream<'static, Result<String>>> {
+    let mut chunks = path.chunks().await?.peekable();
+    let decoder = match std::pin::Pin::new(&mut chunks).peek().await {
+        Some(Ok(first)) => Codec::from_magic(first).map(Decoder::new).transpose()?,
+        _ => None,
+    };
+    Ok(chunk_lines(chunks, decoder).boxed())
+}
+
+/// Split a stream of chunks into lines as they come, decompressing them first if there's a decoder
+fn chunk_lines(
+    chunks: impl futures::Stream<Item = Result<Bytes>> + Unpin + Send + 'static,
+    decoder: Option<Decoder>,
+) -> impl futures::Stream<Item = Result<String>> + Send {
+    struct State<S> {
+        chunks: S,
+        decoder: Option<Decoder>,
+        done: bool,
+        /// Bytes after the last complete line
+        partial: Vec<u8>,
+        lines: VecDeque<String>,
+    }
+    let state = State {
+        chunks,
+        decoder,
+        done: false,
+        partial: vec![],
+        lines: VecDeque::new(),
+    };
+    futures::stream::try_unfold(state, |mut state| async move {
+        while state.lines.is_empty() && !state.done {
+            let Some(chunk) = state.chunks.try_next().await? else {
+                state.done = true;
+                if let Some(decoder) = state.decoder.take() {
+                    state.partial.extend(decoder.finish()?);
+                }
+                if !state.partial.is_empty() {
+                    let text = String::from_utf8(std::mem::take(&mut state.partial))?;
+                    state.lines.extend(text.lines().map(str::to_string));
+                }
+                continue;
+            };
+            match state.decoder.as_mut() {
+                Some(decoder) => state.partial.extend(decoder.update(&chunk)?),
+                None => state.partial.extend_from_slice(&chunk),
+            }
+            // Everything up to the last newline is whole lines, the rest waits for the next chunk
+            if let Some(end) = state.partial.iter().rposition(|&b| b == b'\n') {
+                let rest = state.partial.split_off(end + 1);
+                let text = String::from_utf8(std::mem::replace(&mut state.partial, rest))?;
+                state.lines.extend(text.lines().map(str::to_string));
+            }
+        }
+        let line = state.lines.pop_front();
+        Ok(line.map(|line| (line, state)))
+    })
+}
+
+/// Saves a listing to a file or an object as it's written, compressing it if its name ends in
+/// `.gz`, `.zst` or `.bz2`
+pub struct ListingWriter {
+    sink: Sink,
+    encoder: Option<Encoder>,
+    /// Lines not written yet, so the encoder and uploads see enough at once
+    pending: Vec<u8>,
+}
+
+enum Sink {
+    File(tokio::io::BufWriter<tokio::fs::File>),
+    /// Multipart uploads only appear once they're completed, so a crashed run never leaves
+    /// a truncated listing behind, and there's no need for a temporary object
+    Object(Box<Upload>),
+}
+
+impl ListingWriter {
+    /// Create the file or start the upload, starting with the preamble
+    pub async fn create(to: &ListingPath, preamble: &Preamble) -> Result<Self> {
+        let sink = match to {
+            ListingPath::File(path) => {
+                let file = tokio::fs::File::create(path)
+                    .await
+                    .with_context(|| format!("Creating {to}"))?;
+                Sink::File(tokio::io::BufWriter::new(file))
+            }
+            ListingPath::Object(url) => {
+                let root = Root::open(url)?;
+                Sink::Object(Box::new(Upload::start(&root, &root.path).await?))
+            }
+        };
+        let mut writer = Self {
+            sink,
+            encoder: to.codec().map(Encoder::new).transpose()?,
+            pending: vec![],
+        };
+        writer.write(preamble).await?;
+        Ok(writer)
+    }
+
+    /// Add a line to the listing
+    pub async fn write(&mut self, item: impl Serialize) -> Result<()> {
+        serde_json::to_writer(&mut self.pending, &item)?;
+        self.pending.push(b'\n');
+        if self.pending.len() >= 1024 * 1024 {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<()> {
+        let data = match &mut self.encoder {
+            Some(encoder) => encoder.update(&self.pending)?,
+            None => std::mem::take(&mut self.pending),
+        };
+        self.pending.clear();
+        match &mut self.sink {
+            Sink::File(file) => file.write_all(&data).await?,
+            Sink::Object(upload) => upload.write(&data).await?,
+        }
+        Ok(())
+    }
+
+    /// Write everything that's left, and put the listing in place
+    pub async fn finish(mut self) -> Result<()> {
+        self.flush().await?;
+        let rest = self.encoder.take().map(Encoder::finish).transpose()?;
+        match self.sink {
+            Sink::File(mut file) => {
+                file.write_all(&rest.unwrap_or_default()).await?;
+                Ok(file.flush().await?)
+            }
+            Sink::Object(mut upload) => {
+                let written = upload.write(&rest.unwrap_or_default()).await;
+                upload.finish_or_abort(written).await
+            }
+        }
+    }
+
+    /// Give up on the listing, so an incomplete upload doesn't linger
+    pub async fn abort(self) {
+        if let Sink::Object(upload) = self.sink {
+            let _ = upload
+                .finish_or_abort(Err(anyhow::anyhow!("Aborted")))
+                .await;
+        }
+    }
+}
+
+/// Parse every remaining line of a listing as an object
+fn read_objects(
+    reader: impl tokio::io::AsyncBufRead + Unpin,
+) -> impl futures::Stream<Item = Result<ObjectMeta>> {
+    parse_objects(
+        tokio_stream::wrappers::LinesStream::new(reader.lines()).map_err(anyhow::Error::from),
+    )
+}
+
+fn parse_objects(
+    lines: impl futures::Stream<Item = Result<String>>,
+) -> impl futures::Stream<Item = Result<ObjectMeta>> {
+    parse_exports(lines).map_ok(ObjectMeta::from)
+}
+
+fn parse_exports(
+    lines: impl futures::Stream<Item = Result<String>>,
+) -> impl futures::Stream<Item = Result<ObjectExport>> {
+    // The preamble is the first line, so objects start on the second
+    lines.enumerate().map(|(index, line)| {
+        serde_json::from_str::<ObjectExport>(&line?)
+            .with_context(|| format!("Reading line {} as an object", index + 2))
+    })
+}
+
+/// A queue that writes lines to stdout.
+///
+/// In case the motivation is not clear, there are a few reasons for this:
+/// * We don't want to panic when the pipe is closed
+/// * We want to make sure only whole lines are written
+/// * We want to include a BufRead for performance
+///
+/// * But synchronous blocking on the main thread is not a super big deal as long as
+///   it isn't on every single line
+pub struct StdoutWriter<T = ObjectExport> {
+    tx: tokio::sync::mpsc::Sender<T>,
+    handle: tokio::task::JoinHandle<Result<()>>,
+    /// Whether lines go to stdout, which stops taking them when it's closed, rather than being saved
+    to_stdout: bool,
+}
+
+impl<T: Serialize + Send + 'static> StdoutWriter<T> {
+    /// Start writing a stream, beginning with its preamble
+    pub fn start(preamble: &Preamble) -> Result<Self> {
+        Self::start_with(Some(preamble))
+    }
+
+    /// Start writing lines that are not an obvious3 stream, such as per-object reports
+    pub fn start_bare() -> Result<Self> {
+        Self::start_with(None)
+    }
+
+    fn start_with(preamble: Option<&Preamble>) -> Result<Self> {
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<T>(100);
+        let mut buffer = std::io::BufWriter::new(std::io::stdout());
+        if let Some(preamble) = preamble {
+            buffer.write_all(serde_json::to_string(preamble).unwrap().as_bytes())?;
+            buffer.write_all(b"\n")?;
+        }
+        let handle = tokio::spawn(async move {
+            while let Some(obj) = rx.recv().await {
+                let mut line = serde_json::to_string(&obj).unwrap();
+                // This is blocking IO and it could cause a momentary pause in the stream
+                // For our use case that is probably okay
+                line.push('\n');
+                if buffer.write_all(line.as_bytes()).is_err() {
+                    // Writing to stdout failed, so we should stop, but we don't need to error
+                    // because probably it's just a broken pipe
+                    return Ok(());
+                }
+                // Don't leave lines sitting in the buffer while waiting for more,
+                // since long running commands like `watch` may not send any for a while
+                if rx.is_empty() && buffer.flush().is_err() {
+                    return Ok(());
+                }
+            }
+            let _ = buffer.flush();
+            Ok(())
+        });
+        Ok(Self {
+            tx,
+            handle,
+            to_stdout: true,
+        })
+    }
+
+    /// Like [`StdoutWriter::start`], but save the stream to a file or an object instead
+    pub async fn save(preamble: &Preamble, to: &ListingPath) -> Result<Self> {
+        let mut writer = ListingWriter::create(to, preamble).await?;
+        let (tx, mut rx) = tokio::sync::mpsc::channel::<T>(100);
+        let handle = tokio::spawn(async move {
+            let mut written = Ok(());
+            while let Some(item) = rx.recv().await {
+                written = writer.write(item).await;
+                if written.is_err() {
+                    break;
+                }
+            }
+            match written {
+                Ok(()) => writer.finish().await,
+                Err(e) => {
+                    writer.abort().await;
+                    Err(e)
+                }
+            }
+        });
+        Ok(Self {
+            tx,
+            handle,
+            to_stdout: false,
+        })
+    }
+
+    /// Write a line to stdout.
+    ///
+    /// Once stdout is closed this fails like a broken pipe, for [`ignore_broken_pipe`] to tell apart.
+    pub async fn write(&self, item: T) -> Result<()> {
+        self.tx.send(item).await.map_err(|_| match self.to_stdout {
+            true => std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Stdout was closed").into(),
+            false => anyhow::anyhow!("Saving the listing failed"),
+        })
+    }
+
+    /// Wait for every queued line to be written
+    pub async fn finish(self) -> Result<()> {
+        drop(self.tx);
+        self.handle.await?
+    }
+}
+
+/// Reports objects that a command failed to process.
+///
+/// Failures always go to stderr, and can also be saved as a listing (preamble included)
+/// so they can be retried by piping the file back into the same command.
+pub struct FailureLog {
+    file: Option<Mutex<std::io::BufWriter<std::fs::File>>>,
+    count: AtomicUsize,
+}
+
+impl FailureLog {
+    /// Start a log, saving failures to `path` as well as stderr if one is given
+    pub fn create(path: Option<&std::path::Path>, preamble: &Preamble) -> Result<Self> {
+        let file = path
+            .map(|path| -> Result<_> {
+                let mut file = std::io::BufWriter::new(
+                    std::fs::File::create(path)
+                        .with_context(|| format!("Creating {}", path.display()))?,
+                );
+                file.write_all(serde_json::to_string(preamble)?.as_bytes())?;
+                file.write_all(b"\n")?;
+                Ok(Mutex::new(file))
+            })
+            .transpose()?;
+        Ok(Self {
+            file,
+            count: AtomicUsize::new(0),
+        })
+    }
+
+    /// Record that an object failed, and why
+    pub fn record(&self, meta: &ObjectMeta, error: &anyhow::Error) -> Result<()> {
+        eprintln!("{}: {error:#}", meta.location);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        if let Some(file) = &self.file {
+            let mut line = serde_json::to_string(&ObjectExport::from(meta.clone()))?;
+            line.push('\n');
+            file.lock().unwrap().write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Flush the saved listing and return how many objects failed
+    pub fn finish(self) -> Result<usize> {
+        if let Some(file) = self.file {
+            file.into_inner().unwrap().flush()?;
+        }
+        Ok(self.count.into_inner())
+    }
+}
+
+/// How many objects [`delete_all`] deleted, or would have in a dry run, and their total size
+#[derive(Debug, Default)]
+pub struct Deleted {
+    pub count: usize,
+    pub bytes: u64,
+}
+
+/// Delete objects as they come, or only count them without `yes`, printing each one to
+/// `writer` if there is one.
+///
+/// Failures are recorded in `failures` rather than returned, so one stubborn object doesn't
+/// stop the rest.
+pub async fn delete_all(
+    root: &Root,
+    objects: impl futures::Stream<Item = Result<ObjectMeta>>,
+    yes: bool,
+    writer: Option<&StdoutWriter>,
+    failures: &FailureLog,
+    concurrency: usize,
+) -> Result<Deleted> {
+    let count = &AtomicUsize::new(0);
+    let bytes = &AtomicU64::new(0);
+    objects
+        .try_for_each_concurrent(concurrency, |meta| async move {
+            if yes {
+                if let Err(e) = root.store.delete(&meta.location).await {
+                    let e = anyhow::Error::from(e).context("Failed to delete");
+                    return failures.record(&meta, &e);
+                }
+            }
+            count.fetch_add(1, Ordering::Relaxed);
+            bytes.fetch_add(meta.size as u64, Ordering::Relaxed);
+            match writer {
+                Some(writer) => writer.write(meta.into()).await,
+                None => Ok(()),
+            }
+        })
+        .await?;
+    Ok(Deleted {
+        count: count.load(Ordering::Relaxed),
+        bytes: bytes.load(Ordering::Relaxed),
+    })
+}
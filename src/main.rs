@@ -1,13 +1,69 @@
-
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use clap::{Parser, Subcommand};
 use object_store::path::Path as ObjectStorePath;
-use object_store::{ObjectMeta};
+use object_store::ObjectMeta;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+mod append;
+mod cat;
+mod checksum;
+mod codec;
+mod compress;
+mod concat;
+mod copy;
+mod cost;
+mod decompress;
+mod dedupe;
+mod diff;
+mod du;
+mod exec;
+mod expire;
+mod expr;
 mod find;
+mod fsck;
+mod generate;
+mod get;
+mod glob;
+mod grep;
+mod hash;
+mod head;
+mod histogram;
+mod join;
+mod latest;
+mod listing;
+mod merge;
+mod mv;
+mod presign;
+mod prune;
+mod put;
+mod quarantine;
+mod random;
+mod range_get;
+mod rename;
+mod rm;
+mod sample;
+mod set_meta;
+mod snapshot;
+mod sort;
+mod split;
+mod stat;
+mod store;
+mod summary;
+mod sync;
+mod tag;
+mod tar;
+mod top;
+mod touch;
+mod tree;
+mod undelete;
+mod uniq;
+mod units;
+mod untar;
+mod verify;
+mod versions;
+mod watch;
 
 #[derive(Debug, Parser)]
 struct Args {
@@ -24,13 +80,273 @@ enum IOAction {
     /// List objects recursively, and filter them by various criteria. Can be chained.
     ///
     /// Example: `obvious3 find -r /path -b '.*\.parquet' | obvious3 find --not --after 3`
-    Find(find::Find),
+    Find(Box<find::FindCommand>),
+    /// Copy every object in a listing read from stdin to another root.
+    /// Directory markers, like `find --skip-dir-markers` leaves out, aren't copied.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/logs -b '\.gz$' | obvious3 cp --dest s3://archive/logs`
+    Cp(copy::Cp),
+    /// Delete objects, either from a listing on stdin or by listing a root directly.
+    ///
+    /// Only prints what would be deleted unless `--yes` is passed.
+    ///
+    /// Example: `obvious3 find -r s3://bucket --before 2592000 | obvious3 rm --yes`
+    Rm(rm::Rm),
+    /// Move every object in a listing read from stdin to another root.
+    ///
+    /// Each copy is checked before the original is deleted.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/old | obvious3 mv --dest s3://bucket/new`
+    Mv(mv::Mv),
+    /// Download every object in a listing read from stdin into a local directory.
+    /// Directory markers, like `find --skip-dir-markers` leaves out, aren't downloaded.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/logs | obvious3 get --dest ./downloads`
+    Get(get::Get),
+    /// Upload local files into an object store, printing the uploaded objects as a listing.
+    ///
+    /// Example: `obvious3 put --root ./data --dest s3://bucket/prefix`
+    Put(put::Put),
+    /// Copy objects that are missing or have a different size from one root to another.
+    ///
+    /// With `--delete`, also remove destination objects missing from the source.
+    ///
+    /// Example: `obvious3 sync --from s3://a/prefix --to gs://b/prefix --dry-run`
+    Sync(sync::Synchronize),
+    /// Print the contents of an object, or of every object in a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/logs -b '\.json$' | obvious3 cat | jq .`
+    Cat(cat::Cat),
+    /// Print the first lines or bytes of every object in a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket -b '\.csv$' | obvious3 head --lines 5`
+    Head(head::Head),
+    /// Look up the metadata of specific objects, without listing anything.
+    ///
+    /// Example: `obvious3 stat s3://bucket/path/key.parquet`
+    Stat(stat::Stat),
+    /// Add a checksum of each object's contents to a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/data | obvious3 hash --algo sha256 > manifest.ndjson`
+    Hash(hash::Hash),
+    /// Total the sizes and counts of objects under each prefix, like `du` does for directories.
+    ///
+    /// Example: `obvious3 find -r s3://bucket | obvious3 du --depth 2`
+    Du(du::Du),
+    /// Find groups of identical objects in a listing read from stdin, and optionally delete the copies.
+    ///
+    /// Example: `obvious3 find -r s3://bucket | obvious3 dedupe --delete-duplicates --yes`
+    Dedupe(dedupe::Dedupe),
+    /// Compare two listings or roots, printing the objects that were added, removed or changed.
+    ///
+    /// Exits with an error if there are any differences.
+    ///
+    /// Example: `obvious3 diff --left yesterday.ndjson --right s3://bucket/data`
+    Diff(diff::Diff),
+    /// Bundle every object in a listing read from stdin into a tar archive, in listing order.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/small | obvious3 tar --gzip > bundle.tar.gz`
+    Tar(tar::Tar),
+    /// Extract a tar archive into objects under a root, printing them as a listing.
+    ///
+    /// Example: `obvious3 untar --src s3://bucket/bundle.tar.gz --dest s3://bucket/extracted/`
+    Untar(untar::Untar),
+    /// Write a compressed copy of every object in a listing read from stdin to another root.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/json | obvious3 compress --dest s3://bucket/compressed/`
+    Compress(compress::Compress),
+    /// Write a decompressed copy of every object in a listing read from stdin to another root.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/compressed | obvious3 decompress --dest s3://bucket/json/`
+    Decompress(decompress::Decompress),
+    /// Refresh the last modified time of every object in a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/keep | obvious3 touch`
+    Touch(touch::Touch),
+    /// Rename every object in a listing read from stdin by rewriting its path with a regex.
+    ///
+    /// Example: `obvious3 find -r s3://bucket -p 'raw/(\d{4})-(\d{2})/(.*)' | obvious3 rename --to 'curated/$1/$2/$3'`
+    Rename(rename::Rename),
+    /// Check that the objects in a saved listing still exist unchanged, exiting with an error if not.
+    ///
+    /// Example: `obvious3 verify --input manifest.ndjson`
+    Verify(verify::Verify),
+    /// Print a time-limited signed URL for each object in a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/reports | obvious3 presign --expires 24h`
+    Presign(presign::Presign),
+    /// Run a command for each object in a listing read from stdin, like xargs.
+    ///
+    /// Example: `obvious3 find -r s3://bucket -b '\.parquet$' | obvious3 exec -- duckdb -c "select count(*) from read_parquet('{url}')"`
+    Exec(exec::Exec),
+    /// Search the contents of every object in a listing read from stdin, printing matching lines.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/logs | obvious3 grep -e 'ERROR|panic'`
+    Grep(grep::Grep),
+    /// Join every object in a listing read from stdin into a single object.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/shards | obvious3 concat --dest s3://bucket/merged.ndjson --separator '\n'`
+    Concat(concat::Concat),
+    /// Divide a listing read from stdin into several listing files, to share work out.
+    ///
+    /// Example: `obvious3 find -r s3://bucket | obvious3 split --shards 16 --out 'work-{index}.ndjson'`
+    Split(split::Split),
+    /// Pick a random subset of objects, from a root or a listing read from stdin.
+    ///
+    /// Example: `obvious3 sample -r s3://bucket/logs --count 100 --seed 7`
+    Sample(sample::Sample),
+    /// Sort a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket | obvious3 sort --by size --reverse`
+    Sort(sort::Sort),
+    /// Drop repeated locations from a listing read from stdin, such as several listings joined together.
+    ///
+    /// Example: `cat monday.ndjson tuesday.ndjson | obvious3 uniq --keep newest`
+    Uniq(uniq::Uniq),
+    /// Combine several saved listings into one stream.
+    ///
+    /// Example: `obvious3 merge monday.ndjson tuesday.ndjson.gz --sorted`
+    Merge(merge::Merge),
+    /// Print totals and statistics over objects, from a root or a listing read from stdin.
+    ///
+    /// Example: `obvious3 summary -r s3://bucket/logs --format json`
+    Summary(summary::Summary),
+    /// Draw objects as a tree of prefixes, from a root or a listing read from stdin.
+    ///
+    /// Example: `obvious3 tree -r s3://bucket --depth 2`
+    Tree(tree::Tree),
+    /// Show the largest, oldest, newest or most deeply nested objects, from a root or a listing read from stdin.
+    ///
+    /// Example: `obvious3 top -r s3://bucket --by size --count 50`
+    Top(top::Top),
+    /// Count objects in buckets of size or age, from a root or a listing read from stdin.
+    ///
+    /// Example: `obvious3 histogram -r s3://bucket --field size --buckets 1KiB,1MiB,100MiB,1GiB`
+    Histogram(histogram::Histogram),
+    /// List a root over and over, printing objects that are new or changed since the last time.
+    ///
+    /// Example: `obvious3 watch -r s3://bucket/incoming --interval 30s --state-file incoming.json`
+    Watch(watch::Watch),
+    /// Save a listing of a root to a file, or print only what changed in the root since one was saved.
+    ///
+    /// Example: `obvious3 snapshot diff --base monday.ndjson.gz | obvious3 cp --dest file:///backup/`
+    Snapshot(snapshot::Snapshot),
+    /// Keep only the newest objects in each group, grouping by a regex capture or by prefix.
+    ///
+    /// Example: `obvious3 latest -r s3://bucket/tables --group-by '^([^/]+)/part-'`
+    Latest(latest::Latest),
+    /// Keep the newest objects in each prefix and delete the rest, as a dry run unless given `--yes`.
+    ///
+    /// Example: `obvious3 prune -r s3://backups/daily --keep 14 --group-by-prefix 1 --yes`
+    Prune(prune::Prune),
+    /// Delete objects older than a given age, as a dry run unless given `--yes`.
+    ///
+    /// Example: `obvious3 expire -r s3://bucket/tmp --older-than 30d --min-keep 5 --yes`
+    Expire(expire::Expire),
+    /// Move the objects in a listing read from stdin under a holding root, saving a manifest to undo it.
+    ///
+    /// Example: `obvious3 find -r s3://bucket -b '\.bad$' | obvious3 quarantine --dest s3://bucket/_quarantine --manifest-out held.ndjson`
+    Quarantine(quarantine::Quarantine),
+    /// Set the tags on objects, from a root or a listing read from stdin, replacing any they had.
+    ///
+    /// Only S3 and Azure can store tags, and tags can't be read back, so `get` and `rm` always fail.
+    ///
+    /// Example: `obvious3 tag set retention=30d -r s3://bucket/logs`
+    Tag(tag::Tag),
+    /// Rewrite the content type, cache control or user metadata of every object in a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/reports -b '\.csv$' | obvious3 set-meta --content-type auto`
+    SetMeta(set_meta::SetMeta),
+    /// List every version of each object, with `is_latest` and `is_delete_marker` fields.
+    ///
+    /// The stores can't list versions yet, so for now this only shows the latest version of each object,
+    /// with a warning.
+    ///
+    /// Example: `obvious3 versions -r s3://bucket/prefix`
+    Versions(versions::Versions),
+    /// Bring back objects whose latest version is a delete marker, reading the output of `versions` from stdin.
+    ///
+    /// Only a dry run unless given `--yes`. Until `versions` can list delete markers, this needs versions
+    /// listed some other way, and refuses a listing without any delete markers rather than doing nothing.
+    ///
+    /// Example: `obvious3 versions -r s3://bucket/prefix | obvious3 undelete --yes`
+    Undelete(undelete::Undelete),
+    /// Read byte ranges of an object, or of every object in a listing read from stdin.
+    ///
+    /// Example: `obvious3 find -r s3://bucket/tables -b '\.parquet$' | obvious3 range-get --suffix 8`
+    RangeGet(range_get::RangeGet),
+    /// Add data from stdin, or from other objects, to the end of an object.
+    ///
+    /// Example: `gzip -c today.ndjson | obvious3 append --dest s3://bucket/log.ndjson.gz`
+    Append(append::Append),
+    /// Create a reproducible tree of objects with random names, sizes and contents, for testing.
+    ///
+    /// Example: `obvious3 generate -r file:///tmp/test --count 10000 --size 1KiB..10MiB --depth 3 --seed 42`
+    Generate(generate::Generate),
+    /// Compare a manifest with a live listing in both directions, reporting a status for every key.
+    ///
+    /// Exits with an error unless every key is `ok`.
+    ///
+    /// Example: `obvious3 fsck --manifest manifest.ndjson -r s3://bucket/prefix`
+    Fsck(fsck::Fsck),
+    /// Estimate the monthly storage cost of a root or a listing read from stdin, by class and by prefix.
+    ///
+    /// Example: `obvious3 cost -r s3://bucket --assume-class s3-standard-ia`
+    Cost(cost::Cost),
 }
 
 impl IOAction {
     async fn run(&self, global_args: &Args) -> Result<()> {
         match self {
             IOAction::Find(f) => f.run(global_args).await,
+            IOAction::Cp(c) => c.run(global_args).await,
+            IOAction::Rm(r) => r.run(global_args).await,
+            IOAction::Mv(m) => m.run(global_args).await,
+            IOAction::Get(g) => g.run(global_args).await,
+            IOAction::Put(p) => p.run(global_args).await,
+            IOAction::Sync(s) => s.run(global_args).await,
+            IOAction::Cat(c) => c.run(global_args).await,
+            IOAction::Head(h) => h.run(global_args).await,
+            IOAction::Stat(s) => s.run(global_args).await,
+            IOAction::Hash(h) => h.run(global_args).await,
+            IOAction::Du(d) => d.run(global_args).await,
+            IOAction::Dedupe(d) => d.run(global_args).await,
+            IOAction::Diff(d) => d.run(global_args).await,
+            IOAction::Tar(t) => t.run(global_args).await,
+            IOAction::Untar(u) => u.run(global_args).await,
+            IOAction::Compress(c) => c.run(global_args).await,
+            IOAction::Decompress(d) => d.run(global_args).await,
+            IOAction::Touch(t) => t.run(global_args).await,
+            IOAction::Rename(r) => r.run(global_args).await,
+            IOAction::Verify(v) => v.run(global_args).await,
+            IOAction::Presign(p) => p.run(global_args).await,
+            IOAction::Exec(e) => e.run(global_args).await,
+            IOAction::Grep(g) => g.run(global_args).await,
+            IOAction::Concat(c) => c.run(global_args).await,
+            IOAction::Split(s) => s.run(global_args).await,
+            IOAction::Sample(s) => s.run(global_args).await,
+            IOAction::Sort(s) => s.run(global_args).await,
+            IOAction::Uniq(u) => u.run(global_args).await,
+            IOAction::Merge(m) => m.run(global_args).await,
+            IOAction::Summary(s) => s.run(global_args).await,
+            IOAction::Tree(t) => t.run(global_args).await,
+            IOAction::Top(t) => t.run(global_args).await,
+            IOAction::Histogram(h) => h.run(global_args).await,
+            IOAction::Watch(w) => w.run(global_args).await,
+            IOAction::Snapshot(s) => s.run(global_args).await,
+            IOAction::Latest(l) => l.run(global_args).await,
+            IOAction::Prune(p) => p.run(global_args).await,
+            IOAction::Expire(e) => e.run(global_args).await,
+            IOAction::Quarantine(q) => q.run(global_args).await,
+            IOAction::Tag(t) => t.run(global_args).await,
+            IOAction::SetMeta(s) => s.run(global_args).await,
+            IOAction::Versions(v) => v.run(global_args).await,
+            IOAction::Undelete(u) => u.run(global_args).await,
+            IOAction::RangeGet(r) => r.run(global_args).await,
+            IOAction::Append(a) => a.run(global_args).await,
+            IOAction::Generate(g) => g.run(global_args).await,
+            IOAction::Fsck(f) => f.run(global_args).await,
+            IOAction::Cost(c) => c.run(global_args).await,
         }
     }
 }
@@ -55,19 +371,64 @@ pub struct ObjectExport {
     /// The unique identifier for the object
     ///
     /// <https://datatracker.ietf.org/doc/html/rfc9110#name-etag>
+    ///
+    /// Like `version`, it's always printed, as null if there isn't one,
+    /// but it can be left out of listings that are read in.
+    #[serde(default)]
     pub e_tag: Option<String>,
     /// A version indicator for this object
+    #[serde(default)]
     pub version: Option<String>,
+    /// The storage class the store keeps the object in, like `GLACIER`.
+    /// Always printed too, as null for stores that don't report one.
+    #[serde(default)]
+    pub storage_class: Option<String>,
+    /// Which of the preamble's roots the object was listed from, when there are several
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<usize>,
+    /// More fields to print, like the regex captures of `find --captures`.
+    /// Commands that pass objects on unchanged, like `sort`, print them again.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+/// What a listing says about an object besides its metadata, for commands that pass it on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Annotations {
+    pub root: Option<usize>,
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ObjectExport {
+    /// Split the object's metadata from the rest, to put back together with [`Self::annotated`]
+    pub fn split(mut self) -> (ObjectMeta, Annotations) {
+        let annotations = Annotations {
+            root: self.root,
+            extra: std::mem::take(&mut self.extra),
+        };
+        (self.into(), annotations)
+    }
+
+    pub fn annotated(meta: ObjectMeta, annotations: Annotations) -> Self {
+        Self {
+            root: annotations.root,
+            extra: annotations.extra,
+            ..meta.into()
+        }
+    }
 }
 
 impl From<ObjectMeta> for ObjectExport {
     fn from(meta: ObjectMeta) -> Self {
         Self {
+            storage_class: store::storage_class(&meta).map(String::from),
             location: meta.location.to_string(),
             last_modified: meta.last_modified,
             size: meta.size,
-            e_tag: meta.e_tag,
-            version: meta.version,
+            e_tag: present(meta.e_tag),
+            version: present(meta.version),
+            root: None,
+            extra: Default::default(),
         }
     }
 }
@@ -78,12 +439,17 @@ impl From<ObjectExport> for ObjectMeta {
             location: ObjectStorePath::from(export.location),
             last_modified: export.last_modified,
             size: export.size,
-            e_tag: export.e_tag,
-            version: export.version,
+            e_tag: present(export.e_tag),
+            version: present(export.version),
         }
     }
 }
 
+/// An etag or version, unless it's empty, as some stores report one they don't have
+fn present(field: Option<String>) -> Option<String> {
+    field.filter(|field| !field.trim_matches('"').is_empty())
+}
+
 /// A header line for each object listing,
 /// which includes the object store they refer to, along with some metadata.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -93,5 +459,143 @@ pub enum Preamble {
     Obvious3_0 {
         /// The object store that the objects are from
         root: Url,
+        /// The regex the objects' paths were last filtered by, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path_match: Option<String>,
     },
+    /// Protocol version 1, for listings of several roots at once
+    Obvious3_1 {
+        /// The roots the objects were listed from, which each object refers to by index
+        roots: Vec<Url>,
+        /// The regex the objects' paths were last filtered by, if any
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path_match: Option<String>,
+    },
+}
+
+impl Preamble {
+    pub fn new(root: Url) -> Self {
+        Preamble::Obvious3_0 {
+            root,
+            path_match: None,
+        }
+    }
+
+    /// A preamble for several roots, which stays at version 0 when there's only one
+    pub fn with_roots(mut roots: Vec<Url>) -> Self {
+        match roots.len() {
+            1 => Self::new(roots.remove(0)),
+            _ => Preamble::Obvious3_1 {
+                roots,
+                path_match: None,
+            },
+        }
+    }
+
+    /// The root URL of the object store the listing refers to.
+    ///
+    /// Objects in a listing of several roots each say which one they're from, and might be in
+    /// different stores, so it's an error to treat them all as being from one.
+    pub fn root(&self) -> Result<&Url> {
+        match self.roots() {
+            [root] => Ok(root),
+            roots => anyhow::bail!(
+                "This listing is of {} roots, but only find can read a listing of several. \
+                 List the roots one at a time instead.",
+                roots.len()
+            ),
+        }
+    }
+
+    /// Every root the listing refers to
+    pub fn roots(&self) -> &[Url] {
+        match self {
+            Preamble::Obvious3_0 { root, .. } => std::slice::from_ref(root),
+            Preamble::Obvious3_1 { roots, .. } => roots,
+        }
+    }
+
+    /// The regex the objects' paths were last filtered by, if any
+    pub fn path_match(&self) -> Option<&str> {
+        match self {
+            Preamble::Obvious3_0 { path_match, .. } | Preamble::Obvious3_1 { path_match, .. } => {
+                path_match.as_deref()
+            }
+        }
+    }
+
+    /// Record the regex that objects' paths have now been filtered by
+    pub fn set_path_match(&mut self, regex: &str) {
+        match self {
+            Preamble::Obvious3_0 { path_match, .. } | Preamble::Obvious3_1 { path_match, .. } => {
+                *path_match = Some(regex.to_string())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_listings_of_one_root_have_a_root() {
+        let one: Preamble =
+            serde_json::from_str(r#"{"file_type":"Obvious3_0","root":"file:///tmp/a"}"#).unwrap();
+        assert_eq!(one.root().unwrap().as_str(), "file:///tmp/a");
+
+        let several: Preamble =
+            serde_json::from_str(r#"{"file_type":"Obvious3_1","roots":["s3://a/x","s3://b/y"]}"#)
+                .unwrap();
+        assert_eq!(several.roots().len(), 2);
+        assert!(several.root().is_err());
+    }
+
+    #[test]
+    fn objects_keep_their_root_when_read_back() {
+        let line = r#"{"location":"y/k","last_modified":"2024-01-01T00:00:00Z","size":1,"root":1}"#;
+        let object: ObjectExport = serde_json::from_str(line).unwrap();
+        assert_eq!(object.root, Some(1));
+        let written = serde_json::to_string(&object).unwrap();
+        assert!(written.contains(r#""root":1"#), "{written}");
+    }
+
+    #[test]
+    fn objects_keep_their_added_fields_when_read_back() {
+        let line = r#"{"location":"y/k.csv","last_modified":"2024-01-01T00:00:00Z","size":1,"e_tag":null,"version":null,"storage_class":null,"basename":"k.csv","dataset":null,"depth":2}"#;
+        let object: ObjectExport = serde_json::from_str(line).unwrap();
+        assert_eq!(object.extra["basename"], "k.csv");
+        assert_eq!(object.extra.len(), 3);
+
+        let (meta, annotations) = object.clone().split();
+        assert_eq!(meta.location.as_ref(), "y/k.csv");
+        let again = ObjectExport::annotated(meta, annotations);
+        assert_eq!(again, object);
+        let written: serde_json::Value = serde_json::to_value(&again).unwrap();
+        assert_eq!(
+            written,
+            serde_json::from_str::<serde_json::Value>(line).unwrap()
+        );
+    }
+
+    #[test]
+    fn missing_etags_and_versions_are_read_any_way_and_printed_as_null() {
+        for fields in [
+            "",
+            r#","e_tag":null,"version":null"#,
+            r#","e_tag":"","version":"""#,
+            r#","e_tag":"\"\"","version":"""#,
+        ] {
+            let line = format!(
+                r#"{{"location":"y/k","last_modified":"2024-01-01T00:00:00Z","size":1{fields}}}"#
+            );
+            let meta: ObjectMeta = serde_json::from_str::<ObjectExport>(&line).unwrap().into();
+            assert_eq!((&meta.e_tag, &meta.version), (&None, &None), "{line}");
+            let written = serde_json::to_value(ObjectExport::from(meta)).unwrap();
+            assert_eq!(written["e_tag"], serde_json::Value::Null, "{line}");
+            assert_eq!(written["version"], serde_json::Value::Null, "{line}");
+            assert_eq!(written["storage_class"], serde_json::Value::Null, "{line}");
+            assert!(written.as_object().unwrap().contains_key("e_tag"));
+        }
+    }
 }
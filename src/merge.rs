@@ -0,0 +1,159 @@
+use std::collections::HashMap;
+
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::listing::{self, ListingPath, StdoutWriter};
+use crate::store::Root;
+use crate::uniq::Keep;
+use crate::{Args, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Merge {
+    /// The listings to combine, which may be compressed, as files or objects in a store
+    #[arg(required = true, value_parser = ListingPath::parse)]
+    inputs: Vec<ListingPath>,
+    /// Every input is already sorted by location, so merge them in order without holding them in memory
+    #[arg(long)]
+    sorted: bool,
+    /// Which record to keep when several inputs have the same location
+    #[arg(long, value_enum, default_value_t = Keep::Newest)]
+    conflict: Keep,
+}
+
+impl Merge {
+    /// Work out one preamble that describes every input
+    fn combined_preamble(preambles: &[Preamble]) -> Result<Preamble> {
+        let roots = preambles
+            .iter()
+            .map(Preamble::root)
+            .collect::<Result<Vec<_>>>()?;
+        let first = roots[0];
+        if roots.iter().all(|&root| root == first) {
+            return Ok(Preamble::new(first.clone()));
+        }
+        // Locations are full paths within the store, so the top of the store works for every input
+        let root = Root::open(first)?;
+        for &other in &roots[1..] {
+            if !root.same_store(&root.open_sibling(other)?) {
+                bail!(
+                    "{first} and {other} are in different stores, which one listing can't describe"
+                );
+            }
+        }
+        Ok(Preamble::new(root.store_url()?))
+    }
+
+    /// Interleave sorted inputs into one sorted stream, resolving repeated locations as they meet
+    async fn merge_sorted(
+        &self,
+        mut inputs: Vec<BoxStream<'_, Result<ObjectMeta>>>,
+        stdout: &StdoutWriter,
+    ) -> Result<usize> {
+        let mut heads = Vec::with_capacity(inputs.len());
+        for input in &mut inputs {
+            heads.push(input.try_next().await?);
+        }
+        let mut pending: Option<ObjectMeta> = None;
+        let mut conflicts = 0;
+        loop {
+            let next = heads
+                .iter()
+                .enumerate()
+                .filter_map(|(index, head)| Some((index, head.as_ref()?)))
+                .min_by(|(_, a), (_, b)| a.location.cmp(&b.location))
+                .map(|(index, _)| index);
+            let Some((index, Some(meta))) = next.map(|index| (index, heads[index].take())) else {
+                break;
+            };
+            heads[index] = inputs[index].try_next().await?;
+            if let Some(following) = &heads[index] {
+                ensure!(
+                    following.location >= meta.location,
+                    "{} isn't sorted: {} comes after {}",
+                    self.inputs[index],
+                    following.location,
+                    meta.location
+                );
+            }
+
+            pending = match pending {
+                Some(kept) if kept.location == meta.location => {
+                    conflicts += 1;
+                    Some(if self.conflict.prefers(&meta, &kept) {
+                        meta
+                    } else {
+                        kept
+                    })
+                }
+                Some(kept) => {
+                    stdout.write(kept.into()).await?;
+                    Some(meta)
+                }
+                None => Some(meta),
+            };
+        }
+        if let Some(kept) = pending {
+            stdout.write(kept.into()).await?;
+        }
+        Ok(conflicts)
+    }
+
+    /// Combine inputs in any order, keeping one record per location in memory
+    async fn merge_unsorted(
+        &self,
+        inputs: Vec<BoxStream<'_, Result<ObjectMeta>>>,
+        stdout: &StdoutWriter,
+    ) -> Result<usize> {
+        let mut kept = HashMap::<ObjectStorePath, (usize, ObjectMeta)>::new();
+        let mut conflicts = 0;
+        let mut objects = futures::stream::iter(inputs).flatten();
+        while let Some(meta) = objects.try_next().await? {
+            let position = kept.len();
+            match kept.get_mut(&meta.location) {
+                Some((_, existing)) => {
+                    conflicts += 1;
+                    if self.conflict.prefers(&meta, existing) {
+                        *existing = meta;
+                    }
+                }
+                None => {
+                    kept.insert(meta.location.clone(), (position, meta));
+                }
+            }
+        }
+        let mut kept: Vec<_> = kept.into_values().collect();
+        kept.sort_by_key(|(position, _)| *position);
+        for (_, meta) in kept {
+            stdout.write(meta.into()).await?;
+        }
+        Ok(conflicts)
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let mut preambles = vec![];
+        let mut inputs = vec![];
+        for path in &self.inputs {
+            let (preamble, objects) = listing::read_file(path).await?;
+            preambles.push(preamble);
+            inputs.push(objects.boxed());
+        }
+        let stdout = StdoutWriter::start(&Self::combined_preamble(&preambles)?)?;
+        let conflicts = if self.sorted {
+            self.merge_sorted(inputs, &stdout).await?
+        } else {
+            self.merge_unsorted(inputs, &stdout).await?
+        };
+        stdout.finish().await?;
+
+        eprintln!(
+            "Merged {} listings, resolving {conflicts} repeated locations",
+            self.inputs.len()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,249 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::copy::{copy_object, MAX_SINGLE_COPY};
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Mv {
+    /// The root to move objects into. Paths relative to the source root are preserved.
+    #[arg(short, long)]
+    dest: String,
+    /// Also save the objects that failed to move here, as a listing that can be piped back in
+    #[arg(long)]
+    failed_out: Option<PathBuf>,
+}
+
+/// Copy an object, make sure it arrived intact, and only then delete the original
+pub async fn move_object(
+    source: &Root,
+    meta: &ObjectMeta,
+    dest: &Root,
+    location: &ObjectStorePath,
+) -> Result<ObjectMeta> {
+    // The copy would do nothing, and deleting the source would then lose the object
+    ensure!(
+        !source.is_same_object(&meta.location, dest, location),
+        "{location} would be moved onto itself, not deleting it"
+    );
+    copy_object(source, meta, dest, location).await?;
+    finish_move(source, meta, dest, location).await
+}
+
+/// Move an object like [`move_object`], but never onto something already there, where the
+/// store can copy on that condition.
+///
+/// Otherwise, as when the object has to be streamed or the store can't check, it's moved as
+/// usual, and only whoever picked the location can keep others away from it.
+pub async fn move_object_if_new(
+    source: &Root,
+    meta: &ObjectMeta,
+    dest: &Root,
+    location: &ObjectStorePath,
+) -> Result<ObjectMeta> {
+    if source.same_store(dest) && meta.size <= MAX_SINGLE_COPY {
+        match source
+            .store
+            .copy_if_not_exists(&meta.location, location)
+            .await
+        {
+            Ok(()) => return finish_move(source, meta, dest, location).await,
+            Err(object_store::Error::AlreadyExists { .. }) => {
+                bail!("{location} already exists, not overwriting it")
+            }
+            Err(object_store::Error::NotSupported { .. } | object_store::Error::NotImplemented) => {
+            }
+            Err(e) => return Err(e.into()),
+        }
+    }
+    move_object(source, meta, dest, location).await
+}
+
+/// Make sure a copy arrived intact, and only then delete the original
+async fn finish_move(
+    source: &Root,
+    meta: &ObjectMeta,
+    dest: &Root,
+    location: &ObjectStorePath,
+) -> Result<ObjectMeta> {
+    let copied = dest.store.head(location).await?;
+    ensure!(
+        copied.size == meta.size,
+        "Copy at {location} has {} bytes but {} were expected, not deleting the source",
+        copied.size,
+        meta.size
+    );
+    source.store.delete(&meta.location).await?;
+    Ok(copied)
+}
+
+impl Mv {
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let source = Root::open(preamble.root()?)?;
+        let dest = source.open_sibling(&listing::parse_root(&self.dest)?)?;
+        let failures = FailureLog::create(self.failed_out.as_deref(), &preamble)?;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.url.clone()))?;
+        let writer = &stdout;
+        let (source, dest, failures_ref) = (&source, &dest, &failures);
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                let moved = match source.rebase(&meta.location, dest) {
+                    Ok(location) => move_object(source, &meta, dest, &location).await,
+                    Err(e) => Err(e),
+                };
+                match moved {
+                    Ok(moved) => writer.write(moved.into()).await,
+                    Err(e) => failures_ref.record(&meta, &e),
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to move {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    /// An empty local directory to move objects around in, and its URL
+    fn scratch(name: &str) -> (PathBuf, Url) {
+        let dir = std::env::temp_dir().join(format!("obvious3-mv-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let url = Url::from_directory_path(&dir).unwrap();
+        (dir, url)
+    }
+
+    async fn put(root: &Root, key: &str, body: &'static str) -> ObjectMeta {
+        let location = root.path.child(key);
+        root.store.put(&location, body.into()).await.unwrap();
+        root.store.head(&location).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn moves_between_roots() {
+        let (_dir, url) = scratch("moves");
+        let source = Root::open(&url.join("src/").unwrap()).unwrap();
+        let dest = source.open_sibling(&url.join("dest/").unwrap()).unwrap();
+        let meta = put(&source, "a.txt", "hello").await;
+        let location = source.rebase(&meta.location, &dest).unwrap();
+
+        let moved = move_object(&source, &meta, &dest, &location).await.unwrap();
+        assert_eq!(moved.location, location);
+        assert_eq!(moved.size, 5);
+        assert!(source.store.head(&meta.location).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn only_moves_onto_nothing_if_asked() {
+        let (_dir, url) = scratch("if-new");
+        let source = Root::open(&url.join("src/").unwrap()).unwrap();
+        let dest = source.open_sibling(&url.join("dest/").unwrap()).unwrap();
+        let meta = put(&source, "a.txt", "hello").await;
+        let taken = put(&dest, "a.txt", "taken").await;
+
+        let error = move_object_if_new(&source, &meta, &dest, &taken.location)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("already exists"), "{error}");
+        let kept = dest.store.get(&taken.location).await.unwrap();
+        assert_eq!(kept.bytes().await.unwrap(), "taken");
+        assert!(source.store.head(&meta.location).await.is_ok());
+
+        let location = dest.path.child("b.txt");
+        let moved = move_object_if_new(&source, &meta, &dest, &location)
+            .await
+            .unwrap();
+        assert_eq!(moved.size, 5);
+        assert!(source.store.head(&meta.location).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_to_move_onto_itself() {
+        let (_dir, url) = scratch("itself");
+        let source = Root::open(&url).unwrap();
+        let dest = source.open_sibling(&url).unwrap();
+        let meta = put(&source, "a.txt", "hello").await;
+        let location = source.rebase(&meta.location, &dest).unwrap();
+
+        let error = move_object(&source, &meta, &dest, &location)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("onto itself"), "{error}");
+        let kept = source.store.get(&meta.location).await.unwrap();
+        assert_eq!(kept.bytes().await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn keeps_the_source_when_the_copy_fails() {
+        let (dir, url) = scratch("copy-fails");
+        let source = Root::open(&url.join("src/").unwrap()).unwrap();
+        let meta = put(&source, "a.txt", "hello").await;
+        // Nothing can be written under a file, even with permission to write anywhere
+        std::fs::write(dir.join("blocker"), "").unwrap();
+        let dest = source
+            .open_sibling(&url.join("blocker/dest/").unwrap())
+            .unwrap();
+        let location = source.rebase(&meta.location, &dest).unwrap();
+
+        assert!(move_object(&source, &meta, &dest, &location).await.is_err());
+        assert!(source.store.head(&meta.location).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn keeps_the_source_when_the_copy_is_the_wrong_size() {
+        // Separate stores, so the object is streamed and checked rather than copied server side
+        let source = Root::open(&Url::parse("memory:///src").unwrap()).unwrap();
+        let dest = Root::open(&Url::parse("memory:///dest").unwrap()).unwrap();
+        let mut meta = put(&source, "a.txt", "hello").await;
+        meta.size = 6;
+        let location = source.rebase(&meta.location, &dest).unwrap();
+
+        let error = move_object(&source, &meta, &dest, &location)
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("not deleting the source"),
+            "{error}"
+        );
+        assert!(source.store.head(&meta.location).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn saves_failures_as_a_listing_to_retry() {
+        let (dir, url) = scratch("failed-out");
+        let source = Root::open(&url).unwrap();
+        let meta = put(&source, "a.txt", "hello").await;
+        let path = dir.join("failed.ndjson");
+
+        let failures = FailureLog::create(Some(&path), &Preamble::new(url.clone())).unwrap();
+        failures
+            .record(&meta, &anyhow::anyhow!("copy failed"))
+            .unwrap();
+        assert_eq!(failures.finish().unwrap(), 1);
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        let mut lines = saved.lines();
+        let preamble: Preamble = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(preamble.root().unwrap(), &url);
+        let failed: crate::ObjectExport = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(ObjectMeta::from(failed).location, meta.location);
+        assert_eq!(lines.next(), None);
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Result};
+use clap::{Parser, ValueEnum};
+use futures::TryStreamExt;
+use object_store::aws::AmazonS3Builder;
+use object_store::azure::MicrosoftAzureBuilder;
+use object_store::gcp::GoogleCloudStorageBuilder;
+use object_store::signer::Signer;
+use object_store::ObjectStoreScheme;
+use url::Url;
+
+use crate::listing::{self, StdoutWriter};
+use crate::units::parse_duration;
+use crate::{Args, ObjectExport};
+
+/// The longest S3 allows a presigned URL to last
+const MAX_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Parser)]
+pub struct Presign {
+    /// How long the URLs stay valid, like `15m` or `24h`. At most a week.
+    #[arg(long, value_parser = parse_duration, default_value = "1h")]
+    expires: Duration,
+    /// What the URLs let their holder do
+    #[arg(long, value_enum, default_value_t = Method::Get)]
+    method: Method,
+    /// Print plain URLs, or the listing with a `signed_url` field added to each object
+    #[arg(long, value_enum, default_value_t = Format::Urls)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Method {
+    /// Download the object
+    Get,
+    /// Upload to the object's location, replacing it
+    Put,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Urls,
+    Ndjson,
+}
+
+impl Method {
+    fn http(self) -> http::Method {
+        match self {
+            Method::Get => http::Method::GET,
+            Method::Put => http::Method::PUT,
+        }
+    }
+}
+
+/// Open something to sign URLs for the store a root is in, with credentials from the environment
+/// like any other store
+fn signer(root: &Url) -> Result<Arc<dyn Signer>> {
+    let (scheme, _) = ObjectStoreScheme::parse(root)?;
+    Ok(match scheme {
+        ObjectStoreScheme::AmazonS3 => Arc::new(
+            AmazonS3Builder::from_env()
+                .with_url(root.as_str())
+                .build()?,
+        ),
+        ObjectStoreScheme::GoogleCloudStorage => Arc::new(
+            GoogleCloudStorageBuilder::from_env()
+                .with_url(root.as_str())
+                .build()?,
+        ),
+        ObjectStoreScheme::MicrosoftAzure => Arc::new(
+            MicrosoftAzureBuilder::from_env()
+                .with_url(root.as_str())
+                .build()?,
+        ),
+        _ => bail!("{root} can't be presigned, only S3, GCS and Azure stores support signed URLs"),
+    })
+}
+
+impl Presign {
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        ensure!(
+            !self.expires.is_zero(),
+            "--expires must be longer than zero"
+        );
+        ensure!(
+            self.expires <= MAX_EXPIRY,
+            "--expires can be at most a week, not {}",
+            humantime::format_duration(self.expires)
+        );
+        let preamble = listing::read_preamble()?;
+        // Check the store before reading any objects, so nothing fails halfway through
+        let signer = &signer(preamble.root()?)?;
+        let signed = listing::read_stdin()
+            .map_ok(|meta| async move {
+                let url = signer
+                    .signed_url(self.method.http(), &meta.location, self.expires)
+                    .await?;
+                anyhow::Ok((meta, url))
+            })
+            .try_buffered(global_args.concurrency);
+        let mut signed = std::pin::pin!(signed);
+        match self.format {
+            Format::Urls => {
+                let mut stdout = std::io::BufWriter::new(std::io::stdout());
+                let written = async {
+                    while let Some((_, url)) = signed.try_next().await? {
+                        writeln!(stdout, "{url}")?;
+                    }
+                    stdout.flush()?;
+                    Ok(())
+                };
+                listing::ignore_broken_pipe(written.await)
+            }
+            Format::Ndjson => {
+                let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+                while let Some((meta, url)) = signed.try_next().await? {
+                    let mut object = ObjectExport::from(meta);
+                    object
+                        .extra
+                        .insert("signed_url".to_string(), url.to_string().into());
+                    stdout.write(object).await?;
+                }
+                stdout.finish().await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::path::Path as ObjectStorePath;
+
+    #[tokio::test]
+    async fn signs_s3_urls_without_contacting_it() {
+        // Signing only needs the credentials, so these don't have to be real
+        std::env::set_var("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE");
+        std::env::set_var(
+            "AWS_SECRET_ACCESS_KEY",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        );
+        std::env::set_var("AWS_DEFAULT_REGION", "us-east-1");
+        let signer = signer(&Url::parse("s3://bucket/prefix/").unwrap()).unwrap();
+        let location = ObjectStorePath::from("prefix/report.csv");
+        for method in [Method::Get, Method::Put] {
+            let url = signer
+                .signed_url(method.http(), &location, Duration::from_secs(3600))
+                .await
+                .unwrap();
+            assert!(url.path().ends_with("bucket/prefix/report.csv"), "{url}");
+            let query: Vec<_> = url.query_pairs().collect();
+            assert!(
+                query
+                    .iter()
+                    .any(|(k, v)| k == "X-Amz-Expires" && v == "3600"),
+                "{url}"
+            );
+            assert!(query.iter().any(|(k, _)| k == "X-Amz-Signature"), "{url}");
+        }
+    }
+
+    #[test]
+    fn refuses_stores_that_cant_sign() {
+        for root in ["file:///tmp/data", "memory:///"] {
+            let error = signer(&Url::parse(root).unwrap()).unwrap_err();
+            assert!(error.to_string().contains("can't be presigned"), "{error}");
+        }
+    }
+}
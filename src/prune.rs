@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::du;
+use crate::find::Find;
+use crate::latest::newest_first;
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Prune {
+    /// Which objects to consider. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// How many of the newest objects to keep in each group
+    #[arg(long)]
+    keep: usize,
+    /// Group objects by their prefix this many levels below the root.
+    /// At 0, everything under the root is one group.
+    #[arg(long, default_value = "0")]
+    group_by_prefix: usize,
+    /// Refuse to delete anything if more than this many objects would be deleted
+    #[arg(long)]
+    max_delete: Option<usize>,
+    /// Actually delete the objects. Without this, only print what would be deleted.
+    #[arg(short, long)]
+    yes: bool,
+}
+
+impl Prune {
+    /// Which of the matches to delete: all but the `--keep` newest in each group
+    fn doomed(&self, root: &Root, matches: Vec<ObjectMeta>) -> Result<Vec<ObjectMeta>> {
+        let mut groups = BTreeMap::<ObjectStorePath, Vec<ObjectMeta>>::new();
+        for meta in matches {
+            let prefix = du::prefix(root, &meta.location, self.group_by_prefix)?;
+            groups.entry(prefix).or_default().push(meta);
+        }
+        let mut doomed = vec![];
+        for (prefix, group) in &mut groups {
+            group.sort_by(|a, b| newest_first(a).cmp(&newest_first(b)));
+            let older = group.split_off(self.keep.min(group.len()));
+            eprintln!(
+                "{prefix}/: keeping {}, deleting {}",
+                group.len(),
+                older.len()
+            );
+            doomed.extend(older);
+        }
+        Ok(doomed)
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        ensure!(self.keep > 0, "--keep must be at least 1");
+        let filter = self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+
+        let matches: Vec<ObjectMeta> = self
+            .find
+            .objects(listed.as_ref())
+            .try_filter(|meta| std::future::ready(filter.is_match(meta)))
+            .try_collect()
+            .await?;
+        // Decide everything before deleting anything, so --max-delete can stop it all
+        let doomed = self.doomed(&root, matches)?;
+        if let Some(max_delete) = self.max_delete {
+            if doomed.len() > max_delete {
+                bail!(
+                    "Refusing to delete {} objects, which is more than --max-delete {max_delete}",
+                    doomed.len()
+                );
+            }
+        }
+
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let failures = FailureLog::create(None, &preamble)?;
+        let deleted = listing::delete_all(
+            &root,
+            futures::stream::iter(doomed.into_iter().map(anyhow::Ok)),
+            self.yes,
+            Some(&stdout),
+            &failures,
+            global_args.concurrency,
+        )
+        .await?;
+        stdout.finish().await?;
+
+        if self.yes {
+            eprintln!("Deleted {} objects", deleted.count);
+        } else {
+            eprintln!(
+                "Dry run: {} objects would be deleted. Pass --yes to delete them.",
+                deleted.count
+            );
+        }
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to delete {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use url::Url;
+
+    /// An object modified this many minutes after noon
+    fn at(location: &str, minutes: i64) -> ObjectMeta {
+        let noon = "2024-06-01T12:00:00Z".parse::<chrono::DateTime<chrono::Utc>>();
+        ObjectMeta {
+            location: ObjectStorePath::from(location),
+            last_modified: noon.unwrap() + chrono::Duration::minutes(minutes),
+            size: 0,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest_in_each_group() {
+        let root = Root::open(&Url::parse("memory:///data/").unwrap()).unwrap();
+        let prune = Prune::try_parse_from(["prune", "--keep", "2", "--group-by-prefix", "1"]);
+        let matches = vec![
+            at("data/a/1", 1),
+            at("data/a/3", 3),
+            at("data/a/2", 2),
+            at("data/a/0", 0),
+            // Groups smaller than --keep lose nothing
+            at("data/b/1", 1),
+            at("data/c/5", 5),
+            at("data/c/9", 9),
+        ];
+        let doomed = prune.unwrap().doomed(&root, matches.clone()).unwrap();
+        let doomed: Vec<_> = doomed.iter().map(|meta| meta.location.as_ref()).collect();
+        assert_eq!(doomed, ["data/a/1", "data/a/0"]);
+
+        // Without grouping, the newest two of everything stay
+        let prune = Prune::try_parse_from(["prune", "--keep", "2"]).unwrap();
+        let doomed = prune.doomed(&root, matches).unwrap();
+        let mut doomed: Vec<_> = doomed.iter().map(|meta| meta.location.as_ref()).collect();
+        doomed.sort();
+        assert_eq!(
+            doomed,
+            ["data/a/0", "data/a/1", "data/a/2", "data/a/3", "data/b/1"]
+        );
+    }
+}
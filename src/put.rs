@@ -0,0 +1,109 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+use tokio::io::AsyncBufReadExt;
+
+use crate::copy::copy_object;
+use crate::listing::{self, StdoutWriter};
+use crate::store::{with_retries, Root};
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Put {
+    /// The local directory to upload recursively. If not specified, file paths are read from stdin, one per line.
+    #[arg(short, long)]
+    root: Option<String>,
+    /// The root to upload into
+    #[arg(short, long)]
+    dest: String,
+    /// The local directory that keys are relative to.
+    ///
+    /// Defaults to `--root`, or the current directory for paths read from stdin.
+    /// So `--root ./data/2024 --prefix-strip ./data` would upload into `<dest>/2024/...`
+    #[arg(long)]
+    prefix_strip: Option<String>,
+    /// How many more times to try uploading a file before giving up on it
+    #[arg(long, default_value = "3")]
+    retries: usize,
+}
+
+impl Put {
+    /// Stream the metadata of every local file to upload
+    fn files<'a>(root: Option<&'a Root>, strip: &'a Root) -> BoxStream<'a, Result<ObjectMeta>> {
+        match root {
+            Some(root) => root
+                .store
+                .list(Some(&root.path))
+                .map_err(anyhow::Error::from)
+                .boxed(),
+            None => {
+                let lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+                tokio_stream::wrappers::LinesStream::new(lines)
+                    .map_err(anyhow::Error::from)
+                    .try_filter(|line| futures::future::ready(!line.trim().is_empty()))
+                    .and_then(move |line| async move {
+                        let location = ObjectStorePath::from_filesystem_path(line.trim())
+                            .with_context(|| format!("Reading {}", line.trim()))?;
+                        Ok(strip.store.head(&location).await?)
+                    })
+                    .boxed()
+            }
+        }
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let root = self
+            .root
+            .as_deref()
+            .map(|r| Root::open(&listing::parse_root(r)?))
+            .transpose()?;
+        let strip = match (&self.prefix_strip, &root) {
+            (Some(strip), _) => Root::open(&listing::parse_root(strip)?)?,
+            (None, Some(root)) => root.clone(),
+            (None, None) => Root::open(&listing::parse_root(".")?)?,
+        };
+        if strip.url.scheme() != "file" {
+            bail!("put uploads local files, but {} is not local", strip.url);
+        }
+        let dest = strip.open_sibling(&listing::parse_root(&self.dest)?)?;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.url.clone()))?;
+        let writer = &stdout;
+        let failed = &AtomicUsize::new(0);
+        let (strip, dest) = (&strip, &dest);
+        Self::files(root.as_ref(), strip)
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                let uploaded = match strip.rebase(&meta.location, dest) {
+                    Ok(location) => {
+                        with_retries(self.retries, || async {
+                            copy_object(strip, &meta, dest, &location).await?;
+                            Ok(dest.store.head(&location).await?)
+                        })
+                        .await
+                    }
+                    Err(e) => Err(e),
+                };
+                match uploaded {
+                    Ok(uploaded) => writer.write(ObjectExport::from(uploaded)).await,
+                    Err(e) => {
+                        eprintln!("Failed to upload {}: {e:#}", meta.location);
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        Ok(())
+                    }
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let failed = failed.load(Ordering::Relaxed);
+        if failed > 0 {
+            bail!("Failed to upload {failed} files");
+        }
+        Ok(())
+    }
+}
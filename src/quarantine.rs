@@ -0,0 +1,221 @@
+use std::collections::HashSet;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::mv::move_object_if_new;
+use crate::store::Root;
+use crate::{Args, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Quarantine {
+    /// The root to move objects under. Each keeps its whole original path below it.
+    #[arg(short, long, required_unless_present = "restore")]
+    dest: Option<String>,
+    /// Save where every object came from and went to here, so they can be restored later
+    #[arg(long, required_unless_present = "restore")]
+    manifest_out: Option<PathBuf>,
+    /// Move the objects in a saved manifest back where they came from, instead of reading stdin
+    #[arg(long, conflicts_with_all = ["dest", "manifest_out"])]
+    restore: Option<PathBuf>,
+}
+
+/// A line of the manifest, naming both places an object has been
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    original: Url,
+    quarantine: Url,
+}
+
+/// Where to put an object in quarantine, adding the time if something is already there.
+///
+/// Each location is claimed, so no two objects in one run are moved to the same place, even
+/// when they're moved at once.
+async fn quarantine_location(
+    dest: &Root,
+    meta: &ObjectMeta,
+    claimed: &Mutex<HashSet<ObjectStorePath>>,
+) -> Result<ObjectStorePath> {
+    let location: ObjectStorePath = dest.path.parts().chain(meta.location.parts()).collect();
+    let taken = match dest.store.head(&location).await {
+        Err(object_store::Error::NotFound { .. }) => false,
+        Ok(_) => true,
+        Err(e) => return Err(e.into()),
+    };
+    let mut claimed = claimed.lock().unwrap();
+    if !taken && claimed.insert(location.clone()) {
+        return Ok(location);
+    }
+    let now = Utc::now().format("%Y%m%dT%H%M%S%.3fZ");
+    let mut stamped = ObjectStorePath::from(format!("{location}.{now}"));
+    for n in 1.. {
+        if claimed.insert(stamped.clone()) {
+            break;
+        }
+        stamped = ObjectStorePath::from(format!("{location}.{now}.{n}"));
+    }
+    Ok(stamped)
+}
+
+impl Quarantine {
+    async fn quarantine(&self, global_args: &Args, dest: &str, manifest: &Path) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let source = Root::open(preamble.root()?)?;
+        let dest = source.open_sibling(&listing::parse_root(dest)?)?;
+        let manifest = Mutex::new(std::io::BufWriter::new(
+            std::fs::File::create(manifest)
+                .with_context(|| format!("Creating {}", manifest.display()))?,
+        ));
+        let failures = FailureLog::create(None, &preamble)?;
+        let claimed = &Mutex::new(HashSet::new());
+
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.url.clone()))?;
+        let writer = &stdout;
+        let (source, dest, failures_ref, manifest_ref) = (&source, &dest, &failures, &manifest);
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                let moved = async {
+                    let location = quarantine_location(dest, &meta, claimed).await?;
+                    // Something else could still get there between looking and moving
+                    let moved = move_object_if_new(source, &meta, dest, &location).await?;
+                    // Record each move as soon as it happens, so an interrupted run can still be undone
+                    let entry = Entry {
+                        original: source.object_url(&meta.location)?,
+                        quarantine: dest.object_url(&location)?,
+                    };
+                    let mut line = serde_json::to_string(&entry)?;
+                    line.push('\n');
+                    manifest_ref.lock().unwrap().write_all(line.as_bytes())?;
+                    anyhow::Ok(moved)
+                };
+                match moved.await {
+                    Ok(moved) => writer.write(moved.into()).await,
+                    Err(e) => failures_ref.record(&meta, &e),
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+        manifest.into_inner().unwrap().flush()?;
+
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to quarantine {failed} objects");
+        }
+        Ok(())
+    }
+
+    async fn restore(&self, global_args: &Args, manifest: &Path) -> Result<()> {
+        let file = std::fs::File::open(manifest)
+            .with_context(|| format!("Opening {}", manifest.display()))?;
+        // Open each store once, rather than once per object
+        let mut last: Option<Root> = None;
+        let mut open = |url: &Url| -> Result<Root> {
+            let root = match &last {
+                Some(root) => root.open_sibling(url)?,
+                None => Root::open(url)?,
+            };
+            last = Some(root.clone());
+            Ok(root)
+        };
+        let mut moves = vec![];
+        for line in std::io::BufReader::new(file).lines() {
+            let entry: Entry = serde_json::from_str(&line?)
+                .with_context(|| format!("Reading {}", manifest.display()))?;
+            moves.push((open(&entry.quarantine)?, open(&entry.original)?));
+        }
+        let Some((_, first)) = moves.first() else {
+            return Ok(());
+        };
+        if moves
+            .iter()
+            .any(|(_, original)| !original.same_store(first))
+        {
+            bail!("Objects can only be restored to one store at a time");
+        }
+        let preamble = Preamble::new(first.store_url()?);
+        let failures = FailureLog::create(None, &preamble)?;
+        let failures_ref = &failures;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let writer = &stdout;
+        futures::stream::iter(moves.into_iter().map(anyhow::Ok))
+            .try_for_each_concurrent(global_args.concurrency, |(held, original)| async move {
+                let meta = match held.store.head(&held.path).await {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        // All there is to say about an object that can't be found is where
+                        let missing = ObjectMeta {
+                            location: held.path.clone(),
+                            last_modified: Utc::now(),
+                            size: 0,
+                            e_tag: None,
+                            version: None,
+                        };
+                        return failures_ref.record(&missing, &e.into());
+                    }
+                };
+                // Something new may have taken its place since, which shouldn't be lost
+                let restored = match original.store.head(&original.path).await {
+                    Ok(_) => Err(anyhow::anyhow!(
+                        "{} exists again, not overwriting it",
+                        original.path
+                    )),
+                    Err(_) => move_object_if_new(&held, &meta, &original, &original.path).await,
+                };
+                match restored {
+                    Ok(restored) => writer.write(restored.into()).await,
+                    Err(e) => failures_ref.record(&meta, &e),
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to restore {failed} objects");
+        }
+        Ok(())
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        match (&self.restore, &self.dest, &self.manifest_out) {
+            (Some(manifest), _, _) => self.restore(global_args, manifest).await,
+            (None, Some(dest), Some(manifest)) => {
+                self.quarantine(global_args, dest, manifest).await
+            }
+            _ => bail!("quarantine needs --dest and --manifest-out, or --restore"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn never_picks_the_same_place_twice() {
+        let dest = Root::open(&Url::parse("memory:///held/").unwrap()).unwrap();
+        let location = ObjectStorePath::from("data/a.csv");
+        dest.store.put(&location, "a".into()).await.unwrap();
+        let meta = dest.store.head(&location).await.unwrap();
+        let claimed = Mutex::new(HashSet::new());
+
+        let first = quarantine_location(&dest, &meta, &claimed).await.unwrap();
+        assert_eq!(first.as_ref(), "held/data/a.csv");
+        // The same object listed again, before the first has been moved
+        let second = quarantine_location(&dest, &meta, &claimed).await.unwrap();
+        let third = quarantine_location(&dest, &meta, &claimed).await.unwrap();
+        assert!(second.as_ref().starts_with("held/data/a.csv."), "{second}");
+        assert_ne!(second, third);
+    }
+}
@@ -0,0 +1,45 @@
+//! A small seedable random number generator, for sampling and shuffling reproducibly
+
+/// SplitMix64, which is fast and plenty random for choosing objects, though not for secrets
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Start from a seed, so the same seed always gives the same numbers
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Start from a seed that's different every time, or from `seed` if one is given
+    pub fn seeded(seed: Option<u64>) -> Self {
+        Self::new(seed.unwrap_or_else(random_seed))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A number in `[0, n)`, for `n` above zero
+    pub fn below(&mut self, n: u64) -> u64 {
+        // Multiplying instead of taking the remainder avoids favoring small numbers
+        ((self.next_u64() as u128 * n as u128) >> 64) as u64
+    }
+}
+
+/// A seed that's different every time, for when none is given
+pub fn random_seed() -> u64 {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    now.as_nanos() as u64 ^ ((std::process::id() as u64) << 32)
+}
@@ -0,0 +1,198 @@
+use std::ops::Range;
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct RangeGet {
+    /// The object to read. If not specified, every object in a listing read from stdin is read.
+    url: Option<String>,
+    /// A range of bytes to read, counting from 0 and including both ends, like `0-4095`.
+    /// Can be given more than once.
+    #[arg(long = "range", value_parser = parse_range)]
+    ranges: Vec<(usize, usize)>,
+    /// Also read this many bytes from the end of each object
+    #[arg(long)]
+    suffix: Option<usize>,
+    /// Save the ranges of each object to a file under this directory, instead of printing them.
+    /// Paths relative to the source root are preserved.
+    #[arg(short, long)]
+    dest: Option<PathBuf>,
+}
+
+/// A line of the report when saving to files
+#[derive(Debug, Serialize)]
+struct Saved {
+    location: String,
+    path: PathBuf,
+    bytes: usize,
+}
+
+fn parse_range(text: &str) -> Result<(usize, usize)> {
+    let Some((start, end)) = text.split_once('-') else {
+        bail!("Ranges are written as start-end, like 0-4095, not {text:?}");
+    };
+    let (start, end): (usize, usize) = (start.parse()?, end.parse()?);
+    if end < start {
+        bail!("The range {text} ends before it starts");
+    }
+    Ok((start, end))
+}
+
+impl RangeGet {
+    /// The ranges to read from an object, clamped to its size
+    fn ranges(&self, meta: &ObjectMeta) -> Vec<Range<usize>> {
+        let mut ranges = vec![];
+        for &(start, end) in &self.ranges {
+            if start >= meta.size {
+                eprintln!(
+                    "Warning: skipping bytes {start}-{end} of {}, which is only {} bytes",
+                    meta.location, meta.size
+                );
+                continue;
+            }
+            if end >= meta.size {
+                eprintln!(
+                    "Warning: reading bytes {start}-{} of {} instead of {start}-{end}",
+                    meta.size - 1,
+                    meta.location
+                );
+            }
+            ranges.push(start..(end + 1).min(meta.size));
+        }
+        if let Some(suffix) = self.suffix {
+            if suffix > meta.size {
+                eprintln!(
+                    "Warning: reading all of {}, which is only {} bytes",
+                    meta.location, meta.size
+                );
+            }
+            ranges.push(meta.size.saturating_sub(suffix)..meta.size);
+        }
+        ranges.retain(|range| !range.is_empty());
+        ranges
+    }
+
+    /// Read every range of an object in one request, so stores can coalesce nearby ranges
+    async fn read(&self, root: &Root, meta: &ObjectMeta) -> Result<Vec<(Range<usize>, Vec<u8>)>> {
+        let ranges = self.ranges(meta);
+        if ranges.is_empty() {
+            return Ok(vec![]);
+        }
+        let contents = root.store.get_ranges(&meta.location, &ranges).await?;
+        Ok(ranges
+            .into_iter()
+            .zip(contents)
+            .map(|(range, content)| (range, content.to_vec()))
+            .collect())
+    }
+
+    /// Render an object's ranges for stdout, each with a header naming the object and the bytes
+    async fn render(&self, root: &Root, meta: &ObjectMeta) -> Result<Vec<u8>> {
+        let mut block = vec![];
+        for (range, content) in self.read(root, meta).await? {
+            block.extend(
+                format!(
+                    "==> {} bytes {}-{} <==\n",
+                    meta.location,
+                    range.start,
+                    range.end - 1
+                )
+                .into_bytes(),
+            );
+            block.extend(content);
+            block.push(b'\n');
+        }
+        Ok(block)
+    }
+
+    async fn save(&self, root: &Root, meta: &ObjectMeta, dest: &std::path::Path) -> Result<Saved> {
+        let relative: Vec<_> = root.relative(&meta.location)?.collect();
+        // A single URL is its own root, so it's named after the object instead
+        let path = match relative.is_empty() {
+            true => dest.join(meta.location.filename().unwrap_or_default()),
+            false => relative
+                .iter()
+                .fold(dest.to_path_buf(), |path, part| path.join(part.as_ref())),
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::File::create(&path)
+            .await
+            .with_context(|| format!("Creating {}", path.display()))?;
+        let mut bytes = 0;
+        for (_, content) in self.read(root, meta).await? {
+            file.write_all(&content).await?;
+            bytes += content.len();
+        }
+        file.flush().await?;
+        Ok(Saved {
+            location: meta.location.to_string(),
+            path,
+            bytes,
+        })
+    }
+
+    /// The store, and the objects to read from it
+    async fn objects(&self) -> Result<(Root, BoxStream<'static, Result<ObjectMeta>>)> {
+        match &self.url {
+            Some(url) => {
+                let root = Root::open(&listing::parse_root(url)?)?;
+                let meta = root.store.head(&root.path).await?;
+                Ok((root, futures::stream::iter([Ok(meta)]).boxed()))
+            }
+            None => {
+                let root = Root::open(listing::read_preamble()?.root()?)?;
+                Ok((root, listing::read_stdin().boxed()))
+            }
+        }
+    }
+
+    async fn print_all(&self, global_args: &Args) -> Result<()> {
+        let (root, objects) = self.objects().await?;
+        let root = &root;
+        let mut stdout = tokio::io::stdout();
+        // Fetch concurrently, but print in listing order
+        let mut blocks = std::pin::pin!(objects
+            .map_ok(|meta| async move { self.render(root, &meta).await })
+            .try_buffered(global_args.concurrency));
+        while let Some(block) = blocks.try_next().await? {
+            stdout.write_all(&block).await?;
+        }
+        Ok(stdout.flush().await?)
+    }
+
+    async fn save_all(&self, global_args: &Args, dest: &std::path::Path) -> Result<()> {
+        let (root, objects) = self.objects().await?;
+        let root = &root;
+        let stdout = StdoutWriter::start_bare()?;
+        let writer = &stdout;
+        objects
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                writer.write(self.save(root, &meta, dest).await?).await
+            })
+            .await?;
+        stdout.finish().await
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        if self.ranges.is_empty() && self.suffix.is_none() {
+            bail!("Give at least one --range or --suffix to read");
+        }
+        match &self.dest {
+            Some(dest) => self.save_all(global_args, dest).await,
+            None => listing::ignore_broken_pipe(self.print_all(global_args).await),
+        }
+    }
+}
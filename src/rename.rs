@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::mv::move_object;
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Rename {
+    /// A regex matched against each object's full path.
+    /// Defaults to the `--path-match` regex of the `find` that made the listing, if it had only one
+    /// and no `--not`, `--any` or `--ignore-case`, so that every object matches it.
+    #[arg(long)]
+    from: Option<String>,
+    /// What to replace the matched part of the path with. `$1` or `${name}` insert capture groups.
+    #[arg(long)]
+    to: String,
+    /// Only print each old and new path, without renaming anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Replace objects that already exist at the new paths
+    #[arg(long)]
+    overwrite: bool,
+    /// Also save the objects that failed to be renamed here, as a listing that can be piped back in
+    #[arg(long)]
+    failed_out: Option<PathBuf>,
+}
+
+impl Rename {
+    /// Work out every object's new path, refusing if two objects would end up at the same one
+    fn plan(
+        &self,
+        regex: &regex::Regex,
+        objects: Vec<ObjectMeta>,
+    ) -> Result<Vec<(ObjectMeta, ObjectStorePath)>> {
+        let mut plan = vec![];
+        let mut sources = HashMap::<ObjectStorePath, ObjectStorePath>::new();
+        let mut collisions = 0;
+        let mut unmatched = 0;
+        for meta in objects {
+            let old = meta.location.as_ref();
+            if !regex.is_match(old) {
+                unmatched += 1;
+                continue;
+            }
+            let new = regex.replace(old, self.to.as_str());
+            let location = ObjectStorePath::parse(new.as_ref()).with_context(|| {
+                format!("{old} would be renamed to {new}, which isn't a valid path")
+            })?;
+            if location == meta.location {
+                continue;
+            }
+            if let Some(other) = sources.insert(location.clone(), meta.location.clone()) {
+                eprintln!("Both {other} and {old} would be renamed to {location}");
+                collisions += 1;
+            }
+            plan.push((meta, location));
+        }
+        if unmatched > 0 {
+            eprintln!("Skipping {unmatched} objects that don't match the regex");
+        }
+        if collisions > 0 {
+            bail!("Refusing to rename, {collisions} objects would collide with another");
+        }
+        // Renames happen at once, so one onto an object that's being renamed itself could
+        // replace it before it's moved away
+        let renamed: HashSet<&ObjectStorePath> =
+            plan.iter().map(|(meta, _)| &meta.location).collect();
+        let mut chained = 0;
+        for (meta, location) in &plan {
+            if renamed.contains(location) {
+                eprintln!(
+                    "{} would be renamed to {location}, which is being renamed too",
+                    meta.location
+                );
+                chained += 1;
+            }
+        }
+        if chained > 0 {
+            bail!(
+                "Refusing to rename, {chained} objects would be renamed onto others that are being renamed. \
+                 Rename them in separate steps."
+            );
+        }
+        Ok(plan)
+    }
+
+    /// Refuse to replace existing objects, unless asked to
+    async fn check_existing(
+        &self,
+        root: &Root,
+        plan: &[(ObjectMeta, ObjectStorePath)],
+        concurrency: usize,
+    ) -> Result<()> {
+        if self.overwrite {
+            return Ok(());
+        }
+        let existing: Vec<&ObjectStorePath> = futures::stream::iter(plan)
+            .map(|(_, location)| async move {
+                match root.store.head(location).await {
+                    Ok(_) => Ok(Some(location)),
+                    Err(object_store::Error::NotFound { .. }) => Ok(None),
+                    Err(e) => Err(anyhow::Error::from(e)),
+                }
+            })
+            .buffer_unordered(concurrency)
+            .try_filter_map(|found| async move { Ok(found) })
+            .try_collect()
+            .await?;
+        for location in &existing {
+            eprintln!("{location} already exists");
+        }
+        if !existing.is_empty() {
+            bail!(
+                "Refusing to replace {} existing objects. Pass --overwrite to replace them.",
+                existing.len()
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let from = match (&self.from, preamble.path_match()) {
+            (Some(from), _) => from.as_str(),
+            (None, Some(path_match)) => path_match,
+            (None, None) => {
+                bail!("Pass --from, since the listing wasn't filtered by one --path-match to reuse")
+            }
+        };
+        let regex = regex::Regex::new(from)?;
+        let root = &Root::open(preamble.root()?)?;
+        // Collisions can only be found by looking at everything first
+        let objects: Vec<ObjectMeta> = listing::read_stdin().try_collect().await?;
+        let plan = self.plan(&regex, objects)?;
+        self.check_existing(root, &plan, global_args.concurrency)
+            .await?;
+
+        if self.dry_run {
+            for (meta, location) in &plan {
+                println!("{} -> {location}", meta.location);
+            }
+            eprintln!("Dry run: {} objects would be renamed", plan.len());
+            return Ok(());
+        }
+
+        let failures = FailureLog::create(self.failed_out.as_deref(), &preamble)?;
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let (writer, failures_ref) = (&stdout, &failures);
+        futures::stream::iter(plan.iter().map(anyhow::Ok))
+            .try_for_each_concurrent(global_args.concurrency, |(meta, location)| async move {
+                match move_object(root, meta, root, location).await {
+                    Ok(moved) => writer.write(moved.into()).await,
+                    Err(e) => failures_ref.record(meta, &e),
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let failed = failures.finish()?;
+        eprintln!("Renamed {} objects", plan.len() - failed);
+        if failed > 0 {
+            bail!("Failed to rename {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn object(location: &str) -> ObjectMeta {
+        ObjectMeta {
+            location: ObjectStorePath::from(location),
+            last_modified: chrono::Utc::now(),
+            size: 0,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    /// Plan renaming these objects from one regex to a replacement, as old and new paths
+    fn plan(from: &str, to: &str, locations: &[&str]) -> Result<Vec<(String, String)>> {
+        let rename = Rename::try_parse_from(["rename", "--overwrite", "--to", to]).unwrap();
+        let objects = locations.iter().map(|location| object(location)).collect();
+        let plan = rename.plan(&regex::Regex::new(from).unwrap(), objects)?;
+        Ok(plan
+            .into_iter()
+            .map(|(meta, location)| (meta.location.to_string(), location.to_string()))
+            .collect())
+    }
+
+    #[test]
+    fn plans_renames_of_the_objects_that_match() {
+        let planned = plan(
+            r"/(\d+)\.csv$",
+            "/part-$1.csv",
+            &["x/1.csv", "x/2.json", "x/3.csv"],
+        );
+        assert_eq!(
+            planned.unwrap(),
+            [("x/1.csv", "x/part-1.csv"), ("x/3.csv", "x/part-3.csv")]
+                .map(|(old, new)| (old.to_string(), new.to_string()))
+        );
+        // Objects that would keep their path are left alone
+        assert!(plan("x", "x", &["x/1.csv"]).unwrap().is_empty());
+        assert!(plan(r"\d", "n", &["x/1.csv", "x/2.csv"]).is_err());
+    }
+
+    #[test]
+    fn refuses_renames_onto_objects_being_renamed() {
+        // x/a_a would be replaced by x/a while it's being moved to x/a_a_a
+        let chained = plan("^x/a", "x/a_a", &["x/a", "x/a_a"]);
+        let error = chained.unwrap_err().to_string();
+        assert!(error.contains("separate steps"), "{error}");
+        assert_eq!(plan("^x/a", "x/a_a", &["x/a"]).unwrap().len(), 1);
+        assert_eq!(plan("^x/a", "x/a_a", &["x/a", "x/a_b"]).unwrap().len(), 2);
+        // A swap is a chain both ways
+        let swapped = plan("^x/(.)(.)$", "x/$2$1", &["x/ab", "x/ba"]);
+        assert!(swapped.is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+
+use crate::find::Find;
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Rm {
+    /// Which objects to delete. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// Actually delete the objects. Without this, only print what would be deleted.
+    #[arg(short, long, visible_alias = "force")]
+    yes: bool,
+}
+
+impl Rm {
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let filter = &self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        // Piped listings don't open the store, but we need it to delete anything
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let failures = FailureLog::create(None, &preamble)?;
+        let matches = self
+            .find
+            .objects(listed.as_ref())
+            .try_filter(|meta| std::future::ready(filter.is_match(meta)));
+        let deleted = listing::delete_all(
+            &root,
+            matches,
+            self.yes,
+            Some(&stdout),
+            &failures,
+            global_args.concurrency,
+        )
+        .await?;
+        stdout.finish().await?;
+
+        if self.yes {
+            eprintln!("Deleted {} objects", deleted.count);
+        } else {
+            eprintln!(
+                "Dry run: {} objects would be deleted. Pass --yes to delete them.",
+                deleted.count
+            );
+        }
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to delete {failed} objects");
+        }
+        Ok(())
+    }
+}
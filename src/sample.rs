@@ -0,0 +1,74 @@
+use anyhow::{ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+
+use crate::find::Find;
+use crate::listing::StdoutWriter;
+use crate::random::Rng;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+#[command(group = clap::ArgGroup::new("how").required(true).args(["count", "rate"]))]
+pub struct Sample {
+    #[command(flatten)]
+    find: Find,
+    /// Pick exactly this many objects, or all of them if there are fewer.
+    /// Only this many are kept in memory, however long the listing is.
+    #[arg(short = 'n', long)]
+    count: Option<usize>,
+    /// Pick each object with this probability, like `0.01` for about one in a hundred
+    #[arg(long)]
+    rate: Option<f64>,
+}
+
+impl Sample {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        if let Some(rate) = self.rate {
+            ensure!(
+                (0.0..=1.0).contains(&rate),
+                "--rate must be between 0 and 1"
+            );
+        }
+        let filter = self.find.filter().await?;
+        let (preamble, root) = self.find.open()?;
+        let mut rng = Rng::new(self.find.seed());
+        // Objects are read one at a time, since the choices depend on the order they arrive in
+        let mut objects = std::pin::pin!(self
+            .find
+            .objects(root.as_ref())
+            .try_filter(|meta| futures::future::ready(filter.is_match(meta))));
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+
+        let Some(count) = self.count else {
+            let rate = self.rate.unwrap_or_default();
+            while let Some(meta) = objects.try_next().await? {
+                if rng.next_f64() < rate {
+                    stdout.write(meta.into()).await?;
+                }
+            }
+            return stdout.finish().await;
+        };
+
+        // Reservoir sampling: the nth object replaces a random pick with probability count/n
+        let mut reservoir: Vec<(usize, ObjectMeta)> = Vec::with_capacity(count);
+        let mut seen = 0;
+        while let Some(meta) = objects.try_next().await? {
+            if reservoir.len() < count {
+                reservoir.push((seen, meta));
+            } else {
+                let pick = rng.below(seen as u64 + 1) as usize;
+                if pick < count {
+                    reservoir[pick] = (seen, meta);
+                }
+            }
+            seen += 1;
+        }
+        // Keep the picks in the order they were listed
+        reservoir.sort_by_key(|(index, _)| *index);
+        for (_, meta) in reservoir {
+            stdout.write(meta.into()).await?;
+        }
+        stdout.finish().await
+    }
+}
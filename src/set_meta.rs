@@ -0,0 +1,248 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, ensure, Result};
+use bytes::Bytes;
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use object_store::{Attribute, Attributes, ObjectMeta, PutMode, PutOptions, TagSet, UpdateVersion};
+
+use crate::copy::{Upload, PART_SIZE};
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct SetMeta {
+    /// The content type to give every object, or `auto` to guess it from each extension
+    #[arg(long)]
+    content_type: Option<String>,
+    /// The cache control header to give every object, like `max-age=3600`
+    #[arg(long)]
+    cache_control: Option<String>,
+    /// User metadata to set, like `owner=analytics`. Can be given more than once.
+    #[arg(long = "meta", value_parser = parse_key_value)]
+    metadata: Vec<(String, String)>,
+    /// Also save the objects that failed to be updated here, as a listing that can be piped back in
+    #[arg(long)]
+    failed_out: Option<PathBuf>,
+}
+
+/// Guess a content type from an extension, for the common formats browsers can show
+fn guess_content_type(extension: &str) -> Option<&'static str> {
+    Some(match extension.to_ascii_lowercase().as_str() {
+        "csv" => "text/csv",
+        "tsv" => "text/tab-separated-values",
+        "txt" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "css" => "text/css",
+        "js" => "text/javascript",
+        "json" => "application/json",
+        "ndjson" | "jsonl" => "application/x-ndjson",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "mp4" => "video/mp4",
+        "gz" => "application/gzip",
+        "zip" => "application/zip",
+        "tar" => "application/x-tar",
+        "parquet" => "application/vnd.apache.parquet",
+        _ => return None,
+    })
+}
+
+/// Parse a `key=value` argument, where the value may be empty but the key may not
+fn parse_key_value(text: &str) -> Result<(String, String)> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => bail!("Expected key=value, not {text:?}"),
+    }
+}
+
+/// Write an object back with new attributes and tags, only if it's still the version that was
+/// read, so nothing written in between is lost
+async fn put_unchanged(
+    root: &Root,
+    read: &ObjectMeta,
+    body: Bytes,
+    attributes: Attributes,
+    tags: TagSet,
+) -> Result<()> {
+    let opts = PutOptions {
+        mode: PutMode::Update(UpdateVersion {
+            e_tag: read.e_tag.clone(),
+            version: read.version.clone(),
+        }),
+        attributes,
+        tags,
+    };
+    match root.store.put_opts(&read.location, body.into(), opts).await {
+        Ok(_) => Ok(()),
+        Err(object_store::Error::Precondition { .. } | object_store::Error::NotFound { .. }) => {
+            bail!(
+                "{} changed while it was being rewritten, nothing was written",
+                read.location
+            )
+        }
+        Err(object_store::Error::NotImplemented) => bail!(
+            "{} can't be rewritten safely, since the store doesn't support conditional writes",
+            read.location
+        ),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Rewrite an object over itself with changed attributes and a new set of tags, since stores
+/// can't change either in place. Any tags it had before are replaced.
+pub async fn rewrite(
+    root: &Root,
+    meta: &ObjectMeta,
+    change: impl FnOnce(Attributes) -> Attributes,
+    tags: TagSet,
+) -> Result<ObjectMeta> {
+    let existing = root.store.get(&meta.location).await?;
+    let attributes = change(existing.attributes.clone());
+    let read = existing.meta.clone();
+    if meta.size <= PART_SIZE {
+        let body = existing.bytes().await?;
+        put_unchanged(root, &read, body, attributes, tags).await?;
+    } else {
+        // The old version stays readable until the upload completes, so this can stream
+        let mut body = existing.into_stream();
+        let mut upload = Upload::start_tagged(root, &meta.location, attributes, tags).await?;
+        let streamed: Result<()> = async {
+            while let Some(chunk) = body.next().await {
+                upload.write(&chunk?).await?;
+            }
+            // Uploads in parts can't be conditional, so this is the last chance to notice
+            let current = root.store.head(&meta.location).await?;
+            ensure!(
+                (&current.e_tag, &current.version) == (&read.e_tag, &read.version),
+                "{} changed while it was being rewritten, nothing was written",
+                meta.location
+            );
+            Ok(())
+        }
+        .await;
+        upload.finish_or_abort(streamed).await?;
+    }
+    Ok(root.store.head(&meta.location).await?)
+}
+
+impl SetMeta {
+    /// The object's existing attributes, with the ones given on the command line replacing them
+    fn merged(&self, meta: &ObjectMeta, mut attributes: Attributes) -> Attributes {
+        let content_type = match self.content_type.as_deref() {
+            Some("auto") => meta.location.extension().and_then(guess_content_type),
+            other => other,
+        };
+        if let Some(content_type) = content_type {
+            attributes.insert(Attribute::ContentType, content_type.to_string().into());
+        }
+        if let Some(cache_control) = &self.cache_control {
+            attributes.insert(Attribute::CacheControl, cache_control.clone().into());
+        }
+        for (key, value) in &self.metadata {
+            attributes.insert(
+                Attribute::Metadata(key.clone().into()),
+                value.clone().into(),
+            );
+        }
+        attributes
+    }
+
+    /// Rewrite an object over itself with new attributes
+    async fn update(&self, root: &Root, meta: &ObjectMeta) -> Result<ObjectMeta> {
+        rewrite(
+            root,
+            meta,
+            |attributes| self.merged(meta, attributes),
+            TagSet::default(),
+        )
+        .await
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        ensure!(
+            self.content_type.is_some()
+                || self.cache_control.is_some()
+                || !self.metadata.is_empty(),
+            "Give at least one of --content-type, --cache-control or --meta to set"
+        );
+        let preamble = listing::read_preamble()?;
+        // Check the store before reading any objects, so nothing fails halfway through
+        let url = preamble.root()?;
+        if url.scheme() == "file" {
+            bail!("The local filesystem can't store content types or metadata");
+        }
+        let root = &Root::open(url)?;
+        let failures = FailureLog::create(self.failed_out.as_deref(), &preamble)?;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let (writer, failures_ref) = (&stdout, &failures);
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                match self.update(root, &meta).await {
+                    Ok(updated) => writer.write(updated.into()).await,
+                    Err(e) => failures_ref.record(&meta, &e),
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to update {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::path::Path as ObjectStorePath;
+    use url::Url;
+
+    #[tokio::test]
+    async fn only_writes_over_the_version_it_read() {
+        let root = Root::open(&Url::parse("memory:///data/").unwrap()).unwrap();
+        let location = ObjectStorePath::from("data/a.csv");
+        root.store.put(&location, "a,b\n".into()).await.unwrap();
+        let meta = root.store.head(&location).await.unwrap();
+
+        let set_meta = SetMeta::try_parse_from(["set-meta", "--content-type", "auto"]).unwrap();
+        let updated = set_meta.update(&root, &meta).await.unwrap();
+        assert_ne!(updated.e_tag, meta.e_tag);
+        let attributes = root.store.get(&location).await.unwrap().attributes;
+        assert_eq!(
+            attributes.get(&Attribute::ContentType).map(|v| v.as_ref()),
+            Some("text/csv")
+        );
+
+        // Written again since it was read, so what was read is out of date
+        root.store.put(&location, "c,d\n".into()).await.unwrap();
+        let error = put_unchanged(
+            &root,
+            &updated,
+            "a,b\n".into(),
+            Attributes::new(),
+            TagSet::default(),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("changed"), "{error}");
+        let kept = root
+            .store
+            .get(&location)
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap();
+        assert_eq!(kept, "c,d\n");
+    }
+}
@@ -0,0 +1,171 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use futures::TryStreamExt;
+use serde::Serialize;
+
+use crate::diff::has_changed;
+use crate::join::{merge_join, Joined};
+use crate::listing::{self, ListingPath, ListingWriter, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Snapshot {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// List a root and save every object in it to a file, sorted by key.
+    ///
+    /// The file is compressed if its name ends in `.gz` or `.zst`.
+    Create {
+        /// The root to list
+        #[arg(short, long)]
+        root: String,
+        /// Where to save the snapshot, a file or an object in a store
+        #[arg(long, value_parser = ListingPath::parse)]
+        out: ListingPath,
+    },
+    /// List a root again and print only what was added, removed or changed since a snapshot
+    Diff {
+        /// The snapshot to compare against, a file or an object in a store
+        #[arg(long, value_parser = ListingPath::parse)]
+        base: ListingPath,
+        /// The root to list, by default the one the snapshot was taken of
+        #[arg(short, long)]
+        root: Option<String>,
+    },
+}
+
+/// How an object differs from the snapshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Change {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// An object that differs from the snapshot, as it is now or, if it was removed, as it was
+#[derive(Debug, Serialize)]
+struct Changed {
+    change: Change,
+    #[serde(flatten)]
+    object: ObjectExport,
+}
+
+/// Save a snapshot, streaming it to the file so it never has to fit in memory
+async fn create(root: &str, out: &ListingPath) -> Result<()> {
+    let root = Root::open(&listing::parse_root(root)?)?;
+    let mut writer = ListingWriter::create(out, &Preamble::new(root.url.clone())).await?;
+    let mut objects = root.list_sorted();
+    let mut count = 0;
+    let written: Result<()> = async {
+        while let Some(meta) = objects.try_next().await? {
+            count += 1;
+            writer.write(ObjectExport::from(meta)).await?;
+        }
+        Ok(())
+    }
+    .await;
+    match written {
+        Ok(()) => writer.finish().await?,
+        Err(e) => {
+            writer.abort().await;
+            return Err(e);
+        }
+    }
+    eprintln!("Saved {count} objects to {out}");
+    Ok(())
+}
+
+/// Compare a root to a snapshot, holding only one object from each side at a time
+async fn diff(base: &ListingPath, root: Option<&str>) -> Result<()> {
+    let (preamble, snapshot) = listing::read_file(base).await?;
+    let before = Root::open(preamble.root()?)?;
+    let now = match root {
+        Some(root) => Root::open(&listing::parse_root(root)?)?,
+        None => before.clone(),
+    };
+    let before_ref = &before;
+    let snapshot =
+        snapshot.and_then(|meta| async move { Ok((before_ref.key(&meta.location)?, meta)) });
+
+    let stdout = StdoutWriter::start(&Preamble::new(now.url.clone()))?;
+    let (mut added, mut removed, mut changed) = (0, 0, 0);
+    let mut joined = std::pin::pin!(merge_join(snapshot, now.list_keyed()));
+    while let Some(step) = joined.try_next().await? {
+        let (change, meta) = match step {
+            Joined::Left(mut old) => {
+                removed += 1;
+                // Name it where it would be now, so the output is one listing of the current root
+                old.location = before.rebase(&old.location, &now)?;
+                (Change::Removed, old)
+            }
+            Joined::Right(new) => {
+                added += 1;
+                (Change::Added, new)
+            }
+            Joined::Both(old, new) if has_changed(&old, &new) => {
+                changed += 1;
+                (Change::Changed, new)
+            }
+            Joined::Both(..) => continue,
+        };
+        stdout
+            .write(Changed {
+                change,
+                object: meta.into(),
+            })
+            .await?;
+    }
+    stdout.finish().await?;
+    eprintln!("{added} added, {removed} removed, {changed} changed");
+    Ok(())
+}
+
+impl Snapshot {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        match &self.action {
+            Action::Create { root, out } => create(root, out).await,
+            Action::Diff { base, root } => diff(base, root.as_deref()).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn saves_and_reads_back_compressed_snapshots() {
+        let dir = std::env::temp_dir().join(format!("obvious3-snapshot-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("root/sub")).unwrap();
+        std::fs::write(dir.join("root/a.txt"), "a").unwrap();
+        std::fs::write(dir.join("root/sub/b.txt"), "bb").unwrap();
+        let root = dir.join("root");
+
+        for name in ["plain.ndjson", "packed.ndjson.gz", "packed.ndjson.zst"] {
+            let out = ListingPath::parse(dir.join(name).to_str().unwrap()).unwrap();
+            create(root.to_str().unwrap(), &out).await.unwrap();
+            let saved = std::fs::read(dir.join(name)).unwrap();
+            assert_eq!(
+                saved.first() == Some(&b'{'),
+                name == "plain.ndjson",
+                "{name}"
+            );
+
+            let (_, objects) = listing::read_file(&out).await.unwrap();
+            let objects: Vec<_> = objects.try_collect().await.unwrap();
+            let keys: Vec<_> = objects
+                .iter()
+                .map(|meta| meta.location.filename())
+                .collect();
+            assert_eq!(keys, [Some("a.txt"), Some("b.txt")], "{name}");
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use std::cmp::Ordering;
+
+use anyhow::{bail, Result};
+use clap::{Parser, ValueEnum};
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+
+use crate::listing::{self, StdoutWriter};
+use crate::units::{format_size, parse_size};
+use crate::{Annotations, Args, ObjectExport};
+
+#[derive(Debug, Parser)]
+pub struct Sort {
+    /// What to sort objects by. Ties are broken by path.
+    #[arg(long, value_enum, default_value_t = SortKey::Path)]
+    by: SortKey,
+    /// Sort from largest to smallest, or newest to oldest
+    #[arg(long)]
+    reverse: bool,
+    /// Give up rather than hold more than about this much of the listing in memory, like `2GiB`
+    #[arg(long, value_parser = parse_size, default_value = "1GiB")]
+    max_memory: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Path,
+    Size,
+    Mtime,
+}
+
+impl SortKey {
+    /// Compare two objects by this key, falling back to their paths
+    pub fn compare(self, a: &ObjectMeta, b: &ObjectMeta) -> Ordering {
+        let ordering = match self {
+            SortKey::Path => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::Mtime => a.last_modified.cmp(&b.last_modified),
+        };
+        ordering.then_with(|| a.location.cmp(&b.location))
+    }
+}
+
+/// Roughly how much memory an object takes up once it's been read
+fn footprint(meta: &ObjectMeta, annotations: &Annotations) -> u64 {
+    (std::mem::size_of::<(ObjectMeta, Annotations)>()
+        + meta.location.as_ref().len()
+        + meta.e_tag.as_ref().map_or(0, String::len)
+        + meta.version.as_ref().map_or(0, String::len)
+        + annotations
+            .extra
+            .iter()
+            .map(|(key, value)| key.len() + value.to_string().len())
+            .sum::<usize>()) as u64
+}
+
+impl Sort {
+    /// Read every object into memory and sort them, keeping what else the listing says about each
+    async fn sort(
+        &self,
+        objects: impl futures::Stream<Item = Result<ObjectExport>>,
+    ) -> Result<Vec<ObjectExport>> {
+        let mut objects = std::pin::pin!(objects);
+        let mut sorted = vec![];
+        let mut memory = 0;
+        while let Some(object) = objects.try_next().await? {
+            let (meta, annotations) = object.split();
+            memory += footprint(&meta, &annotations);
+            if memory > self.max_memory {
+                bail!(
+                    "The listing needs more than --max-memory {} to sort, after {} objects",
+                    format_size(self.max_memory),
+                    sorted.len()
+                );
+            }
+            sorted.push((meta, annotations));
+        }
+        sorted.sort_by(|(a, _), (b, _)| {
+            let ordering = self.by.compare(a, b);
+            if self.reverse {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+        Ok(sorted
+            .into_iter()
+            .map(|(meta, annotations)| ObjectExport::annotated(meta, annotations))
+            .collect())
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let sorted = self.sort(listing::read_stdin_exports()).await?;
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        for object in sorted {
+            stdout.write(object).await?;
+        }
+        stdout.finish().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(location: &str, size: usize, extra: &str) -> Result<ObjectExport> {
+        Ok(serde_json::from_str(&format!(
+            r#"{{"location":"{location}","last_modified":"2024-01-01T00:00:00Z","size":{size},{extra}}}"#
+        ))?)
+    }
+
+    #[tokio::test]
+    async fn keeps_the_fields_earlier_commands_added() {
+        let sort = Sort::try_parse_from(["sort", "--by", "size"]).unwrap();
+        let objects = futures::stream::iter([
+            line("b", 2, r#""root":1,"basename":"b","dataset":"x""#),
+            line("a", 1, r#""root":0,"basename":"a","depth":1"#),
+        ]);
+        let sorted = sort.sort(objects).await.unwrap();
+        let written: Vec<_> = sorted
+            .iter()
+            .map(|object| serde_json::to_value(object).unwrap())
+            .collect();
+        assert_eq!(written[0]["location"], "a");
+        assert_eq!(written[0]["root"], 0);
+        assert_eq!(written[0]["depth"], 1);
+        assert_eq!(written[1]["basename"], "b");
+        assert_eq!(written[1]["dataset"], "x");
+    }
+
+    #[tokio::test]
+    async fn counts_the_added_fields_against_max_memory() {
+        let sort = Sort::try_parse_from(["sort", "--max-memory", "1KiB"]).unwrap();
+        let padding = "p".repeat(2000);
+        let objects = futures::stream::iter([line("a", 1, &format!(r#""note":"{padding}""#))]);
+        assert!(sort.sort(objects).await.is_err());
+    }
+}
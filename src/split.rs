@@ -0,0 +1,139 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+
+use crate::listing;
+use crate::store::Root;
+use crate::units::{format_size, parse_size};
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+#[command(group = clap::ArgGroup::new("how").required(true).args(["shards", "max_bytes"]))]
+pub struct Split {
+    /// Where to write each shard. `{index}` is replaced with the shard's number, counting from 0.
+    #[arg(short, long)]
+    out: String,
+    /// Split into this many shards, dealing objects out in turn
+    #[arg(long)]
+    shards: Option<usize>,
+    /// Split into shards of about this many bytes each, like `50GiB`.
+    ///
+    /// The whole listing is read into memory first, so objects can be balanced across shards.
+    #[arg(long, value_parser = parse_size)]
+    max_bytes: Option<u64>,
+    /// With `--shards`, choose each object's shard by a hash of its key,
+    /// so the same object always lands in the same shard
+    #[arg(long, requires = "shards")]
+    by_hash: bool,
+}
+
+/// FNV-1a, which unlike the standard library's hasher is guaranteed to stay the same between builds
+fn stable_hash(key: &str) -> u64 {
+    key.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The output files, each an independent listing with its own preamble
+struct Shards {
+    files: Vec<BufWriter<File>>,
+    objects: Vec<usize>,
+    bytes: Vec<u64>,
+}
+
+impl Shards {
+    fn create(template: &str, count: usize, preamble: &Preamble) -> Result<Self> {
+        // Pad the numbers so the files sort in order
+        let width = count.saturating_sub(1).to_string().len();
+        let files = (0..count)
+            .map(|index| {
+                let path = PathBuf::from(template.replace("{index}", &format!("{index:0width$}")));
+                let mut file = BufWriter::new(
+                    File::create(&path).with_context(|| format!("Creating {}", path.display()))?,
+                );
+                writeln!(file, "{}", serde_json::to_string(preamble)?)?;
+                Ok(file)
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            files,
+            objects: vec![0; count],
+            bytes: vec![0; count],
+        })
+    }
+
+    fn write(&mut self, index: usize, object: ObjectExport) -> Result<()> {
+        self.objects[index] += 1;
+        self.bytes[index] += object.size as u64;
+        let line = serde_json::to_string(&object)?;
+        writeln!(self.files[index], "{line}")?;
+        Ok(())
+    }
+
+    /// The shard with the fewest bytes so far
+    fn smallest(&self) -> usize {
+        (0..self.bytes.len())
+            .min_by_key(|&index| self.bytes[index])
+            .unwrap_or_default()
+    }
+
+    fn finish(self) -> Result<()> {
+        for (index, mut file) in self.files.into_iter().enumerate() {
+            file.flush()?;
+            eprintln!(
+                "Shard {index}: {} objects, {}",
+                self.objects[index],
+                format_size(self.bytes[index])
+            );
+        }
+        Ok(())
+    }
+}
+
+impl Split {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        ensure!(
+            self.out.contains("{index}"),
+            "--out needs to contain {{index}}, or every shard would be written to the same file"
+        );
+        let preamble = listing::read_preamble()?;
+        let objects = listing::read_stdin_exports();
+
+        let Some(max_bytes) = self.max_bytes else {
+            let count = self.shards.unwrap_or_default();
+            ensure!(count > 0, "--shards must be at least 1");
+            let root = Root::open(preamble.root()?)?;
+            let mut shards = Shards::create(&self.out, count, &preamble)?;
+            let mut objects = std::pin::pin!(objects);
+            let mut turn = 0;
+            while let Some(object) = objects.try_next().await? {
+                let index = if self.by_hash {
+                    let location = ObjectStorePath::from(object.location.as_str());
+                    (stable_hash(&root.key(&location)?) % count as u64) as usize
+                } else {
+                    turn += 1;
+                    (turn - 1) % count
+                };
+                shards.write(index, object)?;
+            }
+            return shards.finish();
+        };
+
+        ensure!(max_bytes > 0, "--max-bytes must be more than zero");
+        let mut objects: Vec<ObjectExport> = objects.try_collect().await?;
+        let total: u64 = objects.iter().map(|object| object.size as u64).sum();
+        let count = total.div_ceil(max_bytes).max(1) as usize;
+        // Placing the largest objects first, each into the emptiest shard, keeps the shards even
+        objects.sort_by_key(|object| std::cmp::Reverse(object.size));
+        let mut shards = Shards::create(&self.out, count, &preamble)?;
+        for object in objects {
+            shards.write(shards.smallest(), object)?;
+        }
+        shards.finish()
+    }
+}
@@ -0,0 +1,108 @@
+use anyhow::{bail, Result};
+use clap::{Parser, ValueEnum};
+use futures::StreamExt;
+
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Stat {
+    /// The objects to look up
+    #[arg(required = true)]
+    urls: Vec<String>,
+    /// How to print the objects
+    #[arg(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// An obvious3 listing, rooted at the top of the store
+    Json,
+    /// An aligned table for people to read
+    Table,
+}
+
+impl Stat {
+    fn print_table(objects: &[ObjectExport]) {
+        let rows: Vec<[String; 5]> = objects
+            .iter()
+            .map(|o| {
+                [
+                    o.location.clone(),
+                    o.size.to_string(),
+                    o.last_modified.to_rfc3339(),
+                    o.e_tag.clone().unwrap_or_default(),
+                    o.version.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        let header = ["LOCATION", "SIZE", "LAST_MODIFIED", "E_TAG", "VERSION"].map(String::from);
+        let mut widths = [0; 5];
+        for row in std::iter::once(&header).chain(&rows) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        for row in std::iter::once(&header).chain(&rows) {
+            let line = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ");
+            println!("{}", line.trim_end());
+        }
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let roots = self
+            .urls
+            .iter()
+            .map(|url| Root::open(&listing::parse_root(url)?))
+            .collect::<Result<Vec<_>>>()?;
+        let store_url = roots[0].store_url()?;
+        if self.format == Format::Json
+            && roots
+                .iter()
+                .any(|r| r.store_url().ok().as_ref() != Some(&store_url))
+        {
+            bail!("A listing can only refer to one store, so stat them separately or use --format table");
+        }
+
+        // Look them up concurrently, but keep them in the order given
+        let results: Vec<_> = futures::stream::iter(&roots)
+            .map(|root| async move { (root, root.store.head(&root.path).await) })
+            .buffered(global_args.concurrency)
+            .collect()
+            .await;
+        let mut objects = vec![];
+        let mut missing = 0;
+        for (root, result) in results {
+            match result {
+                Ok(meta) => objects.push(ObjectExport::from(meta)),
+                Err(object_store::Error::NotFound { .. }) => {
+                    eprintln!("No such object: {}", root.url);
+                    missing += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        match self.format {
+            Format::Json => {
+                let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(store_url))?;
+                for object in objects {
+                    stdout.write(object).await?;
+                }
+                stdout.finish().await?;
+            }
+            Format::Table => Self::print_table(&objects),
+        }
+        if missing > 0 {
+            bail!("{missing} of the objects do not exist");
+        }
+        Ok(())
+    }
+}
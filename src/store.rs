@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::{Path as ObjectStorePath, PathPart};
+use object_store::{ObjectMeta, ObjectStore, ObjectStoreScheme};
+use url::Url;
+
+/// An object store along with the path inside it that a command is rooted at
+#[derive(Debug, Clone)]
+pub struct Root {
+    /// The URL this root was opened from
+    pub url: Url,
+    /// The store containing the root
+    pub store: Arc<dyn ObjectStore>,
+    /// The path within the store, which object locations are expected to live under
+    pub path: ObjectStorePath,
+    /// Identifies the store itself, ignoring the path within it
+    identity: String,
+}
+
+impl Root {
+    /// Open the store a URL refers to
+    pub fn open(url: &Url) -> Result<Self> {
+        let (store, path) = object_store::parse_url(url)?;
+        Ok(Self {
+            url: url.clone(),
+            store: Arc::from(store),
+            path,
+            identity: Self::identity(url)?,
+        })
+    }
+
+    /// Open another root, reusing this root's store when they both refer to the same one
+    pub fn open_sibling(&self, url: &Url) -> Result<Self> {
+        let identity = Self::identity(url)?;
+        if identity != self.identity {
+            return Self::open(url);
+        }
+        let (_, path) = ObjectStoreScheme::parse(url)?;
+        Ok(Self {
+            url: url.clone(),
+            store: self.store.clone(),
+            path,
+            identity,
+        })
+    }
+
+    /// The URL of the top of the store this root is in
+    pub fn store_url(&self) -> Result<Url> {
+        Ok(Url::parse(&format!("{}/", self.identity))?)
+    }
+
+    /// The full URL of an object in this root's store
+    pub fn object_url(&self, location: &ObjectStorePath) -> Result<Url> {
+        join_location(&self.identity, location)
+    }
+
+    /// Whether both roots live in the same store, so objects can be copied server side
+    pub fn same_store(&self, other: &Root) -> bool {
+        Arc::ptr_eq(&self.store, &other.store)
+    }
+
+    /// Whether a location in this root's store is the very object at one in another root's
+    pub fn is_same_object(
+        &self,
+        location: &ObjectStorePath,
+        other: &Root,
+        other_location: &ObjectStorePath,
+    ) -> bool {
+        self.identity == other.identity && location == other_location
+    }
+
+    /// Whether everything under `other` is under this root too
+    pub fn contains(&self, other: &Root) -> bool {
+        self.identity == other.identity && other.path.prefix_match(&self.path).is_some()
+    }
+
+    /// Everything about the URL except the path inside the store
+    fn identity(url: &Url) -> Result<String> {
+        let (_, path) = ObjectStoreScheme::parse(url)?;
+        // A root like `s3://bucket/prefix/` has a path of `prefix`, without the slash
+        let full = url[..url::Position::AfterPath].trim_end_matches('/');
+        Ok(full
+            .strip_suffix(path.as_ref())
+            .unwrap_or(full)
+            .trim_end_matches('/')
+            .to_string())
+    }
+
+    /// The parts of an object's location below this root
+    pub fn relative<'a>(
+        &self,
+        location: &'a ObjectStorePath,
+    ) -> Result<impl Iterator<Item = PathPart<'a>> + 'a> {
+        location
+            .prefix_match(&self.path)
+            .with_context(|| format!("{location} is not under the root {}", self.url))
+    }
+
+    /// The location of an object relative to this root, for comparing objects across roots
+    pub fn key(&self, location: &ObjectStorePath) -> Result<String> {
+        Ok(self
+            .relative(location)?
+            .collect::<ObjectStorePath>()
+            .to_string())
+    }
+
+    /// List every object under this root in key order.
+    ///
+    /// Cloud stores already list in lexicographic order, but the local filesystem doesn't,
+    /// so local listings are collected and sorted in memory first.
+    pub fn list_sorted(&self) -> BoxStream<'_, Result<ObjectMeta>> {
+        let listing = self
+            .store
+            .list(Some(&self.path))
+            .map_err(anyhow::Error::from);
+        if self.url.scheme() != "file" {
+            return listing.boxed();
+        }
+        futures::stream::once(async move {
+            let mut objects: Vec<ObjectMeta> = listing.try_collect().await?;
+            objects.sort_by(|a, b| a.location.cmp(&b.location));
+            anyhow::Ok(futures::stream::iter(objects.into_iter().map(anyhow::Ok)))
+        })
+        .try_flatten()
+        .boxed()
+    }
+
+    /// List every object in key order, paired with its key relative to this root
+    pub fn list_keyed(&self) -> BoxStream<'_, Result<(String, ObjectMeta)>> {
+        self.list_sorted()
+            .and_then(move |meta| async move { Ok((self.key(&meta.location)?, meta)) })
+            .boxed()
+    }
+
+    /// Find where an object under this root would be located under another root
+    pub fn rebase(&self, location: &ObjectStorePath, onto: &Root) -> Result<ObjectStorePath> {
+        Ok(onto.path.parts().chain(self.relative(location)?).collect())
+    }
+}
+
+/// The full URL of an object in the same store as a root, like `s3://bucket/prefix/key`,
+/// without opening the store.
+///
+/// Locations are full paths within the store, so the root's own path is replaced rather than
+/// joined onto, and it makes no difference whether the root ends in a slash.
+pub fn object_url(root: &Url, location: &ObjectStorePath) -> Result<Url> {
+    join_location(&Root::identity(root)?, location)
+}
+
+/// The URL of the top of the store a root is in, like `s3://bucket/`, without opening the store
+pub fn store_url(root: &Url) -> Result<Url> {
+    Ok(Url::parse(&format!("{}/", Root::identity(root)?))?)
+}
+
+/// Put a location at the top of the store a root's identity refers to
+fn join_location(identity: &str, location: &ObjectStorePath) -> Result<Url> {
+    let mut url = Url::parse(&format!("{identity}/"))?;
+    url.set_path(&format!("{}{location}", url.path()));
+    Ok(url)
+}
+
+/// An object's etag without the quotes some stores wrap it in, so etags compare the same everywhere
+pub fn etag(meta: &ObjectMeta) -> Option<&str> {
+    meta.e_tag
+        .as_deref()
+        .map(|e_tag| e_tag.trim_matches('"'))
+        .filter(|e_tag| !e_tag.is_empty())
+}
+
+/// The storage class the store keeps an object in, like `GLACIER`, or `None` if it has no such
+/// thing. object_store doesn't pass on the class from listings or HEAD yet, so for now no store
+/// reports one.
+pub fn storage_class(_meta: &ObjectMeta) -> Option<&str> {
+    None
+}
+
+/// Whether an object is only a marker standing in for a directory, rather than real data.
+///
+/// Hadoop and EMR create zero byte objects ending in `_$folder$`. Consoles create them ending in
+/// a slash instead, but object paths drop trailing slashes as they're parsed,
+/// so those look just like any other empty object by the time they get here. `get` tells them
+/// apart by the directory the objects under them need.
+pub fn is_dir_marker(meta: &ObjectMeta) -> bool {
+    meta.size == 0 && meta.location.as_ref().ends_with("_$folder$")
+}
+
+/// Run an operation, trying again with exponential backoff if it fails.
+///
+/// `retries` is the number of extra attempts after the first one.
+pub async fn with_retries<T, F, Fut>(retries: usize, mut operation: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T>>,
+{
+    let mut delay = std::time::Duration::from_millis(100);
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < retries => {
+                tracing::debug!("Attempt {} failed, retrying: {e:#}", attempt + 1);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(location: &str, size: usize) -> ObjectMeta {
+        ObjectMeta {
+            location: ObjectStorePath::from(location),
+            last_modified: Default::default(),
+            size,
+            e_tag: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn only_empty_folder_objects_are_dir_markers() {
+        assert!(is_dir_marker(&meta("logs_$folder$", 0)));
+        assert!(is_dir_marker(&meta("data/2024/logs_$folder$", 0)));
+        // Keys that only contain slashes, or mention a folder somewhere else, are data
+        assert!(!is_dir_marker(&meta("data/2024/logs", 0)));
+        assert!(!is_dir_marker(&meta("logs_$folder$/a.csv", 0)));
+        // Something written there isn't standing in for anything
+        assert!(!is_dir_marker(&meta("logs_$folder$", 12)));
+    }
+}
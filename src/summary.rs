@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::{Parser, ValueEnum};
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+use serde::Serialize;
+
+use crate::find::Find;
+use crate::units::format_size;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Summary {
+    #[command(flatten)]
+    find: Find,
+    /// Print a table for people, or one JSON document for scripts
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+/// Totals over every matching object, built up one object at a time
+#[derive(Debug, Default, Serialize)]
+struct Stats {
+    objects: usize,
+    bytes: u64,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    mean_size: Option<f64>,
+    oldest: Option<DateTime<Utc>>,
+    newest: Option<DateTime<Utc>>,
+    /// Objects per extension, with an empty string for objects without one
+    extensions: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    fn add(&mut self, meta: &ObjectMeta) {
+        let size = meta.size as u64;
+        self.objects += 1;
+        self.bytes += size;
+        self.min_size = Some(self.min_size.map_or(size, |min| min.min(size)));
+        self.max_size = Some(self.max_size.map_or(size, |max| max.max(size)));
+        self.oldest = Some(
+            self.oldest
+                .map_or(meta.last_modified, |t| t.min(meta.last_modified)),
+        );
+        self.newest = Some(
+            self.newest
+                .map_or(meta.last_modified, |t| t.max(meta.last_modified)),
+        );
+        let extension = meta.location.extension().unwrap_or_default();
+        *self
+            .extensions
+            .entry(extension.to_ascii_lowercase())
+            .or_default() += 1;
+    }
+
+    fn print(&self) {
+        let size = |bytes: Option<u64>| bytes.map_or("-".to_string(), format_size);
+        let time = |t: Option<DateTime<Utc>>| t.map_or("-".to_string(), |t| t.to_rfc3339());
+        println!("Objects:  {}", self.objects);
+        println!("Bytes:    {} ({})", self.bytes, format_size(self.bytes));
+        println!("Smallest: {}", size(self.min_size));
+        println!("Largest:  {}", size(self.max_size));
+        println!(
+            "Mean:     {}",
+            size(self.mean_size.map(|mean| mean.round() as u64))
+        );
+        println!("Oldest:   {}", time(self.oldest));
+        println!("Newest:   {}", time(self.newest));
+        if self.extensions.is_empty() {
+            return;
+        }
+        println!("Extensions:");
+        let mut extensions: Vec<_> = self.extensions.iter().collect();
+        extensions.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let width = extensions.iter().map(|(_, n)| n.to_string().len()).max();
+        for (extension, count) in extensions {
+            let extension = match extension.as_str() {
+                "" => "(none)",
+                extension => extension,
+            };
+            println!(
+                "  {count:>width$}  {extension}",
+                width = width.unwrap_or_default()
+            );
+        }
+    }
+}
+
+impl Summary {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let filter = self.find.filter().await?;
+        let (_, root) = self.find.open()?;
+        let mut stats = Stats::default();
+        let mut objects = std::pin::pin!(self.find.objects(root.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if filter.is_match(&meta) {
+                stats.add(&meta);
+            }
+        }
+        if stats.objects > 0 {
+            stats.mean_size = Some(stats.bytes as f64 / stats.objects as f64);
+        }
+
+        match self.format {
+            Format::Text => stats.print(),
+            Format::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        }
+        Ok(())
+    }
+}
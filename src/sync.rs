@@ -0,0 +1,195 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+use serde::Serialize;
+
+use crate::copy::copy_object;
+use crate::join::{merge_join, Joined};
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Synchronize {
+    /// The root to copy objects from
+    #[arg(long)]
+    from: String,
+    /// The root to copy objects into. Paths relative to `--from` are preserved.
+    #[arg(long)]
+    to: String,
+    /// Only print the plan, without copying or deleting anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Also delete objects from the destination that don't exist at the source, mirroring it.
+    ///
+    /// Deletions only start after the source has been fully listed and copied.
+    #[arg(long)]
+    delete: bool,
+    /// Refuse to delete anything if more than this many objects would be deleted
+    #[arg(long, requires = "delete")]
+    max_delete: Option<usize>,
+    /// Refuse to delete anything if more than this percent of the destination would be deleted
+    #[arg(long, requires = "delete")]
+    max_delete_percent: Option<f64>,
+}
+
+/// What sync does with an object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Action {
+    /// The object is missing from the destination, or its size differs
+    Copy,
+    /// The destination already has the object
+    Skip,
+    /// The object is only at the destination, and `--delete` was passed.
+    /// Unlike the other actions, the location refers to the destination.
+    Delete,
+}
+
+/// A line of the plan, which is a listing of the source objects with what happens to each
+#[derive(Debug, Serialize)]
+struct Step {
+    action: Action,
+    #[serde(flatten)]
+    object: ObjectExport,
+}
+
+impl Synchronize {
+    /// Check the safety limits before deleting anything
+    fn check_deletions(&self, deleting: usize, dest_objects: usize) -> Result<()> {
+        if let Some(max) = self.max_delete {
+            if deleting > max {
+                bail!("Refusing to delete {deleting} objects, more than --max-delete {max}");
+            }
+        }
+        if let Some(max) = self.max_delete_percent {
+            let percent = 100.0 * deleting as f64 / dest_objects.max(1) as f64;
+            if percent > max {
+                bail!(
+                    "Refusing to delete {deleting} of {dest_objects} objects ({percent:.1}%), \
+                    more than --max-delete-percent {max}"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let source = Root::open(&listing::parse_root(&self.from)?)?;
+        let dest = source.open_sibling(&listing::parse_root(&self.to)?)?;
+
+        let stdout = StdoutWriter::start(&Preamble::new(source.url.clone()))?;
+        let writer = &stdout;
+        let copied = &AtomicUsize::new(0);
+        let skipped = &AtomicUsize::new(0);
+        let failed = &AtomicUsize::new(0);
+        let dest_objects = &AtomicUsize::new(0);
+        // Only the extraneous objects are kept in memory, not the whole listing
+        let extraneous = &Mutex::new(Vec::<ObjectMeta>::new());
+        let (source, dest) = (&source, &dest);
+        merge_join(source.list_keyed(), dest.list_keyed())
+            .try_for_each_concurrent(global_args.concurrency, |joined| async move {
+                let (action, meta) = match joined {
+                    Joined::Left(src) => (Action::Copy, src),
+                    Joined::Both(src, dst) => {
+                        dest_objects.fetch_add(1, Ordering::Relaxed);
+                        if src.size != dst.size {
+                            (Action::Copy, src)
+                        } else {
+                            (Action::Skip, src)
+                        }
+                    }
+                    Joined::Right(dst) => {
+                        dest_objects.fetch_add(1, Ordering::Relaxed);
+                        if self.delete {
+                            extraneous.lock().unwrap().push(dst);
+                        }
+                        return Ok(());
+                    }
+                };
+                if action == Action::Copy && !self.dry_run {
+                    let location = source.rebase(&meta.location, dest)?;
+                    if let Err(e) = copy_object(source, &meta, dest, &location).await {
+                        eprintln!("Failed to copy {}: {e:#}", meta.location);
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                }
+                match action {
+                    Action::Copy => copied.fetch_add(1, Ordering::Relaxed),
+                    _ => skipped.fetch_add(1, Ordering::Relaxed),
+                };
+                writer
+                    .write(Step {
+                        action,
+                        object: meta.into(),
+                    })
+                    .await
+            })
+            .await?;
+
+        let extraneous = std::mem::take(&mut *extraneous.lock().unwrap());
+        let limits = self.check_deletions(extraneous.len(), dest_objects.load(Ordering::Relaxed));
+        let mut deleted = 0;
+        if self.dry_run {
+            if let Err(e) = &limits {
+                eprintln!("{e}");
+            }
+            for meta in extraneous {
+                deleted += 1;
+                writer
+                    .write(Step {
+                        action: Action::Delete,
+                        object: meta.into(),
+                    })
+                    .await?;
+            }
+        } else if !extraneous.is_empty() {
+            limits?;
+            let locations =
+                futures::stream::iter(extraneous.iter().map(|m| Ok(m.location.clone())));
+            // Results come back in the same order as the locations went in
+            let mut results = dest
+                .store
+                .delete_stream(locations.boxed())
+                .zip(futures::stream::iter(extraneous.iter()));
+            while let Some((result, meta)) = results.next().await {
+                match result {
+                    Ok(_) => {
+                        deleted += 1;
+                        writer
+                            .write(Step {
+                                action: Action::Delete,
+                                object: meta.clone().into(),
+                            })
+                            .await?;
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to delete {}: {e}", meta.location);
+                        failed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+        stdout.finish().await?;
+
+        let (copied, skipped) = (
+            copied.load(Ordering::Relaxed),
+            skipped.load(Ordering::Relaxed),
+        );
+        if self.dry_run {
+            eprintln!("Dry run: would copy {copied} objects, delete {deleted} and skip {skipped}");
+        } else {
+            eprintln!("Copied {copied} objects, deleted {deleted} and skipped {skipped}");
+        }
+        let failed = failed.load(Ordering::Relaxed);
+        if failed > 0 {
+            bail!("Failed to copy or delete {failed} objects");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Result};
+use clap::{Parser, Subcommand};
+use futures::TryStreamExt;
+use object_store::{ObjectStoreScheme, TagSet};
+use url::Url;
+
+use crate::find::Find;
+use crate::listing::{FailureLog, StdoutWriter};
+use crate::set_meta;
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Tag {
+    #[command(subcommand)]
+    action: Action,
+}
+
+#[derive(Debug, Subcommand)]
+enum Action {
+    /// Print the listing with a `tags` map added to each object
+    Get {
+        /// Which objects to read the tags of. Without a root, the listing is read from stdin.
+        #[command(flatten)]
+        find: Find,
+    },
+    /// Replace the tags on objects with these ones. Tags already on an object can't be read
+    /// back to keep, so any it had before are removed.
+    Set {
+        /// The tags to set, like `retention=30d`
+        #[arg(required = true, value_parser = parse_tag)]
+        tags: Vec<(String, String)>,
+        /// Which objects to tag. Without a root, the listing is read from stdin.
+        #[command(flatten)]
+        find: Find,
+        /// Only print which objects would be tagged, without changing anything
+        #[arg(long)]
+        dry_run: bool,
+        /// Also save the objects that failed to be tagged here, as a listing that can be piped
+        /// back in
+        #[arg(long)]
+        failed_out: Option<PathBuf>,
+    },
+    /// Remove tags from objects by key
+    Rm {
+        /// The keys of the tags to remove
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// Which objects to untag. Without a root, the listing is read from stdin.
+        #[command(flatten)]
+        find: Find,
+    },
+}
+
+fn parse_tag(text: &str) -> Result<(String, String)> {
+    match text.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => bail!("Tags are written as key=value, not {text:?}"),
+    }
+}
+
+/// Check the store can do what's asked before reading any objects, so nothing fails halfway
+/// through. object_store can only attach tags while uploading, and has no way to read them back.
+fn check_store(action: &Action, root: &Url) -> Result<()> {
+    let (scheme, _) = ObjectStoreScheme::parse(root)?;
+    if !matches!(
+        scheme,
+        ObjectStoreScheme::AmazonS3 | ObjectStoreScheme::MicrosoftAzure
+    ) {
+        bail!("Tags are unsupported by this store: {root} has no tagging API");
+    }
+    match action {
+        Action::Set { .. } => Ok(()),
+        Action::Get { .. } => bail!("Reading tags is unsupported by this store: {root}"),
+        Action::Rm { .. } => bail!(
+            "Removing single tags is unsupported by this store, since {root} can't read its tags \
+             back. `tag set` replaces them all."
+        ),
+    }
+}
+
+impl Tag {
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let find = match &self.action {
+            Action::Get { find } | Action::Set { find, .. } | Action::Rm { find, .. } => find,
+        };
+        let filter = &find.filter().await?;
+        let (preamble, listed) = find.open()?;
+        // Piped listings don't open the store, but we need it to tag anything
+        let root = &match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+        check_store(&self.action, &root.url)?;
+        let Action::Set {
+            tags,
+            dry_run,
+            failed_out,
+            ..
+        } = &self.action
+        else {
+            unreachable!("only set gets past check_store")
+        };
+        let tags = &tags
+            .iter()
+            .fold(TagSet::default(), |mut set, (key, value)| {
+                set.push(key, value);
+                set
+            });
+
+        let failures = FailureLog::create(failed_out.as_deref(), &preamble)?;
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let (writer, failures_ref) = (&stdout, &failures);
+        let tagged = &AtomicUsize::new(0);
+        find.objects(listed.as_ref())
+            .try_filter(|meta| std::future::ready(filter.is_match(meta)))
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                if *dry_run {
+                    tagged.fetch_add(1, Ordering::Relaxed);
+                    return writer.write(meta.into()).await;
+                }
+                match set_meta::rewrite(root, &meta, |kept| kept, tags.clone()).await {
+                    Ok(updated) => {
+                        tagged.fetch_add(1, Ordering::Relaxed);
+                        writer.write(updated.into()).await
+                    }
+                    Err(e) => failures_ref.record(&meta, &e),
+                }
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let tagged = tagged.load(Ordering::Relaxed);
+        if *dry_run {
+            eprintln!("Dry run: {tagged} objects would be tagged");
+        } else {
+            eprintln!("Tagged {tagged} objects");
+        }
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to tag {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn action(args: &[&str]) -> Action {
+        let args = ["tag"].iter().chain(args);
+        Tag::try_parse_from(args).unwrap().action
+    }
+
+    #[test]
+    fn tags_are_keys_and_values() {
+        assert_eq!(
+            parse_tag("retention=30d").unwrap(),
+            ("retention".to_string(), "30d".to_string())
+        );
+        assert_eq!(
+            parse_tag("empty=").unwrap(),
+            ("empty".to_string(), String::new())
+        );
+        assert!(parse_tag("=30d").is_err());
+        assert!(parse_tag("retention").is_err());
+    }
+
+    #[test]
+    fn refuses_stores_that_cant_tag_up_front() {
+        let set = action(&["set", "retention=30d"]);
+        for root in ["memory:///data/", "file:///tmp/data/", "gs://bucket/data/"] {
+            let error = check_store(&set, &Url::parse(root).unwrap()).unwrap_err();
+            assert!(error.to_string().contains("unsupported"), "{error}");
+        }
+        let s3 = Url::parse("s3://bucket/data/").unwrap();
+        check_store(&set, &s3).unwrap();
+        for unreadable in [action(&["get"]), action(&["rm", "retention"])] {
+            let error = check_store(&unreadable, &s3).unwrap_err();
+            assert!(error.to_string().contains("unsupported"), "{error}");
+        }
+    }
+}
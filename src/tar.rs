@@ -0,0 +1,227 @@
+use anyhow::{ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::codec::{Codec, Encoder};
+use crate::copy::Upload;
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::{Args, ObjectExport, Preamble};
+
+/// Tar archives are made of blocks this size
+pub const BLOCK: usize = 512;
+/// The largest size the ustar header has room for, in octal
+const MAX_USTAR_SIZE: u64 = 0o77777777777;
+
+#[derive(Debug, Parser)]
+pub struct Tar {
+    /// Upload the archive as this object, rather than writing it to stdout
+    #[arg(short, long)]
+    dest: Option<String>,
+    /// Compress the archive with gzip
+    #[arg(short = 'z', long)]
+    gzip: bool,
+}
+
+/// Write a number as a zero padded octal field, ending in a NUL
+fn octal(field: &mut [u8], value: u64) {
+    let end = field.len() - 1;
+    field[..end].copy_from_slice(format!("{value:0end$o}").as_bytes());
+}
+
+/// One ustar header block
+fn ustar(name: &[u8], prefix: &[u8], size: u64, mtime: u64, kind: u8) -> [u8; BLOCK] {
+    let mut header = [0u8; BLOCK];
+    header[..name.len()].copy_from_slice(name);
+    octal(&mut header[100..108], 0o644);
+    octal(&mut header[108..116], 0);
+    octal(&mut header[116..124], 0);
+    octal(&mut header[124..136], size);
+    octal(&mut header[136..148], mtime);
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix);
+    // The checksum is calculated as if its own field were spaces
+    header[148..156].fill(b' ');
+    let checksum: u64 = header.iter().map(|b| *b as u64).sum();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    header
+}
+
+/// Split a name into the ustar prefix and name fields, if it fits
+fn split_name(name: &str) -> Option<(&[u8], &[u8])> {
+    let bytes = name.as_bytes();
+    if bytes.len() <= 100 {
+        return Some((bytes, b""));
+    }
+    bytes
+        .iter()
+        .enumerate()
+        .filter(|(i, b)| **b == b'/' && *i <= 155 && bytes.len() - i - 1 <= 100)
+        .map(|(i, _)| (&bytes[i + 1..], &bytes[..i]))
+        .find(|(name, _)| !name.is_empty())
+}
+
+/// A pax extended header record, which starts with its own length
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {key}={value}\n");
+    let mut length = rest.len();
+    // Adding the length can make it one digit longer, so repeat until it settles
+    while length.to_string().len() + rest.len() != length {
+        length = length.to_string().len() + rest.len();
+    }
+    format!("{length}{rest}")
+}
+
+/// The header blocks for a file entry, using a pax extended header for names and sizes ustar can't hold
+pub fn file_header(name: &str, size: u64, mtime: i64) -> Vec<u8> {
+    let mtime = mtime.max(0) as u64;
+    let split = split_name(name);
+    let mut records = String::new();
+    if split.is_none() {
+        records.push_str(&pax_record("path", name));
+    }
+    if size > MAX_USTAR_SIZE {
+        records.push_str(&pax_record("size", &size.to_string()));
+    }
+
+    let mut blocks = vec![];
+    if !records.is_empty() {
+        let records = records.into_bytes();
+        blocks.extend_from_slice(&ustar(
+            b"././@PaxHeader",
+            b"",
+            records.len() as u64,
+            mtime,
+            b'x',
+        ));
+        blocks.extend_from_slice(&records);
+        blocks.resize(blocks.len().next_multiple_of(BLOCK), 0);
+    }
+    // Readers that understand pax ignore these fields, and others get something close
+    let (short_name, prefix) = split.unwrap_or_else(|| {
+        let bytes = name.as_bytes();
+        (&bytes[bytes.len() - 100..], b"")
+    });
+    blocks.extend_from_slice(&ustar(
+        short_name,
+        prefix,
+        size.min(MAX_USTAR_SIZE),
+        mtime,
+        b'0',
+    ));
+    blocks
+}
+
+/// Where the archive is written
+enum Output {
+    Stdout(tokio::io::Stdout),
+    Upload(Upload),
+}
+
+/// The archive's destination, compressing it on the way if requested
+struct Sink {
+    output: Output,
+    gzip: Option<Encoder>,
+}
+
+impl Sink {
+    async fn write(&mut self, data: &[u8]) -> Result<()> {
+        let compressed;
+        let data = match &mut self.gzip {
+            Some(encoder) => {
+                compressed = encoder.update(data)?;
+                &compressed[..]
+            }
+            None => data,
+        };
+        match &mut self.output {
+            Output::Stdout(out) => out.write_all(data).await?,
+            Output::Upload(upload) => upload.write(data).await?,
+        }
+        Ok(())
+    }
+
+    async fn finish(mut self, written: Result<()>) -> Result<()> {
+        let written = match (written, self.gzip.take()) {
+            (Ok(()), Some(encoder)) => match encoder.finish() {
+                Ok(rest) => self.write(&rest).await,
+                Err(err) => Err(err),
+            },
+            (written, _) => written,
+        };
+        match self.output {
+            Output::Stdout(mut out) => {
+                written?;
+                Ok(out.flush().await?)
+            }
+            Output::Upload(upload) => upload.finish_or_abort(written).await,
+        }
+    }
+}
+
+impl Tar {
+    /// Write every object in the listing to the archive in listing order, fetching ahead concurrently
+    async fn archive(root: &Root, sink: &mut Sink, concurrency: usize) -> Result<()> {
+        let mut objects = std::pin::pin!(listing::read_stdin()
+            .map_ok(|meta| async move {
+                let key = root.key(&meta.location)?;
+                anyhow::Ok((key, root.store.get(&meta.location).await?))
+            })
+            .try_buffered(concurrency));
+        while let Some((key, object)) = objects.try_next().await? {
+            let size = object.meta.size;
+            sink.write(&file_header(
+                &key,
+                size as u64,
+                object.meta.last_modified.timestamp(),
+            ))
+            .await?;
+            let mut body = object.into_stream();
+            let mut written = 0;
+            while let Some(chunk) = body.try_next().await? {
+                written += chunk.len();
+                sink.write(&chunk).await?;
+            }
+            ensure!(
+                written == size,
+                "{key} changed size while it was being archived"
+            );
+            sink.write(&[0; BLOCK][..(BLOCK - size % BLOCK) % BLOCK])
+                .await?;
+        }
+        // The end of the archive is marked by two empty blocks
+        sink.write(&[0; 2 * BLOCK]).await
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let root = Root::open(listing::read_preamble()?.root()?)?;
+        let dest = self
+            .dest
+            .as_deref()
+            .map(|dest| root.open_sibling(&listing::parse_root(dest)?))
+            .transpose()?;
+        let output = match &dest {
+            Some(dest) => Output::Upload(Upload::start(dest, &dest.path).await?),
+            None => Output::Stdout(tokio::io::stdout()),
+        };
+        let mut sink = Sink {
+            output,
+            gzip: self.gzip.then(|| Encoder::new(Codec::Gzip)).transpose()?,
+        };
+        let written = Self::archive(&root, &mut sink, global_args.concurrency).await;
+        let finished = sink.finish(written).await;
+
+        let Some(dest) = dest else {
+            return listing::ignore_broken_pipe(finished);
+        };
+        finished?;
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.store_url()?))?;
+        stdout
+            .write(ObjectExport::from(dest.store.head(&dest.path).await?))
+            .await?;
+        stdout.finish().await
+    }
+}
@@ -0,0 +1,212 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use anyhow::{bail, Result};
+use clap::{Parser, ValueEnum};
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+use serde::Serialize;
+
+use crate::du::{self, Usage};
+use crate::find::Find;
+use crate::listing::StdoutWriter;
+use crate::store::Root;
+use crate::units::format_size;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Top {
+    /// Which objects to rank. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// What to rank objects by
+    #[arg(long, value_enum, default_value_t = Rank::Size)]
+    by: Rank,
+    /// How many objects to show
+    #[arg(short = 'n', long, default_value = "10")]
+    count: usize,
+    /// Print a table for people, or a listing that can be piped into other commands
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Total objects by their prefix this many levels below the root, and rank the prefixes instead
+    #[arg(long)]
+    group_prefix: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Rank {
+    /// Largest first
+    Size,
+    /// Least recently modified first
+    Oldest,
+    /// Most recently modified first
+    Newest,
+    /// Most deeply nested first
+    Depth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Table,
+    Ndjson,
+}
+
+/// A line of the NDJSON output with `--group-prefix`
+#[derive(Debug, Serialize)]
+struct Group {
+    prefix: String,
+    objects: u64,
+    bytes: u64,
+}
+
+/// An object in the running top N, ordered so the best ranked is the greatest
+struct Ranked {
+    score: i64,
+    meta: ObjectMeta,
+}
+
+impl Ranked {
+    fn key(&self) -> (i64, Reverse<&ObjectStorePath>) {
+        // Ties go to the first path alphabetically, so the output doesn't depend on listing order
+        (self.score, Reverse(&self.meta.location))
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Top {
+    fn score(&self, root: &Root, meta: &ObjectMeta) -> Result<i64> {
+        Ok(match self.by {
+            Rank::Size => meta.size as i64,
+            Rank::Oldest => -meta.last_modified.timestamp_micros(),
+            Rank::Newest => meta.last_modified.timestamp_micros(),
+            Rank::Depth => root.relative(&meta.location)?.count() as i64,
+        })
+    }
+
+    fn print_table(&self, root: &Root, ranked: &[ObjectMeta]) -> Result<()> {
+        let rows = ranked
+            .iter()
+            .map(|meta| {
+                let value = match self.by {
+                    Rank::Size => format_size(meta.size as u64),
+                    Rank::Oldest | Rank::Newest => meta.last_modified.to_rfc3339(),
+                    Rank::Depth => root.relative(&meta.location)?.count().to_string(),
+                };
+                Ok((value, meta.location.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let width = rows.iter().map(|(value, _)| value.len()).max().unwrap_or(0);
+        for (value, location) in rows {
+            println!("{value:>width$}  {location}");
+        }
+        Ok(())
+    }
+
+    /// Rank prefixes by the total size of the objects under them
+    async fn top_prefixes(&self, depth: usize) -> Result<()> {
+        if self.by != Rank::Size {
+            bail!("--group-prefix can only rank prefixes by size");
+        }
+        let filter = self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+        let mut totals = HashMap::<ObjectStorePath, Usage>::new();
+        let mut objects = std::pin::pin!(self.find.objects(listed.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if filter.is_match(&meta) {
+                let usage = totals
+                    .entry(du::prefix(&root, &meta.location, depth)?)
+                    .or_default();
+                usage.bytes += meta.size as u64;
+                usage.objects += 1;
+            }
+        }
+        let mut totals: Vec<_> = totals.into_iter().collect();
+        totals.sort_by(|(a_prefix, a), (b_prefix, b)| {
+            b.bytes.cmp(&a.bytes).then_with(|| a_prefix.cmp(b_prefix))
+        });
+        totals.truncate(self.count);
+
+        if self.format == Format::Table {
+            du::print_totals(totals.into_iter().collect());
+            return Ok(());
+        }
+        let stdout = StdoutWriter::start_bare()?;
+        for (prefix, usage) in totals {
+            stdout
+                .write(Group {
+                    prefix: format!("{prefix}/"),
+                    objects: usage.objects,
+                    bytes: usage.bytes,
+                })
+                .await?;
+        }
+        stdout.finish().await
+    }
+
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        if let Some(depth) = self.group_prefix {
+            return self.top_prefixes(depth).await;
+        }
+        let filter = self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+        // The worst of the top N is on top of the heap, ready to be pushed out by something better
+        let mut heap = BinaryHeap::<Reverse<Ranked>>::with_capacity(self.count + 1);
+        let mut objects = std::pin::pin!(self.find.objects(listed.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if !filter.is_match(&meta) {
+                continue;
+            }
+            heap.push(Reverse(Ranked {
+                score: self.score(&root, &meta)?,
+                meta,
+            }));
+            if heap.len() > self.count {
+                heap.pop();
+            }
+        }
+        let ranked: Vec<ObjectMeta> = heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|Reverse(ranked)| ranked.meta)
+            .collect();
+
+        match self.format {
+            Format::Table => self.print_table(&root, &ranked),
+            Format::Ndjson => {
+                let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+                for meta in ranked {
+                    stdout.write(meta.into()).await?;
+                }
+                stdout.finish().await
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, ensure, Result};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+
+use crate::copy::stream_object;
+use crate::listing::{self, FailureLog, StdoutWriter};
+use crate::store::Root;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Touch {
+    /// Only print what would be touched, without changing anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Also save the objects that failed to be touched here, as a listing that can be piped back in
+    #[arg(long)]
+    failed_out: Option<PathBuf>,
+}
+
+impl Touch {
+    /// Refresh an object's last modified time, returning its new metadata.
+    ///
+    /// Copying an object onto itself is cheapest, but some stores refuse to or keep the old time,
+    /// and the local filesystem would leave a stray hard link behind, so those are uploaded again.
+    async fn touch(root: &Root, meta: &ObjectMeta) -> Result<ObjectMeta> {
+        if root.url.scheme() != "file"
+            && root
+                .store
+                .copy(&meta.location, &meta.location)
+                .await
+                .is_ok()
+        {
+            let copied = root.store.head(&meta.location).await?;
+            if copied.last_modified > meta.last_modified {
+                return Ok(copied);
+            }
+        }
+        stream_object(root, meta, root, &meta.location).await?;
+        let uploaded = root.store.head(&meta.location).await?;
+        ensure!(
+            uploaded.last_modified > meta.last_modified,
+            "{} was uploaded again but its last modified time didn't change",
+            meta.location
+        );
+        Ok(uploaded)
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let root = &Root::open(preamble.root()?)?;
+        let failures = FailureLog::create(self.failed_out.as_deref(), &preamble)?;
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let (writer, failures_ref) = (&stdout, &failures);
+        let touched = &AtomicUsize::new(0);
+
+        listing::read_stdin()
+            .try_for_each_concurrent(global_args.concurrency, |meta| async move {
+                if self.dry_run {
+                    touched.fetch_add(1, Ordering::Relaxed);
+                    return writer.write(meta.into()).await;
+                }
+                let updated = match Self::touch(root, &meta).await {
+                    Ok(updated) => updated,
+                    Err(e) => return failures_ref.record(&meta, &e),
+                };
+                touched.fetch_add(1, Ordering::Relaxed);
+                if global_args.verbose && updated.e_tag != meta.e_tag {
+                    eprintln!(
+                        "The etag of {} changed from {} to {}",
+                        meta.location,
+                        meta.e_tag.as_deref().unwrap_or("none"),
+                        updated.e_tag.as_deref().unwrap_or("none")
+                    );
+                }
+                writer.write(updated.into()).await
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let touched = touched.load(Ordering::Relaxed);
+        if self.dry_run {
+            eprintln!("Dry run: {touched} objects would be touched");
+        } else {
+            eprintln!("Touched {touched} objects");
+        }
+        let failed = failures.finish()?;
+        if failed > 0 {
+            bail!("Failed to touch {failed} objects");
+        }
+        Ok(())
+    }
+}
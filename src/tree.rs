@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+
+use crate::find::Find;
+use crate::store::Root;
+use crate::units::format_size;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Tree {
+    /// Which objects to show. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+    /// How many levels below the root to show. Anything deeper is only counted in its parent.
+    #[arg(short, long, default_value = "3")]
+    depth: usize,
+    /// Show at most this many entries in each prefix, largest first
+    #[arg(long, default_value = "20")]
+    max_children: usize,
+}
+
+/// A prefix or object, with totals for everything under it.
+///
+/// Only the levels that get printed are kept, so memory grows with the number of
+/// prefixes near the root rather than with the number of objects.
+#[derive(Debug, Default)]
+struct Node {
+    objects: u64,
+    bytes: u64,
+    /// Whether anything is under this node, so it's a prefix rather than an object
+    prefix: bool,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn add<'a>(&mut self, mut parts: impl Iterator<Item = &'a str>, depth: usize, bytes: u64) {
+        self.objects += 1;
+        self.bytes += bytes;
+        let Some(part) = parts.next() else { return };
+        self.prefix = true;
+        if depth == 0 {
+            return;
+        }
+        self.children
+            .entry(part.to_string())
+            .or_default()
+            .add(parts, depth - 1, bytes);
+    }
+
+    fn describe(&self, name: &str) -> String {
+        if self.prefix {
+            format!(
+                "{name}/  ({} objects, {})",
+                self.objects,
+                format_size(self.bytes)
+            )
+        } else {
+            format!("{name}  ({})", format_size(self.bytes))
+        }
+    }
+
+    fn print_children(&self, indent: &str, max_children: usize) {
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by(|(a_name, a), (b_name, b)| b.bytes.cmp(&a.bytes).then(a_name.cmp(b_name)));
+        let hidden = children.len().saturating_sub(max_children);
+        children.truncate(max_children);
+        let shown = children.len();
+        for (index, (name, child)) in children.into_iter().enumerate() {
+            let last = index + 1 == shown && hidden == 0;
+            let (branch, continued) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            println!("{indent}{branch}{}", child.describe(name));
+            child.print_children(&format!("{indent}{continued}"), max_children);
+        }
+        if hidden > 0 {
+            println!("{indent}└── (… and {hidden} more)");
+        }
+    }
+}
+
+impl Tree {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let filter = self.find.filter().await?;
+        let (preamble, listed) = self.find.open()?;
+        let root = match &listed {
+            Some(root) => root.clone(),
+            None => Root::open(preamble.root()?)?,
+        };
+        let mut tree = Node {
+            prefix: true,
+            ..Default::default()
+        };
+        let mut objects = std::pin::pin!(self.find.objects(listed.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if filter.is_match(&meta) {
+                let parts: Vec<_> = root.relative(&meta.location)?.collect();
+                tree.add(
+                    parts.iter().map(|p| p.as_ref()),
+                    self.depth,
+                    meta.size as u64,
+                );
+            }
+        }
+
+        println!("{}", tree.describe(root.url.as_str().trim_end_matches('/')));
+        tree.print_children("", self.max_children);
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectStorePath;
+use object_store::GetOptions;
+use serde::Serialize;
+use tokio::io::AsyncBufReadExt;
+
+use crate::copy::{Upload, PART_SIZE};
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::versions::VersionExport;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Undelete {
+    /// Actually restore the objects. Without this, only print what would be restored.
+    #[arg(short, long)]
+    yes: bool,
+}
+
+/// What happened to a deleted key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    /// The newest version with content was copied back over the delete marker
+    Restored,
+    /// The key would be restored with `--yes`
+    WouldRestore,
+    /// Every version of the key is a delete marker, so there is nothing to bring back
+    Unrecoverable,
+    /// Restoring the key was attempted and failed
+    Failed,
+}
+
+/// A line of the report, one per deleted key
+#[derive(Debug, Serialize)]
+struct Report {
+    location: String,
+    outcome: Outcome,
+    /// The version that was or would be restored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+}
+
+/// Copy an old version of an object back to its key, making it the latest again
+async fn restore(root: &Root, location: &ObjectStorePath, version: &VersionExport) -> Result<()> {
+    let opts = GetOptions {
+        version: version.object.version.clone(),
+        ..Default::default()
+    };
+    let old = root.store.get_opts(location, opts).await?;
+    if version.object.size <= PART_SIZE {
+        root.store.put(location, old.bytes().await?.into()).await?;
+    } else {
+        let mut body = old.into_stream();
+        let mut upload = Upload::start(root, location).await?;
+        let streamed: Result<()> = async {
+            while let Some(chunk) = body.next().await {
+                upload.write(&chunk?).await?;
+            }
+            Ok(())
+        }
+        .await;
+        upload.finish_or_abort(streamed).await?;
+    }
+    Ok(())
+}
+
+/// The keys whose latest version is a delete marker, with every version of each
+fn deleted(
+    keys: BTreeMap<String, Vec<VersionExport>>,
+) -> Result<Vec<(String, Vec<VersionExport>)>> {
+    let read = keys.len();
+    let deleted: Vec<_> = keys
+        .into_iter()
+        .filter(|(_, versions)| versions.iter().any(|v| v.is_latest && v.is_delete_marker))
+        .collect();
+    // Doing nothing quietly would look like there was nothing deleted
+    if deleted.is_empty() && read > 0 {
+        bail!(
+            "None of the {read} objects read are behind a delete marker. \
+             `versions` can't list delete markers yet, so its output never has any to restore."
+        );
+    }
+    Ok(deleted)
+}
+
+impl Undelete {
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let root = &Root::open(preamble.root()?)?;
+
+        // Every version of a key has to be seen before knowing which one to restore
+        let mut keys = BTreeMap::<String, Vec<VersionExport>>::new();
+        let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+        while let Some(line) = lines.next_line().await? {
+            let version: VersionExport = serde_json::from_str(&line)
+                .context("Reading a version. (Pipe in the output of `obvious3 versions`)")?;
+            keys.entry(version.object.location.clone())
+                .or_default()
+                .push(version);
+        }
+        let deleted = deleted(keys)?;
+
+        let stdout = StdoutWriter::start_bare()?;
+        let writer = &stdout;
+        let failed = &AtomicUsize::new(0);
+        let unrecoverable = &AtomicUsize::new(0);
+        let restored = &AtomicUsize::new(0);
+        futures::stream::iter(deleted.into_iter().map(anyhow::Ok))
+            .try_for_each_concurrent(global_args.concurrency, |(location, versions)| async move {
+                let newest = versions
+                    .iter()
+                    .filter(|v| !v.is_delete_marker)
+                    .max_by_key(|v| v.object.last_modified);
+                let outcome = match newest {
+                    None => {
+                        unrecoverable.fetch_add(1, Ordering::Relaxed);
+                        Outcome::Unrecoverable
+                    }
+                    Some(_) if !self.yes => {
+                        restored.fetch_add(1, Ordering::Relaxed);
+                        Outcome::WouldRestore
+                    }
+                    Some(version) => {
+                        let path = ObjectStorePath::from(location.as_str());
+                        match restore(root, &path, version).await {
+                            Ok(()) => {
+                                restored.fetch_add(1, Ordering::Relaxed);
+                                Outcome::Restored
+                            }
+                            Err(e) => {
+                                eprintln!("{location}: {e:#}");
+                                failed.fetch_add(1, Ordering::Relaxed);
+                                Outcome::Failed
+                            }
+                        }
+                    }
+                };
+                writer
+                    .write(Report {
+                        version: newest.and_then(|v| v.object.version.clone()),
+                        location,
+                        outcome,
+                    })
+                    .await
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let restored = restored.load(Ordering::Relaxed);
+        let unrecoverable = unrecoverable.load(Ordering::Relaxed);
+        if self.yes {
+            eprintln!("Restored {restored} objects, {unrecoverable} had nothing to restore");
+        } else {
+            eprintln!(
+                "Dry run: {restored} objects would be restored, {unrecoverable} have nothing to restore. Pass --yes to restore them."
+            );
+        }
+        let failed = failed.load(Ordering::Relaxed);
+        if failed > 0 {
+            bail!("Failed to restore {failed} objects");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(location: &str, is_latest: bool, is_delete_marker: bool) -> VersionExport {
+        let line = format!(
+            r#"{{"location":"{location}","last_modified":"2024-01-01T00:00:00Z","size":1,"is_latest":{is_latest},"is_delete_marker":{is_delete_marker}}}"#
+        );
+        serde_json::from_str(&line).unwrap()
+    }
+
+    fn keys(versions: Vec<VersionExport>) -> BTreeMap<String, Vec<VersionExport>> {
+        let mut keys = BTreeMap::<_, Vec<_>>::new();
+        for version in versions {
+            keys.entry(version.object.location.clone())
+                .or_default()
+                .push(version);
+        }
+        keys
+    }
+
+    #[test]
+    fn picks_out_keys_behind_delete_markers() {
+        let deleted = deleted(keys(vec![
+            version("x/gone", true, true),
+            version("x/gone", false, false),
+            version("x/kept", true, false),
+        ]))
+        .unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0].0, "x/gone");
+        assert_eq!(deleted[0].1.len(), 2);
+    }
+
+    #[test]
+    fn refuses_a_listing_without_delete_markers() {
+        // Like the output of `versions`, which only has the latest versions for now
+        let error = deleted(keys(vec![
+            version("x/a", true, false),
+            version("x/b", true, false),
+        ]))
+        .unwrap_err();
+        assert!(
+            error.to_string().contains("None of the 2 objects"),
+            "{error}"
+        );
+        assert!(deleted(BTreeMap::new()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+use clap::{Parser, ValueEnum};
+use futures::TryStreamExt;
+use object_store::path::Path as ObjectStorePath;
+use object_store::ObjectMeta;
+
+use crate::listing::{self, StdoutWriter};
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Uniq {
+    /// Which record to keep when a location appears more than once
+    #[arg(long, value_enum, default_value_t = Keep::First)]
+    keep: Keep,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Keep {
+    /// The first one read, which lets records be written as soon as they arrive
+    First,
+    /// The most recently modified
+    Newest,
+    /// The largest
+    Largest,
+}
+
+impl Keep {
+    /// Whether `candidate` should replace `kept`
+    pub fn prefers(self, candidate: &ObjectMeta, kept: &ObjectMeta) -> bool {
+        match self {
+            Keep::First => false,
+            Keep::Newest => candidate.last_modified > kept.last_modified,
+            Keep::Largest => candidate.size > kept.size,
+        }
+    }
+}
+
+impl Uniq {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let preamble = listing::read_preamble()?;
+        let mut objects = std::pin::pin!(listing::read_stdin_concatenated(&preamble));
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        let mut duplicates = 0;
+
+        if self.keep == Keep::First {
+            let mut seen = HashSet::<ObjectStorePath>::new();
+            while let Some(meta) = objects.try_next().await? {
+                if seen.insert(meta.location.clone()) {
+                    stdout.write(meta.into()).await?;
+                } else {
+                    duplicates += 1;
+                }
+            }
+        } else {
+            // Nothing can be written until the end, since a better record could come at any time.
+            // Records keep the position of the first one seen for their location.
+            let mut kept = HashMap::<ObjectStorePath, (usize, ObjectMeta)>::new();
+            while let Some(meta) = objects.try_next().await? {
+                let position = kept.len();
+                match kept.get_mut(&meta.location) {
+                    Some((_, existing)) => {
+                        duplicates += 1;
+                        if self.keep.prefers(&meta, existing) {
+                            *existing = meta;
+                        }
+                    }
+                    None => {
+                        kept.insert(meta.location.clone(), (position, meta));
+                    }
+                }
+            }
+            let mut kept: Vec<_> = kept.into_values().collect();
+            kept.sort_by_key(|(position, _)| *position);
+            for (_, meta) in kept {
+                stdout.write(meta.into()).await?;
+            }
+        }
+        stdout.finish().await?;
+
+        eprintln!("Dropped {duplicates} duplicates");
+        Ok(())
+    }
+}
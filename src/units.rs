@@ -0,0 +1,214 @@
+//! Conversions between byte counts, durations or times and the friendlier forms people read and write
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+const IEC_UNITS: [&str; 7] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+/// Format a byte count with a binary unit, like `1.5 GiB`
+pub fn format_size(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < IEC_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{value:.1} {}", IEC_UNITS[unit])
+    }
+}
+
+/// Parse a byte count like `5GiB`, `100MB`, `32k` or `1.5 GiB`.
+///
+/// SI units are powers of 1000 and IEC units powers of 1024, in any case.
+/// A bare number is a count of bytes.
+pub fn parse_size(text: &str) -> anyhow::Result<u64> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| anyhow::anyhow!("{text:?} doesn't start with a number"))?;
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000_u64.pow(2),
+        "g" | "gb" => 1000_u64.pow(3),
+        "t" | "tb" => 1000_u64.pow(4),
+        "p" | "pb" => 1000_u64.pow(5),
+        "ki" | "kib" => 1 << 10,
+        "mi" | "mib" => 1 << 20,
+        "gi" | "gib" => 1 << 30,
+        "ti" | "tib" => 1 << 40,
+        "pi" | "pib" => 1 << 50,
+        other => anyhow::bail!(
+            "Unknown unit {other:?}, expected one of B, kB, MB, GB, TB, PB, KiB, MiB, GiB, TiB or PiB"
+        ),
+    };
+    let bytes = number * multiplier as f64;
+    anyhow::ensure!(bytes <= u64::MAX as f64, "{text:?} is too large");
+    Ok(bytes.round() as u64)
+}
+
+/// Parse a duration like `3d`, `2h30m` or `1w`, or a bare number of seconds.
+///
+/// Zero isn't a useful age to filter by, so it's rejected along with negative durations.
+pub fn parse_duration(text: &str) -> anyhow::Result<std::time::Duration> {
+    let text = text.trim();
+    anyhow::ensure!(
+        !text.starts_with('-'),
+        "{text:?} is negative, durations count back from now"
+    );
+    let duration = match text.parse::<u64>() {
+        Ok(seconds) => std::time::Duration::from_secs(seconds),
+        Err(_) => humantime::parse_duration(text)
+            .map_err(|e| anyhow::anyhow!("{e}, expected something like 90, 3d, 2h30m or 1w"))?,
+    };
+    anyhow::ensure!(
+        !duration.is_zero(),
+        "{text:?} is zero, give a positive duration"
+    );
+    Ok(duration)
+}
+
+/// Epoch timestamps at least this large are in milliseconds, since in seconds they'd be after the year 5000
+const EPOCH_MILLIS_FROM: i64 = 100_000_000_000;
+
+/// Parse a time in any of the forms people tend to write them, taking UTC if there's no offset:
+/// RFC3339 like `2024-06-01T12:00:00Z`, a date like `2024-06-01` for its midnight,
+/// `2024-06-01 12:00` or `2024-06-01 12:00:00`, RFC2822 like `Sat, 1 Jun 2024 12:00:00 +0000`,
+/// or seconds or milliseconds since 1970, told apart by how large they are.
+pub fn parse_time(text: &str) -> anyhow::Result<DateTime<Utc>> {
+    let text = text.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Ok(time.to_utc());
+    }
+    if let Ok(epoch) = text.parse::<i64>() {
+        let time = match epoch.abs() >= EPOCH_MILLIS_FROM {
+            true => DateTime::from_timestamp_millis(epoch),
+            false => DateTime::from_timestamp(epoch, 0),
+        };
+        return time.ok_or_else(|| anyhow::anyhow!("{text:?} is too far from 1970 to be a time"));
+    }
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+    ] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(text, format) {
+            return Ok(time.and_utc());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_time(Default::default()).and_utc());
+    }
+    if let Ok(time) = DateTime::parse_from_rfc2822(text) {
+        return Ok(time.to_utc());
+    }
+    anyhow::bail!(
+        "{text:?} isn't a time, expected RFC3339 like 2024-06-01T12:00:00Z, a date like 2024-06-01, \
+         2024-06-01 12:00[:00], RFC2822 like \"Sat, 1 Jun 2024 12:00:00 +0000\", \
+         or seconds or milliseconds since 1970"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes_in_either_kind_of_unit() {
+        assert_eq!(parse_size("1234").unwrap(), 1234);
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("100MB").unwrap(), 100_000_000);
+        assert_eq!(parse_size("32k").unwrap(), 32_000);
+        assert_eq!(parse_size("5GiB").unwrap(), 5 << 30);
+        assert_eq!(parse_size("1.5 GiB").unwrap(), 3 << 29);
+        assert_eq!(parse_size("2kib").unwrap(), 2048);
+        assert_eq!(parse_size(" 7 b ").unwrap(), 7);
+        assert!(parse_size("MB").is_err());
+        assert!(parse_size("5 parsecs").is_err());
+        assert!(parse_size("100000PiB").is_err());
+    }
+
+    #[test]
+    fn formats_sizes_in_binary_units() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 << 30), "5.0 GiB");
+    }
+
+    #[test]
+    fn parses_times_in_every_accepted_form() {
+        let noon = "2024-06-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let midnight = "2024-06-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        for text in [
+            "2024-06-01T12:00:00Z",
+            "2024-06-01T14:00:00+02:00",
+            "2024-06-01 12:00:00",
+            "2024-06-01 12:00",
+            "2024-06-01T12:00",
+            "Sat, 1 Jun 2024 12:00:00 +0000",
+            "1717243200",
+            "1717243200000",
+            " 2024-06-01 12:00 ",
+        ] {
+            assert_eq!(parse_time(text).unwrap(), noon, "{text}");
+        }
+        assert_eq!(parse_time("2024-06-01").unwrap(), midnight);
+        assert_eq!(
+            parse_time("2024-06-01 12:00:00.250").unwrap(),
+            noon + chrono::Duration::milliseconds(250)
+        );
+    }
+
+    #[test]
+    fn tells_epoch_seconds_from_milliseconds_by_size() {
+        assert_eq!(parse_time("0").unwrap(), DateTime::UNIX_EPOCH);
+        // The largest number still taken as seconds is in the year 5138
+        let seconds = parse_time(&(EPOCH_MILLIS_FROM - 1).to_string()).unwrap();
+        assert_eq!(seconds.timestamp(), EPOCH_MILLIS_FROM - 1);
+        let millis = parse_time(&EPOCH_MILLIS_FROM.to_string()).unwrap();
+        assert_eq!(millis.timestamp_millis(), EPOCH_MILLIS_FROM);
+        assert!(parse_time(&i64::MAX.to_string()).is_err());
+    }
+
+    #[test]
+    fn lists_the_accepted_forms_when_a_time_doesnt_parse() {
+        for text in [
+            "yesterday",
+            "2024-13-01",
+            "2024-06-01 25:00",
+            "06/01/2024",
+            "",
+        ] {
+            let error = parse_time(text).unwrap_err().to_string();
+            assert!(error.contains("RFC3339"), "{text}: {error}");
+            assert!(error.contains("2024-06-01 12:00[:00]"), "{text}: {error}");
+        }
+    }
+
+    #[test]
+    fn parses_durations_with_units_or_bare_seconds() {
+        let secs = std::time::Duration::from_secs;
+        assert_eq!(parse_duration("90").unwrap(), secs(90));
+        assert_eq!(parse_duration("3d").unwrap(), secs(3 * 86400));
+        assert_eq!(parse_duration("2h30m").unwrap(), secs(9000));
+        assert_eq!(parse_duration("2h 30m").unwrap(), secs(9000));
+        assert_eq!(parse_duration("1w").unwrap(), secs(7 * 86400));
+        assert_eq!(parse_duration(" 15m ").unwrap(), secs(900));
+        assert_eq!(
+            parse_duration("250ms").unwrap(),
+            std::time::Duration::from_millis(250)
+        );
+        for text in ["0", "0s", "-5m", "", "soon", "5 parsecs"] {
+            assert!(parse_duration(text).is_err(), "{text}");
+        }
+        let error = parse_duration("3 fortnights").unwrap_err().to_string();
+        assert!(error.contains("expected something like"), "{error}");
+    }
+}
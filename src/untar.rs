@@ -0,0 +1,347 @@
+use anyhow::{bail, ensure, Context, Result};
+use bytes::Bytes;
+use clap::Parser;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::path::Path as ObjectStorePath;
+use object_store::{Attribute, Attributes, ObjectMeta, PutOptions};
+use tokio::io::AsyncReadExt;
+
+use crate::codec::{self, Codec, Decoder};
+use crate::copy::{Upload, PART_SIZE};
+use crate::listing::{self, StdoutWriter};
+use crate::store::Root;
+use crate::tar::BLOCK;
+use crate::{Args, ObjectExport, Preamble};
+
+#[derive(Debug, Parser)]
+pub struct Untar {
+    /// The archive to extract. If not specified, it is read from stdin.
+    #[arg(short, long)]
+    src: Option<String>,
+    /// The root to extract into. Paths inside the archive are preserved below it.
+    #[arg(short, long)]
+    dest: String,
+    /// Decompress the archive with gzip. Gzip and zstd archives are also detected by their first
+    /// bytes.
+    #[arg(short = 'z', long)]
+    gzip: bool,
+}
+
+/// Reads an archive in whole pieces, decompressing it on the way if needed
+struct Archive {
+    source: BoxStream<'static, Result<Bytes>>,
+    decoder: Option<Decoder>,
+    buffer: Vec<u8>,
+    done: bool,
+}
+
+impl Archive {
+    async fn open(mut source: BoxStream<'static, Result<Bytes>>, gzip: bool) -> Result<Self> {
+        // Look at the first bytes to see whether it's compressed
+        let mut start = vec![];
+        while start.len() < codec::MAGIC_LEN {
+            match source.try_next().await? {
+                Some(chunk) => start.extend_from_slice(&chunk),
+                None => break,
+            }
+        }
+        let codec = Codec::from_magic(&start).or(gzip.then_some(Codec::Gzip));
+        let mut decoder = codec.map(Decoder::new).transpose()?;
+        let buffer = match &mut decoder {
+            Some(decoder) => decoder.update(&start)?,
+            None => start,
+        };
+        Ok(Self {
+            source,
+            decoder,
+            buffer,
+            done: false,
+        })
+    }
+
+    /// Read until at least `len` bytes are buffered, or the archive ends
+    async fn fill(&mut self, len: usize) -> Result<()> {
+        while self.buffer.len() < len && !self.done {
+            match (self.source.try_next().await?, &mut self.decoder) {
+                (Some(chunk), Some(decoder)) => self.buffer.extend(decoder.update(&chunk)?),
+                (Some(chunk), None) => self.buffer.extend_from_slice(&chunk),
+                (None, decoder) => {
+                    if let Some(decoder) = decoder.take() {
+                        self.buffer.extend(decoder.finish()?);
+                    }
+                    self.done = true;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read exactly `len` bytes, or nothing if the archive has ended
+    async fn read(&mut self, len: usize) -> Result<Option<Vec<u8>>> {
+        self.fill(len).await?;
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+        ensure!(
+            self.buffer.len() >= len,
+            "The archive ended in the middle of an entry"
+        );
+        let rest = self.buffer.split_off(len);
+        Ok(Some(std::mem::replace(&mut self.buffer, rest)))
+    }
+
+    /// Read up to `len` bytes, but at least one
+    async fn read_some(&mut self, len: usize) -> Result<Vec<u8>> {
+        self.fill(1).await?;
+        ensure!(
+            !self.buffer.is_empty(),
+            "The archive ended in the middle of an entry"
+        );
+        let rest = self.buffer.split_off(len.min(self.buffer.len()));
+        Ok(std::mem::replace(&mut self.buffer, rest))
+    }
+
+    /// Read an entry's contents along with the padding after it
+    async fn read_padded(&mut self, size: u64) -> Result<Vec<u8>> {
+        let padded = (size as usize).next_multiple_of(BLOCK);
+        let mut content = self
+            .read(padded)
+            .await?
+            .context("The archive ended in the middle of an entry")?;
+        content.truncate(size as usize);
+        Ok(content)
+    }
+
+    /// Skip over an entry's contents along with the padding after it
+    async fn skip_padded(&mut self, size: u64) -> Result<()> {
+        self.skip(size.next_multiple_of(BLOCK as u64)).await
+    }
+
+    async fn skip(&mut self, len: u64) -> Result<()> {
+        let mut remaining = len;
+        while remaining > 0 {
+            remaining -= self
+                .read_some(remaining.min(PART_SIZE as u64) as usize)
+                .await?
+                .len() as u64;
+        }
+        Ok(())
+    }
+}
+
+/// Read a numeric header field, which is octal text or, for large values, big endian binary
+fn number(field: &[u8]) -> Result<u64> {
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold((field[0] & 0x7f) as u64, |n, b| (n << 8) | *b as u64));
+    }
+    let text = std::str::from_utf8(field)?.trim_matches(|c: char| c == '\0' || c == ' ');
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(text, 8).with_context(|| format!("Corrupt tar header: bad number {text:?}"))
+}
+
+/// A NUL terminated text field
+fn text(field: &[u8]) -> String {
+    let end = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// The parts of a tar header that matter for extracting it
+struct Header {
+    name: String,
+    size: u64,
+    mtime: i64,
+    kind: u8,
+}
+
+impl Header {
+    fn parse(block: &[u8]) -> Result<Self> {
+        let expected = number(&block[148..156])?;
+        let actual: u64 = block
+            .iter()
+            .enumerate()
+            .map(|(i, b)| if (148..156).contains(&i) { b' ' } else { *b } as u64)
+            .sum();
+        ensure!(expected == actual, "Corrupt tar header: bad checksum");
+        let mut name = text(&block[..100]);
+        if &block[257..262] == b"ustar" {
+            let prefix = text(&block[345..500]);
+            if !prefix.is_empty() {
+                name = format!("{prefix}/{name}");
+            }
+        }
+        Ok(Self {
+            name,
+            size: number(&block[124..136])?,
+            mtime: number(&block[136..148])? as i64,
+            kind: block[156],
+        })
+    }
+
+    /// Apply the pax extended header records that replace ustar fields
+    fn apply_pax(&mut self, records: &[u8]) -> Result<()> {
+        let mut rest = records;
+        while !rest.is_empty() {
+            let space = rest
+                .iter()
+                .position(|b| *b == b' ')
+                .context("Corrupt pax header")?;
+            let length: usize = std::str::from_utf8(&rest[..space])?
+                .parse()
+                .context("Corrupt pax header")?;
+            ensure!(length > space && length <= rest.len(), "Corrupt pax header");
+            let record = String::from_utf8_lossy(&rest[space + 1..length - 1]);
+            if let Some((key, value)) = record.split_once('=') {
+                match key {
+                    "path" => self.name = value.to_string(),
+                    "size" => self.size = value.parse().context("Corrupt pax size")?,
+                    "mtime" => {
+                        let seconds = value.split('.').next().unwrap_or_default();
+                        self.mtime = seconds.parse().context("Corrupt pax mtime")?;
+                    }
+                    _ => {}
+                }
+            }
+            rest = &rest[length..];
+        }
+        Ok(())
+    }
+}
+
+/// Stream stdin in chunks
+fn read_stdin_bytes() -> BoxStream<'static, Result<Bytes>> {
+    futures::stream::try_unfold(tokio::io::stdin(), |mut stdin| async move {
+        let mut buf = vec![0; 64 * 1024];
+        let read = stdin.read(&mut buf).await?;
+        buf.truncate(read);
+        Ok((read > 0).then(|| (Bytes::from(buf), stdin)))
+    })
+    .boxed()
+}
+
+impl Untar {
+    /// Where an entry is extracted to
+    fn destination(dest: &Root, name: &str) -> Result<ObjectStorePath> {
+        let name = name.trim_start_matches("./").trim_start_matches('/');
+        let relative = ObjectStorePath::parse(name)
+            .with_context(|| format!("Can't extract {name:?}, it isn't a valid object path"))?;
+        Ok(dest.path.parts().chain(relative.parts()).collect())
+    }
+
+    /// Record the modification time as metadata, since stores set their own last modified time.
+    /// The local filesystem can't store metadata, so it's skipped there.
+    fn attributes(dest: &Root, mtime: i64) -> Attributes {
+        let mut attributes = Attributes::new();
+        if dest.url.scheme() != "file" {
+            attributes.insert(
+                Attribute::Metadata("mtime".into()),
+                mtime.to_string().into(),
+            );
+        }
+        attributes
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let dest = Root::open(&listing::parse_root(&self.dest)?)?;
+        let source = match &self.src {
+            Some(src) => {
+                let src = dest.open_sibling(&listing::parse_root(src)?)?;
+                src.store
+                    .get(&src.path)
+                    .await?
+                    .into_stream()
+                    .map_err(anyhow::Error::from)
+                    .boxed()
+            }
+            None => read_stdin_bytes(),
+        };
+        let mut archive = Archive::open(source, self.gzip).await?;
+
+        let stdout: StdoutWriter = StdoutWriter::start(&Preamble::new(dest.url.clone()))?;
+        // The archive is read in order, but small entries are uploaded in the background
+        let mut uploads = tokio::task::JoinSet::<Result<ObjectMeta>>::new();
+        let mut pax: Option<Vec<u8>> = None;
+        let mut long_name: Option<String> = None;
+        while let Some(block) = archive.read(BLOCK).await? {
+            if block.iter().all(|b| *b == 0) {
+                // An empty block marks the end of the archive
+                break;
+            }
+            let mut header = Header::parse(&block)?;
+            if let Some(records) = pax.take() {
+                header.apply_pax(&records)?;
+            }
+            if let Some(name) = long_name.take() {
+                header.name = name;
+            }
+            match header.kind {
+                // Extended headers apply to the next entry
+                b'x' => pax = Some(archive.read_padded(header.size).await?),
+                b'L' => {
+                    let name = archive.read_padded(header.size).await?;
+                    long_name = Some(text(&name));
+                }
+                b'0' | b'\0' | b'7' => {
+                    let location = Self::destination(&dest, &header.name)?;
+                    let attributes = Self::attributes(&dest, header.mtime);
+                    if header.size as usize <= PART_SIZE {
+                        let content = archive.read_padded(header.size).await?;
+                        let store = dest.store.clone();
+                        while uploads.len() >= global_args.concurrency {
+                            let meta = uploads.join_next().await.unwrap()??;
+                            stdout.write(ObjectExport::from(meta)).await?;
+                        }
+                        uploads.spawn(async move {
+                            let opts = PutOptions {
+                                attributes,
+                                ..Default::default()
+                            };
+                            store.put_opts(&location, content.into(), opts).await?;
+                            Ok(store.head(&location).await?)
+                        });
+                    } else {
+                        let mut upload = Upload::start_with(&dest, &location, attributes).await?;
+                        let streamed: Result<()> = async {
+                            let mut remaining = header.size as usize;
+                            while remaining > 0 {
+                                let chunk = archive.read_some(remaining.min(PART_SIZE)).await?;
+                                remaining -= chunk.len();
+                                upload.write(&chunk).await?;
+                            }
+                            Ok(())
+                        }
+                        .await;
+                        upload.finish_or_abort(streamed).await?;
+                        archive
+                            .skip(header.size.next_multiple_of(BLOCK as u64) - header.size)
+                            .await?;
+                        stdout
+                            .write(ObjectExport::from(dest.store.head(&location).await?))
+                            .await?;
+                    }
+                }
+                // Directories don't need to exist in an object store
+                b'5' => {}
+                b'g' => archive.skip_padded(header.size).await?,
+                kind => {
+                    eprintln!(
+                        "Skipping {}, since entries of type {:?} can't be extracted",
+                        header.name, kind as char
+                    );
+                    archive.skip_padded(header.size).await?;
+                }
+            }
+        }
+        while let Some(meta) = uploads.join_next().await {
+            stdout.write(ObjectExport::from(meta??)).await?;
+        }
+        if pax.is_some() || long_name.is_some() {
+            bail!("The archive ended after an extended header");
+        }
+        stdout.finish().await
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use futures::stream::BoxStream;
+use futures::{StreamExt, TryStreamExt};
+use object_store::ObjectMeta;
+use serde::Serialize;
+
+use crate::listing::{self, ListingPath, StdoutWriter};
+use crate::store::{self, Root};
+use crate::{Args, ObjectExport};
+
+#[derive(Debug, Parser)]
+pub struct Verify {
+    /// Read the manifest from this file, or an object in a store, instead of stdin
+    #[arg(short, long, value_parser = ListingPath::parse)]
+    input: Option<ListingPath>,
+    /// Only check that each object still exists, without comparing its metadata
+    #[arg(long)]
+    fast: bool,
+}
+
+/// How an object in the store compares to the manifest.
+/// When several things changed, the first of these is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    Missing,
+    SizeMismatch,
+    EtagMismatch,
+    LastModifiedMismatch,
+}
+
+/// A line of the report: the object as the manifest recorded it, and what was found instead
+#[derive(Debug, Serialize)]
+struct Check {
+    status: Status,
+    #[serde(flatten)]
+    object: ObjectExport,
+    /// The object as it is now, only when it changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    found: Option<ObjectExport>,
+}
+
+impl Verify {
+    /// Compare one object from the manifest with the store
+    async fn check(
+        &self,
+        root: &Root,
+        expected: &ObjectMeta,
+    ) -> Result<(Status, Option<ObjectMeta>)> {
+        let found = match root.store.head(&expected.location).await {
+            Ok(found) => found,
+            Err(object_store::Error::NotFound { .. }) => return Ok((Status::Missing, None)),
+            Err(e) => return Err(e.into()),
+        };
+        let status = if self.fast {
+            Status::Ok
+        } else if found.size != expected.size {
+            Status::SizeMismatch
+        } else if matches!((store::etag(expected), store::etag(&found)), (Some(e), Some(f)) if e != f)
+        {
+            Status::EtagMismatch
+        } else if found.last_modified != expected.last_modified {
+            Status::LastModifiedMismatch
+        } else {
+            Status::Ok
+        };
+        Ok((status, Some(found)))
+    }
+
+    pub async fn run(&self, global_args: &Args) -> Result<()> {
+        let (preamble, objects): (_, BoxStream<Result<ObjectMeta>>) = match &self.input {
+            Some(path) => {
+                let (preamble, objects) = listing::read_file(path).await?;
+                (preamble, objects.boxed())
+            }
+            None => (listing::read_preamble()?, listing::read_stdin().boxed()),
+        };
+        let root = &Root::open(preamble.root()?)?;
+
+        let stdout = StdoutWriter::start(&preamble)?;
+        let writer = &stdout;
+        let checked = &AtomicUsize::new(0);
+        let missing = &AtomicUsize::new(0);
+        let changed = &AtomicUsize::new(0);
+        objects
+            .try_for_each_concurrent(global_args.concurrency, |expected| async move {
+                let (status, found) = self.check(root, &expected).await?;
+                checked.fetch_add(1, Ordering::Relaxed);
+                match status {
+                    Status::Ok => {}
+                    Status::Missing => {
+                        missing.fetch_add(1, Ordering::Relaxed);
+                    }
+                    _ => {
+                        changed.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+                writer
+                    .write(Check {
+                        status,
+                        object: expected.into(),
+                        found: found.filter(|_| status != Status::Ok).map(Into::into),
+                    })
+                    .await
+            })
+            .await?;
+        stdout.finish().await?;
+
+        let (missing, changed) = (
+            missing.load(Ordering::Relaxed),
+            changed.load(Ordering::Relaxed),
+        );
+        eprintln!(
+            "Checked {} objects: {missing} missing and {changed} changed",
+            checked.load(Ordering::Relaxed)
+        );
+        if missing + changed > 0 {
+            bail!("The store doesn't match the manifest");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use clap::Parser;
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::find::Find;
+use crate::listing::StdoutWriter;
+use crate::{Args, ObjectExport};
+
+#[derive(Debug, Parser)]
+pub struct Versions {
+    /// Which objects to list the versions of. Without a root, the listing is read from stdin.
+    #[command(flatten)]
+    find: Find,
+}
+
+/// One version of an object
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionExport {
+    #[serde(flatten)]
+    pub object: ObjectExport,
+    /// Whether this is the version a plain read gets
+    pub is_latest: bool,
+    /// Whether this version records a deletion rather than holding any content
+    pub is_delete_marker: bool,
+}
+
+impl Versions {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let filter = self.find.filter().await?;
+        let (preamble, root) = self.find.open()?;
+        // object_store only lists current objects, whatever the store keeps behind them
+        eprintln!(
+            "Warning: listing versions isn't supported for {}, showing only the latest version of each object",
+            preamble.root()?
+        );
+
+        let stdout = StdoutWriter::start(&preamble)?;
+        let mut objects = std::pin::pin!(self.find.objects(root.as_ref()));
+        while let Some(meta) = objects.try_next().await? {
+            if filter.is_match(&meta) {
+                stdout
+                    .write(VersionExport {
+                        object: meta.into(),
+                        is_latest: true,
+                        is_delete_marker: false,
+                    })
+                    .await?;
+            }
+        }
+        stdout.finish().await
+    }
+}
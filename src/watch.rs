@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Utc};
+use clap::Parser;
+use futures::TryStreamExt;
+use object_store::ObjectMeta;
+use serde::{Deserialize, Serialize};
+
+use crate::find::Find;
+use crate::listing::StdoutWriter;
+use crate::units::parse_duration;
+use crate::Args;
+
+#[derive(Debug, Parser)]
+pub struct Watch {
+    /// The root to watch, and which of its objects to report
+    #[command(flatten)]
+    find: Find,
+    /// How long to wait between listings, like `30s` or `5m`
+    #[arg(long, value_parser = parse_duration, default_value = "30s")]
+    interval: Duration,
+    /// Remember what has been seen in this file, so a restart only reports what changed since
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+}
+
+/// What was last seen of an object, enough to tell whether it has changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Seen {
+    size: usize,
+    e_tag: Option<String>,
+    last_modified: DateTime<Utc>,
+}
+
+impl Seen {
+    fn of(meta: &ObjectMeta) -> Self {
+        Self {
+            size: meta.size,
+            e_tag: meta.e_tag.clone(),
+            last_modified: meta.last_modified,
+        }
+    }
+
+    /// Whether the object is different now.
+    /// Etags are trusted over modification times, which can be skewed between clocks.
+    fn changed(&self, now: &Seen) -> bool {
+        if self.size != now.size {
+            return true;
+        }
+        match (&self.e_tag, &now.e_tag) {
+            (Some(before), Some(after)) => before != after,
+            _ => self.last_modified != now.last_modified,
+        }
+    }
+}
+
+fn load_state(path: &Path) -> Result<HashMap<String, Seen>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("Reading the state in {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Replace the state file all at once, so stopping halfway never leaves it corrupt
+fn save_state(path: &Path, state: &HashMap<String, Seen>) -> Result<()> {
+    let temporary = path.with_extension("tmp");
+    std::fs::write(&temporary, serde_json::to_vec(state)?)?;
+    std::fs::rename(&temporary, path)
+        .with_context(|| format!("Saving the state to {}", path.display()))
+}
+
+impl Watch {
+    pub async fn run(&self, _global_args: &Args) -> Result<()> {
+        let filter = self.find.filter().await?;
+        let (preamble, root) = self.find.open()?;
+        let Some(root) = root else {
+            bail!("watch needs a --root to list");
+        };
+        let mut state = match &self.state_file {
+            Some(path) => load_state(path)?,
+            None => HashMap::new(),
+        };
+
+        // Listen for Ctrl-C from the start, so it never interrupts a poll halfway through
+        let (stop, mut stopped) = tokio::sync::watch::channel(false);
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                let _ = stop.send(true);
+            }
+        });
+
+        let stdout: StdoutWriter = StdoutWriter::start(&preamble)?;
+        loop {
+            let mut current = HashMap::with_capacity(state.len());
+            let mut objects = std::pin::pin!(self.find.objects(Some(&root)));
+            while let Some(meta) = objects.try_next().await? {
+                let seen = Seen::of(&meta);
+                let key = meta.location.to_string();
+                let new = state.get(&key).is_none_or(|before| before.changed(&seen));
+                if new && filter.is_match(&meta) {
+                    stdout.write(meta.into()).await?;
+                }
+                current.insert(key, seen);
+            }
+            // Objects that were deleted are forgotten, so they are reported again if they come back
+            state = current;
+            if let Some(path) = &self.state_file {
+                save_state(path, &state)?;
+            }
+
+            if *stopped.borrow() {
+                break;
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(self.interval) => {}
+                _ = stopped.changed() => break,
+            }
+        }
+        stdout.finish().await
+    }
+}